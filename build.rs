@@ -1,4 +1,82 @@
+use std::path::{Path, PathBuf};
+
+/// Links the embedded libpython built by `cargo xtask prepare-embed-python`.
+///
+/// The xtask writes the linking directives to `cargo_metadata.txt` in its
+/// output directory, `embed-dest` unless `EMBED_PYTHON_DIR` says otherwise.
+/// PyO3 must be pointed at the matching configuration by setting
+/// `PYO3_CONFIG_FILE` to `pyo3-build-config.txt` in the same directory.
+///
+/// Files the executable needs at run-time, e.g. a dynamically linked
+/// libpython, are placed in the `runtime` directory and installed next to
+/// the executable.
+///
+/// If the directory has a `default_python_config.rs`, the `embedded_python`
+/// cfg is set and `EMBED_PYTHON_CONFIG` points at the file. Without it, the
+/// executable runs with a Python installation found on the host.
 fn main() {
     // use wlr_libpy::bld_cfg::configure_static_libs;
     // configure_static_libs().unwrap().emit_link_flags();
+
+    println!("cargo:rerun-if-env-changed=EMBED_PYTHON_DIR");
+    println!("cargo:rustc-check-cfg=cfg(embedded_python)");
+
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("wasm32") {
+        return;
+    }
+
+    let dir = std::env::var_os("EMBED_PYTHON_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("embed-dest"));
+    let metadata = dir.join("cargo_metadata.txt");
+    println!("cargo:rerun-if-changed={}", metadata.display());
+
+    if let Ok(content) = std::fs::read_to_string(&metadata) {
+        for line in content.lines().filter(|line| line.starts_with("cargo:")) {
+            println!("{}", line);
+        }
+    }
+
+    let python_config = dir.join("default_python_config.rs");
+    println!("cargo:rerun-if-changed={}", python_config.display());
+    if python_config.is_file() {
+        println!("cargo:rustc-cfg=embedded_python");
+        println!(
+            "cargo:rustc-env=EMBED_PYTHON_CONFIG={}",
+            python_config.display()
+        );
+    }
+
+    let runtime_dir = dir.join("runtime");
+    println!("cargo:rerun-if-changed={}", runtime_dir.display());
+
+    // OUT_DIR is <target>/<profile>/build/<package>-<hash>/out.
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    if let (Ok(entries), Some(profile_dir)) =
+        (std::fs::read_dir(&runtime_dir), out_dir.ancestors().nth(3))
+    {
+        for entry in entries.flatten() {
+            install_runtime_file(&entry.path(), &profile_dir.join(entry.file_name()));
+        }
+    }
+}
+
+/// Make a runtime file available next to the executable.
+fn install_runtime_file(source: &Path, dest: &Path) {
+    if dest.symlink_metadata().is_ok() {
+        return;
+    }
+
+    #[cfg(unix)]
+    let res = std::os::unix::fs::symlink(source, dest);
+    #[cfg(not(unix))]
+    let res = std::fs::copy(source, dest).map(|_| ());
+
+    if let Err(err) = res {
+        println!(
+            "cargo:warning=unable to install {} next to the executable: {}",
+            source.display(),
+            err
+        );
+    }
 }
@@ -25,18 +25,64 @@ fn main() {
         }
     }
 
-    // let interpreter_config = pyo3_build_config::get();
-    //
-    // // Re-export the path to the configured Python interpreter. 
-    // // Tests can use this to derive a useful default 
-    // // config that leverages it.
-    // let python_interpreter = interpreter_config
-    //     .executable
-    //     .as_ref()
-    //     .expect("PyO3 configuration does not define Python executable path");
-    //
-    // println!(
-    //     "cargo:rustc-env=PYTHON_INTERPRETER_PATH={}",
-    //     python_interpreter
-    // );
+    let interpreter_config = pyo3_build_config::get();
+
+    // PyO3 ignores its `abi3-py310` feature when PYO3_CONFIG_FILE is set, so a
+    // configuration with `abi3=false` builds against the full API of its Python
+    // version instead. The embedded interpreter's configuration does that on
+    // purpose, but it must not go unnoticed nor target older versions.
+    println!("cargo:rerun-if-env-changed=PYO3_CONFIG_FILE");
+    if !interpreter_config.abi3 && std::env::var_os("PYO3_CONFIG_FILE").is_some() {
+        let version = interpreter_config.version;
+        if (version.major, version.minor) < (3, 10) {
+            panic!(
+                "PYO3_CONFIG_FILE disables abi3 for Python {}, but pyembed requires Python 3.10 or newer",
+                version
+            );
+        }
+
+        println!(
+            "cargo:warning=PYO3_CONFIG_FILE disables abi3: building against the full Python {} API",
+            version
+        );
+    }
+
+    // Expose PyO3's `Py_3_*` and `Py_LIMITED_API` cfgs, so code requiring the
+    // full API of a Python version can be gated on it.
+    pyo3_build_config::use_pyo3_cfgs();
+    println!("cargo:rustc-check-cfg=cfg(Py_3_12)");
+    println!("cargo:rustc-check-cfg=cfg(Py_LIMITED_API)");
+
+    // Binaries dynamically linking libpython look for it next to themselves,
+    // so the library can be shipped alongside the executable. Link arguments
+    // only apply to this crate's tests; executables depending on pyembed get
+    // the rpath from the xtask's cargo metadata.
+    if let (true, Some(lib_dir)) = (interpreter_config.shared, &interpreter_config.lib_dir) {
+        if let Ok(os) = std::env::var("CARGO_CFG_TARGET_OS") {
+            match os.as_str() {
+                "linux" => {
+                    println!("cargo:rustc-link-arg=-Wl,-rpath,$ORIGIN");
+                }
+                "macos" => {
+                    println!("cargo:rustc-link-arg=-Wl,-rpath,@executable_path");
+                }
+                _ => {}
+            }
+        }
+
+        println!("cargo:rustc-env=PYEMBED_LIBPYTHON_DIR={}", lib_dir);
+    }
+
+    // Re-export the path to the configured Python interpreter.
+    // Tests can use this to derive a useful default
+    // config that leverages it.
+    let python_interpreter = interpreter_config
+        .executable
+        .as_ref()
+        .expect("PyO3 configuration does not define Python executable path");
+
+    println!(
+        "cargo:rustc-env=PYTHON_INTERPRETER_PATH={}",
+        python_interpreter
+    );
 }
@@ -0,0 +1,404 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Restricting what plugin code may do.
+
+Plugins normally run with all privileges of the interpreter. A
+[CapabilityPolicy] takes away some of them: network access, starting
+processes and threads, and access to files outside chosen directories.
+
+When enabled via
+[crate::OxidizedPythonInterpreterConfig::enforce_capability_policies], an
+audit hook (see PEP 578) is installed after interpreter initialization. It
+checks audit events raised by the running code against the policy of the
+active plugin. Plugin code is run under a policy by holding the guard
+returned by [enter_plugin()] while calling into it:
+
+```ignore
+let _guard = pyembed::capabilities::enter_plugin("bandpass", policy.clone());
+interp.with_gil(|py| plugin.call0(py))?;
+```
+
+Operations a policy denies fail with `PermissionError`. Every denial is
+also logged as a warning naming the plugin and what it attempted.
+
+The following audit events are checked:
+
+| Capability | Events                                                      |
+|------------|-------------------------------------------------------------|
+| network    | `socket.*`, `urllib.Request`                                |
+| processes  | `subprocess.Popen`, `os.system`, `os.exec`, `os.spawn`, `os.posix_spawn`, `os.fork`, `os.forkpty`, `os.startfile`, `_winapi.CreateProcess` |
+| threads    | `_thread.start_new_thread`                                  |
+| files      | `open`, `os.listdir`, `os.scandir`, `os.mkdir`, `os.remove`, `os.rmdir`, `os.rename`, `os.chmod`, `os.truncate`, `os.symlink`, `os.link`, `shutil.copyfile`, `shutil.rmtree` |
+
+Policies are tracked per thread. Since they would not apply in new threads,
+restricted plugins can't start threads. Python only raises an audit event
+for this since 3.13. With older versions, threads started by a plugin run
+unrestricted. Reading Python source, bytecode and
+extension module files is always allowed, so plugins can import modules.
+Directories on `sys.path` may always be listed for the same reason.
+Restricting where modules come from is the job of [crate::sandbox].
+
+Audit hooks are not a security boundary: code using `ctypes` or native
+extensions can bypass them. Policies keep well-behaved code from overstepping
+and make it visible when code tries to.
+*/
+
+use {
+    pyo3::{
+        exceptions::PyPermissionError,
+        prelude::*,
+        types::{PyCFunction, PyString, PyTuple},
+    },
+    std::{
+        cell::RefCell,
+        fmt::{Display, Formatter},
+        marker::PhantomData,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+/// Audit events requiring network access.
+const NETWORK_EVENTS: &[&str] = &["urllib.Request"];
+
+/// Audit events starting other processes.
+const PROCESS_EVENTS: &[&str] = &[
+    "subprocess.Popen",
+    "os.system",
+    "os.exec",
+    "os.spawn",
+    "os.posix_spawn",
+    "os.fork",
+    "os.forkpty",
+    "os.startfile",
+    "_winapi.CreateProcess",
+];
+
+/// Audit events accessing files, with the positions of path arguments.
+const FILE_EVENTS: &[(&str, &[usize])] = &[
+    ("os.mkdir", &[0]),
+    ("os.remove", &[0]),
+    ("os.rmdir", &[0]),
+    ("os.rename", &[0, 1]),
+    ("os.chmod", &[0]),
+    ("os.truncate", &[0]),
+    ("os.symlink", &[0, 1]),
+    ("os.link", &[0, 1]),
+    ("shutil.copyfile", &[0, 1]),
+    ("shutil.rmtree", &[0]),
+];
+
+/// Audit events listing directories.
+const LISTING_EVENTS: &[&str] = &["os.listdir", "os.scandir"];
+
+/// File suffixes of modules, which may always be read.
+const MODULE_SUFFIXES: &[&str] = &[".py", ".pyc", ".so", ".pyd", ".dylib"];
+
+/// Capabilities granted to a plugin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapabilityPolicy {
+    /// Whether the plugin may use the network.
+    pub allow_network: bool,
+    /// Whether the plugin may start processes.
+    pub allow_processes: bool,
+    /// Directories, including subdirectories, the plugin may access files in.
+    ///
+    /// [None] allows access everywhere.
+    pub allowed_paths: Option<Vec<PathBuf>>,
+}
+
+impl CapabilityPolicy {
+    /// A policy granting everything.
+    pub fn unrestricted() -> Self {
+        Self {
+            allow_network: true,
+            allow_processes: true,
+            allowed_paths: None,
+        }
+    }
+
+    /// A policy denying network access and processes, limiting file access
+    /// to `allowed_paths`.
+    pub fn restricted(allowed_paths: Vec<PathBuf>) -> Self {
+        Self {
+            allow_network: false,
+            allow_processes: false,
+            allowed_paths: Some(allowed_paths),
+        }
+    }
+
+    fn is_unrestricted(&self) -> bool {
+        self.allow_network && self.allow_processes && self.allowed_paths.is_none()
+    }
+
+    fn permits(&self, access: &Access) -> bool {
+        match access {
+            Access::Network => self.allow_network,
+            Access::Process => self.allow_processes,
+            Access::Thread => self.is_unrestricted(),
+            Access::File(path) => match &self.allowed_paths {
+                None => true,
+                Some(allowed) => allowed.iter().any(|allowed| path.starts_with(allowed)),
+            },
+        }
+    }
+}
+
+/// An operation denied by a [CapabilityPolicy].
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CapabilityViolation {
+    /// The plugin attempting the operation.
+    plugin: String,
+    /// Name of the audit event raised for the operation.
+    event: String,
+    /// What was denied.
+    detail: String,
+}
+
+impl Display for CapabilityViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "plugin {} is not allowed to {} ({})",
+            self.plugin, self.detail, self.event
+        )
+    }
+}
+
+/// An operation subject to policies, derived from an audit event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Access {
+    Network,
+    Process,
+    Thread,
+    File(PathBuf),
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Network => write!(f, "use the network"),
+            Self::Process => write!(f, "start processes"),
+            Self::Thread => write!(f, "start threads"),
+            Self::File(path) => write!(f, "access {}", path.display()),
+        }
+    }
+}
+
+thread_local! {
+    /// Plugins running on this thread with their policies, innermost last.
+    static ACTIVE_PLUGINS: RefCell<Vec<(String, Arc<CapabilityPolicy>)>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// Keeps a plugin's policy in effect on the current thread.
+///
+/// Dropping the guard restores the policy of the enclosing plugin, if any.
+#[must_use = "the policy is only in effect while the guard is alive"]
+pub struct PluginGuard {
+    // Policies are tracked per thread, so the guard must stay on its thread.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for PluginGuard {
+    fn drop(&mut self) {
+        ACTIVE_PLUGINS.with(|active| {
+            active.borrow_mut().pop();
+        });
+    }
+}
+
+/// Run code on the current thread as `plugin`, restricted by `policy`.
+pub fn enter_plugin(plugin: impl Into<String>, policy: Arc<CapabilityPolicy>) -> PluginGuard {
+    ACTIVE_PLUGINS.with(|active| active.borrow_mut().push((plugin.into(), policy)));
+
+    PluginGuard {
+        _not_send: PhantomData,
+    }
+}
+
+fn path_arg(args: &Bound<'_, PyTuple>, index: usize) -> Option<PathBuf> {
+    let arg = args.get_item(index).ok()?;
+    // Paths may be str, bytes or os.PathLike. File descriptors aren't checked.
+    let path = arg
+        .py()
+        .import_bound("os")
+        .ok()?
+        .call_method1("fsdecode", (arg,))
+        .ok()?;
+    let path = PathBuf::from(path.downcast::<PyString>().ok()?.to_cow().ok()?.as_ref());
+
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        std::env::current_dir().ok().map(|cwd| cwd.join(path))
+    }
+}
+
+fn is_read_mode(mode: &Bound<'_, PyAny>) -> bool {
+    // open() events for file descriptors have no mode.
+    mode.is_none()
+        || mode
+            .downcast::<PyString>()
+            .ok()
+            .and_then(|mode| mode.to_cow().ok().map(|mode| mode.starts_with('r')))
+            .unwrap_or(false)
+}
+
+/// Whether `path` is a directory the import system may search for modules.
+fn is_on_search_path(py: Python, path: &Path) -> bool {
+    let Ok(search_paths) = py.import_bound("sys").and_then(|sys| sys.getattr("path")) else {
+        return false;
+    };
+    let Ok(search_paths) = search_paths.extract::<Vec<PathBuf>>() else {
+        return false;
+    };
+
+    search_paths
+        .iter()
+        .any(|search_path| path.starts_with(search_path))
+}
+
+fn is_module_file(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    MODULE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// The operations an audit event stands for.
+fn accesses(event: &str, args: &Bound<'_, PyTuple>) -> Vec<Access> {
+    if event.starts_with("socket.") || NETWORK_EVENTS.contains(&event) {
+        return vec![Access::Network];
+    }
+    if PROCESS_EVENTS.contains(&event) {
+        return vec![Access::Process];
+    }
+    if event == "_thread.start_new_thread" {
+        return vec![Access::Thread];
+    }
+
+    if event == "open" {
+        let reading = args.get_item(1).is_ok_and(|mode| is_read_mode(&mode));
+
+        return match path_arg(args, 0) {
+            Some(path) if !(reading && is_module_file(&path)) => vec![Access::File(path)],
+            _ => vec![],
+        };
+    }
+
+    if LISTING_EVENTS.contains(&event) {
+        return match path_arg(args, 0) {
+            Some(path) if !is_on_search_path(args.py(), &path) => vec![Access::File(path)],
+            _ => vec![],
+        };
+    }
+
+    FILE_EVENTS
+        .iter()
+        .find(|(name, _)| *name == event)
+        .map(|(_, indices)| {
+            indices
+                .iter()
+                .filter_map(|&index| path_arg(args, index))
+                .map(Access::File)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn check_audit_event(event: &str, args: &Bound<'_, PyTuple>) -> PyResult<()> {
+    let Some((plugin, policy)) = ACTIVE_PLUGINS.with(|active| active.borrow().last().cloned())
+    else {
+        return Ok(());
+    };
+    if policy.is_unrestricted() {
+        return Ok(());
+    }
+
+    let Some(access) = accesses(event, args)
+        .into_iter()
+        .find(|access| !policy.permits(access))
+    else {
+        return Ok(());
+    };
+
+    let violation = CapabilityViolation {
+        plugin,
+        event: event.to_string(),
+        detail: access.to_string(),
+    };
+    log::warn!("{}", violation);
+
+    Err(PyPermissionError::new_err(violation.to_string()))
+}
+
+/// Install the audit hook enforcing the policies of running plugins.
+pub(crate) fn install_audit_hook(py: Python) -> PyResult<()> {
+    let hook = PyCFunction::new_closure_bound(
+        py,
+        Some("pyembed_capability_policies\0"),
+        None,
+        |args: &Bound<'_, PyTuple>, _kwargs| -> PyResult<()> {
+            let event = args.get_item(0)?;
+            let event = event.downcast::<PyString>()?.to_cow()?;
+            let event_args = args.get_item(1)?;
+
+            check_audit_event(&event, event_args.downcast::<PyTuple>()?)
+        },
+    )?;
+
+    py.import_bound("sys")?
+        .call_method1("addaudithook", (hook,))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restricted_policy() {
+        let policy = CapabilityPolicy::restricted(vec![PathBuf::from("/data")]);
+
+        assert!(!policy.permits(&Access::Network));
+        assert!(!policy.permits(&Access::Process));
+        assert!(!policy.permits(&Access::Thread));
+        assert!(policy.permits(&Access::File(PathBuf::from("/data/events.txt"))));
+        assert!(!policy.permits(&Access::File(PathBuf::from("/database"))));
+    }
+
+    #[test]
+    fn test_partial_policy() {
+        let policy = CapabilityPolicy {
+            allow_network: true,
+            ..CapabilityPolicy::unrestricted()
+        };
+        assert!(policy.is_unrestricted());
+
+        let policy = CapabilityPolicy {
+            allow_processes: false,
+            ..CapabilityPolicy::unrestricted()
+        };
+        assert!(policy.permits(&Access::Network));
+        assert!(!policy.permits(&Access::Process));
+        assert!(!policy.permits(&Access::Thread));
+        assert!(policy.permits(&Access::File(PathBuf::from("/anywhere"))));
+    }
+
+    #[test]
+    fn test_nested_plugins() {
+        let outer = enter_plugin("outer", Arc::new(CapabilityPolicy::unrestricted()));
+        {
+            let _inner = enter_plugin("inner", Arc::new(CapabilityPolicy::restricted(vec![])));
+            let active = ACTIVE_PLUGINS.with(|active| active.borrow().last().cloned());
+            assert_eq!(active.unwrap().0, "inner");
+        }
+        let active = ACTIVE_PLUGINS.with(|active| active.borrow().last().cloned());
+        assert_eq!(active.unwrap().0, "outer");
+
+        drop(outer);
+        assert!(ACTIVE_PLUGINS.with(|active| active.borrow().is_empty()));
+    }
+}
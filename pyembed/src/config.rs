@@ -5,8 +5,12 @@
 //! Data structures for configuring a Python interpreter.
 
 use {
-    crate::error::NewInterpreterError,
-    oxidized_importer::{PackedResourcesSource, PythonResourcesState},
+    crate::{
+        error::NewInterpreterError,
+        host_python::{self, HostPython},
+        importer::{PackedResourcesSource, PythonResourcesState},
+        sandbox::ImportSandbox,
+    },
     pyo3::ffi as pyffi,
     python_packaging::interpreter::{
         MemoryAllocatorBackend, MultiprocessingStartMethod, PythonInterpreterConfig,
@@ -120,7 +124,8 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// be installed as per [Self::allocator_raw], [Self::allocator_mem],
     /// [Self::allocator_obj], and [Self::allocator_pymalloc_arena]. If a custom
     /// allocator backend is defined but all the `allocator_*` flags are [false],
-    /// the allocator won't be used.
+    /// the allocator won't be used. Interpreter creation fails if the backend
+    /// isn't compiled into the binary (see the `allocator-*` crate features).
     pub allocator_backend: MemoryAllocatorBackend,
 
     /// Whether to install the custom allocator for the `raw` memory domain.
@@ -189,6 +194,37 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// (see other `allocator_*` fields).
     pub allocator_debug: bool,
 
+    /// Whether to report allocations still outstanding after interpreter finalization.
+    ///
+    /// Default value: [false]
+    ///
+    /// Interpreter finalization behavior: after `Py_FinalizeEx()` returns, a
+    /// summary of memory still allocated through the custom allocator is
+    /// logged as a warning. CPython doesn't release all memory on finalization,
+    /// so some outstanding allocations are expected.
+    ///
+    /// Only has an effect if [Self::allocator_backend] tracks allocations,
+    /// which is currently only the case for [MemoryAllocatorBackend::Rust].
+    pub allocator_report_leaks: bool,
+
+    /// Maximum number of bytes the Python heap may occupy.
+    ///
+    /// Default value: [None]
+    ///
+    /// Interpreter initialization behavior: the limit is installed on the custom
+    /// allocator before Python is initialized. Allocations that would grow the
+    /// heap beyond it fail, which Python surfaces as `MemoryError`. The limit
+    /// must be large enough for the interpreter to initialize.
+    ///
+    /// Requires [Self::allocator_backend] to be [MemoryAllocatorBackend::Rust],
+    /// as only it tracks allocation sizes. The limit only covers the domains
+    /// the custom allocator is installed to. Memory allocated by extension
+    /// modules through other means (e.g. `malloc()`) isn't counted.
+    ///
+    /// A soft limit with a callback can be set at run-time via
+    /// [crate::PythonMemoryAllocator::set_soft_limit()].
+    pub allocator_hard_limit: Option<usize>,
+
     /// Whether to automatically set missing "path configuration" fields.
     ///
     /// If `true`, various path configuration
@@ -383,6 +419,31 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// `sys._MEIPASS` will not be defined.
     pub sys_meipass: bool,
 
+    /// Whether the application forwards interrupt requests to Python itself.
+    ///
+    /// Default value: [false]
+    ///
+    /// Interpreter initialization behavior: If [true], Python doesn't install
+    /// its signal handlers (`PyConfig.install_signal_handlers = 0`), overriding
+    /// [PythonInterpreterConfig::install_signal_handlers]. Notably, Ctrl-C
+    /// no longer raises `KeyboardInterrupt` on its own. Instead, the
+    /// application delivers interrupts via
+    /// [crate::MainPythonInterpreter::interrupt_handle()]. See the
+    /// [crate::interrupt] module for details.
+    pub forward_interrupts: bool,
+
+    /// Whether to forward Python logging and warnings to the Rust `log` crate.
+    ///
+    /// Default value: [false]
+    ///
+    /// Interpreter initialization behavior: If [true], a handler forwarding
+    /// records to `log` is added to the root Python logger and
+    /// `warnings.showwarning` is replaced with a function logging warnings to
+    /// the `py.warnings` target. The root logger level is set from
+    /// `log::max_level()`, so the Rust logger should be initialized before the
+    /// interpreter.
+    pub log_forwarding: bool,
+
     /// How to resolve the `terminfo` database.
     ///
     /// Default value: [TerminfoResolution::Dynamic]
@@ -395,6 +456,65 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// platform.
     pub terminfo_resolution: TerminfoResolution,
 
+    /// Path of a Python standard library on the filesystem.
+    ///
+    /// This is used when the standard library isn't embedded, e.g. when
+    /// dynamically linking libpython for development builds.
+    ///
+    /// Default value: [None]
+    ///
+    /// [Self::resolve()] behavior: the token `$ORIGIN` is expanded to the
+    /// resolved value of [Self::origin]. If set, the path and its `lib-dynload`
+    /// directory are appended to [PythonInterpreterConfig::module_search_paths].
+    pub stdlib_path: Option<PathBuf>,
+
+    /// Whether to fall back to a Python installation on the host.
+    ///
+    /// This lets development builds run without an embedded Python
+    /// distribution. See [crate::host_python] for how the installation is
+    /// found.
+    ///
+    /// Default value: [false]
+    ///
+    /// [Self::resolve()] behavior: if there are no [Self::packed_resources],
+    /// [Self::stdlib_path] is not an existing directory and neither
+    /// [PythonInterpreterConfig::module_search_paths] nor
+    /// [PythonInterpreterConfig::home] are set, a host Python installation is
+    /// discovered. Its `sys.path` becomes
+    /// [PythonInterpreterConfig::module_search_paths] and its base prefix
+    /// [PythonInterpreterConfig::home]. Unless set,
+    /// [PythonInterpreterConfig::executable] becomes its executable, so
+    /// `sys.executable` can run Python code, e.g. `multiprocessing` workers.
+    /// Resolution fails if no usable installation is found.
+    pub host_python_fallback: bool,
+
+    /// Restricts where modules may be imported from.
+    ///
+    /// See [crate::sandbox] for what is enforced.
+    ///
+    /// Default value: [None]
+    ///
+    /// [Self::resolve()] behavior: the token `$ORIGIN` is expanded in
+    /// [ImportSandbox::allowed_paths]. If set, the isolated profile is forced
+    /// and [PythonInterpreterConfig::module_search_paths] is limited to
+    /// entries below the allowed paths, defaulting to the allowed paths.
+    ///
+    /// Interpreter initialization behavior: an audit hook checking the files
+    /// modules are loaded from is installed before any module is imported
+    /// from the filesystem.
+    pub import_sandbox: Option<ImportSandbox>,
+
+    /// Whether to enforce the capability policies of plugins.
+    ///
+    /// See [crate::capabilities] for how plugins are run under a policy.
+    ///
+    /// Default value: [false]
+    ///
+    /// Interpreter initialization behavior: if set, an audit hook enforcing
+    /// the policy of the running plugin is installed after the main
+    /// initialization.
+    pub enforce_capability_policies: bool,
+
     /// Path to use to define the `TCL_LIBRARY` environment variable.
     ///
     /// This directory should contain an `init.tcl` file. It is commonly
@@ -440,6 +560,8 @@ impl<'a> Default for OxidizedPythonInterpreterConfig<'a> {
             allocator_obj: false,
             allocator_pymalloc_arena: false,
             allocator_debug: false,
+            allocator_report_leaks: false,
+            allocator_hard_limit: None,
             set_missing_path_configuration: true,
             oxidized_importer: false,
             filesystem_importer: true,
@@ -451,7 +573,13 @@ impl<'a> Default for OxidizedPythonInterpreterConfig<'a> {
             multiprocessing_start_method: MultiprocessingStartMethod::Auto,
             sys_frozen: false,
             sys_meipass: false,
+            forward_interrupts: false,
+            log_forwarding: false,
             terminfo_resolution: TerminfoResolution::Dynamic,
+            stdlib_path: None,
+            host_python_fallback: false,
+            import_sandbox: None,
+            enforce_capability_policies: false,
             tcl_library: None,
             write_modules_directory_env: None,
         }
@@ -509,17 +637,80 @@ impl<'a> OxidizedPythonInterpreterConfig<'a> {
             })
             .collect::<Vec<_>>();
 
-        let module_search_paths = self
-            .interpreter_config
-            .module_search_paths
+        let mut module_search_paths =
+            self.interpreter_config
+                .module_search_paths
+                .as_ref()
+                .map(|x| {
+                    x.iter()
+                        .map(|p| {
+                            PathBuf::from(
+                                p.display().to_string().replace("$ORIGIN", &origin_string),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                });
+
+        let mut stdlib_path = self
+            .stdlib_path
             .as_ref()
-            .map(|x| {
-                x.iter()
+            .map(|x| PathBuf::from(x.display().to_string().replace("$ORIGIN", &origin_string)));
+
+        let mut home = self.interpreter_config.home.clone();
+
+        let has_embedded_python = !packed_resources.is_empty()
+            || stdlib_path.as_ref().is_some_and(|p| p.is_dir())
+            || module_search_paths.is_some()
+            || home.is_some();
+
+        let host_python = if self.host_python_fallback && !has_embedded_python {
+            let python = host_python::discover()?;
+            log::info!("no embedded Python distribution; using host {}", python);
+
+            module_search_paths = Some(python.module_search_paths.clone());
+            home = Some(python.base_prefix.clone());
+            stdlib_path = None;
+
+            Some(python)
+        } else {
+            None
+        };
+
+        if let Some(stdlib_path) = &stdlib_path {
+            let paths = module_search_paths.get_or_insert_with(Vec::new);
+
+            for path in [stdlib_path.clone(), stdlib_path.join("lib-dynload")] {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+
+        let mut interpreter_config = self.interpreter_config;
+
+        if let Some(python) = &host_python {
+            interpreter_config
+                .executable
+                .get_or_insert_with(|| python.executable.clone());
+        }
+
+        let import_sandbox = self.import_sandbox.map(|sandbox| {
+            let sandbox = ImportSandbox {
+                allowed_paths: sandbox
+                    .allowed_paths
+                    .iter()
                     .map(|p| {
                         PathBuf::from(p.display().to_string().replace("$ORIGIN", &origin_string))
                     })
-                    .collect::<Vec<_>>()
-            });
+                    .collect(),
+                ..sandbox
+            };
+
+            isolate(&mut interpreter_config);
+            module_search_paths = Some(sandbox.filter_search_paths(module_search_paths.take()));
+
+            sandbox
+        });
 
         let tcl_library = self
             .tcl_library
@@ -531,21 +722,39 @@ impl<'a> OxidizedPythonInterpreterConfig<'a> {
                 exe: Some(exe),
                 origin: Some(origin),
                 interpreter_config: PythonInterpreterConfig {
+                    home,
                     module_search_paths,
-                    ..self.interpreter_config
+                    ..interpreter_config
                 },
                 argv,
                 packed_resources,
+                stdlib_path,
+                import_sandbox,
                 tcl_library,
                 ..self
             },
+            host_python,
         })
     }
 }
 
+/// Force settings isolating the interpreter from its environment.
+///
+/// Used for [OxidizedPythonInterpreterConfig::import_sandbox]: `PYTHON*`
+/// environment variables, the user site-packages directory and the current
+/// directory are ignored.
+fn isolate(config: &mut PythonInterpreterConfig) {
+    config.profile = PythonInterpreterProfile::Isolated;
+    config.isolated = Some(true);
+    config.use_environment = Some(false);
+    config.user_site_directory = Some(false);
+    config.python_path_env = None;
+}
+
 /// An `OxidizedPythonInterpreterConfig` that has fields resolved.
 pub struct ResolvedOxidizedPythonInterpreterConfig<'a> {
     inner: OxidizedPythonInterpreterConfig<'a>,
+    host_python: Option<HostPython>,
 }
 
 impl<'a> Deref for ResolvedOxidizedPythonInterpreterConfig<'a> {
@@ -567,6 +776,13 @@ impl<'a> TryFrom<OxidizedPythonInterpreterConfig<'a>>
 }
 
 impl<'a> ResolvedOxidizedPythonInterpreterConfig<'a> {
+    /// The host Python installation used in place of an embedded distribution.
+    ///
+    /// See [OxidizedPythonInterpreterConfig::host_python_fallback].
+    pub fn host_python(&self) -> Option<&HostPython> {
+        self.host_python.as_ref()
+    }
+
     /// Obtain the value for the current executable.
     pub fn exe(&self) -> &PathBuf {
         self.inner.exe.as_ref().expect("exe should have a value")
@@ -604,7 +820,7 @@ impl<'a> ResolvedOxidizedPythonInterpreterConfig<'a> {
 }
 
 impl<'a, 'config: 'a> TryFrom<&ResolvedOxidizedPythonInterpreterConfig<'config>>
-    for PythonResourcesState<'a, u8>
+    for PythonResourcesState<'a>
 {
     type Error = NewInterpreterError;
 
@@ -630,17 +846,13 @@ impl<'a, 'config: 'a> TryFrom<&ResolvedOxidizedPythonInterpreterConfig<'config>>
             }
         }
 
-        state
-            .index_interpreter_builtins()
-            .map_err(NewInterpreterError::Simple)?;
-
         Ok(state)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use {super::*, anyhow::Result};
+    use {super::*, crate::sandbox::SandboxViolationAction, anyhow::Result};
 
     #[test]
     fn test_packed_resources_implicit_origin() -> Result<()> {
@@ -687,4 +899,106 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_stdlib_path_origin() -> Result<()> {
+        let mut config = OxidizedPythonInterpreterConfig {
+            origin: Some(PathBuf::from("/other/origin")),
+            stdlib_path: Some(PathBuf::from("$ORIGIN/stdlib")),
+            ..Default::default()
+        };
+        config.interpreter_config.module_search_paths = Some(vec![PathBuf::from("$ORIGIN/lib")]);
+
+        let resolved = config.resolve()?;
+
+        assert_eq!(
+            resolved.stdlib_path,
+            Some(PathBuf::from("/other/origin/stdlib"))
+        );
+        assert_eq!(
+            resolved.interpreter_config.module_search_paths,
+            Some(vec![
+                PathBuf::from("/other/origin/lib"),
+                PathBuf::from("/other/origin/stdlib"),
+                PathBuf::from("/other/origin/stdlib/lib-dynload"),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_python_fallback_unused_with_packed_resources() -> Result<()> {
+        let mut config = OxidizedPythonInterpreterConfig {
+            host_python_fallback: true,
+            ..Default::default()
+        };
+        config
+            .packed_resources
+            .push(PackedResourcesSource::MemoryMappedPath(PathBuf::from(
+                "$ORIGIN/lib/packed-resources",
+            )));
+
+        let resolved = config.resolve()?;
+
+        assert!(resolved.host_python().is_none());
+        assert_eq!(resolved.interpreter_config.module_search_paths, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_python_fallback_executable() -> Result<()> {
+        let config = OxidizedPythonInterpreterConfig {
+            host_python_fallback: true,
+            ..Default::default()
+        };
+
+        let resolved = config.resolve()?;
+
+        assert_eq!(
+            resolved.interpreter_config.executable.as_ref(),
+            Some(&resolved.host_python().unwrap().executable)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_sandbox() -> Result<()> {
+        let mut config = OxidizedPythonInterpreterConfig {
+            origin: Some(PathBuf::from("/other/origin")),
+            stdlib_path: Some(PathBuf::from("$ORIGIN/stdlib")),
+            import_sandbox: Some(ImportSandbox {
+                allowed_paths: vec![PathBuf::from("$ORIGIN")],
+                violation_action: SandboxViolationAction::Block,
+            }),
+            ..Default::default()
+        };
+        config.interpreter_config.use_environment = Some(true);
+        config.interpreter_config.module_search_paths =
+            Some(vec![PathBuf::from("/usr/lib/python3")]);
+
+        let resolved = config.resolve()?;
+
+        assert_eq!(
+            resolved.import_sandbox.as_ref().unwrap().allowed_paths,
+            vec![PathBuf::from("/other/origin")]
+        );
+        assert_eq!(
+            resolved.interpreter_config.profile,
+            PythonInterpreterProfile::Isolated
+        );
+        assert_eq!(resolved.interpreter_config.use_environment, Some(false));
+        assert_eq!(resolved.interpreter_config.user_site_directory, Some(false));
+        assert_eq!(
+            resolved.interpreter_config.module_search_paths,
+            Some(vec![
+                PathBuf::from("/other/origin/stdlib"),
+                PathBuf::from("/other/origin/stdlib/lib-dynload"),
+            ])
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,243 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Discovery of a Python installation on the host.
+
+Binaries built without an embedded Python distribution can run with the
+standard library and packages of a Python installation found on the host,
+e.g. an active virtualenv. This is meant for development builds: the host
+installation has to match the libpython the binary is linked against.
+*/
+
+use {
+    crate::error::NewInterpreterError,
+    pyo3::ffi as pyffi,
+    std::{
+        ffi::CStr,
+        fmt::{Display, Formatter},
+        path::{Path, PathBuf},
+        process::Command,
+    },
+};
+
+/// Oldest supported Python version, as required by the stable ABI we build against.
+pub const MINIMUM_VERSION: (u8, u8) = (3, 10);
+
+/// Environment variable naming the Python executable to use.
+///
+/// It takes precedence over all other candidates.
+pub const HOST_PYTHON_ENV: &str = "PYEMBED_HOST_PYTHON";
+
+/// Prints version, executable, prefixes and `sys.path`, separated by NUL.
+const PROBE_SCRIPT: &str = "import sys; sys.stdout.write('\\0'.join(\
+    [str(v) for v in sys.version_info[:3]] \
+    + [sys.executable, sys.prefix, sys.base_prefix] \
+    + [p for p in sys.path if p]))";
+
+/// A Python installation on the host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostPython {
+    /// The Python executable.
+    pub executable: PathBuf,
+    /// Major, minor and patch version.
+    pub version: (u8, u8, u8),
+    /// `sys.prefix`, which is the virtualenv for virtualenvs.
+    pub prefix: PathBuf,
+    /// `sys.base_prefix`, the installation the standard library comes from.
+    pub base_prefix: PathBuf,
+    /// `sys.path` of the executable, without the entry for the current directory.
+    pub module_search_paths: Vec<PathBuf>,
+}
+
+impl HostPython {
+    /// Query the installation `executable` belongs to.
+    pub fn from_executable(executable: impl AsRef<Path>) -> Result<Self, String> {
+        let executable = executable.as_ref();
+        let output = Command::new(executable)
+            .args(["-c", PROBE_SCRIPT])
+            .output()
+            .map_err(|err| format!("unable to run: {}", err))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "probe failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let stdout = String::from_utf8(output.stdout)
+            .map_err(|_| "probe output is not valid UTF-8".to_string())?;
+
+        Self::parse_probe_output(&stdout)
+    }
+
+    fn parse_probe_output(output: &str) -> Result<Self, String> {
+        let mut fields = output.split('\0');
+        let mut next = |what: &str| {
+            fields
+                .next()
+                .ok_or_else(|| format!("probe output lacks {}", what))
+        };
+
+        let mut version_part = |what: &str| -> Result<u8, String> {
+            next(what)?
+                .parse()
+                .map_err(|_| format!("probe output has invalid {}", what))
+        };
+        let version = (
+            version_part("major version")?,
+            version_part("minor version")?,
+            version_part("patch version")?,
+        );
+
+        let executable = PathBuf::from(next("executable")?);
+        let prefix = PathBuf::from(next("prefix")?);
+        let base_prefix = PathBuf::from(next("base prefix")?);
+
+        Ok(Self {
+            executable,
+            version,
+            prefix,
+            base_prefix,
+            module_search_paths: fields.map(PathBuf::from).collect(),
+        })
+    }
+
+    /// Whether this is a virtualenv.
+    pub fn is_virtualenv(&self) -> bool {
+        self.prefix != self.base_prefix
+    }
+}
+
+impl Display for HostPython {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (major, minor, patch) = self.version;
+        write!(
+            f,
+            "Python {}.{}.{} ({})",
+            major,
+            minor,
+            patch,
+            self.executable.display()
+        )?;
+        if self.is_virtualenv() {
+            write!(f, " in virtualenv {}", self.prefix.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Major and minor version of the libpython this binary is linked against.
+pub fn linked_version() -> Option<(u8, u8)> {
+    // Py_GetVersion() returns a static string and may be called before
+    // the interpreter is initialized.
+    let version = unsafe { CStr::from_ptr(pyffi::Py_GetVersion()) };
+    let mut parts = version.to_str().ok()?.split(|c: char| !c.is_ascii_digit());
+
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// Python executables to try, in order of preference.
+///
+/// These are the executable named by [HOST_PYTHON_ENV], those of an active
+/// virtualenv or conda environment and the `python3` and `python` on `PATH`.
+pub fn candidates() -> Vec<PathBuf> {
+    let bin_dir = if cfg!(windows) { "Scripts" } else { "bin" };
+    let exe_name = if cfg!(windows) {
+        "python.exe"
+    } else {
+        "python"
+    };
+
+    let mut candidates = vec![];
+    if let Some(python) = std::env::var_os(HOST_PYTHON_ENV) {
+        candidates.push(PathBuf::from(python));
+    }
+    for env in ["VIRTUAL_ENV", "CONDA_PREFIX"] {
+        if let Some(prefix) = std::env::var_os(env).map(PathBuf::from) {
+            candidates.push(prefix.join(bin_dir).join(exe_name));
+            // conda installs the executable at the root on Windows.
+            if cfg!(windows) {
+                candidates.push(prefix.join(exe_name));
+            }
+        }
+    }
+    candidates.extend(["python3", "python"].into_iter().map(PathBuf::from));
+
+    candidates
+}
+
+/// Find a Python installation on the host usable by this binary.
+///
+/// Candidates from [candidates()] are tried in order. The first one at least
+/// at [MINIMUM_VERSION] and matching the linked libpython, if known, is used.
+pub fn discover() -> Result<HostPython, NewInterpreterError> {
+    let linked = linked_version();
+    let mut rejected = vec![];
+
+    for candidate in candidates() {
+        let reason = match HostPython::from_executable(&candidate) {
+            Ok(python) => {
+                let major_minor = (python.version.0, python.version.1);
+                if major_minor < MINIMUM_VERSION {
+                    format!(
+                        "version {}.{} is older than {}.{}",
+                        major_minor.0, major_minor.1, MINIMUM_VERSION.0, MINIMUM_VERSION.1
+                    )
+                } else if let Some(linked) = linked.filter(|linked| *linked != major_minor) {
+                    format!(
+                        "version {}.{} does not match the linked libpython {}.{}",
+                        major_minor.0, major_minor.1, linked.0, linked.1
+                    )
+                } else {
+                    return Ok(python);
+                }
+            }
+            Err(err) => err,
+        };
+
+        log::debug!("not using host Python {}: {}", candidate.display(), reason);
+        rejected.push(format!("{}: {}", candidate.display(), reason));
+    }
+
+    Err(NewInterpreterError::Dynamic(format!(
+        "no usable host Python found; set {} to a Python executable ({})",
+        HOST_PYTHON_ENV,
+        rejected.join("; ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_output() {
+        let output = "3\x0011\x007\x00/venv/bin/python\x00/venv\x00/usr\x00/usr/lib/python311.zip\x00/venv/lib/python3.11/site-packages";
+
+        let python = HostPython::parse_probe_output(output).unwrap();
+
+        assert_eq!(python.version, (3, 11, 7));
+        assert_eq!(python.executable, PathBuf::from("/venv/bin/python"));
+        assert!(python.is_virtualenv());
+        assert_eq!(
+            python.module_search_paths,
+            vec![
+                PathBuf::from("/usr/lib/python311.zip"),
+                PathBuf::from("/venv/lib/python3.11/site-packages"),
+            ]
+        );
+        assert_eq!(
+            python.to_string(),
+            "Python 3.11.7 (/venv/bin/python) in virtualenv /venv"
+        );
+    }
+
+    #[test]
+    fn test_parse_probe_output_truncated() {
+        assert!(HostPython::parse_probe_output("3\x0011").is_err());
+    }
+}
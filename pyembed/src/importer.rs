@@ -0,0 +1,934 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! A meta path importer servicing imports from packed resources.
+
+[OxidizedFinder] is installed as the first entry on `sys.meta_path` during
+interpreter initialization when
+[crate::OxidizedPythonInterpreterConfig::oxidized_importer] is enabled. It
+imports Python modules from the [crate::packed_resources::Resource]s indexed by
+a [PythonResourcesState]. So applications can ship their Python code inside a
+single executable.
+
+The finder also takes over the roles of the standard library's
+`BuiltinImporter` and `FrozenImporter`, which are removed from
+`sys.meta_path`: requests for built-in and frozen modules are delegated to
+them.
+
+Besides module loading, the finder implements:
+
+* `get_resource_reader()`, so `importlib.resources` can read package data.
+* `find_distributions()`, so `importlib.metadata` can read distribution
+  metadata.
+* A path hook, so `pkgutil.iter_modules()` can enumerate packaged modules.
+
+Extension modules can't be imported from memory. They must be installed on
+the filesystem and found by the standard `PathFinder`.
+*/
+
+use {
+    crate::packed_resources::{PackedResources, Resource},
+    pyo3::{
+        exceptions::{PyFileNotFoundError, PyImportError},
+        ffi as pyffi,
+        prelude::*,
+        sync::GILOnceCell,
+        types::{PyBytes, PyDict, PyList},
+    },
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+/// Name of the built-in module providing the importer.
+pub const OXIDIZED_IMPORTER_NAME_STR: &str = "oxidized_importer";
+
+/// [OXIDIZED_IMPORTER_NAME_STR] as a NUL terminated C string.
+pub const OXIDIZED_IMPORTER_NAME: &[u8] = b"oxidized_importer\0";
+
+/// Python code backing `importlib.resources` and `importlib.metadata` support.
+///
+/// Evaluated on first use, as the modules it needs aren't importable during
+/// early interpreter initialization.
+const HELPERS_SOURCE: &str = r#"
+import io
+import pathlib
+import posixpath
+
+import importlib.metadata
+
+try:
+    from importlib.resources.abc import Traversable
+except ImportError:
+    from importlib.abc import Traversable
+
+
+class OxidizedTraversable(Traversable):
+    """A package resource or directory of package resources held in memory."""
+
+    def __init__(self, reader, path):
+        self._reader = reader
+        self._path = path
+
+    def __repr__(self):
+        return "<OxidizedTraversable %r>" % self._path
+
+    @property
+    def name(self):
+        return posixpath.basename(self._path)
+
+    def iterdir(self):
+        for name in self._reader._children(self._path):
+            yield self.joinpath(name)
+
+    def is_dir(self):
+        return self._reader._is_dir(self._path)
+
+    def is_file(self):
+        return self._reader._read(self._path) is not None
+
+    def joinpath(self, *descendants):
+        path = posixpath.normpath(posixpath.join(self._path, *descendants))
+        return OxidizedTraversable(self._reader, "" if path == "." else path)
+
+    def __truediv__(self, child):
+        return self.joinpath(child)
+
+    def read_bytes(self):
+        data = self._reader._read(self._path)
+        if data is None:
+            raise FileNotFoundError(self._path)
+        return data
+
+    def read_text(self, encoding=None, errors=None):
+        return self.read_bytes().decode(encoding or "utf-8", errors or "strict")
+
+    def open(self, mode="r", *args, **kwargs):
+        if mode not in ("r", "rb"):
+            raise ValueError("package resources are read-only: %r" % mode)
+
+        data = io.BytesIO(self.read_bytes())
+        if mode == "rb":
+            return data
+        return io.TextIOWrapper(data, *args, **kwargs)
+
+
+class OxidizedDistribution(importlib.metadata.Distribution):
+    """Distribution metadata held in memory."""
+
+    def __init__(self, files):
+        self._files = files
+
+    def read_text(self, filename):
+        data = self._files.get(filename)
+        if data is None:
+            return None
+        return data.decode("utf-8")
+
+    def locate_file(self, path):
+        return pathlib.PurePosixPath(path)
+"#;
+
+/// Defines a source of packed resources data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PackedResourcesSource<'a> {
+    /// A reference to raw bytes in memory, e.g. obtained via `include_bytes!()`.
+    Memory(&'a [u8]),
+
+    /// A file to memory-map.
+    MemoryMappedPath(PathBuf),
+}
+
+impl<'a> From<&'a [u8]> for PackedResourcesSource<'a> {
+    fn from(data: &'a [u8]) -> Self {
+        Self::Memory(data)
+    }
+}
+
+/// Normalize a distribution name for comparison, as per PEP 503.
+fn normalize_distribution_name(name: &str) -> String {
+    name.to_lowercase().replace(['-', '.'], "_")
+}
+
+/// Holds the Python resources an [OxidizedFinder] can service.
+#[derive(Default)]
+pub struct PythonResourcesState<'a> {
+    /// Path to the current executable.
+    current_exe: PathBuf,
+
+    /// Directory relative paths are resolved against.
+    origin: PathBuf,
+
+    /// Indexed resources, by name.
+    resources: HashMap<Cow<'a, str>, Resource<'a>>,
+
+    /// Memory mappings backing resources data.
+    ///
+    /// Resources borrowing from a mapping claim the lifetime `'a`, which can
+    /// be longer than the mapping. So resources are only ever handed out for
+    /// the duration of a borrow of the state.
+    backing_mmaps: Vec<memmap2::Mmap>,
+}
+
+impl<'a> PythonResourcesState<'a> {
+    /// Obtain the path of the current executable.
+    pub fn current_exe(&self) -> &Path {
+        &self.current_exe
+    }
+
+    /// Set the path of the current executable.
+    pub fn set_current_exe(&mut self, path: PathBuf) {
+        self.current_exe = path;
+    }
+
+    /// Obtain the origin directory.
+    pub fn origin(&self) -> &Path {
+        &self.origin
+    }
+
+    /// Set the origin directory.
+    pub fn set_origin(&mut self, path: PathBuf) {
+        self.origin = path;
+    }
+
+    /// Index packed resources data.
+    ///
+    /// Resources replace previously indexed resources having the same name.
+    pub fn index_data(&mut self, data: &'a [u8]) -> Result<(), &'static str> {
+        for resource in PackedResources::parse(data)?.iter() {
+            self.add_resource(resource?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Memory-map a file holding packed resources data and index it.
+    pub fn index_path_memory_mapped(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+
+        let f = std::fs::File::open(path)
+            .map_err(|e| format!("error opening {}: {}", path.display(), e))?;
+        let mmap = unsafe { memmap2::Mmap::map(&f) }
+            .map_err(|e| format!("error memory mapping {}: {}", path.display(), e))?;
+
+        // The mapping lives as long as self and moving a Mmap doesn't move the
+        // mapping. Resources borrowing from it don't escape a borrow of self.
+        let data: &'a [u8] = unsafe { std::slice::from_raw_parts(mmap.as_ptr(), mmap.len()) };
+        self.backing_mmaps.push(mmap);
+
+        self.index_data(data)
+            .map_err(|e| format!("error indexing {}: {}", path.display(), e))
+    }
+
+    /// Add a resource.
+    ///
+    /// An existing resource with the same name is replaced.
+    pub fn add_resource(&mut self, resource: Resource<'a>) -> Result<(), &'static str> {
+        if resource.name.is_empty() {
+            return Err("resource name cannot be empty");
+        }
+
+        self.resources.insert(resource.name.clone(), resource);
+
+        Ok(())
+    }
+
+    /// Whether a resource with the given name is indexed.
+    pub fn has_resource(&self, name: &str) -> bool {
+        self.resources.contains_key(name)
+    }
+
+    /// Obtain an indexed resource.
+    ///
+    /// The resource may borrow from memory owned by this instance, so it can't
+    /// outlive the borrow of `self`.
+    pub fn resource(&self, name: &str) -> Option<&Resource<'_>> {
+        self.resources.get(name)
+    }
+
+    /// Obtain an indexed resource if it is a Python module.
+    fn module(&self, name: &str) -> Option<&Resource<'_>> {
+        self.resource(name).filter(|r| r.is_module())
+    }
+
+    /// Direct child modules of a package (or top-level modules for `""`) as `(name, is_package)`.
+    fn child_modules(&self, package: &str) -> Vec<(String, bool)> {
+        let mut res = self
+            .resources
+            .values()
+            .filter(|r| r.is_module())
+            .filter_map(|r| {
+                let child = if package.is_empty() {
+                    r.name.as_ref()
+                } else {
+                    r.name.strip_prefix(package)?.strip_prefix('.')?
+                };
+
+                if child.contains('.') {
+                    None
+                } else {
+                    Some((child.to_string(), r.is_package || r.is_namespace_package))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        res.sort();
+
+        res
+    }
+
+    /// Resources holding distribution metadata matching an optional name.
+    fn distributions(&self, name: Option<&str>) -> Vec<&Resource<'_>> {
+        let name = name.map(normalize_distribution_name);
+
+        let mut res = self
+            .resources
+            .values()
+            .filter(|r| r.in_memory_distribution_resources.is_some())
+            .filter(|r| match &name {
+                Some(name) => &normalize_distribution_name(&r.name) == name,
+                None => true,
+            })
+            .collect::<Vec<_>>();
+
+        res.sort_by(|a, b| a.name.cmp(&b.name));
+
+        res
+    }
+}
+
+/// State shared by an [OxidizedFinder] and the objects it creates.
+pub struct ImporterState {
+    /// The resources being serviced.
+    resources: PythonResourcesState<'static>,
+
+    /// `sys.flags.optimize` at the time the importer was created.
+    optimize_level: i32,
+
+    /// Start method to set when `multiprocessing` is imported.
+    multiprocessing_set_start_method: Option<String>,
+
+    /// `_frozen_importlib.ModuleSpec`.
+    module_spec_type: PyObject,
+
+    /// `_frozen_importlib.BuiltinImporter`.
+    builtin_importer: PyObject,
+
+    /// `_frozen_importlib.FrozenImporter`.
+    frozen_importer: PyObject,
+
+    /// Module compiled from [HELPERS_SOURCE].
+    helpers: GILOnceCell<Py<PyModule>>,
+}
+
+impl ImporterState {
+    fn new(py: Python, resources: PythonResourcesState<'static>) -> PyResult<Self> {
+        let bootstrap = py.import_bound("_frozen_importlib")?;
+
+        let optimize_level = py
+            .import_bound("sys")?
+            .getattr("flags")?
+            .getattr("optimize")?
+            .extract::<i32>()?;
+
+        Ok(Self {
+            resources,
+            optimize_level,
+            multiprocessing_set_start_method: None,
+            module_spec_type: bootstrap.getattr("ModuleSpec")?.unbind(),
+            builtin_importer: bootstrap.getattr("BuiltinImporter")?.unbind(),
+            frozen_importer: bootstrap.getattr("FrozenImporter")?.unbind(),
+            helpers: GILOnceCell::new(),
+        })
+    }
+
+    /// Set the `multiprocessing` start method to install when `multiprocessing` is imported.
+    pub fn set_multiprocessing_set_start_method(&mut self, value: Option<String>) {
+        self.multiprocessing_set_start_method = value;
+    }
+
+    fn helpers<'py>(&self, py: Python<'py>) -> PyResult<&Bound<'py, PyModule>> {
+        self.helpers
+            .get_or_try_init(py, || {
+                Ok::<_, PyErr>(
+                    PyModule::from_code_bound(
+                        py,
+                        HELPERS_SOURCE,
+                        "oxidized_importer/_helpers.py",
+                        "oxidized_importer._helpers",
+                    )?
+                    .unbind(),
+                )
+            })
+            .map(|m| m.bind(py))
+    }
+
+    /// Whether `fullname` is a built-in extension module.
+    fn is_builtin(&self, py: Python, fullname: &str) -> PyResult<bool> {
+        py.import_bound("sys")?
+            .getattr("builtin_module_names")?
+            .contains(fullname)
+    }
+
+    /// Whether `fullname` is a frozen module.
+    fn is_frozen(&self, py: Python, fullname: &str) -> PyResult<bool> {
+        py.import_bound("_imp")?
+            .call_method1("is_frozen", (fullname,))?
+            .extract()
+    }
+}
+
+/// A meta path finder and loader for packed resources.
+///
+/// See the [module documentation](self) for details.
+#[pyclass(module = "oxidized_importer")]
+pub struct OxidizedFinder {
+    state: Arc<ImporterState>,
+}
+
+impl OxidizedFinder {
+    /// Build a `ModuleSpec` for a module in our resources.
+    fn resource_spec<'py>(
+        slf: &Bound<'py, Self>,
+        resource: &Resource,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let state = slf.borrow().state.clone();
+
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item(
+            "is_package",
+            resource.is_package || resource.is_namespace_package,
+        )?;
+
+        let spec = state
+            .module_spec_type
+            .bind(py)
+            .call((resource.name.as_ref(), slf), Some(&kwargs))?;
+
+        // Packages get a virtual search location below the executable, which
+        // the path hook recognizes.
+        if resource.is_package || resource.is_namespace_package {
+            let mut location = state.resources.current_exe().to_path_buf();
+            location.extend(resource.name.split('.'));
+
+            spec.setattr(
+                "submodule_search_locations",
+                PyList::new_bound(py, [location.display().to_string()]),
+            )?;
+        }
+
+        Ok(spec)
+    }
+}
+
+#[pymethods]
+impl OxidizedFinder {
+    #[pyo3(signature = (fullname, path, target=None))]
+    fn find_spec<'py>(
+        slf: &Bound<'py, Self>,
+        fullname: &str,
+        path: &Bound<'py, PyAny>,
+        target: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<PyObject> {
+        let py = slf.py();
+        let _ = target;
+        let state = slf.borrow().state.clone();
+
+        if let Some(resource) = state.resources.module(fullname) {
+            return Ok(Self::resource_spec(slf, resource)?.unbind());
+        }
+
+        for importer in [&state.builtin_importer, &state.frozen_importer] {
+            let spec = importer
+                .bind(py)
+                .call_method1("find_spec", (fullname, path))?;
+
+            if !spec.is_none() {
+                return Ok(spec.unbind());
+            }
+        }
+
+        Ok(py.None())
+    }
+
+    /// Legacy finder API. Returns the loader for a module or [None].
+    #[pyo3(signature = (fullname, path=None))]
+    fn find_module<'py>(
+        slf: &Bound<'py, Self>,
+        fullname: &str,
+        path: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<PyObject> {
+        let py = slf.py();
+        let path = path.cloned().unwrap_or_else(|| py.None().into_bound(py));
+        let spec = Self::find_spec(slf, fullname, &path, None)?;
+
+        if spec.is_none(py) {
+            Ok(py.None())
+        } else {
+            Ok(spec.getattr(py, "loader")?)
+        }
+    }
+
+    fn invalidate_caches(&self) {}
+
+    /// Use default module creation semantics.
+    fn create_module(&self, py: Python, spec: &Bound<PyAny>) -> PyObject {
+        let _ = spec;
+        py.None()
+    }
+
+    fn exec_module(slf: &Bound<Self>, module: &Bound<PyAny>) -> PyResult<()> {
+        let py = slf.py();
+        let name = module
+            .getattr("__spec__")?
+            .getattr("name")?
+            .extract::<String>()?;
+
+        let code = slf.borrow().get_code(py, &name)?;
+        if code.is_none(py) {
+            return Err(PyImportError::new_err((
+                format!("cannot find code for module {}", name),
+                name,
+            )));
+        }
+
+        py.import_bound("builtins")?
+            .call_method1("exec", (code, module.getattr("__dict__")?))?;
+
+        // Processes started by multiprocessing need to know how to run our
+        // executable. Setting the start method as soon as the module is loaded
+        // makes this behave consistently across platforms.
+        if name == "multiprocessing" {
+            if let Some(method) = &slf.borrow().state.multiprocessing_set_start_method {
+                module.call_method1("set_start_method", (method.as_str(),))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_code(&self, py: Python, fullname: &str) -> PyResult<PyObject> {
+        let state = &self.state;
+
+        let resource = match state.resources.module(fullname) {
+            Some(resource) => resource,
+            None => {
+                if state.is_frozen(py, fullname)? {
+                    return state
+                        .frozen_importer
+                        .call_method1(py, "get_code", (fullname,));
+                } else if state.is_builtin(py, fullname)? {
+                    return Ok(py.None());
+                }
+
+                return Err(PyImportError::new_err((
+                    format!("cannot find module {}", fullname),
+                    fullname.to_string(),
+                )));
+            }
+        };
+
+        if let Some(bytecode) = resource.bytecode(state.optimize_level) {
+            return py
+                .import_bound("marshal")?
+                .call_method1("loads", (PyBytes::new_bound(py, bytecode),))
+                .map(|code| code.unbind());
+        }
+
+        let source = resource.in_memory_source.as_deref().unwrap_or_default();
+
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("dont_inherit", true)?;
+        kwargs.set_item("optimize", state.optimize_level)?;
+
+        py.import_bound("builtins")?
+            .call_method(
+                "compile",
+                (PyBytes::new_bound(py, source), fullname, "exec"),
+                Some(&kwargs),
+            )
+            .map(|code| code.unbind())
+    }
+
+    fn get_source(&self, py: Python, fullname: &str) -> PyResult<PyObject> {
+        let source = match self
+            .state
+            .resources
+            .module(fullname)
+            .and_then(|r| r.in_memory_source.as_deref())
+        {
+            Some(source) => source,
+            None => return Ok(py.None()),
+        };
+
+        py.import_bound("importlib.util")?
+            .call_method1("decode_source", (PyBytes::new_bound(py, source),))
+            .map(|source| source.unbind())
+    }
+
+    /// Modules aren't backed by files.
+    fn get_filename(&self, fullname: &str) -> PyResult<()> {
+        Err(PyImportError::new_err((
+            format!("{} is not backed by a file", fullname),
+            fullname.to_string(),
+        )))
+    }
+
+    fn is_package(&self, fullname: &str) -> PyResult<bool> {
+        match self.state.resources.module(fullname) {
+            Some(r) => Ok(r.is_package || r.is_namespace_package),
+            None => Err(PyImportError::new_err((
+                format!("cannot find module {}", fullname),
+                fullname.to_string(),
+            ))),
+        }
+    }
+
+    fn get_resource_reader(&self, py: Python, fullname: &str) -> PyResult<PyObject> {
+        match self.state.resources.module(fullname) {
+            Some(r) if r.is_package => Ok(OxidizedResourceReader {
+                state: self.state.clone(),
+                package: fullname.to_string(),
+            }
+            .into_py(py)),
+            _ => Ok(py.None()),
+        }
+    }
+
+    /// `importlib.metadata` hook returning distributions matching a context.
+    #[pyo3(signature = (context=None))]
+    fn find_distributions<'py>(
+        &self,
+        py: Python<'py>,
+        context: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let name = match context {
+            Some(context) => context.getattr("name")?.extract::<Option<String>>()?,
+            None => None,
+        };
+
+        let distribution_type = self.state.helpers(py)?.getattr("OxidizedDistribution")?;
+
+        let distributions = self
+            .state
+            .resources
+            .distributions(name.as_deref())
+            .into_iter()
+            .map(|resource| {
+                let files = PyDict::new_bound(py);
+
+                for (filename, data) in resource.in_memory_distribution_resources.iter().flatten() {
+                    files.set_item(filename.as_ref(), PyBytes::new_bound(py, data))?;
+                }
+
+                distribution_type.call1((files,))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        Ok(PyList::new_bound(py, distributions))
+    }
+
+    /// A `sys.path_hooks` entry recognizing the virtual package locations of this finder.
+    fn path_hook(slf: &Bound<Self>, path: &Bound<PyAny>) -> PyResult<OxidizedPathEntryFinder> {
+        let path_str = path.str()?.to_string();
+        let exe = slf.borrow().state.resources.current_exe().to_path_buf();
+
+        let package = Path::new(&path_str)
+            .strip_prefix(&exe)
+            .ok()
+            .and_then(|rest| {
+                rest.components()
+                    .map(|c| c.as_os_str().to_str())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .map(|parts| parts.join("."));
+
+        match package {
+            Some(package) if !exe.as_os_str().is_empty() => Ok(OxidizedPathEntryFinder {
+                finder: slf.clone().unbind(),
+                package,
+            }),
+            _ => Err(PyImportError::new_err(format!(
+                "{} is not a location of OxidizedFinder",
+                path_str
+            ))),
+        }
+    }
+}
+
+/// A `sys.path_hooks` finder for a single package location of an [OxidizedFinder].
+#[pyclass(module = "oxidized_importer")]
+pub struct OxidizedPathEntryFinder {
+    finder: Py<OxidizedFinder>,
+
+    /// Package this path entry represents. Empty for top-level modules.
+    package: String,
+}
+
+#[pymethods]
+impl OxidizedPathEntryFinder {
+    #[pyo3(signature = (fullname, target=None))]
+    fn find_spec(
+        &self,
+        py: Python,
+        fullname: &str,
+        target: Option<&Bound<PyAny>>,
+    ) -> PyResult<PyObject> {
+        let _ = target;
+        let parent = fullname.rsplit_once('.').map(|(p, _)| p).unwrap_or("");
+
+        if parent != self.package {
+            return Ok(py.None());
+        }
+
+        let finder = self.finder.bind(py);
+        let state = finder.borrow().state.clone();
+
+        match state.resources.module(fullname) {
+            Some(resource) => Ok(OxidizedFinder::resource_spec(finder, resource)?.unbind()),
+            None => Ok(py.None()),
+        }
+    }
+
+    fn invalidate_caches(&self) {}
+
+    /// Enumerate modules in this location, for `pkgutil.iter_modules()`.
+    #[pyo3(signature = (prefix=""))]
+    fn iter_modules<'py>(&self, py: Python<'py>, prefix: &str) -> PyResult<Bound<'py, PyList>> {
+        let modules = self
+            .finder
+            .bind(py)
+            .borrow()
+            .state
+            .resources
+            .child_modules(&self.package)
+            .into_iter()
+            .map(|(name, is_package)| (format!("{}{}", prefix, name), is_package))
+            .collect::<Vec<_>>();
+
+        Ok(PyList::new_bound(py, modules))
+    }
+}
+
+/// A resource reader for a package, used by `importlib.resources`.
+#[pyclass(module = "oxidized_importer")]
+pub struct OxidizedResourceReader {
+    state: Arc<ImporterState>,
+    package: String,
+}
+
+impl OxidizedResourceReader {
+    fn resources(&self) -> Option<&HashMap<Cow<'_, str>, Cow<'_, [u8]>>> {
+        self.state
+            .resources
+            .resource(&self.package)
+            .and_then(|r| r.in_memory_package_resources.as_ref())
+    }
+
+    /// Direct children of a directory, mapped to whether they are directories.
+    fn children_of(&self, path: &str) -> BTreeMap<String, bool> {
+        let mut res = BTreeMap::new();
+
+        for key in self.resources().into_iter().flat_map(|r| r.keys()) {
+            let rest = if path.is_empty() {
+                Some(key.as_ref())
+            } else {
+                key.strip_prefix(path)
+                    .and_then(|rest| rest.strip_prefix('/'))
+            };
+
+            if let Some(rest) = rest {
+                match rest.split_once('/') {
+                    Some((dir, _)) => res.insert(dir.to_string(), true),
+                    None => res.insert(rest.to_string(), false),
+                };
+            }
+        }
+
+        res
+    }
+}
+
+#[pymethods]
+impl OxidizedResourceReader {
+    fn open_resource<'py>(&self, py: Python<'py>, resource: &str) -> PyResult<Bound<'py, PyAny>> {
+        match self.resources().and_then(|r| r.get(resource)) {
+            Some(data) => py
+                .import_bound("io")?
+                .call_method1("BytesIO", (PyBytes::new_bound(py, data),)),
+            None => Err(PyFileNotFoundError::new_err(resource.to_string())),
+        }
+    }
+
+    /// Resources don't exist on the filesystem.
+    fn resource_path(&self, resource: &str) -> PyResult<()> {
+        Err(PyFileNotFoundError::new_err(resource.to_string()))
+    }
+
+    fn is_resource(&self, name: &str) -> bool {
+        self.resources().map_or(false, |r| r.contains_key(name))
+    }
+
+    fn contents<'py>(&self, py: Python<'py>) -> Bound<'py, PyList> {
+        PyList::new_bound(py, self.children_of("").into_keys())
+    }
+
+    /// Obtain a `Traversable` for the package, as used by `importlib.resources.files()`.
+    fn files<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+
+        slf.borrow()
+            .state
+            .helpers(py)?
+            .getattr("OxidizedTraversable")?
+            .call1((slf, ""))
+    }
+
+    fn _read<'py>(&self, py: Python<'py>, path: &str) -> Option<Bound<'py, PyBytes>> {
+        self.resources()
+            .and_then(|r| r.get(path))
+            .map(|data| PyBytes::new_bound(py, data))
+    }
+
+    fn _is_dir(&self, path: &str) -> bool {
+        path.is_empty() || !self.children_of(path).is_empty()
+    }
+
+    fn _children<'py>(&self, py: Python<'py>, path: &str) -> Bound<'py, PyList> {
+        PyList::new_bound(py, self.children_of(path).into_keys())
+    }
+}
+
+fn init_module(py: Python) -> PyResult<Bound<PyModule>> {
+    let m = PyModule::new_bound(py, OXIDIZED_IMPORTER_NAME_STR)?;
+    m.add_class::<OxidizedFinder>()?;
+    m.add_class::<OxidizedPathEntryFinder>()?;
+    m.add_class::<OxidizedResourceReader>()?;
+
+    Ok(m)
+}
+
+/// Initialization function of the `oxidized_importer` built-in extension module.
+///
+/// # Safety
+///
+/// Must only be called by the Python interpreter, with the GIL held.
+#[allow(non_snake_case)]
+pub unsafe extern "C" fn PyInit_oxidized_importer() -> *mut pyffi::PyObject {
+    // This is called during core initialization, where pyo3's GIL checks
+    // would fail. But CPython guarantees the GIL is held.
+    let py = Python::assume_gil_acquired();
+
+    match init_module(py) {
+        Ok(m) => m.into_ptr(),
+        Err(err) => {
+            err.restore(py);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Replace `BuiltinImporter` and `FrozenImporter` on `sys.meta_path` with an [OxidizedFinder].
+///
+/// `configure` can customize the [ImporterState] before the finder is created.
+///
+/// # Safety
+///
+/// Python objects can't carry lifetimes, so the finder treats the resources
+/// state as `'static`. The caller must ensure the data borrowed by
+/// `resources_state` outlives the finder, i.e. remains valid until the
+/// interpreter is finalized.
+pub unsafe fn replace_meta_path_importers<'a, F>(
+    py: Python,
+    oxidized_importer: &Bound<PyModule>,
+    resources_state: Box<PythonResourcesState<'a>>,
+    configure: Option<F>,
+) -> PyResult<()>
+where
+    F: FnOnce(&mut ImporterState),
+{
+    let _ = oxidized_importer;
+
+    let resources = std::mem::transmute::<PythonResourcesState<'a>, PythonResourcesState<'static>>(
+        *resources_state,
+    );
+
+    let mut state = ImporterState::new(py, resources)?;
+    if let Some(configure) = configure {
+        configure(&mut state);
+    }
+
+    let builtin_importer = state.builtin_importer.clone_ref(py);
+    let frozen_importer = state.frozen_importer.clone_ref(py);
+
+    let finder = Bound::new(
+        py,
+        OxidizedFinder {
+            state: Arc::new(state),
+        },
+    )?;
+
+    let meta_path = py
+        .import_bound("sys")?
+        .getattr("meta_path")?
+        .downcast_into::<PyList>()?;
+
+    let retained = meta_path
+        .iter()
+        .filter(|importer| !importer.is(&builtin_importer) && !importer.is(&frozen_importer))
+        .collect::<Vec<_>>();
+
+    meta_path.call_method1("clear", ())?;
+    meta_path.append(&finder)?;
+    for importer in retained {
+        meta_path.append(importer)?;
+    }
+
+    Ok(())
+}
+
+/// Remove the standard library's filesystem importers.
+///
+/// Removes `PathFinder` from `sys.meta_path` and clears `sys.path_hooks` and
+/// `sys.path_importer_cache`.
+pub fn remove_external_importers(sys_module: &Bound<PyModule>) -> PyResult<()> {
+    let py = sys_module.py();
+    let path_finder = py
+        .import_bound("_frozen_importlib_external")?
+        .getattr("PathFinder")?;
+
+    let meta_path = sys_module.getattr("meta_path")?.downcast_into::<PyList>()?;
+
+    let retained = meta_path
+        .iter()
+        .filter(|importer| !importer.is(&path_finder))
+        .collect::<Vec<_>>();
+
+    meta_path.call_method1("clear", ())?;
+    for importer in retained {
+        meta_path.append(importer)?;
+    }
+
+    sys_module.getattr("path_hooks")?.call_method0("clear")?;
+    sys_module
+        .getattr("path_importer_cache")?
+        .call_method0("clear")?;
+
+    Ok(())
+}
+
+/// Register the path hook of an [OxidizedFinder] as the first entry of `sys.path_hooks`.
+pub fn install_path_hook(finder: &Bound<PyAny>, sys_module: &Bound<PyModule>) -> PyResult<()> {
+    let hook = finder.getattr("path_hook")?;
+
+    sys_module
+        .getattr("path_hooks")?
+        .call_method1("insert", (0, hook))?;
+
+    Ok(())
+}
@@ -6,22 +6,24 @@
 
 use {
     crate::{
+        capabilities,
         config::{OxidizedPythonInterpreterConfig, ResolvedOxidizedPythonInterpreterConfig},
         conversion::osstring_to_bytes,
         error::NewInterpreterError,
-        osutils::resolve_terminfo_dirs,
-        pyalloc::PythonMemoryAllocator,
+        host_python::HostPython,
+        importer::{
+            install_path_hook, remove_external_importers, replace_meta_path_importers,
+            ImporterState, OxidizedFinder, PyInit_oxidized_importer, PythonResourcesState,
+            OXIDIZED_IMPORTER_NAME, OXIDIZED_IMPORTER_NAME_STR,
+        },
+        interrupt::InterruptHandle,
+        osutils::{resolve_terminfo_dirs, TerminfoDirs},
+        pyalloc::{AllocationStats, PythonMemoryAllocator},
+        pylog::install_log_forwarding,
+        sandbox::install_audit_hook,
     },
     once_cell::sync::Lazy,
-    oxidized_importer::{
-        install_path_hook, remove_external_importers, replace_meta_path_importers, ImporterState,
-        OxidizedFinder, PyInit_oxidized_importer, PythonResourcesState, OXIDIZED_IMPORTER_NAME,
-        OXIDIZED_IMPORTER_NAME_STR,
-    },
-    pyo3::{
-        exceptions::PyRuntimeError, ffi as pyffi, prelude::*, types::PyDict, AsPyPointer,
-        PyTypeInfo,
-    },
+    pyo3::{exceptions::PyRuntimeError, ffi as pyffi, prelude::*, types::PyDict, PyTypeInfo},
     python_packaging::interpreter::{MultiprocessingStartMethod, TerminfoResolution},
     std::{
         collections::BTreeSet,
@@ -32,9 +34,19 @@ use {
     },
 };
 
+#[cfg(all(Py_3_12, not(Py_LIMITED_API)))]
+use crate::subinterpreter::{SubInterpreter, SubInterpreterConfig};
+
 static GLOBAL_INTERPRETER_GUARD: Lazy<std::sync::Mutex<()>> =
     Lazy::new(|| std::sync::Mutex::new(()));
 
+/// Maximum number of individual allocations listed in a leak report.
+const LEAK_REPORT_LIMIT: usize = 20;
+
+/// Module of the helper `multiprocessing` starts with
+/// `-c 'from multiprocessing.resource_tracker import main;main(<fd>)'`.
+const MULTIPROCESSING_RESOURCE_TRACKER: &str = "multiprocessing.resource_tracker";
+
 /// Manages an embedded Python interpreter.
 ///
 /// Python interpreters have global state and there can only be a single
@@ -92,6 +104,10 @@ pub struct MainPythonInterpreter<'interpreter, 'resources: 'interpreter> {
     pub(crate) allocator: Option<PythonMemoryAllocator>,
     /// File to write containing list of modules when the interpreter finalizes.
     write_modules_path: Option<PathBuf>,
+    /// Delivers interrupt requests to Python jobs.
+    interrupt: InterruptHandle,
+    /// The `terminfo` directories found by [TerminfoResolution::Dynamic].
+    terminfo_dirs: Option<TerminfoDirs>,
 }
 
 impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
@@ -103,23 +119,28 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
     ) -> Result<MainPythonInterpreter<'interpreter, 'resources>, NewInterpreterError> {
         let config: ResolvedOxidizedPythonInterpreterConfig<'resources> = config.try_into()?;
 
-        match config.terminfo_resolution {
+        let terminfo_dirs = match config.terminfo_resolution {
             TerminfoResolution::Dynamic => {
-                if let Some(v) = resolve_terminfo_dirs() {
-                    env::set_var("TERMINFO_DIRS", v);
+                let dirs = resolve_terminfo_dirs();
+                if let Some(dirs) = dirs.as_ref().filter(|dirs| !dirs.dirs.is_empty()) {
+                    env::set_var("TERMINFO_DIRS", dirs.env_value());
                 }
+                dirs
             }
             TerminfoResolution::Static(ref v) => {
                 env::set_var("TERMINFO_DIRS", v);
+                None
             }
-            TerminfoResolution::None => {}
-        }
+            TerminfoResolution::None => None,
+        };
 
         let mut res = MainPythonInterpreter {
             config,
             interpreter_guard: None,
             allocator: None,
             write_modules_path: None,
+            interrupt: InterruptHandle::new(),
+            terminfo_dirs,
         };
 
         res.init()?;
@@ -149,7 +170,7 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
             std::env::set_var("TCL_LIBRARY", tcl_library);
         }
 
-        set_pyimport_inittab(&self.config);
+        set_pyimport_inittab(Some(&self.config));
 
         // Pre-configure Python.
         let pre_config = pyffi::PyPreConfig::try_from(&self.config)?;
@@ -166,7 +187,18 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         };
 
         // Set the memory allocator domains if they are configured.
-        self.allocator = PythonMemoryAllocator::from_backend(self.config.allocator_backend);
+        self.allocator = PythonMemoryAllocator::from_backend(self.config.allocator_backend)?;
+
+        if let Some(limit) = self.config.allocator_hard_limit {
+            match &self.allocator {
+                Some(allocator) if allocator.set_hard_limit(Some(limit)) => {}
+                _ => {
+                    return Err(NewInterpreterError::Simple(
+                        "allocator_hard_limit requires the rust allocator backend",
+                    ));
+                }
+            }
+        }
 
         if let Some(allocator) = &self.allocator {
             if self.config.allocator_raw {
@@ -204,6 +236,12 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         // our custom importer before Python attempts any imports.
         py_config._init_main = 0;
 
+        // The application forwards interrupts itself. So Python must not take
+        // over SIGINT.
+        if self.config.forward_interrupts {
+            py_config.install_signal_handlers = 0;
+        }
+
         let status = unsafe { pyffi::Py_InitializeFromConfig(&py_config) };
         if unsafe { pyffi::PyStatus_Exception(status) } != 0 {
             return Err(NewInterpreterError::new_from_pystatus(
@@ -220,6 +258,18 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         // importlib._bootstrap_external. This is where we work our magic to
         // inject our custom importer.
 
+        // The import sandbox has to be in place before the main initialization
+        // imports modules from the filesystem, e.g. `site`.
+        if let Some(sandbox) = &self.config.import_sandbox {
+            unsafe {
+                Python::with_gil_unchecked(|py| {
+                    install_audit_hook(py, sandbox).map_err(|err| {
+                        NewInterpreterError::new_from_pyerr(py, err, "installing import sandbox")
+                    })
+                })?
+            };
+        }
+
         let oxidized_finder_loaded =
             unsafe { Python::with_gil_unchecked(|py| self.inject_oxidized_importer(py))? };
 
@@ -304,13 +354,21 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         // potentially encounter a use-after-free if the importer is used after self.config
         // is dropped. However, that would require self to be dropped. And if self is dropped,
         // there should no longer be a Python interpreter around. So it follows that the
-        // importer state cannot be dropped after self.
+        // importer state cannot be dropped after self. This upholds the safety contract of
+        // replace_meta_path_importers(): the resources borrow from self.config, which is
+        // only dropped after the interpreter is finalized.
 
-        replace_meta_path_importers(py, oxidized_importer, resources_state, Some(cb)).map_err(
-            |err| {
-                NewInterpreterError::new_from_pyerr(py, err, "initialization of oxidized importer")
-            },
-        )?;
+        unsafe {
+            replace_meta_path_importers(
+                py,
+                &oxidized_importer.as_borrowed(),
+                resources_state,
+                Some(cb),
+            )
+        }
+        .map_err(|err| {
+            NewInterpreterError::new_from_pyerr(py, err, "initialization of oxidized importer")
+        })?;
 
         Ok(true)
     }
@@ -351,7 +409,7 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         // _Py_InitializeMain.
 
         if !self.config.filesystem_importer {
-            remove_external_importers(sys_module).map_err(|err| {
+            remove_external_importers(&sys_module.as_borrowed()).map_err(|err| {
                 NewInterpreterError::new_from_pyerr(py, err, "removing external importers")
             })?;
         }
@@ -375,7 +433,7 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
                 .find(|finder| {
                     // This should never fail.
                     if let Ok(finder) = finder {
-                        OxidizedFinder::is_type_of(finder)
+                        OxidizedFinder::is_type_of_bound(&finder.as_borrowed())
                     } else {
                         false
                     }
@@ -385,7 +443,7 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         };
 
         if let Some(Ok(finder)) = oxidized_finder {
-            install_path_hook(finder, sys_module).map_err(|err| {
+            install_path_hook(&finder.as_borrowed(), &sys_module.as_borrowed()).map_err(|err| {
                 NewInterpreterError::new_from_pyerr(
                     py,
                     err,
@@ -450,6 +508,18 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
             }
         }
 
+        if self.config.enforce_capability_policies {
+            capabilities::install_audit_hook(py).map_err(|err| {
+                NewInterpreterError::new_from_pyerr(py, err, "installing capability policies")
+            })?;
+        }
+
+        if self.config.log_forwarding {
+            install_log_forwarding(py).map_err(|err| {
+                NewInterpreterError::new_from_pyerr(py, err, "installing log forwarding")
+            })?;
+        }
+
         let write_modules_path = if let Some(key) = &self.config.write_modules_directory_env {
             if let Ok(path) = std::env::var(key) {
                 let path = PathBuf::from(path);
@@ -490,6 +560,51 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         Ok(write_modules_path)
     }
 
+    /// Obtain a handle for interrupting Python jobs.
+    ///
+    /// See the [crate::interrupt] module documentation for how this is used.
+    /// Handles remain valid across [Self::restart()].
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Obtain the host Python installation the interpreter runs with.
+    ///
+    /// Returns [None] when the embedded Python distribution is used. See
+    /// [OxidizedPythonInterpreterConfig::host_python_fallback].
+    pub fn host_python(&self) -> Option<&HostPython> {
+        self.config.host_python()
+    }
+
+    /// Obtain the `terminfo` directories `TERMINFO_DIRS` was set to.
+    ///
+    /// Returns [None] unless they were resolved dynamically. See
+    /// [OxidizedPythonInterpreterConfig::terminfo_resolution].
+    pub fn terminfo_dirs(&self) -> Option<&TerminfoDirs> {
+        self.terminfo_dirs.as_ref()
+    }
+
+    /// Obtain the custom memory allocator, if one is installed.
+    ///
+    /// This can be used to adjust memory limits at run-time. See
+    /// [PythonMemoryAllocator::set_hard_limit()] and
+    /// [PythonMemoryAllocator::set_soft_limit()].
+    pub fn allocator(&self) -> Option<&PythonMemoryAllocator> {
+        self.allocator.as_ref()
+    }
+
+    /// Obtain a snapshot of memory allocation statistics.
+    ///
+    /// Returns [None] unless the configured allocator backend tracks
+    /// allocations. Currently only [MemoryAllocatorBackend::Rust] does.
+    ///
+    /// [MemoryAllocatorBackend::Rust]: python_packaging::interpreter::MemoryAllocatorBackend::Rust
+    pub fn allocator_stats(&self) -> Option<AllocationStats> {
+        self.allocator
+            .as_ref()
+            .and_then(|allocator| allocator.stats())
+    }
+
     /// Proxy for [Python::with_gil()].
     ///
     /// This allows running Python code via the PyO3 Rust APIs. Alternatively,
@@ -502,6 +617,31 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         Python::with_gil(f)
     }
 
+    /// Create an isolated sub-interpreter.
+    ///
+    /// The sub-interpreter gets its own `sys.modules` and module state. With
+    /// the default [SubInterpreterConfig], it also owns its GIL (PEP 684), so
+    /// code running in it can execute in parallel with this interpreter and
+    /// other sub-interpreters when driven from separate threads.
+    ///
+    /// The returned [SubInterpreter] borrows this instance, ensuring every
+    /// sub-interpreter is ended before the main interpreter is finalized.
+    ///
+    /// Must not be called with the GIL held.
+    #[cfg(all(Py_3_12, not(Py_LIMITED_API)))]
+    pub fn new_sub_interpreter(
+        &self,
+        config: SubInterpreterConfig,
+    ) -> Result<SubInterpreter<'_>, NewInterpreterError> {
+        if unsafe { pyffi::Py_IsInitialized() } == 0 {
+            return Err(NewInterpreterError::Simple(
+                "cannot create sub-interpreter: main interpreter is not initialized",
+            ));
+        }
+
+        SubInterpreter::new(config)
+    }
+
     /// Runs `Py_RunMain()` and finalizes the interpreter.
     ///
     /// This will execute whatever is configured by the Python interpreter config
@@ -524,10 +664,21 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
 
     /// Run in "multiprocessing worker" mode.
     ///
-    /// This should be called when `sys.argv[1] == "--multiprocessing-fork"`. It
-    /// will parse arguments for the worker from `sys.argv` and call into the
-    /// `multiprocessing` module to perform work.
+    /// This should be called when [Self::is_multiprocessing()] is true. For
+    /// workers, it will parse arguments from `sys.argv` and call into the
+    /// `multiprocessing` module to perform work. For the resource tracker, it
+    /// calls its `main()` with the file descriptor passed in the `-c` code.
     pub fn run_multiprocessing(&self) -> PyResult<i32> {
+        if let Some(fd) = self.multiprocessing_resource_tracker_fd() {
+            return self.with_gil(|py| {
+                py.import_bound("multiprocessing.resource_tracker")?
+                    .getattr("main")?
+                    .call1((fd,))?;
+
+                Ok(0)
+            });
+        }
+
         // This code effectively reimplements multiprocessing.spawn.freeze_support(),
         // except entirely in the Rust domain. This function effectively verifies
         // `sys.argv[1] == "--multiprocessing-fork"` then parsed key=value arguments
@@ -542,7 +693,7 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
         }
 
         self.with_gil(|py| {
-            let kwargs = PyDict::new(py);
+            let kwargs = PyDict::new_bound(py);
 
             for arg in argv.iter().skip(2) {
                 let arg = arg.to_string_lossy();
@@ -572,8 +723,10 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
                 kwargs.set_item(key, value)?;
             }
 
-            let spawn_module = py.import("multiprocessing.spawn")?;
-            spawn_module.getattr("spawn_main")?.call1((kwargs,))?;
+            let spawn_module = py.import_bound("multiprocessing.spawn")?;
+            spawn_module
+                .getattr("spawn_main")?
+                .call((), Some(&kwargs))?;
 
             Ok(0)
         })
@@ -584,10 +737,36 @@ impl<'interpreter, 'resources> MainPythonInterpreter<'interpreter, 'resources> {
     /// The `multiprocessing` module can work by spawning new processes
     /// with arguments `--multiprocessing-fork [key=value] ...`. This function
     /// detects if the current Python interpreter is configured for said execution.
+    ///
+    /// It also detects the resource tracker, which `multiprocessing` starts
+    /// with `-c <code>` like a Python interpreter.
     pub fn is_multiprocessing(&self) -> bool {
         let argv = self.config.resolve_sys_argv();
 
-        argv.len() >= 2 && argv[1] == "--multiprocessing-fork"
+        (argv.len() >= 2 && argv[1] == "--multiprocessing-fork")
+            || self.multiprocessing_resource_tracker_fd().is_some()
+    }
+
+    /// The file descriptor to pass to the resource tracker if this is one.
+    ///
+    /// Only the exact code `multiprocessing` uses is recognized. Other code
+    /// passed with `-c` isn't run.
+    fn multiprocessing_resource_tracker_fd(&self) -> Option<i32> {
+        let argv = self.config.resolve_sys_argv();
+        let [.., flag, code] = argv else {
+            return None;
+        };
+        if flag != "-c" {
+            return None;
+        }
+
+        code.to_str()?
+            .strip_prefix("from ")?
+            .strip_prefix(MULTIPROCESSING_RESOURCE_TRACKER)?
+            .strip_prefix(" import main;main(")?
+            .strip_suffix(')')?
+            .parse()
+            .ok()
     }
 
     /// Runs the Python interpreter.
@@ -628,7 +807,12 @@ static mut REPLACED_BUILTIN_EXTENSIONS: Option<Vec<pyffi::_inittab>> = None;
 /// We maintain our own shadow copy of this array and synchronize it
 /// to PyImport_Inittab during interpreter initialization so we don't
 /// call the broken APIs.
-fn set_pyimport_inittab(config: &OxidizedPythonInterpreterConfig) {
+///
+/// Without a config, PyImport_Inittab is reset to the builtin extensions
+/// of the Python distribution. This is done after the interpreter is
+/// finalized, so a subsequent interpreter doesn't see the extensions
+/// registered by a prior configuration.
+fn set_pyimport_inittab(config: Option<&OxidizedPythonInterpreterConfig>) {
     // If this is our first time, copy the canonical source to our shadow
     // copy.
     unsafe {
@@ -652,7 +836,7 @@ fn set_pyimport_inittab(config: &OxidizedPythonInterpreterConfig) {
     // Now make a copy and add in new extensions.
     let mut extensions = unsafe { ORIGINAL_BUILTIN_EXTENSIONS.as_ref().unwrap().clone() };
 
-    if config.oxidized_importer {
+    if config.is_some_and(|config| config.oxidized_importer) {
         let ptr = PyInit_oxidized_importer as *const ();
         extensions.push(pyffi::_inittab {
             name: OXIDIZED_IMPORTER_NAME.as_ptr() as *mut _,
@@ -663,7 +847,9 @@ fn set_pyimport_inittab(config: &OxidizedPythonInterpreterConfig) {
     }
 
     // Add additional extension modules from the config.
-    if let Some(extra_extension_modules) = &config.extra_extension_modules {
+    if let Some(extra_extension_modules) =
+        config.and_then(|config| config.extra_extension_modules.as_ref())
+    {
         for extension in extra_extension_modules {
             let ptr = extension.init_func as *const ();
             extensions.push(pyffi::_inittab {
@@ -682,9 +868,12 @@ fn set_pyimport_inittab(config: &OxidizedPythonInterpreterConfig) {
     });
 
     // And finally replace the static in Python's code with our instance.
+    // The pointer is swapped before the previous shadow copy is dropped so
+    // `PyImport_Inittab` never points at freed memory.
     unsafe {
-        REPLACED_BUILTIN_EXTENSIONS = Some(extensions);
+        let previous = REPLACED_BUILTIN_EXTENSIONS.replace(extensions);
         pyffi::PyImport_Inittab = REPLACED_BUILTIN_EXTENSIONS.as_mut().unwrap().as_mut_ptr();
+        drop(previous);
     }
 }
 
@@ -731,22 +920,40 @@ impl<'interpreter, 'resources> Drop for MainPythonInterpreter<'interpreter, 'res
         // of Py_RunMain(). Possibly something out-of-band beyond our control. We don't
         // muck with the interpreter after finalization because this will likely result
         // in a segfault.
-        if unsafe { pyffi::Py_IsInitialized() } == 0 {
-            return;
-        }
+        if unsafe { pyffi::Py_IsInitialized() } != 0 {
+            if let Some(path) = self.write_modules_path.as_ref() {
+                match self.with_gil(|py| write_modules_to_path(py, path)) {
+                    Ok(_) => {}
+                    Err(msg) => {
+                        eprintln!("error writing modules file: {}", msg);
+                    }
+                }
+            }
+
+            // Acquiring the GIL through pyo3 flushes its pool of pending reference
+            // count changes. This must happen now: applied after a new interpreter
+            // is initialized, they would operate on objects of the finalized one.
+            self.with_gil(|_| {});
 
-        if let Some(path) = self.write_modules_path.as_ref() {
-            match self.with_gil(|py| write_modules_to_path(py, path)) {
-                Ok(_) => {}
-                Err(msg) => {
-                    eprintln!("error writing modules file: {}", msg);
+            unsafe {
+                pyffi::PyGILState_Ensure();
+                pyffi::Py_FinalizeEx();
+            }
+
+            if self.config.allocator_report_leaks {
+                if let Some(report) = self
+                    .allocator
+                    .as_ref()
+                    .and_then(|allocator| allocator.leak_report(LEAK_REPORT_LIMIT))
+                {
+                    log::warn!(
+                        "memory outstanding after interpreter finalization: {}",
+                        report
+                    );
                 }
             }
         }
 
-        unsafe {
-            pyffi::PyGILState_Ensure();
-            pyffi::Py_FinalizeEx();
-        }
+        set_pyimport_inittab(None);
     }
 }
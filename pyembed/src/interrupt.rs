@@ -0,0 +1,275 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Application controlled interruption of running Python code.
+
+By default, Python installs a `SIGINT` handler which raises `KeyboardInterrupt`
+in the main thread. This doesn't work well for GUI applications: the main
+thread typically runs the event loop while Python code runs on worker threads.
+And Ctrl-C in the terminal may not be the only way a user asks to stop: the
+application can offer its own controls for it.
+
+With [crate::OxidizedPythonInterpreterConfig::forward_interrupts] enabled,
+Python doesn't install signal handlers. Instead, the application obtains an
+[InterruptHandle] via [crate::MainPythonInterpreter::interrupt_handle()] and
+forwards interrupt requests to it:
+
+* Code running a Python job registers itself with [InterruptHandle::enter_job()].
+* The first [InterruptHandle::request_interrupt()] raises `KeyboardInterrupt`
+  in the thread running the job.
+* Further requests while the job is still running return
+  [InterruptAction::ForceQuit], telling the application to give up and exit.
+
+Like signals, the `KeyboardInterrupt` is only raised when the interpreter
+executes Python bytecode. A job blocked in a long running C function doesn't
+see it until that function returns. This is why a second request force quits.
+
+On UNIX, [forward_sigint()] routes `SIGINT` to an [InterruptHandle].
+*/
+
+use {
+    pyo3::{ffi as pyffi, prelude::*},
+    std::{
+        os::raw::c_ulong,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// What the application should do in response to an interrupt request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptAction {
+    /// No Python job is running. The request should be handled as if Python
+    /// weren't there, e.g. by closing the application.
+    Idle,
+
+    /// `KeyboardInterrupt` is being raised in the running Python job.
+    Interrupted,
+
+    /// The job didn't stop after a previous interrupt. The application should exit.
+    ForceQuit,
+}
+
+struct InterruptState {
+    /// `threading.get_ident()` of the thread running the current job.
+    job: Option<c_ulong>,
+
+    /// Number of interrupts requested since the current job started.
+    requests: usize,
+}
+
+/// Delivers interrupt requests to Python jobs.
+///
+/// Instances are cheap to clone and can be shared across threads.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    state: Arc<Mutex<InterruptState>>,
+}
+
+impl InterruptHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(InterruptState {
+                job: None,
+                requests: 0,
+            })),
+        }
+    }
+
+    /// Register the current thread as running an interruptible Python job.
+    ///
+    /// The job ends when the returned guard is dropped. Only a single job can
+    /// be registered at a time. Errors if another job is running.
+    pub fn enter_job<'py>(&self, py: Python<'py>) -> PyResult<JobGuard<'py>> {
+        let ident = py
+            .import_bound("threading")?
+            .call_method0("get_ident")?
+            .extract::<c_ulong>()?;
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.job.is_some() {
+            return Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "another interruptible Python job is already running",
+            ));
+        }
+
+        state.job = Some(ident);
+        state.requests = 0;
+
+        Ok(JobGuard {
+            handle: self.clone(),
+            ident,
+            _py: py,
+        })
+    }
+
+    /// Whether a Python job is currently registered.
+    pub fn is_job_running(&self) -> bool {
+        self.state.lock().unwrap().job.is_some()
+    }
+
+    /// Request interruption of the running Python job.
+    ///
+    /// This never blocks on the GIL, so it is safe to call from an event loop.
+    /// The `KeyboardInterrupt` is scheduled from a helper thread once the GIL
+    /// becomes available.
+    pub fn request_interrupt(&self) -> InterruptAction {
+        let mut state = self.state.lock().unwrap();
+
+        let ident = match state.job {
+            Some(ident) => ident,
+            None => return InterruptAction::Idle,
+        };
+
+        state.requests += 1;
+        if state.requests > 1 {
+            return InterruptAction::ForceQuit;
+        }
+
+        drop(state);
+
+        let handle = self.clone();
+        std::thread::spawn(move || handle.deliver(ident));
+
+        InterruptAction::Interrupted
+    }
+
+    /// Schedule `KeyboardInterrupt` in the thread identified by `ident`.
+    fn deliver(&self, ident: c_ulong) {
+        // Python may have been finalized by the time we get here.
+        if unsafe { pyffi::Py_IsInitialized() } == 0 {
+            return;
+        }
+
+        Python::with_gil(|_| {
+            // The job may have ended while we were waiting for the GIL. Since
+            // JobGuard is dropped with the GIL held, it can't end while we hold it.
+            if self.state.lock().unwrap().job != Some(ident) {
+                return;
+            }
+
+            unsafe {
+                pyffi::PyThreadState_SetAsyncExc(ident as _, pyffi::PyExc_KeyboardInterrupt);
+            }
+        });
+    }
+}
+
+/// Marks a thread as running an interruptible Python job.
+///
+/// Obtained from [InterruptHandle::enter_job()]. Dropping the guard ends the
+/// job and discards an interrupt that was requested but not yet raised.
+pub struct JobGuard<'py> {
+    handle: InterruptHandle,
+    ident: c_ulong,
+    /// Ensures the guard is dropped while the GIL is held.
+    _py: Python<'py>,
+}
+
+impl<'py> Drop for JobGuard<'py> {
+    fn drop(&mut self) {
+        let mut state = self.handle.state.lock().unwrap();
+        state.job = None;
+        state.requests = 0;
+
+        // Passing NULL clears a pending asynchronous exception.
+        unsafe {
+            pyffi::PyThreadState_SetAsyncExc(self.ident as _, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(unix)]
+mod sigint {
+    use std::{
+        io::Read,
+        os::{
+            raw::c_int,
+            unix::io::{FromRawFd, RawFd},
+        },
+        sync::atomic::{AtomicI32, Ordering},
+    };
+
+    /// Write end of the self-pipe the signal handler notifies.
+    static SIGINT_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn handle_sigint(_signum: c_int) {
+        // Only async-signal-safe operations are allowed here.
+        let fd = SIGINT_PIPE.load(Ordering::Relaxed);
+        if fd >= 0 {
+            unsafe {
+                libc::write(fd, b"!".as_ptr() as *const _, 1);
+            }
+        }
+    }
+
+    /// Install the `SIGINT` handler and return a reader receiving a byte per signal.
+    pub(super) fn install() -> std::io::Result<std::fs::File> {
+        let mut fds: [RawFd; 2] = [-1, -1];
+
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if SIGINT_PIPE
+            .compare_exchange(-1, fds[1], Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            unsafe {
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+            }
+
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "SIGINT forwarding is already installed",
+            ));
+        }
+
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_sigint as extern "C" fn(c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(std::fs::File::from_raw_fd(fds[0]))
+        }
+    }
+
+    /// Block until the next signal arrives.
+    pub(super) fn wait(reader: &mut std::fs::File) -> std::io::Result<()> {
+        let mut buf = [0u8; 1];
+        reader.read_exact(&mut buf)
+    }
+}
+
+/// Route `SIGINT` (Ctrl-C) to an [InterruptHandle].
+///
+/// Installs a process-wide `SIGINT` handler. Each signal is turned into a call
+/// to [InterruptHandle::request_interrupt()] on a dedicated thread, whose result
+/// is passed to `on_action`. The callback decides how to react, e.g. by exiting
+/// on [InterruptAction::Idle] and [InterruptAction::ForceQuit].
+///
+/// Can only be called once per process.
+#[cfg(unix)]
+pub fn forward_sigint<F>(handle: InterruptHandle, on_action: F) -> std::io::Result<()>
+where
+    F: Fn(InterruptAction) + Send + 'static,
+{
+    let mut reader = sigint::install()?;
+
+    std::thread::Builder::new()
+        .name("pyembed-sigint".to_string())
+        .spawn(move || {
+            while sigint::wait(&mut reader).is_ok() {
+                on_action(handle.request_interrupt());
+            }
+        })?;
+
+    Ok(())
+}
@@ -29,6 +29,9 @@ serves as a high-level interface for running code in the interpreter.
 Under the hood, `pyembed` makes direct use of the `pyo3` crate for
 low-level Python FFI bindings as well as higher-level interfacing.
 
+Python resources imported by [OxidizedFinder] are defined by the
+`python-packed-resources` crate, which is re-exported as [packed_resources].
+
 **It is an explicit goal of this crate to rely on as few external dependencies
 as possible.** This is because we want to minimize bloat in produced binaries.
 
@@ -48,25 +51,56 @@ The optional `allocator-snmalloc` feature controls support for using
 [snmalloc](https://github.com/microsoft/snmalloc) as Python's memory allocator.
 The feature behaves similarly to `jemalloc`, which is documented above.
 
+Requesting an allocator backend whose feature isn't enabled makes
+[MainPythonInterpreter::new()] return an error.
+[PythonMemoryAllocator::is_backend_available()] can be used to check for
+support ahead of time.
+
 The optional `serialization` feature controls whether configuration types
 (such as [OxidizedPythonInterpreterConfig]) implement `Serialize` and
 `Deserialize`.
 */
 
 // #[allow(unused)]
+pub mod capabilities;
 mod config;
 mod conversion;
 mod error;
+pub mod host_python;
+mod importer;
 mod interpreter;
+pub mod interrupt;
 // mod interpreter_config;
-mod osutils;
+pub mod osutils;
 mod pyalloc;
+mod pylog;
+pub mod sandbox;
+#[cfg(all(Py_3_12, not(Py_LIMITED_API)))]
+mod subinterpreter;
 // pub mod technotes;
 
-// #[cfg(test)]
-// mod test;
-
-pub use crate::interpreter::MainPythonInterpreter;
+#[cfg(test)]
+mod test;
+
+pub use crate::{
+    capabilities::CapabilityPolicy,
+    config::OxidizedPythonInterpreterConfig,
+    error::NewInterpreterError,
+    host_python::HostPython,
+    importer::{OxidizedFinder, PackedResourcesSource, PythonResourcesState},
+    interpreter::MainPythonInterpreter,
+    interrupt::{InterruptAction, InterruptHandle, JobGuard},
+    pyalloc::{
+        AllocationDomain, AllocationStats, DomainAllocationStats, OutstandingAllocation,
+        PythonMemoryAllocator, SoftLimitCallback, SIZE_HISTOGRAM_BUCKETS,
+    },
+    sandbox::{ImportSandbox, SandboxViolationAction},
+};
+
+pub use python_packed_resources as packed_resources;
+
+#[cfg(all(Py_3_12, not(Py_LIMITED_API)))]
+pub use crate::subinterpreter::{SubInterpreter, SubInterpreterConfig};
 
 // #[allow(unused_imports)]
 // pub use {
@@ -2,9 +2,22 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+/*! Locating operating system resources.
+
+A bundled Python distribution and the libraries it ships with look for some
+system resources at the paths they were built for, which may not exist on the
+machine the application runs on. This module detects the operating system and
+Linux distribution to find the actual paths of the `terminfo` database, the
+CA certificate bundle and the fontconfig configuration.
+*/
+
 use {
     once_cell::sync::Lazy,
-    std::path::{Path, PathBuf},
+    std::{
+        ffi::OsString,
+        fmt::{Display, Formatter},
+        path::{Path, PathBuf},
+    },
 };
 
 /// terminfo directories for Debian based distributions.
@@ -12,28 +25,74 @@ use {
 /// Search for `--with-terminfo-dirs` at
 /// https://salsa.debian.org/debian/ncurses/blob/master/debian/rules to find
 /// the source of truth for this.
-const TERMINFO_DIRS_DEBIAN: &str = "/etc/terminfo:/lib/terminfo:/usr/share/terminfo";
+const TERMINFO_DIRS_DEBIAN: &[&str] = &["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"];
 
 /// terminfo directories for RedHat based distributions.
 ///
 /// CentOS compiled with
 /// `--with-terminfo-dirs=%{_sysconfdir}/terminfo:%{_datadir}/terminfo`.
-const TERMINFO_DIRS_REDHAT: &str = "/etc/terminfo:/usr/share/terminfo";
+const TERMINFO_DIRS_REDHAT: &[&str] = &["/etc/terminfo", "/usr/share/terminfo"];
+
+/// terminfo directories for Arch based distributions.
+///
+/// ncurses is built with the default terminfo directory.
+const TERMINFO_DIRS_ARCH: &[&str] = &["/usr/share/terminfo"];
+
+/// terminfo directories for Alpine.
+///
+/// `ncurses-terminfo-base` installs common terminals to `/etc/terminfo`, the
+/// others are in `/usr/share/terminfo`.
+const TERMINFO_DIRS_ALPINE: &[&str] = &["/etc/terminfo", "/usr/share/terminfo"];
+
+/// terminfo directories for SUSE based distributions.
+const TERMINFO_DIRS_SUSE: &[&str] = &["/etc/terminfo", "/usr/share/terminfo"];
+
+/// terminfo directories for NixOS.
+///
+/// There are no files outside the Nix store, so these are the profiles
+/// packages are installed to.
+const TERMINFO_DIRS_NIXOS: &[&str] = &[
+    "/run/current-system/sw/share/terminfo",
+    "/nix/var/nix/profiles/default/share/terminfo",
+];
 
 /// terminfo directories for macOS.
-const TERMINFO_DIRS_MACOS: &str = "/usr/share/terminfo";
-
-static TERMINFO_DIRS_COMMON: Lazy<Vec<PathBuf>> = Lazy::new(|| {
-    vec![
-        PathBuf::from("/usr/local/etc/terminfo"),
-        PathBuf::from("/usr/local/lib/terminfo"),
-        PathBuf::from("/usr/local/share/terminfo"),
-        PathBuf::from("/etc/terminfo"),
-        PathBuf::from("/usr/lib/terminfo"),
-        PathBuf::from("/lib/terminfo"),
-        PathBuf::from("/usr/share/terminfo"),
-    ]
-});
+const TERMINFO_DIRS_MACOS: &[&str] = &["/usr/share/terminfo"];
+
+/// terminfo directories to look for on unknown Linux distributions.
+const TERMINFO_DIRS_COMMON: &[&str] = &[
+    "/usr/local/etc/terminfo",
+    "/usr/local/lib/terminfo",
+    "/usr/local/share/terminfo",
+    "/etc/terminfo",
+    "/usr/lib/terminfo",
+    "/lib/terminfo",
+    "/usr/share/terminfo",
+];
+
+/// CA certificate bundle of Debian based distributions, Alpine and NixOS.
+const CA_BUNDLE_DEBIAN: &str = "/etc/ssl/certs/ca-certificates.crt";
+
+/// CA certificate bundle of RedHat based distributions.
+const CA_BUNDLE_REDHAT: &str = "/etc/pki/tls/certs/ca-bundle.crt";
+
+/// CA certificate bundle of Arch based distributions.
+const CA_BUNDLE_ARCH: &str = "/etc/ca-certificates/extracted/tls-ca-bundle.pem";
+
+/// CA certificate bundle of SUSE based distributions.
+const CA_BUNDLE_SUSE: &str = "/var/lib/ca-certificates/ca-bundle.pem";
+
+/// CA certificate bundle of macOS.
+const CA_BUNDLE_MACOS: &str = "/etc/ssl/cert.pem";
+
+/// fontconfig configuration file of Linux distributions.
+const FONTCONFIG_FILE_LINUX: &str = "/etc/fonts/fonts.conf";
+
+/// fontconfig configuration files of Homebrew on Apple silicon and Intel Macs.
+const FONTCONFIG_FILES_MACOS: &[&str] = &[
+    "/opt/homebrew/etc/fonts/fonts.conf",
+    "/usr/local/etc/fonts/fonts.conf",
+];
 
 #[derive(Clone)]
 enum OsVariant {
@@ -43,12 +102,39 @@ enum OsVariant {
     Other,
 }
 
-enum LinuxDistroVariant {
+/// Linux distribution families differing in where they install things.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinuxDistroVariant {
+    /// Debian, Ubuntu and derivatives.
     Debian,
+    /// RHEL, Fedora, CentOS and derivatives.
     RedHat,
+    /// Arch Linux, Manjaro and derivatives.
+    Arch,
+    /// Alpine Linux.
+    Alpine,
+    /// openSUSE and SUSE Linux Enterprise.
+    Suse,
+    /// NixOS.
+    NixOs,
+    /// A distribution not known to this module.
     Unknown,
 }
 
+impl Display for LinuxDistroVariant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Debian => "Debian",
+            Self::RedHat => "RedHat",
+            Self::Arch => "Arch",
+            Self::Alpine => "Alpine",
+            Self::Suse => "SUSE",
+            Self::NixOs => "NixOS",
+            Self::Unknown => "unknown Linux distribution",
+        })
+    }
+}
+
 static TARGET_OS: Lazy<OsVariant> = Lazy::new(|| {
     if cfg!(target_os = "linux") {
         OsVariant::Linux
@@ -61,83 +147,252 @@ static TARGET_OS: Lazy<OsVariant> = Lazy::new(|| {
     }
 });
 
-struct OsInfo {
-    os: OsVariant,
-    linux_distro: Option<LinuxDistroVariant>,
+static LINUX_DISTRO: Lazy<Option<LinuxDistroVariant>> = Lazy::new(|| match *TARGET_OS {
+    OsVariant::Linux => Some(resolve_linux_distro()),
+    _ => None,
+});
+
+/// Identify a distribution from an `ID` or `ID_LIKE` value of `os-release`.
+fn distro_from_id(id: &str) -> Option<LinuxDistroVariant> {
+    match id {
+        "debian" | "ubuntu" => Some(LinuxDistroVariant::Debian),
+        "rhel" | "fedora" | "centos" => Some(LinuxDistroVariant::RedHat),
+        "arch" | "archarm" => Some(LinuxDistroVariant::Arch),
+        "alpine" => Some(LinuxDistroVariant::Alpine),
+        "nixos" => Some(LinuxDistroVariant::NixOs),
+        _ if id == "suse" || id == "sles" || id.starts_with("opensuse") => {
+            Some(LinuxDistroVariant::Suse)
+        }
+        _ => None,
+    }
+}
+
+/// Identify the distribution described by the contents of `/etc/os-release`.
+///
+/// `ID` is tried before the space separated `ID_LIKE` list, which derivatives
+/// use to name the distributions they are based on.
+fn parse_os_release(data: &str) -> LinuxDistroVariant {
+    let value = |key: &str| {
+        data.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|value| value.trim().trim_matches(|c| c == '"' || c == '\''))
+    };
+
+    value("ID")
+        .into_iter()
+        .chain(value("ID_LIKE").into_iter().flat_map(str::split_whitespace))
+        .find_map(distro_from_id)
+        .unwrap_or(LinuxDistroVariant::Unknown)
 }
 
 fn resolve_linux_distro() -> LinuxDistroVariant {
     // Attempt to resolve the Linux distro by parsing /etc files.
     let os_release = Path::new("/etc/os-release");
 
-    if let Ok(data) = std::fs::read_to_string(os_release) {
-        for line in data.split('\n') {
-            if line.starts_with("ID_LIKE=") {
-                if line.contains("debian") {
-                    return LinuxDistroVariant::Debian;
-                } else if line.contains("rhel") || line.contains("fedora") {
-                    return LinuxDistroVariant::RedHat;
-                }
-            } else if line.starts_with("ID=") && line.contains("fedora") {
-                return LinuxDistroVariant::RedHat;
-            }
+    match std::fs::read_to_string(os_release) {
+        Ok(data) => parse_os_release(&data),
+        // Without os-release, NixOS is still recognizable by this marker.
+        Err(_) if Path::new("/etc/NIXOS").exists() => LinuxDistroVariant::NixOs,
+        Err(_) => LinuxDistroVariant::Unknown,
+    }
+}
+
+/// The Linux distribution this process runs on.
+///
+/// Returns [None] on other operating systems. The distribution is detected
+/// once and cached.
+pub fn linux_distro() -> Option<LinuxDistroVariant> {
+    *LINUX_DISTRO
+}
+
+/// The first of `candidates` that exists.
+fn first_existing<'a>(candidates: impl IntoIterator<Item = &'a str>) -> Option<PathBuf> {
+    candidates
+        .into_iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
+
+/// Directories to use for the `terminfo` database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TerminfoDirs {
+    /// The distribution the directories were chosen for. [None] for macOS.
+    pub distro: Option<LinuxDistroVariant>,
+    /// Directories that exist, in search order.
+    pub dirs: Vec<PathBuf>,
+    /// Directories the distribution might use that don't exist.
+    pub missing: Vec<PathBuf>,
+}
+
+impl TerminfoDirs {
+    fn probe(distro: Option<LinuxDistroVariant>, candidates: &[&str]) -> Self {
+        let (dirs, missing): (Vec<_>, Vec<_>) = candidates
+            .iter()
+            .map(PathBuf::from)
+            .partition(|path| path.is_dir());
+
+        Self {
+            distro,
+            dirs,
+            missing,
         }
     }
 
-    LinuxDistroVariant::Unknown
+    /// Value for the `TERMINFO_DIRS` environment variable.
+    pub fn env_value(&self) -> OsString {
+        // Paths are from the constants above and don't contain separators.
+        std::env::join_paths(&self.dirs).unwrap_or_default()
+    }
 }
 
-fn resolve_os_info() -> OsInfo {
-    let os = TARGET_OS.clone();
-    let linux_distro = match os {
-        OsVariant::Linux => Some(resolve_linux_distro()),
-        _ => None,
-    };
+impl Display for TerminfoDirs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.distro {
+            Some(distro) => write!(f, "terminfo directories for {}: ", distro)?,
+            None => write!(f, "terminfo directories: ")?,
+        }
 
-    OsInfo { os, linux_distro }
+        if self.dirs.is_empty() {
+            write!(f, "none found")?;
+        } else {
+            write!(f, "{}", self.env_value().to_string_lossy())?;
+        }
+        if !self.missing.is_empty() {
+            let missing = self
+                .missing
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>();
+            write!(f, " (missing {})", missing.join(", "))?;
+        }
+
+        Ok(())
+    }
 }
 
-/// Attempt to resolve the value for the `TERMINFO_DIRS` environment variable.
+/// Attempt to resolve the directories for the `TERMINFO_DIRS` environment variable.
 ///
-/// Returns Some() value that `TERMINFO_DIRS` should be set to or None if
-/// no environment variable should be set.
-pub fn resolve_terminfo_dirs() -> Option<String> {
+/// Returns Some() value with the directories `TERMINFO_DIRS` should be set to
+/// or None if no environment variable should be set.
+pub fn resolve_terminfo_dirs() -> Option<TerminfoDirs> {
     // Always respect an environment variable, if present.
     if std::env::var("TERMINFO_DIRS").is_ok() {
         return None;
     }
 
-    let os_info = resolve_os_info();
-
-    match os_info.os {
-        OsVariant::Linux => match os_info.linux_distro.unwrap() {
-            // TODO we could stat() the well-known paths ourselves and omit
-            // paths that don't exist. This /might/ save some syscalls, since
-            // ncurses doesn't appear to be the most frugal w.r.t. filesystem
-            // requests.
-            LinuxDistroVariant::Debian => Some(TERMINFO_DIRS_DEBIAN.to_string()),
-            LinuxDistroVariant::RedHat => Some(TERMINFO_DIRS_REDHAT.to_string()),
-            LinuxDistroVariant::Unknown => {
+    match *TARGET_OS {
+        OsVariant::Linux => {
+            let distro = linux_distro().unwrap_or(LinuxDistroVariant::Unknown);
+            let candidates = match distro {
+                LinuxDistroVariant::Debian => TERMINFO_DIRS_DEBIAN,
+                LinuxDistroVariant::RedHat => TERMINFO_DIRS_REDHAT,
+                LinuxDistroVariant::Arch => TERMINFO_DIRS_ARCH,
+                LinuxDistroVariant::Alpine => TERMINFO_DIRS_ALPINE,
+                LinuxDistroVariant::Suse => TERMINFO_DIRS_SUSE,
+                LinuxDistroVariant::NixOs => TERMINFO_DIRS_NIXOS,
                 // We don't know this Linux variant. Look for common terminfo
                 // database directories and use paths that are found.
-                let paths = TERMINFO_DIRS_COMMON
-                    .iter()
-                    .filter_map(|p| {
-                        if p.exists() {
-                            Some(p.display().to_string())
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<String>>()
-                    .join(":");
-
-                Some(paths)
-            }
-        },
-        OsVariant::MacOs => Some(TERMINFO_DIRS_MACOS.to_string()),
+                LinuxDistroVariant::Unknown => TERMINFO_DIRS_COMMON,
+            };
+
+            Some(TerminfoDirs::probe(Some(distro), candidates))
+        }
+        OsVariant::MacOs => Some(TerminfoDirs::probe(None, TERMINFO_DIRS_MACOS)),
         // Windows doesn't use the terminfo database.
         OsVariant::Windows => None,
         OsVariant::Other => None,
     }
 }
+
+/// Attempt to find the CA certificate bundle of the system.
+///
+/// A bundled OpenSSL looks for certificates where it was built to. This is
+/// a value for `SSL_CERT_FILE` when they aren't found there.
+pub fn resolve_ca_bundle() -> Option<PathBuf> {
+    match *TARGET_OS {
+        OsVariant::Linux => {
+            let preferred = match linux_distro().unwrap_or(LinuxDistroVariant::Unknown) {
+                LinuxDistroVariant::Debian
+                | LinuxDistroVariant::Alpine
+                | LinuxDistroVariant::NixOs => Some(CA_BUNDLE_DEBIAN),
+                LinuxDistroVariant::RedHat => Some(CA_BUNDLE_REDHAT),
+                LinuxDistroVariant::Arch => Some(CA_BUNDLE_ARCH),
+                LinuxDistroVariant::Suse => Some(CA_BUNDLE_SUSE),
+                LinuxDistroVariant::Unknown => None,
+            };
+
+            // Distributions often provide the other paths for compatibility.
+            first_existing(preferred.into_iter().chain([
+                CA_BUNDLE_DEBIAN,
+                CA_BUNDLE_REDHAT,
+                CA_BUNDLE_ARCH,
+                CA_BUNDLE_SUSE,
+            ]))
+        }
+        OsVariant::MacOs => first_existing([CA_BUNDLE_MACOS]),
+        // OpenSSL on Windows has no system bundle. Python uses the
+        // certificate store instead.
+        OsVariant::Windows => None,
+        OsVariant::Other => None,
+    }
+}
+
+/// Attempt to find the fontconfig configuration file of the system.
+///
+/// This is a value for `FONTCONFIG_FILE` when a bundled fontconfig doesn't
+/// find its configuration.
+pub fn resolve_fontconfig_file() -> Option<PathBuf> {
+    match *TARGET_OS {
+        OsVariant::Linux => first_existing([FONTCONFIG_FILE_LINUX]),
+        OsVariant::MacOs => first_existing(FONTCONFIG_FILES_MACOS.iter().copied()),
+        // fontconfig isn't used on Windows.
+        OsVariant::Windows => None,
+        OsVariant::Other => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_os_release() {
+        for (data, distro) in [
+            ("ID=debian\nVERSION_ID=\"12\"", LinuxDistroVariant::Debian),
+            (
+                "ID=linuxmint\nID_LIKE=\"ubuntu debian\"",
+                LinuxDistroVariant::Debian,
+            ),
+            (
+                "ID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"",
+                LinuxDistroVariant::RedHat,
+            ),
+            ("ID=fedora", LinuxDistroVariant::RedHat),
+            ("ID=manjaro\nID_LIKE=arch", LinuxDistroVariant::Arch),
+            ("ID=alpine", LinuxDistroVariant::Alpine),
+            (
+                "ID=\"opensuse-tumbleweed\"\nID_LIKE=\"opensuse suse\"",
+                LinuxDistroVariant::Suse,
+            ),
+            ("ID=nixos", LinuxDistroVariant::NixOs),
+            ("ID=gentoo", LinuxDistroVariant::Unknown),
+            ("", LinuxDistroVariant::Unknown),
+        ] {
+            assert_eq!(parse_os_release(data), distro, "{}", data);
+        }
+    }
+
+    #[test]
+    fn test_terminfo_dirs_display() {
+        let dirs = TerminfoDirs {
+            distro: Some(LinuxDistroVariant::Debian),
+            dirs: vec![PathBuf::from("/etc/terminfo")],
+            missing: vec![PathBuf::from("/lib/terminfo")],
+        };
+
+        assert_eq!(
+            dirs.to_string(),
+            "terminfo directories for Debian: /etc/terminfo (missing /lib/terminfo)"
+        );
+    }
+}
@@ -86,9 +86,18 @@ Rust's allocator API). So even if Rust's global allocator is set to a custom
 allocator, it is preferred to install the Python allocator because its bindings
 to the allocator will be more efficient.
 
+# Memory Limits
+
+Since the Rust allocator backend sees every allocation, it can also enforce
+limits on the size of the Python heap. A _hard limit_ makes allocations that
+would exceed it fail. Python turns these failures into `MemoryError`, which
+plugin code can handle like any other exception. A _soft limit_ invokes a
+Rust callback when the heap grows past it, e.g. to warn the user.
+
 */
 
 use {
+    crate::error::NewInterpreterError,
     core::ffi::c_void,
     pyo3::ffi as pyffi,
     python_packaging::interpreter::MemoryAllocatorBackend,
@@ -96,12 +105,215 @@ use {
         alloc,
         collections::HashMap,
         ops::{Deref, DerefMut},
-        sync::Mutex,
+        sync::{Arc, Mutex},
     },
 };
 
 const MIN_ALIGN: usize = 16;
 
+/// Number of buckets in [AllocationStats::size_histogram].
+pub const SIZE_HISTOGRAM_BUCKETS: usize = 16;
+
+/// Upper bound (inclusive) of the smallest [AllocationStats::size_histogram] bucket.
+const SIZE_HISTOGRAM_MIN: usize = 16;
+
+/// A Python memory allocator domain tracked by a tracking allocator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AllocationDomain {
+    /// The `raw` domain (`PyMem_RawMalloc()` and friends).
+    Raw,
+    /// The `mem` domain (`PyMem_Malloc()` and friends).
+    Mem,
+    /// The `obj` domain (`PyObject_Malloc()` and friends).
+    Obj,
+    /// The `pymalloc` arena allocator.
+    Arena,
+}
+
+impl AllocationDomain {
+    /// All domains, in a stable order.
+    pub const ALL: [AllocationDomain; 4] = [Self::Raw, Self::Mem, Self::Obj, Self::Arena];
+
+    fn index(&self) -> usize {
+        match self {
+            Self::Raw => 0,
+            Self::Mem => 1,
+            Self::Obj => 2,
+            Self::Arena => 3,
+        }
+    }
+
+    /// Resolve the domain of a `PyMemAllocatorDomain` value.
+    ///
+    /// The values of the C enum and [Self::index()] agree.
+    fn from_raw(domain: u32) -> Self {
+        match domain {
+            0 => Self::Raw,
+            1 => Self::Mem,
+            2 => Self::Obj,
+            _ => panic!("unknown memory allocator domain {}", domain),
+        }
+    }
+}
+
+impl std::fmt::Display for AllocationDomain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Raw => "raw",
+            Self::Mem => "mem",
+            Self::Obj => "obj",
+            Self::Arena => "arena",
+        })
+    }
+}
+
+/// Allocation counters for a single [AllocationDomain].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DomainAllocationStats {
+    /// Bytes currently allocated.
+    pub current_bytes: usize,
+    /// Number of live allocations.
+    pub current_allocations: usize,
+    /// Number of allocations (including reallocations) performed so far.
+    pub total_allocations: u64,
+    /// Number of releases performed so far.
+    pub total_frees: u64,
+    /// Number of allocations refused because of the hard limit.
+    pub failed_allocations: u64,
+}
+
+/// A point-in-time snapshot of the statistics of a tracking allocator.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    /// Bytes currently allocated across all domains.
+    pub current_bytes: usize,
+    /// Highest value [Self::current_bytes] has reached.
+    pub peak_bytes: usize,
+    /// Counters for each domain, indexed in [AllocationDomain::ALL] order.
+    pub domains: [DomainAllocationStats; 4],
+    /// Histogram of requested allocation sizes.
+    ///
+    /// Bucket `i` counts requests of at most [Self::size_histogram_bound()]
+    /// bytes that didn't fit a lower bucket. The last bucket is unbounded.
+    pub size_histogram: [u64; SIZE_HISTOGRAM_BUCKETS],
+}
+
+impl AllocationStats {
+    /// Obtain counters for a single domain.
+    pub fn domain(&self, domain: AllocationDomain) -> &DomainAllocationStats {
+        &self.domains[domain.index()]
+    }
+
+    /// Inclusive upper bound of a [Self::size_histogram] bucket.
+    ///
+    /// Returns [None] for the last, unbounded bucket.
+    pub fn size_histogram_bound(bucket: usize) -> Option<usize> {
+        if bucket + 1 >= SIZE_HISTOGRAM_BUCKETS {
+            None
+        } else {
+            Some(SIZE_HISTOGRAM_MIN << bucket)
+        }
+    }
+
+    fn size_histogram_bucket(size: usize) -> usize {
+        (0..SIZE_HISTOGRAM_BUCKETS)
+            .find(|i| match Self::size_histogram_bound(*i) {
+                Some(bound) => size <= bound,
+                None => true,
+            })
+            .unwrap_or(SIZE_HISTOGRAM_BUCKETS - 1)
+    }
+
+    fn record_alloc(&mut self, domain: AllocationDomain, size: usize) {
+        let d = &mut self.domains[domain.index()];
+        d.current_bytes += size;
+        d.current_allocations += 1;
+        d.total_allocations += 1;
+
+        self.current_bytes += size;
+        self.peak_bytes = self.peak_bytes.max(self.current_bytes);
+        self.size_histogram[Self::size_histogram_bucket(size)] += 1;
+    }
+
+    fn record_realloc(&mut self, domain: AllocationDomain, old_size: usize, new_size: usize) {
+        let d = &mut self.domains[domain.index()];
+        d.current_bytes = d.current_bytes - old_size + new_size;
+        d.total_allocations += 1;
+
+        self.current_bytes = self.current_bytes - old_size + new_size;
+        self.peak_bytes = self.peak_bytes.max(self.current_bytes);
+        self.size_histogram[Self::size_histogram_bucket(new_size)] += 1;
+    }
+
+    fn record_free(&mut self, domain: AllocationDomain, size: usize) {
+        let d = &mut self.domains[domain.index()];
+        d.current_bytes -= size;
+        d.current_allocations -= 1;
+        d.total_frees += 1;
+
+        self.current_bytes -= size;
+    }
+}
+
+/// A callback invoked when the Python heap grows past the soft limit.
+///
+/// The callback receives a snapshot of the allocation statistics at the time
+/// the limit was crossed. It is called from within Python's allocator, possibly
+/// without the GIL held. It must not call into Python and should return
+/// quickly. Allocating memory from Rust is fine.
+pub type SoftLimitCallback = dyn Fn(&AllocationStats) + Send + Sync;
+
+/// An allocation that is still live in a tracking allocator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutstandingAllocation {
+    /// Memory address of the allocation.
+    pub address: usize,
+    /// Size of the allocation in bytes.
+    pub size: usize,
+    /// Domain the allocation was made from.
+    pub domain: AllocationDomain,
+}
+
+/// Mutable state of an [AllocationTracker].
+struct TrackerState {
+    allocations: HashMap<*mut c_void, (alloc::Layout, AllocationDomain)>,
+    stats: AllocationStats,
+    /// Maximum number of bytes that can be allocated.
+    hard_limit: Option<usize>,
+    /// Number of bytes above which `soft_limit_callback` fires.
+    soft_limit: Option<usize>,
+    soft_limit_callback: Option<Arc<SoftLimitCallback>>,
+    /// Whether the soft limit was crossed and the heap hasn't shrunk below it since.
+    soft_limit_exceeded: bool,
+}
+
+impl TrackerState {
+    /// Update soft limit state after the heap grew.
+    ///
+    /// Returns the callback to invoke if the soft limit was just crossed.
+    fn check_soft_limit(&mut self) -> Option<(Arc<SoftLimitCallback>, AllocationStats)> {
+        match self.soft_limit {
+            Some(limit) if !self.soft_limit_exceeded && self.stats.current_bytes > limit => {
+                self.soft_limit_exceeded = true;
+
+                self.soft_limit_callback
+                    .as_ref()
+                    .map(|cb| (cb.clone(), self.stats.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Re-arm the soft limit after the heap shrunk below it.
+    fn rearm_soft_limit(&mut self) {
+        if let Some(limit) = self.soft_limit {
+            if self.stats.current_bytes <= limit {
+                self.soft_limit_exceeded = false;
+            }
+        }
+    }
+}
+
 /// Tracks allocations from an allocator.
 ///
 /// Some allocators need to pass the original allocation size and alignment
@@ -112,6 +324,9 @@ const MIN_ALIGN: usize = 16;
 /// out-of-band. Essentially, we create an instance of this on the heap
 /// and store a pointer to it via the allocator "context" C structs.
 ///
+/// Since we're seeing every allocation anyway, we also maintain statistics
+/// about allocations per domain. See [AllocationStats].
+///
 /// The Python raw domain allocator doesn't hold the GIL. So operations
 /// against this data structure called from the context of a raw domain
 /// allocator must be thread safe.
@@ -121,7 +336,16 @@ const MIN_ALIGN: usize = 16;
 /// container operations, requiring a lock for each one. It would be better
 /// to have a RAII guard for scoped logical operation.
 struct AllocationTracker {
-    allocations: Mutex<HashMap<*mut c_void, alloc::Layout>>,
+    state: Mutex<TrackerState>,
+}
+
+/// The "context" handed to Python for a single allocator domain.
+///
+/// All domains of a tracking allocator share the same [AllocationTracker].
+/// The context records which domain an allocation function was called for.
+struct TrackerContext {
+    tracker: *mut AllocationTracker,
+    domain: AllocationDomain,
 }
 
 impl AllocationTracker {
@@ -130,43 +354,180 @@ impl AllocationTracker {
     /// It is automatically boxed because it needs to live on the heap.
     fn new() -> Box<Self> {
         Box::new(Self {
-            allocations: Mutex::new(HashMap::with_capacity(128)),
+            state: Mutex::new(TrackerState {
+                allocations: HashMap::with_capacity(128),
+                stats: AllocationStats::default(),
+                hard_limit: None,
+                soft_limit: None,
+                soft_limit_callback: None,
+                soft_limit_exceeded: false,
+            }),
         })
     }
 
-    /// Construct an instance from a pointer owned by someone else.
+    /// Construct an instance from a [TrackerContext] pointer owned by someone else.
     fn from_owned_ptr(ptr: *mut c_void) -> BorrowedAllocationTracker {
         if ptr.is_null() {
             panic!("must not pass NULL pointer");
         }
 
+        let context = unsafe { &*(ptr as *const TrackerContext) };
+
         BorrowedAllocationTracker {
-            inner: Some(unsafe { Box::from_raw(ptr as *mut AllocationTracker) }),
+            inner: Some(unsafe { Box::from_raw(context.tracker) }),
+            domain: context.domain,
         }
     }
 
     /// Obtain an allocation record in this tracker.
     #[inline]
     fn get_allocation(&self, ptr: *mut c_void) -> Option<alloc::Layout> {
-        self.allocations.lock().unwrap().get(&ptr).cloned()
+        self.state
+            .lock()
+            .unwrap()
+            .allocations
+            .get(&ptr)
+            .map(|(layout, _)| *layout)
+    }
+
+    /// Whether growing the heap by `additional` bytes stays within the hard limit.
+    ///
+    /// Refusals are counted against `domain`.
+    ///
+    /// The check and the subsequent allocation aren't atomic. Concurrent
+    /// allocations in the `raw` domain (which doesn't hold the GIL) can
+    /// overshoot the limit by the size of the allocations in flight.
+    #[inline]
+    fn within_hard_limit(&self, domain: AllocationDomain, additional: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        match state.hard_limit {
+            Some(limit) if state.stats.current_bytes.saturating_add(additional) > limit => {
+                state.stats.domains[domain.index()].failed_allocations += 1;
+                false
+            }
+            _ => true,
+        }
     }
 
     /// Record an allocation in this tracker.
     ///
     /// An existing allocation for the specified memory address will be replaced.
     #[inline]
-    fn insert_allocation(&mut self, ptr: *mut c_void, layout: alloc::Layout) {
-        self.allocations.lock().unwrap().insert(ptr, layout);
+    fn insert_allocation(
+        &mut self,
+        domain: AllocationDomain,
+        ptr: *mut c_void,
+        layout: alloc::Layout,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some((old, old_domain)) = state.allocations.insert(ptr, (layout, domain)) {
+            state.stats.record_free(old_domain, old.size());
+        }
+
+        state.stats.record_alloc(domain, layout.size());
+
+        let callback = state.check_soft_limit();
+
+        // The callback may take arbitrarily long or allocate itself. So don't
+        // hold the lock while running it.
+        drop(state);
+
+        if let Some((callback, stats)) = callback {
+            callback(&stats);
+        }
+    }
+
+    /// Move an allocation record after the memory was reallocated.
+    ///
+    /// Only the net change in size counts towards the limits: the soft limit
+    /// fires if the heap grew past it, but shrinking doesn't re-arm it.
+    #[inline]
+    fn reallocate_allocation(
+        &mut self,
+        old_ptr: *mut c_void,
+        new_ptr: *mut c_void,
+        layout: alloc::Layout,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        let (old_layout, domain) = state
+            .allocations
+            .remove(&old_ptr)
+            .expect("memory address not tracked");
+        state.allocations.insert(new_ptr, (layout, domain));
+        state
+            .stats
+            .record_realloc(domain, old_layout.size(), layout.size());
+
+        let callback = if layout.size() > old_layout.size() {
+            state.check_soft_limit()
+        } else {
+            None
+        };
+
+        drop(state);
+
+        if let Some((callback, stats)) = callback {
+            callback(&stats);
+        }
     }
 
     /// Remove an allocation from this tracker.
     #[inline]
     fn remove_allocation(&mut self, ptr: *mut c_void) -> alloc::Layout {
-        self.allocations
+        let mut state = self.state.lock().unwrap();
+
+        let (layout, domain) = state
+            .allocations
+            .remove(&ptr)
+            .expect("memory address not tracked");
+
+        state.stats.record_free(domain, layout.size());
+        state.rearm_soft_limit();
+
+        layout
+    }
+
+    /// Set the hard limit on the number of allocated bytes.
+    fn set_hard_limit(&self, limit: Option<usize>) {
+        self.state.lock().unwrap().hard_limit = limit;
+    }
+
+    /// Set the soft limit and the callback to invoke when it is crossed.
+    fn set_soft_limit(&self, limit: Option<usize>, callback: Option<Arc<SoftLimitCallback>>) {
+        let mut state = self.state.lock().unwrap();
+
+        state.soft_limit = limit;
+        state.soft_limit_callback = callback;
+        // Fire on the next allocation if we're already above the new limit.
+        state.soft_limit_exceeded = false;
+    }
+
+    /// Obtain a snapshot of allocation statistics.
+    fn stats(&self) -> AllocationStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    /// Obtain all live allocations, largest first.
+    fn outstanding_allocations(&self) -> Vec<OutstandingAllocation> {
+        let mut res = self
+            .state
             .lock()
             .unwrap()
-            .remove(&ptr)
-            .expect("memory address not tracked")
+            .allocations
+            .iter()
+            .map(|(ptr, (layout, domain))| OutstandingAllocation {
+                address: *ptr as usize,
+                size: layout.size(),
+                domain: *domain,
+            })
+            .collect::<Vec<_>>();
+
+        res.sort_by(|a, b| b.size.cmp(&a.size).then(a.address.cmp(&b.address)));
+
+        res
     }
 }
 
@@ -176,6 +537,8 @@ impl AllocationTracker {
 /// when they are dropped.
 struct BorrowedAllocationTracker {
     inner: Option<Box<AllocationTracker>>,
+    /// The domain the borrowing allocator function was called for.
+    domain: AllocationDomain,
 }
 
 impl Deref for BorrowedAllocationTracker {
@@ -200,9 +563,30 @@ impl Drop for BorrowedAllocationTracker {
 
 /// Represents an interface to Rust's memory allocator.
 pub(crate) struct TrackingAllocator {
-    pub allocator: pyffi::PyMemAllocatorEx,
+    /// Allocators for the `raw`, `mem` and `obj` domains, in that order.
+    pub allocators: [pyffi::PyMemAllocatorEx; 3],
     pub arena: pyffi::PyObjectArenaAllocator,
-    _state: Box<AllocationTracker>,
+    /// Contexts referenced by `allocators` and `arena`.
+    _contexts: Vec<Box<TrackerContext>>,
+    /// The tracker the contexts point to.
+    ///
+    /// This is a raw pointer obtained from [Box::into_raw()] and not a [Box]:
+    /// the allocator functions temporarily materialize a [Box] from it, which
+    /// must not alias a [Box] we hold.
+    state: *mut AllocationTracker,
+}
+
+impl TrackingAllocator {
+    /// Obtain the tracker shared by all domains.
+    fn tracker(&self) -> &AllocationTracker {
+        unsafe { &*self.state }
+    }
+}
+
+impl Drop for TrackingAllocator {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(self.state) });
+    }
 }
 
 extern "C" fn rust_malloc(ctx: *mut c_void, size: usize) -> *mut c_void {
@@ -212,11 +596,16 @@ extern "C" fn rust_malloc(ctx: *mut c_void, size: usize) -> *mut c_void {
     };
 
     let mut tracker = AllocationTracker::from_owned_ptr(ctx);
+    let domain = tracker.domain;
+
+    if !tracker.within_hard_limit(domain, size) {
+        return std::ptr::null_mut();
+    }
 
     let layout = unsafe { alloc::Layout::from_size_align_unchecked(size, MIN_ALIGN) };
     let res = unsafe { alloc::alloc(layout) } as *mut _;
 
-    tracker.insert_allocation(res, layout);
+    tracker.insert_allocation(domain, res, layout);
 
     res
 }
@@ -258,11 +647,16 @@ extern "C" fn rust_calloc(ctx: *mut c_void, nelem: usize, elsize: usize) -> *mut
     };
 
     let mut tracker = AllocationTracker::from_owned_ptr(ctx);
+    let domain = tracker.domain;
+
+    if !tracker.within_hard_limit(domain, size) {
+        return std::ptr::null_mut();
+    }
 
     let layout = unsafe { alloc::Layout::from_size_align_unchecked(size, MIN_ALIGN) };
     let res = unsafe { alloc::alloc_zeroed(layout) } as *mut _;
 
-    tracker.insert_allocation(res, layout);
+    tracker.insert_allocation(domain, res, layout);
 
     res
 }
@@ -308,14 +702,25 @@ extern "C" fn rust_realloc(ctx: *mut c_void, ptr: *mut c_void, new_size: usize)
     };
 
     let mut tracker = AllocationTracker::from_owned_ptr(ctx);
+    let domain = tracker.domain;
 
     let layout = unsafe { alloc::Layout::from_size_align_unchecked(new_size, MIN_ALIGN) };
 
-    let old_layout = tracker.remove_allocation(ptr);
+    let old_layout = tracker
+        .get_allocation(ptr)
+        .unwrap_or_else(|| panic!("could not find allocated memory record: {:?}", ptr));
 
-    let res = unsafe { alloc::realloc(ptr as *mut _, old_layout, new_size) } as *mut _;
+    // On failure, the original memory block must be left untouched.
+    if !tracker.within_hard_limit(domain, new_size.saturating_sub(old_layout.size())) {
+        return std::ptr::null_mut();
+    }
+
+    let res = unsafe { alloc::realloc(ptr as *mut _, old_layout, new_size) } as *mut c_void;
+    if res.is_null() {
+        return res;
+    }
 
-    tracker.insert_allocation(res, layout);
+    tracker.reallocate_allocation(ptr, res, layout);
 
     res
 }
@@ -473,14 +878,36 @@ pub struct PythonMemoryAllocator {
 impl PythonMemoryAllocator {
     /// Construct an instance from a `MemoryAllocatorBackend`.
     ///
-    /// Returns `None` if the backend shouldn't be defined.
-    pub fn from_backend(backend: MemoryAllocatorBackend) -> Option<Self> {
+    /// Returns `Ok(None)` for [MemoryAllocatorBackend::Default], which uses
+    /// Python's built-in allocators. Errors if the requested backend isn't
+    /// compiled into this build configuration.
+    pub fn from_backend(
+        backend: MemoryAllocatorBackend,
+    ) -> Result<Option<Self>, NewInterpreterError> {
         match backend {
-            MemoryAllocatorBackend::Default => None,
-            MemoryAllocatorBackend::Jemalloc => Some(Self::jemalloc()),
-            MemoryAllocatorBackend::Mimalloc => Some(Self::mimalloc()),
-            MemoryAllocatorBackend::Snmalloc => Some(Self::snmalloc()),
-            MemoryAllocatorBackend::Rust => Some(Self::rust()),
+            MemoryAllocatorBackend::Default => Ok(None),
+            #[cfg(feature = "jemalloc-sys")]
+            MemoryAllocatorBackend::Jemalloc => Ok(Some(Self::jemalloc())),
+            #[cfg(feature = "libmimalloc-sys")]
+            MemoryAllocatorBackend::Mimalloc => Ok(Some(Self::mimalloc())),
+            #[cfg(feature = "snmalloc-sys")]
+            MemoryAllocatorBackend::Snmalloc => Ok(Some(Self::snmalloc())),
+            MemoryAllocatorBackend::Rust => Ok(Some(Self::rust())),
+            #[allow(unreachable_patterns)]
+            _ => Err(NewInterpreterError::Dynamic(format!(
+                "{} allocator requested but it isn't compiled into this build configuration; try `cargo build --features allocator-{}`",
+                backend, backend
+            ))),
+        }
+    }
+
+    /// Whether a backend is compiled into this build configuration.
+    pub fn is_backend_available(backend: MemoryAllocatorBackend) -> bool {
+        match backend {
+            MemoryAllocatorBackend::Default | MemoryAllocatorBackend::Rust => true,
+            MemoryAllocatorBackend::Jemalloc => cfg!(feature = "jemalloc-sys"),
+            MemoryAllocatorBackend::Mimalloc => cfg!(feature = "libmimalloc-sys"),
+            MemoryAllocatorBackend::Snmalloc => cfg!(feature = "snmalloc-sys"),
         }
     }
 
@@ -506,11 +933,6 @@ impl PythonMemoryAllocator {
         }
     }
 
-    #[cfg(not(feature = "jemalloc-sys"))]
-    pub fn jemalloc() -> Self {
-        panic!("jemalloc allocator requested but it isn't compiled into this build configuration; try `cargo build --features allocator-jemalloc`");
-    }
-
     /// Construct a new instance using mimalloc.
     #[cfg(feature = "libmimalloc-sys")]
     pub fn mimalloc() -> Self {
@@ -533,35 +955,54 @@ impl PythonMemoryAllocator {
         }
     }
 
-    #[cfg(not(feature = "libmimalloc-sys"))]
-    pub fn mimalloc() -> Self {
-        panic!("mimalloc allocator requested but it isn't compiled into this build configuration; try `cargo build --features allocator-mimalloc`");
-    }
-
     /// Construct a new instance using Rust's global allocator.
     pub fn rust() -> Self {
-        // We temporarily convert the box to a raw pointer to workaround
-        // borrow issues.
+        // Ownership of the tracker is transferred to the instance below.
         let state = Box::into_raw(AllocationTracker::new());
 
-        let allocator = pyffi::PyMemAllocatorEx {
-            ctx: state as *mut c_void,
+        let mut contexts = AllocationDomain::ALL
+            .iter()
+            .map(|domain| {
+                Box::new(TrackerContext {
+                    tracker: state,
+                    domain: *domain,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // The boxes keep their addresses when moved into the instance below.
+        let context_ptrs = contexts
+            .iter_mut()
+            .map(|context| context.as_mut() as *mut TrackerContext as *mut c_void)
+            .collect::<Vec<_>>();
+
+        let allocator = |domain: AllocationDomain| pyffi::PyMemAllocatorEx {
+            ctx: context_ptrs[domain.index()],
             malloc: Some(rust_malloc),
             calloc: Some(rust_calloc),
             realloc: Some(rust_realloc),
             free: Some(rust_free),
         };
 
+        let allocators = [
+            allocator(AllocationDomain::Raw),
+            allocator(AllocationDomain::Mem),
+            allocator(AllocationDomain::Obj),
+        ];
+
+        let arena = pyffi::PyObjectArenaAllocator {
+            ctx: context_ptrs[AllocationDomain::Arena.index()],
+            alloc: Some(rust_malloc),
+            free: Some(rust_arena_free),
+        };
+
         Self {
             backend: MemoryAllocatorBackend::Rust,
             instance: AllocatorInstance::Tracking(TrackingAllocator {
-                allocator,
-                arena: pyffi::PyObjectArenaAllocator {
-                    ctx: state as *mut c_void,
-                    alloc: Some(rust_malloc),
-                    free: Some(rust_arena_free),
-                },
-                _state: unsafe { Box::from_raw(state) },
+                allocators,
+                arena,
+                _contexts: contexts,
+                state,
             }),
         }
     }
@@ -588,11 +1029,6 @@ impl PythonMemoryAllocator {
         }
     }
 
-    #[cfg(not(feature = "snmalloc-sys"))]
-    pub fn snmalloc() -> Self {
-        panic!("snmalloc allocator requested but it isn't compiled into this build configuration; try `cargo build --features allocator-snmalloc`");
-    }
-
     /// Obtain the backend used for this instance.
     #[allow(unused)]
     pub fn backend(&self) -> MemoryAllocatorBackend {
@@ -604,8 +1040,115 @@ impl PythonMemoryAllocator {
     /// This should be called before `Py_Initialize*()`.
     pub fn set_allocator(&self, domain: pyffi::PyMemAllocatorDomain) {
         unsafe {
-            pyffi::PyMem_SetAllocator(domain, self.as_memory_allocator() as *mut _);
+            pyffi::PyMem_SetAllocator(
+                domain,
+                self.as_memory_allocator(AllocationDomain::from_raw(domain as u32)) as *mut _,
+            );
+        }
+    }
+
+    /// Obtain a snapshot of allocation statistics.
+    ///
+    /// Returns [None] if this allocator doesn't track allocations. Only the
+    /// Rust backend tracks allocations.
+    pub fn stats(&self) -> Option<AllocationStats> {
+        match &self.instance {
+            AllocatorInstance::Simple(..) => None,
+            AllocatorInstance::Tracking(alloc) => Some(alloc.tracker().stats()),
+        }
+    }
+
+    /// Set the maximum number of bytes the Python heap may occupy.
+    ///
+    /// Allocations that would grow the heap beyond `limit` fail, which Python
+    /// surfaces as `MemoryError`. [None] removes the limit.
+    ///
+    /// Returns [false] if this allocator doesn't track allocations and
+    /// therefore can't enforce limits.
+    pub fn set_hard_limit(&self, limit: Option<usize>) -> bool {
+        match &self.instance {
+            AllocatorInstance::Simple(..) => false,
+            AllocatorInstance::Tracking(alloc) => {
+                alloc.tracker().set_hard_limit(limit);
+                true
+            }
+        }
+    }
+
+    /// Set a threshold on the Python heap size that invokes a callback when crossed.
+    ///
+    /// `callback` fires once each time the heap grows past `limit`. It is
+    /// re-armed once the heap shrinks back to `limit` or below. See
+    /// [SoftLimitCallback] for restrictions on what the callback may do.
+    /// [None] removes the limit.
+    ///
+    /// Returns [false] if this allocator doesn't track allocations.
+    pub fn set_soft_limit<F>(&self, limit: Option<usize>, callback: F) -> bool
+    where
+        F: Fn(&AllocationStats) + Send + Sync + 'static,
+    {
+        match &self.instance {
+            AllocatorInstance::Simple(..) => false,
+            AllocatorInstance::Tracking(alloc) => {
+                alloc
+                    .tracker()
+                    .set_soft_limit(limit, Some(Arc::new(callback)));
+                true
+            }
+        }
+    }
+
+    /// Obtain the allocations that are currently live, largest first.
+    ///
+    /// Returns [None] if this allocator doesn't track allocations.
+    pub fn outstanding_allocations(&self) -> Option<Vec<OutstandingAllocation>> {
+        match &self.instance {
+            AllocatorInstance::Simple(..) => None,
+            AllocatorInstance::Tracking(alloc) => Some(alloc.tracker().outstanding_allocations()),
+        }
+    }
+
+    /// Render a human readable report of outstanding allocations.
+    ///
+    /// At most `limit` individual allocations are listed. Returns [None] if
+    /// this allocator doesn't track allocations or nothing is outstanding.
+    pub fn leak_report(&self, limit: usize) -> Option<String> {
+        let stats = self.stats()?;
+        let outstanding = self.outstanding_allocations()?;
+
+        if outstanding.is_empty() {
+            return None;
+        }
+
+        let mut report = format!(
+            "{} bytes in {} allocations outstanding (peak {} bytes)\n",
+            stats.current_bytes,
+            outstanding.len(),
+            stats.peak_bytes
+        );
+
+        for domain in AllocationDomain::ALL {
+            let d = stats.domain(domain);
+            if d.current_allocations > 0 {
+                report.push_str(&format!(
+                    "  {}: {} bytes in {} allocations\n",
+                    domain, d.current_bytes, d.current_allocations
+                ));
+            }
         }
+
+        for allocation in outstanding.iter().take(limit) {
+            report.push_str(&format!(
+                "  {:#x}: {} bytes ({})\n",
+                allocation.address, allocation.size, allocation.domain
+            ));
+        }
+
+        if outstanding.len() > limit {
+            report.push_str(&format!("  ... and {} more\n", outstanding.len() - limit));
+        }
+
+        Some(report)
     }
 
     /// Set the arena allocator used by the `pymalloc` allocator.
@@ -618,10 +1161,15 @@ impl PythonMemoryAllocator {
     }
 
     /// Obtain the pointer to the `PyMemAllocatorEx` for this allocator.
-    fn as_memory_allocator(&self) -> *const pyffi::PyMemAllocatorEx {
+    fn as_memory_allocator(&self, domain: AllocationDomain) -> *const pyffi::PyMemAllocatorEx {
         match &self.instance {
             AllocatorInstance::Simple(alloc, _) => alloc as *const _,
-            AllocatorInstance::Tracking(alloc) => &alloc.allocator as *const _,
+            AllocatorInstance::Tracking(alloc) => match domain {
+                AllocationDomain::Raw => &alloc.allocators[0] as *const _,
+                AllocationDomain::Mem => &alloc.allocators[1] as *const _,
+                AllocationDomain::Obj => &alloc.allocators[2] as *const _,
+                AllocationDomain::Arena => panic!("arena is not a memory allocator domain"),
+            },
         }
     }
 
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Forwarding of Python logging and warnings to the Rust `log` crate.
+
+Python code reports diagnostics through the `logging` and `warnings`
+modules. By default, both write to `sys.stderr`, bypassing whatever logger the
+embedding application installed.
+
+When enabled via [crate::OxidizedPythonInterpreterConfig::log_forwarding], we
+install a `logging.Handler` on the root logger and replace
+`warnings.showwarning` so records are emitted through the `log` crate instead.
+The Python logger name (e.g. `pyrocko.io`) becomes the `log` target. Warnings
+use the target `py.warnings`, mirroring `logging.captureWarnings()`.
+
+Python levels are mapped to `log` levels as follows:
+
+| Python              | Rust    |
+|---------------------|---------|
+| `CRITICAL`, `ERROR` | `Error` |
+| `WARNING`           | `Warn`  |
+| `INFO`              | `Info`  |
+| `DEBUG`             | `Debug` |
+| anything lower      | `Trace` |
+*/
+
+use pyo3::{prelude::*, types::PyDict};
+
+/// Python source installing the forwarding handler and warnings hook.
+///
+/// Evaluated with `_emit` (the Rust sink) and `_level` (the Python level
+/// corresponding to the `log` crate's max level) in globals.
+const INSTALL_SOURCE: &str = r#"
+import logging
+import warnings
+
+
+class RustLogHandler(logging.Handler):
+    """Forwards log records to the Rust `log` crate."""
+
+    def emit(self, record):
+        try:
+            _emit(record.levelno, record.name, self.format(record))
+        except Exception:
+            self.handleError(record)
+
+
+_original_showwarning = warnings.showwarning
+
+
+def _showwarning(message, category, filename, lineno, file=None, line=None):
+    # Explicit destinations are honored, like logging.captureWarnings() does.
+    if file is not None:
+        _original_showwarning(message, category, filename, lineno, file, line)
+        return
+
+    text = warnings.formatwarning(message, category, filename, lineno, line)
+    _emit(logging.WARNING, "py.warnings", text.rstrip())
+
+
+_root = logging.getLogger()
+_root.addHandler(RustLogHandler())
+_root.setLevel(_level)
+warnings.showwarning = _showwarning
+"#;
+
+/// Convert a Python logging level to a `log` level.
+pub(crate) fn python_level_to_log(level: i32) -> log::Level {
+    match level {
+        l if l >= 40 => log::Level::Error,
+        l if l >= 30 => log::Level::Warn,
+        l if l >= 20 => log::Level::Info,
+        l if l >= 10 => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+/// Convert a `log` level filter to the Python logging level letting the same records through.
+fn log_filter_to_python(filter: log::LevelFilter) -> i32 {
+    match filter {
+        // Above CRITICAL, so nothing is emitted.
+        log::LevelFilter::Off => 100,
+        log::LevelFilter::Error => 40,
+        log::LevelFilter::Warn => 30,
+        log::LevelFilter::Info => 20,
+        log::LevelFilter::Debug => 10,
+        log::LevelFilter::Trace => 1,
+    }
+}
+
+/// Emit a Python log record through the `log` crate.
+#[pyfunction]
+fn emit(level: i32, target: &str, message: &str) {
+    log::log!(target: target, python_level_to_log(level), "{}", message);
+}
+
+/// Install Python logging and warnings forwarding in the current interpreter.
+///
+/// The root logger level is derived from [log::max_level()] at the time of
+/// the call, so records that would be discarded by Rust aren't formatted by
+/// Python in the first place.
+pub(crate) fn install_log_forwarding(py: Python) -> PyResult<()> {
+    let globals = PyDict::new_bound(py);
+    globals.set_item("__name__", "_pyembed_log")?;
+    globals.set_item("_emit", wrap_pyfunction_bound!(emit, py)?)?;
+    globals.set_item("_level", log_filter_to_python(log::max_level()))?;
+
+    py.run_bound(INSTALL_SOURCE, Some(&globals), None)
+}
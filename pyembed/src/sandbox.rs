@@ -0,0 +1,191 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Restricting where Python modules are imported from.
+
+An [ImportSandbox] makes the interpreter run isolated from its environment:
+`PYTHON*` environment variables, the user site-packages directory and the
+current directory are ignored. `sys.path` is limited to directories below
+the allowed paths.
+
+Since Python code can still extend `sys.path` or load modules by path, an
+audit hook (see `sys.addaudithook`) additionally checks the files modules
+are loaded from. Source and bytecode files are recognized by `open` events
+for `.py` and `.pyc` files issued by importlib's path based loaders,
+extension modules by `import` events. Other code may still read Python files,
+e.g. `linecache` for tracebacks. Modules imported from memory by
+`OxidizedFinder` have no file and are always allowed.
+
+Paths are compared lexically, so the allowed paths should be absolute and
+free of symlinks.
+*/
+
+use {
+    pyo3::{
+        exceptions::PyImportError,
+        prelude::*,
+        types::{PyCFunction, PyString, PyTuple},
+    },
+    std::path::{Path, PathBuf},
+};
+
+/// Filename of the frozen module implementing path based imports.
+const IMPORTER_FILENAME: &str = "<frozen importlib._bootstrap_external>";
+
+/// What to do when a module is imported from outside the allowed paths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SandboxViolationAction {
+    /// Log a warning and let the import proceed.
+    Log,
+    /// Fail the import with an `ImportError`.
+    Block,
+}
+
+/// Restricts module imports to a set of directories.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportSandbox {
+    /// Directories modules may be imported from, including subdirectories.
+    ///
+    /// The token `$ORIGIN` is expanded when the interpreter config is resolved.
+    pub allowed_paths: Vec<PathBuf>,
+
+    /// What to do about imports from elsewhere.
+    pub violation_action: SandboxViolationAction,
+}
+
+impl ImportSandbox {
+    /// Whether modules may be imported from `path`.
+    pub fn is_allowed(&self, path: &Path) -> bool {
+        self.allowed_paths
+            .iter()
+            .any(|allowed| path.starts_with(allowed))
+    }
+
+    /// The entries of `paths` below the allowed paths.
+    ///
+    /// Without `paths`, Python would compute search paths from its
+    /// installation prefix. The allowed paths are used instead.
+    pub(crate) fn filter_search_paths(&self, paths: Option<Vec<PathBuf>>) -> Vec<PathBuf> {
+        let Some(paths) = paths else {
+            return self.allowed_paths.clone();
+        };
+
+        paths
+            .into_iter()
+            .filter(|path| {
+                let allowed = self.is_allowed(path);
+                if !allowed {
+                    log::warn!(
+                        "removing {} from module search paths: outside the import sandbox",
+                        path.display()
+                    );
+                }
+                allowed
+            })
+            .collect()
+    }
+
+    /// Whether the running Python code is importlib's path based import
+    /// machinery, which reads the source and bytecode files of modules.
+    fn in_importer(py: Python<'_>) -> bool {
+        let in_importer = || -> PyResult<bool> {
+            // With no Python frame of its own, the audit hook sees the code
+            // raising the event as the innermost frame.
+            let frame = py.import_bound("sys")?.call_method1("_getframe", (0,))?;
+            let filename = frame.getattr("f_code")?.getattr("co_filename")?;
+
+            Ok(filename.downcast::<PyString>()?.to_cow()? == IMPORTER_FILENAME)
+        };
+
+        in_importer().unwrap_or(false)
+    }
+
+    /// The file a module is loaded from, if the audit event is about one.
+    fn module_file(event: &str, args: &Bound<'_, PyTuple>) -> Option<PathBuf> {
+        let path = match event {
+            // Extension modules: (module, filename, ...). The filename is None
+            // when the import starts and set when an extension is loaded.
+            "import" => args.get_item(1).ok()?,
+            // Source and bytecode files: (path, mode, flags).
+            "open" => {
+                let mode = args.get_item(1).ok()?;
+                let reading = mode.is_none()
+                    || mode
+                        .downcast::<PyString>()
+                        .ok()?
+                        .to_cow()
+                        .ok()?
+                        .starts_with('r');
+                if !reading {
+                    return None;
+                }
+                args.get_item(0).ok()?
+            }
+            _ => return None,
+        };
+
+        let path = path.downcast::<PyString>().ok()?.to_cow().ok()?;
+        if event == "open" {
+            let module_file = path.ends_with(".py") || path.ends_with(".pyc");
+            if !module_file || !Self::in_importer(args.py()) {
+                return None;
+            }
+        }
+
+        let path = PathBuf::from(path.as_ref());
+        if path.is_absolute() {
+            Some(path)
+        } else {
+            std::env::current_dir().ok().map(|cwd| cwd.join(path))
+        }
+    }
+
+    fn check_audit_event(&self, event: &str, args: &Bound<'_, PyTuple>) -> PyResult<()> {
+        let Some(path) = Self::module_file(event, args) else {
+            return Ok(());
+        };
+        if self.is_allowed(&path) {
+            return Ok(());
+        }
+
+        match self.violation_action {
+            SandboxViolationAction::Log => {
+                log::warn!(
+                    "importing from {}, which is outside the import sandbox",
+                    path.display()
+                );
+                Ok(())
+            }
+            SandboxViolationAction::Block => Err(PyImportError::new_err(format!(
+                "importing from {} is not allowed: outside the import sandbox",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Install an audit hook enforcing `sandbox` in the interpreter.
+///
+/// Audit hooks can't be removed, so this lasts until the interpreter is
+/// finalized.
+pub(crate) fn install_audit_hook(py: Python, sandbox: &ImportSandbox) -> PyResult<()> {
+    let sandbox = sandbox.clone();
+    let hook = PyCFunction::new_closure_bound(
+        py,
+        Some("pyembed_import_sandbox\0"),
+        None,
+        move |args: &Bound<'_, PyTuple>, _kwargs| -> PyResult<()> {
+            let event = args.get_item(0)?;
+            let event = event.downcast::<PyString>()?.to_cow()?;
+            let event_args = args.get_item(1)?;
+
+            sandbox.check_audit_event(&event, event_args.downcast::<PyTuple>()?)
+        },
+    )?;
+
+    py.import_bound("sys")?
+        .call_method1("addaudithook", (hook,))?;
+
+    Ok(())
+}
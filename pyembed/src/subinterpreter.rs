@@ -0,0 +1,306 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Isolated sub-interpreters.
+
+A sub-interpreter is an additional Python interpreter living in the same
+process as the main interpreter. Each sub-interpreter has its own
+`sys.modules`, `sys.path`, builtins and module state. So a plugin running in
+one sub-interpreter cannot observe or corrupt the state of another.
+
+Since Python 3.12 (PEP 684), a sub-interpreter can also own its GIL. Code
+running in sub-interpreters with their own GIL can execute truly in
+parallel on different OS threads.
+
+Sub-interpreters are created from a running [crate::MainPythonInterpreter]
+via [crate::MainPythonInterpreter::new_sub_interpreter()]. The returned
+[SubInterpreter] borrows the main interpreter, which guarantees that it is
+ended before the main interpreter is finalized.
+
+This module is only available when building against the full (non-`abi3`)
+API of Python 3.12 or newer.
+
+PyO3 doesn't support running code in sub-interpreters: its GIL handling and
+caches always refer to the main interpreter. So sub-interpreters are driven
+through the Python C API only and code is passed to them as source.
+
+Not every extension module supports being loaded into multiple interpreters.
+With [SubInterpreterConfig::check_multi_interp_extensions] enabled (the
+default), importing a single-phase init extension module raises an
+`ImportError` instead of silently sharing state between interpreters.
+*/
+
+use {
+    crate::error::NewInterpreterError,
+    pyo3::ffi as pyffi,
+    std::{ffi::CString, marker::PhantomData, os::raw::c_int},
+};
+
+extern "C" {
+    // Not declared by pyo3-ffi yet.
+    fn Py_NewInterpreterFromConfig(
+        tstate_p: *mut *mut pyffi::PyThreadState,
+        config: *const pyffi::PyInterpreterConfig,
+    ) -> pyffi::PyStatus;
+}
+
+/// Configuration for a [SubInterpreter].
+///
+/// This is a Rust representation of the `PyInterpreterConfig` C struct.
+/// See <https://docs.python.org/3/c-api/init.html#c.PyInterpreterConfig>
+/// for the canonical documentation of each field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubInterpreterConfig {
+    /// Whether the sub-interpreter has its own GIL.
+    ///
+    /// If [true], the sub-interpreter can run Python code in parallel with
+    /// the main interpreter and other sub-interpreters. This requires
+    /// [Self::use_main_obmalloc] to be [false].
+    ///
+    /// Default value: [true]
+    pub own_gil: bool,
+
+    /// Whether the sub-interpreter shares the main interpreter's object allocator state.
+    ///
+    /// Default value: [false]
+    pub use_main_obmalloc: bool,
+
+    /// Whether `os.fork()` is allowed in the sub-interpreter.
+    ///
+    /// Default value: [false]
+    pub allow_fork: bool,
+
+    /// Whether `os.execv()` and friends are allowed in the sub-interpreter.
+    ///
+    /// Default value: [false]
+    pub allow_exec: bool,
+
+    /// Whether the `threading` module can start threads in the sub-interpreter.
+    ///
+    /// Default value: [true]
+    pub allow_threads: bool,
+
+    /// Whether daemon threads can be started in the sub-interpreter.
+    ///
+    /// Default value: [false]
+    pub allow_daemon_threads: bool,
+
+    /// Whether to refuse importing extension modules that don't support multiple interpreters.
+    ///
+    /// Default value: [true]
+    pub check_multi_interp_extensions: bool,
+}
+
+impl Default for SubInterpreterConfig {
+    /// Isolated defaults, mirroring `_PyInterpreterConfig_INIT`.
+    fn default() -> Self {
+        Self {
+            own_gil: true,
+            use_main_obmalloc: false,
+            allow_fork: false,
+            allow_exec: false,
+            allow_threads: true,
+            allow_daemon_threads: false,
+            check_multi_interp_extensions: true,
+        }
+    }
+}
+
+impl SubInterpreterConfig {
+    /// Legacy defaults, mirroring `_PyInterpreterConfig_LEGACY_INIT`.
+    ///
+    /// This is the behavior of `Py_NewInterpreter()`: the GIL and allocator
+    /// state are shared with the main interpreter, so code does not run in
+    /// parallel. But single-phase init extension modules can be imported.
+    pub fn legacy() -> Self {
+        Self {
+            own_gil: false,
+            use_main_obmalloc: true,
+            allow_fork: true,
+            allow_exec: true,
+            allow_threads: true,
+            allow_daemon_threads: true,
+            check_multi_interp_extensions: false,
+        }
+    }
+}
+
+impl TryFrom<&SubInterpreterConfig> for pyffi::PyInterpreterConfig {
+    type Error = NewInterpreterError;
+
+    fn try_from(value: &SubInterpreterConfig) -> Result<Self, Self::Error> {
+        if value.own_gil && value.use_main_obmalloc {
+            return Err(NewInterpreterError::Simple(
+                "a sub-interpreter with its own GIL cannot use the main obmalloc state",
+            ));
+        }
+
+        if !value.use_main_obmalloc && !value.check_multi_interp_extensions {
+            return Err(NewInterpreterError::Simple(
+                "a sub-interpreter with its own obmalloc state must check multi-interpreter extensions",
+            ));
+        }
+
+        let flag = |v: bool| -> c_int {
+            if v {
+                1
+            } else {
+                0
+            }
+        };
+
+        Ok(pyffi::PyInterpreterConfig {
+            use_main_obmalloc: flag(value.use_main_obmalloc),
+            allow_fork: flag(value.allow_fork),
+            allow_exec: flag(value.allow_exec),
+            allow_threads: flag(value.allow_threads),
+            allow_daemon_threads: flag(value.allow_daemon_threads),
+            check_multi_interp_extensions: flag(value.check_multi_interp_extensions),
+            gil: if value.own_gil {
+                pyffi::PyInterpreterConfig_OWN_GIL
+            } else {
+                pyffi::PyInterpreterConfig_SHARED_GIL
+            },
+        })
+    }
+}
+
+/// An isolated Python sub-interpreter.
+///
+/// Instances are obtained from [crate::MainPythonInterpreter::new_sub_interpreter()].
+/// The sub-interpreter is ended via `Py_EndInterpreter()` when the instance
+/// is dropped.
+///
+/// Instances are [Send], so a sub-interpreter can be moved to a dedicated
+/// worker thread. They are not [Sync]: only a single thread at a time may
+/// run code in a given sub-interpreter through this handle.
+pub struct SubInterpreter<'main> {
+    /// The interpreter state of the sub-interpreter.
+    interp: *mut pyffi::PyInterpreterState,
+
+    /// Configuration the sub-interpreter was created with.
+    config: SubInterpreterConfig,
+
+    /// Ties the lifetime of the sub-interpreter to the main interpreter.
+    _main: PhantomData<&'main ()>,
+}
+
+// The raw interpreter state pointer is only dereferenced by CPython while we
+// hold a thread state bound to the current OS thread. So moving the handle
+// across threads is safe.
+unsafe impl<'main> Send for SubInterpreter<'main> {}
+
+impl<'main> SubInterpreter<'main> {
+    /// Create a new sub-interpreter.
+    ///
+    /// The main interpreter must be initialized. The main interpreter's GIL
+    /// must not be held by the calling thread.
+    pub(crate) fn new(config: SubInterpreterConfig) -> Result<Self, NewInterpreterError> {
+        let py_config = pyffi::PyInterpreterConfig::try_from(&config)?;
+
+        unsafe {
+            let gil_state = pyffi::PyGILState_Ensure();
+            let main_tstate = pyffi::PyThreadState_Get();
+
+            let mut tstate: *mut pyffi::PyThreadState = std::ptr::null_mut();
+            let status = Py_NewInterpreterFromConfig(&mut tstate, &py_config);
+
+            if pyffi::PyStatus_Exception(status) != 0 {
+                // On failure the main thread state is restored by CPython.
+                pyffi::PyGILState_Release(gil_state);
+
+                return Err(NewInterpreterError::new_from_pystatus(
+                    &status,
+                    "creating sub-interpreter",
+                ));
+            }
+
+            // The new thread state is current. Obtain its interpreter and then
+            // discard the thread state: we create a fresh thread state for
+            // whichever thread ends up running code in this interpreter.
+            let interp = pyffi::PyInterpreterState_Get();
+            pyffi::PyThreadState_Clear(tstate);
+            pyffi::PyThreadState_Swap(main_tstate);
+            pyffi::PyThreadState_Delete(tstate);
+
+            pyffi::PyGILState_Release(gil_state);
+
+            Ok(Self {
+                interp,
+                config,
+                _main: PhantomData,
+            })
+        }
+    }
+
+    /// The configuration this sub-interpreter was created with.
+    pub fn config(&self) -> &SubInterpreterConfig {
+        &self.config
+    }
+
+    /// The unique ID CPython assigned to this sub-interpreter.
+    pub fn id(&self) -> i64 {
+        unsafe { pyffi::PyInterpreterState_GetID(self.interp) }
+    }
+
+    /// Run a function with a thread state of this sub-interpreter.
+    ///
+    /// A thread state for the current OS thread is created for the duration of
+    /// the call and the sub-interpreter's GIL is held. The calling thread must
+    /// not hold the GIL of any other interpreter.
+    fn with_thread_state<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        unsafe {
+            let tstate = pyffi::PyThreadState_New(self.interp);
+            pyffi::PyEval_RestoreThread(tstate);
+
+            let res = f();
+
+            pyffi::PyThreadState_Clear(tstate);
+            pyffi::PyThreadState_DeleteCurrent();
+
+            res
+        }
+    }
+
+    /// Run Python source code in the `__main__` module of this sub-interpreter.
+    ///
+    /// Uncaught exceptions are printed to `sys.stderr` and result in an error.
+    ///
+    /// The calling thread must not hold the GIL of any other interpreter.
+    /// Notably, this must not be called from within
+    /// [crate::MainPythonInterpreter::with_gil()].
+    pub fn run(&mut self, code: &str) -> Result<(), NewInterpreterError> {
+        let code = CString::new(code)
+            .map_err(|_| NewInterpreterError::Simple("code must not contain NUL bytes"))?;
+
+        match self.with_thread_state(|| unsafe { pyffi::PyRun_SimpleString(code.as_ptr()) }) {
+            0 => Ok(()),
+            _ => Err(NewInterpreterError::Dynamic(format!(
+                "uncaught exception in sub-interpreter {}",
+                self.id()
+            ))),
+        }
+    }
+}
+
+impl<'main> Drop for SubInterpreter<'main> {
+    fn drop(&mut self) {
+        // The main interpreter may have been finalized out-of-band, in which
+        // case all sub-interpreters are already gone.
+        if unsafe { pyffi::Py_IsInitialized() } == 0 {
+            return;
+        }
+
+        unsafe {
+            let tstate = pyffi::PyThreadState_New(self.interp);
+            pyffi::PyEval_RestoreThread(tstate);
+            // Py_EndInterpreter() releases the sub-interpreter's GIL and leaves
+            // no current thread state.
+            pyffi::Py_EndInterpreter(tstate);
+        }
+    }
+}
@@ -3,11 +3,12 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    super::{default_interpreter_config, run_py_test},
-    crate::MainPythonInterpreter,
+    super::{default_interpreter_config, run_py_test, write_packed_resources},
+    crate::{packed_resources::Resource, MainPythonInterpreter, PackedResourcesSource},
     anyhow::Result,
     pyo3::prelude::*,
     rusty_fork::rusty_fork_test,
+    std::{borrow::Cow, collections::HashMap},
 };
 
 fn new_interpreter<'interpreter, 'resources>(
@@ -19,6 +20,60 @@ fn new_interpreter<'interpreter, 'resources>(
     Ok(interp)
 }
 
+/// Create an interpreter importing from packed resources describing a small project.
+fn new_interpreter_with_resources<'interpreter>(
+) -> Result<MainPythonInterpreter<'interpreter, 'static>> {
+    let files = |entries: &[(&'static str, &'static [u8])]| {
+        Some(
+            entries
+                .iter()
+                .map(|(k, v)| (Cow::Borrowed(*k), Cow::Borrowed(*v)))
+                .collect::<HashMap<_, _>>(),
+        )
+    };
+
+    let resources = vec![
+        Resource {
+            name: Cow::Borrowed("packed_pkg"),
+            is_package: true,
+            in_memory_source: Some(Cow::Borrowed(b"VALUE = 'package'\n")),
+            in_memory_package_resources: files(&[
+                ("data.txt", b"hello"),
+                ("sub/nested.bin", b"\x00\x01"),
+            ]),
+            ..Default::default()
+        },
+        Resource {
+            name: Cow::Borrowed("packed_pkg.child"),
+            in_memory_source: Some(Cow::Borrowed(
+                b"from . import VALUE as PARENT\nVALUE = 'child'\n",
+            )),
+            ..Default::default()
+        },
+        Resource {
+            name: Cow::Borrowed("packed_module"),
+            in_memory_source: Some(Cow::Borrowed(b"import sys\nVALUE = 42\n")),
+            ..Default::default()
+        },
+        Resource {
+            name: Cow::Borrowed("packed-dist"),
+            in_memory_distribution_resources: files(&[(
+                "METADATA",
+                b"Metadata-Version: 2.1\nName: packed-dist\nVersion: 1.2.3\n",
+            )]),
+            ..Default::default()
+        },
+    ];
+
+    let data: &'static [u8] = Box::leak(write_packed_resources(&resources).into_boxed_slice());
+
+    let mut config = default_interpreter_config();
+    config.oxidized_importer = true;
+    config.packed_resources = vec![PackedResourcesSource::Memory(data)];
+
+    Ok(MainPythonInterpreter::new(config)?)
+}
+
 fn get_importer(interp: &MainPythonInterpreter) -> Result<PyObject> {
     interp.with_gil(|py| {
         let sys = py.import("sys").unwrap();
@@ -77,6 +132,113 @@ rusty_fork_test! {
         });
     }
 
+    /// Modules and packages are imported from packed resources.
+    #[test]
+    fn packed_resources_import() {
+        let interp = new_interpreter_with_resources().unwrap();
+
+        interp.with_gil(|py| {
+            py.run_bound(
+                r#"
+import packed_module
+import packed_pkg.child
+
+assert packed_module.VALUE == 42
+assert packed_pkg.VALUE == "package"
+assert packed_pkg.child.VALUE == "child"
+assert packed_pkg.child.PARENT == "package"
+assert type(packed_pkg.__loader__).__name__ == "OxidizedFinder"
+assert packed_pkg.__spec__.submodule_search_locations
+
+try:
+    import packed_pkg.missing
+except ImportError:
+    pass
+else:
+    raise AssertionError("ImportError not raised")
+"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    /// importlib.resources reads package resources from packed resources.
+    #[test]
+    fn packed_resources_package_resources() {
+        let interp = new_interpreter_with_resources().unwrap();
+
+        interp.with_gil(|py| {
+            py.run_bound(
+                r#"
+import importlib.resources
+
+files = importlib.resources.files("packed_pkg")
+assert (files / "data.txt").read_bytes() == b"hello"
+assert (files / "data.txt").read_text() == "hello"
+assert (files / "sub").is_dir()
+assert (files / "sub" / "nested.bin").read_bytes() == b"\x00\x01"
+assert sorted(p.name for p in files.iterdir()) == ["data.txt", "sub"]
+assert not (files / "missing.txt").is_file()
+"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    /// importlib.metadata finds distributions in packed resources.
+    #[test]
+    fn packed_resources_metadata() {
+        let interp = new_interpreter_with_resources().unwrap();
+
+        interp.with_gil(|py| {
+            py.run_bound(
+                r#"
+import importlib.metadata
+
+assert importlib.metadata.version("packed-dist") == "1.2.3"
+assert importlib.metadata.version("Packed.Dist") == "1.2.3"
+"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    /// pkgutil.iter_modules() enumerates modules in packed resources.
+    #[test]
+    fn packed_resources_iter_modules() {
+        let interp = new_interpreter_with_resources().unwrap();
+
+        interp.with_gil(|py| {
+            py.run_bound(
+                r#"
+import pkgutil
+import packed_pkg
+
+assert [m.name for m in pkgutil.iter_modules(packed_pkg.__path__)] == ["child"]
+"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    /// Invalid packed resources data fails interpreter creation.
+    #[test]
+    fn packed_resources_invalid() {
+        let mut config = default_interpreter_config();
+        config.oxidized_importer = true;
+        config.packed_resources = vec![PackedResourcesSource::Memory(b"garbage")];
+
+        assert!(MainPythonInterpreter::new(config).is_err());
+    }
+
     /// Run test_importer_builtins.py.
     #[test]
     fn builtins_py() {
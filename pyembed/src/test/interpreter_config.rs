@@ -4,7 +4,7 @@
 
 use {
     super::{default_interpreter_config, set_sys_paths, PYTHON_INTERPRETER_PATH},
-    crate::{MainPythonInterpreter, OxidizedPythonInterpreterConfig},
+    crate::{AllocationDomain, MainPythonInterpreter, OxidizedPythonInterpreterConfig},
     pyo3::{
         ffi as pyffi,
         prelude::*,
@@ -99,6 +99,27 @@ fn assert_importer(oxidized: bool, filesystem: bool) {
     });
 }
 
+/// Run allocation heavy Python code to exercise a custom allocator.
+fn exercise_allocator(interp: &MainPythonInterpreter) {
+    interp.with_gil(|py| {
+        py.run_bound(
+            r#"
+data = [bytearray(i % 4096) for i in range(10000)]
+data = {str(i): list(range(i % 64)) for i in range(10000)}
+grown = b""
+for i in range(1000):
+    grown += b"x" * i
+del data, grown
+import gc
+gc.collect()
+"#,
+            None,
+            None,
+        )
+        .unwrap();
+    });
+}
+
 rusty_fork_test! {
     #[test]
     fn test_default_interpreter() {
@@ -192,6 +213,8 @@ rusty_fork_test! {
 
         assert!(interp.allocator.is_some());
         assert_eq!(interp.allocator.as_ref().unwrap().backend(), MemoryAllocatorBackend::Rust);
+
+        exercise_allocator(&interp);
     }
 
     #[test]
@@ -206,6 +229,137 @@ rusty_fork_test! {
 
         assert!(interp.allocator.is_some());
         assert_eq!(interp.allocator.as_ref().unwrap().backend(), MemoryAllocatorBackend::Rust);
+
+        exercise_allocator(&interp);
+    }
+
+    #[test]
+    fn test_allocator_rust_stats() {
+        let mut config = default_interpreter_config();
+
+        config.allocator_backend = MemoryAllocatorBackend::Rust;
+        config.allocator_raw = true;
+        config.allocator_mem = true;
+        config.allocator_obj = true;
+
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        let before = interp.allocator_stats().unwrap();
+        assert!(before.current_bytes > 0);
+        assert!(before.peak_bytes >= before.current_bytes);
+        for domain in [AllocationDomain::Raw, AllocationDomain::Mem, AllocationDomain::Obj] {
+            assert!(before.domain(domain).total_allocations > 0, "{}", domain);
+        }
+        assert_eq!(before.domain(AllocationDomain::Arena).total_allocations, 0);
+        assert_eq!(
+            before.size_histogram.iter().sum::<u64>(),
+            before.domains.iter().map(|d| d.total_allocations).sum::<u64>()
+        );
+
+        interp.with_gil(|py| {
+            py.eval_bound("bytearray(1048576)", None, None).unwrap();
+        });
+
+        let after = interp.allocator_stats().unwrap();
+        assert!(after.peak_bytes >= before.current_bytes + 1048576);
+
+        let outstanding = interp
+            .allocator
+            .as_ref()
+            .unwrap()
+            .outstanding_allocations()
+            .unwrap();
+        assert_eq!(
+            outstanding.iter().map(|a| a.size).sum::<usize>(),
+            after.current_bytes
+        );
+    }
+
+    #[test]
+    fn test_allocator_default_no_stats() {
+        let config = default_interpreter_config();
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        assert!(interp.allocator_stats().is_none());
+    }
+
+    #[test]
+    fn test_allocator_hard_limit() {
+        let mut config = default_interpreter_config();
+
+        config.allocator_backend = MemoryAllocatorBackend::Rust;
+        config.allocator_raw = true;
+        config.allocator_mem = true;
+        config.allocator_obj = true;
+
+        let mut interp = MainPythonInterpreter::new(config).unwrap();
+
+        let current = interp.allocator_stats().unwrap().current_bytes;
+        assert!(interp
+            .allocator()
+            .unwrap()
+            .set_hard_limit(Some(current + 16 * 1048576)));
+
+        interp.with_gil(|py| {
+            let err = py
+                .eval_bound("bytearray(64 * 1048576)", None, None)
+                .unwrap_err();
+            assert!(err.is_instance_of::<pyo3::exceptions::PyMemoryError>(py));
+
+            // The interpreter keeps working after the failed allocation.
+            py.eval_bound("bytearray(1048576)", None, None).unwrap();
+        });
+
+        let stats = interp.allocator_stats().unwrap();
+        assert!(stats.domains.iter().map(|d| d.failed_allocations).sum::<u64>() > 0);
+        assert!(stats.peak_bytes <= current + 16 * 1048576);
+
+        interp.allocator().unwrap().set_hard_limit(None);
+        interp.with_gil(|py| {
+            py.eval_bound("bytearray(64 * 1048576)", None, None).unwrap();
+        });
+        interp.finalize();
+    }
+
+    #[test]
+    fn test_allocator_hard_limit_requires_tracking() {
+        let mut config = default_interpreter_config();
+        config.allocator_hard_limit = Some(1 << 30);
+
+        assert!(MainPythonInterpreter::new(config).is_err());
+    }
+
+    #[test]
+    fn test_allocator_soft_limit() {
+        let mut config = default_interpreter_config();
+
+        config.allocator_backend = MemoryAllocatorBackend::Rust;
+        config.allocator_raw = true;
+        config.allocator_mem = true;
+        config.allocator_obj = true;
+
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        let fired = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let fired_cb = fired.clone();
+        let limit = interp.allocator_stats().unwrap().current_bytes + 8 * 1048576;
+
+        assert!(interp.allocator().unwrap().set_soft_limit(Some(limit), move |stats| {
+            assert!(stats.current_bytes > limit);
+            fired_cb.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }));
+
+        interp.with_gil(|py| {
+            py.eval_bound("bytearray(1048576)", None, None).unwrap();
+        });
+        assert_eq!(fired.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        interp.with_gil(|py| {
+            // The bytearray is released after evaluation, which re-arms the limit.
+            py.eval_bound("bytearray(16 * 1048576)", None, None).unwrap();
+            py.eval_bound("bytearray(16 * 1048576)", None, None).unwrap();
+        });
+        assert_eq!(fired.load(std::sync::atomic::Ordering::SeqCst), 2);
     }
 
     #[cfg(feature = "jemalloc-sys")]
@@ -222,6 +376,8 @@ rusty_fork_test! {
 
         assert!(interp.allocator.is_some());
         assert_eq!(interp.allocator.as_ref().unwrap().backend(), MemoryAllocatorBackend::Jemalloc);
+
+        exercise_allocator(&interp);
     }
 
     #[cfg(feature = "jemalloc-sys")]
@@ -237,6 +393,8 @@ rusty_fork_test! {
 
         assert!(interp.allocator.is_some());
         assert_eq!(interp.allocator.as_ref().unwrap().backend(), MemoryAllocatorBackend::Jemalloc);
+
+        exercise_allocator(&interp);
     }
 
     #[cfg(feature = "libmimalloc-sys")]
@@ -253,6 +411,8 @@ rusty_fork_test! {
 
         assert!(interp.allocator.is_some());
         assert_eq!(interp.allocator.as_ref().unwrap().backend(), MemoryAllocatorBackend::Mimalloc);
+
+        exercise_allocator(&interp);
     }
 
     #[cfg(feature = "libmimalloc-sys")]
@@ -268,6 +428,8 @@ rusty_fork_test! {
 
         assert!(interp.allocator.is_some());
         assert_eq!(interp.allocator.as_ref().unwrap().backend(), MemoryAllocatorBackend::Mimalloc);
+
+        exercise_allocator(&interp);
     }
 
     #[cfg(feature = "snmalloc-sys")]
@@ -284,6 +446,8 @@ rusty_fork_test! {
 
         assert!(interp.allocator.is_some());
         assert_eq!(interp.allocator.as_ref().unwrap().backend(), MemoryAllocatorBackend::Snmalloc);
+
+        exercise_allocator(&interp);
     }
 
     #[cfg(feature = "snmalloc-sys")]
@@ -299,6 +463,38 @@ rusty_fork_test! {
 
         assert!(interp.allocator.is_some());
         assert_eq!(interp.allocator.as_ref().unwrap().backend(), MemoryAllocatorBackend::Snmalloc);
+
+        exercise_allocator(&interp);
+    }
+
+    #[cfg(not(feature = "jemalloc-sys"))]
+    #[test]
+    fn test_allocator_jemalloc_unavailable() {
+        let mut config = default_interpreter_config();
+        config.allocator_backend = MemoryAllocatorBackend::Jemalloc;
+
+        let err = MainPythonInterpreter::new(config).err().unwrap();
+        assert!(err.to_string().contains("--features allocator-jemalloc"));
+    }
+
+    #[cfg(not(feature = "libmimalloc-sys"))]
+    #[test]
+    fn test_allocator_mimalloc_unavailable() {
+        let mut config = default_interpreter_config();
+        config.allocator_backend = MemoryAllocatorBackend::Mimalloc;
+
+        let err = MainPythonInterpreter::new(config).err().unwrap();
+        assert!(err.to_string().contains("--features allocator-mimalloc"));
+    }
+
+    #[cfg(not(feature = "snmalloc-sys"))]
+    #[test]
+    fn test_allocator_snmalloc_unavailable() {
+        let mut config = default_interpreter_config();
+        config.allocator_backend = MemoryAllocatorBackend::Snmalloc;
+
+        let err = MainPythonInterpreter::new(config).err().unwrap();
+        assert!(err.to_string().contains("--features allocator-snmalloc"));
     }
 
     #[test]
@@ -0,0 +1,238 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    super::default_interpreter_config,
+    crate::{InterruptAction, InterruptHandle, MainPythonInterpreter},
+    pyo3::{exceptions::PyKeyboardInterrupt, prelude::*},
+    rusty_fork::rusty_fork_test,
+    std::time::Duration,
+};
+
+/// Run Python code as an interruptible job on a new thread.
+///
+/// Resolves to whether the code raised `KeyboardInterrupt`.
+fn spawn_job(handle: InterruptHandle, code: &'static str) -> std::thread::JoinHandle<bool> {
+    std::thread::spawn(move || {
+        Python::with_gil(|py| {
+            let _job = handle.enter_job(py).unwrap();
+
+            match py.run_bound(code, None, None) {
+                Ok(()) => false,
+                Err(e) if e.is_instance_of::<PyKeyboardInterrupt>(py) => true,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        })
+    })
+}
+
+fn wait_for_job(handle: &InterruptHandle) {
+    while !handle.is_job_running() {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+rusty_fork_test! {
+    #[test]
+    fn interrupt_without_job() {
+        let mut config = default_interpreter_config();
+        config.forward_interrupts = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        assert_eq!(interp.interrupt_handle().request_interrupt(), InterruptAction::Idle);
+    }
+
+    #[test]
+    fn interrupt_raises_keyboard_interrupt() {
+        let mut config = default_interpreter_config();
+        config.forward_interrupts = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+        let handle = interp.interrupt_handle();
+
+        let job = spawn_job(handle.clone(), "while True: pass");
+        wait_for_job(&handle);
+
+        assert_eq!(handle.request_interrupt(), InterruptAction::Interrupted);
+        assert!(job.join().unwrap());
+
+        assert!(!handle.is_job_running());
+        assert_eq!(handle.request_interrupt(), InterruptAction::Idle);
+
+        // The next job starts with a clean slate.
+        let job = spawn_job(handle.clone(), "x = 1");
+        assert!(!job.join().unwrap());
+    }
+
+    #[test]
+    fn interrupt_repeated_force_quits() {
+        let mut config = default_interpreter_config();
+        config.forward_interrupts = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+        let handle = interp.interrupt_handle();
+
+        let job = spawn_job(
+            handle.clone(),
+            r#"
+import time
+while True:
+    try:
+        time.sleep(0.05)
+    except KeyboardInterrupt:
+        break
+time.sleep(0.5)
+"#,
+        );
+        wait_for_job(&handle);
+
+        assert_eq!(handle.request_interrupt(), InterruptAction::Interrupted);
+        // The job swallowed the first interrupt and keeps running.
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(handle.is_job_running());
+        assert_eq!(handle.request_interrupt(), InterruptAction::ForceQuit);
+
+        assert!(!job.join().unwrap());
+    }
+
+    #[test]
+    fn enter_job_exclusive() {
+        let mut config = default_interpreter_config();
+        config.forward_interrupts = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+        let handle = interp.interrupt_handle();
+
+        interp.with_gil(|py| {
+            let _job = handle.enter_job(py).unwrap();
+            assert!(handle.enter_job(py).is_err());
+        });
+
+        assert!(!handle.is_job_running());
+    }
+
+    #[test]
+    fn forward_interrupts_disables_python_sigint() {
+        let mut config = default_interpreter_config();
+        config.forward_interrupts = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        interp.with_gil(|py| {
+            let default: bool = py
+                .eval_bound(
+                    "__import__('signal').getsignal(__import__('signal').SIGINT) \
+                     is __import__('signal').SIG_DFL",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert!(default);
+        });
+    }
+}
+
+#[cfg(unix)]
+const SIGINT_CHILD_ENV: &str = "PYEMBED_TEST_SIGINT_CHILD";
+
+/// Entry point of the child process spawned by [sigint_forwarding_child_process].
+///
+/// Does nothing unless run by that test.
+#[cfg(unix)]
+#[test]
+fn sigint_child() {
+    if std::env::var_os(SIGINT_CHILD_ENV).is_none() {
+        return;
+    }
+
+    let mut config = default_interpreter_config();
+    config.forward_interrupts = true;
+    let interp = MainPythonInterpreter::new(config).unwrap();
+    let handle = interp.interrupt_handle();
+
+    crate::interrupt::forward_sigint(handle.clone(), |action| match action {
+        InterruptAction::Interrupted => {}
+        InterruptAction::Idle | InterruptAction::ForceQuit => std::process::exit(130),
+    })
+    .unwrap();
+
+    interp.with_gil(|py| {
+        {
+            let _job = handle.enter_job(py).unwrap();
+            py.run_bound(
+                r#"
+print("ready", flush=True)
+try:
+    while True:
+        pass
+except KeyboardInterrupt:
+    print("interrupted", flush=True)
+"#,
+                None,
+                None,
+            )
+            .unwrap();
+        }
+
+        let _job = handle.enter_job(py).unwrap();
+        py.run_bound(
+            r#"
+import time
+print("ready", flush=True)
+while True:
+    try:
+        time.sleep(0.05)
+    except KeyboardInterrupt:
+        print("ignored", flush=True)
+"#,
+            None,
+            None,
+        )
+        .unwrap();
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn sigint_forwarding_child_process() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+    };
+
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "test::interrupt::sigint_child", "--nocapture"])
+        .env(SIGINT_CHILD_ENV, "1")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let pid = child.id() as libc::pid_t;
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+
+    let mut wait_for = |expected: &str| loop {
+        let line = lines
+            .next()
+            .unwrap_or_else(|| panic!("child exited before printing {}", expected))
+            .unwrap();
+
+        if line == expected {
+            break;
+        }
+    };
+
+    let sigint = || assert_eq!(unsafe { libc::kill(pid, libc::SIGINT) }, 0);
+
+    // First job: a single Ctrl-C stops it.
+    wait_for("ready");
+    sigint();
+    wait_for("interrupted");
+
+    // Second job: ignores the interrupt, so the second Ctrl-C force quits.
+    wait_for("ready");
+    sigint();
+    wait_for("ignored");
+    sigint();
+
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(130));
+}
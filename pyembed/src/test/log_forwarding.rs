@@ -0,0 +1,185 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    super::default_interpreter_config,
+    crate::{pylog::python_level_to_log, MainPythonInterpreter},
+    rusty_fork::rusty_fork_test,
+    std::sync::Mutex,
+};
+
+/// A `log` implementation recording everything it receives.
+struct CapturingLogger {
+    records: Mutex<Vec<(log::Level, String, String)>>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.records.lock().unwrap().push((
+            record.level(),
+            record.target().to_string(),
+            record.args().to_string(),
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger {
+    records: Mutex::new(vec![]),
+};
+
+/// Install [LOGGER] as the global logger.
+///
+/// Every test runs in its own process, so this is only called once per process.
+fn install_logger(level: log::LevelFilter) {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(level);
+}
+
+fn records() -> Vec<(log::Level, String, String)> {
+    LOGGER.records.lock().unwrap().clone()
+}
+
+#[test]
+fn level_mapping() {
+    assert_eq!(python_level_to_log(50), log::Level::Error);
+    assert_eq!(python_level_to_log(40), log::Level::Error);
+    assert_eq!(python_level_to_log(35), log::Level::Warn);
+    assert_eq!(python_level_to_log(30), log::Level::Warn);
+    assert_eq!(python_level_to_log(20), log::Level::Info);
+    assert_eq!(python_level_to_log(10), log::Level::Debug);
+    assert_eq!(python_level_to_log(5), log::Level::Trace);
+    assert_eq!(python_level_to_log(0), log::Level::Trace);
+}
+
+rusty_fork_test! {
+    #[test]
+    fn logging_forwarded() {
+        install_logger(log::LevelFilter::Debug);
+
+        let mut config = default_interpreter_config();
+        config.log_forwarding = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        interp.with_gil(|py| {
+            py.run_bound(
+                r#"
+import logging
+logging.getLogger("pyrocko.io").info("loaded %d traces", 3)
+logging.getLogger("pyrocko").debug("debug message")
+logging.getLogger("pyrocko").log(5, "too verbose")
+logging.error("from root")
+"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+
+        assert_eq!(
+            records(),
+            vec![
+                (log::Level::Info, "pyrocko.io".to_string(), "loaded 3 traces".to_string()),
+                (log::Level::Debug, "pyrocko".to_string(), "debug message".to_string()),
+                (log::Level::Error, "root".to_string(), "from root".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn logging_level_follows_max_level() {
+        install_logger(log::LevelFilter::Warn);
+
+        let mut config = default_interpreter_config();
+        config.log_forwarding = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        interp.with_gil(|py| {
+            let level: i32 = py
+                .eval_bound("__import__('logging').getLogger().level", None, None)
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(level, 30);
+        });
+    }
+
+    #[test]
+    fn logging_exception_includes_traceback() {
+        install_logger(log::LevelFilter::Debug);
+
+        let mut config = default_interpreter_config();
+        config.log_forwarding = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        interp.with_gil(|py| {
+            py.run_bound(
+                r#"
+import logging
+try:
+    1 / 0
+except ZeroDivisionError:
+    logging.getLogger("plugin").exception("failed")
+"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+
+        let records = records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, log::Level::Error);
+        assert!(records[0].2.starts_with("failed\nTraceback"));
+        assert!(records[0].2.contains("ZeroDivisionError"));
+    }
+
+    #[test]
+    fn warnings_forwarded() {
+        install_logger(log::LevelFilter::Debug);
+
+        let mut config = default_interpreter_config();
+        config.log_forwarding = true;
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        interp.with_gil(|py| {
+            py.run_bound(
+                r#"
+import warnings
+warnings.simplefilter("always")
+warnings.warn("deprecated thing", DeprecationWarning)
+"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+
+        let records = records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, log::Level::Warn);
+        assert_eq!(records[0].1, "py.warnings");
+        assert!(records[0].2.contains("DeprecationWarning: deprecated thing"));
+    }
+
+    #[test]
+    fn forwarding_disabled_by_default() {
+        install_logger(log::LevelFilter::Debug);
+
+        let config = default_interpreter_config();
+        let interp = MainPythonInterpreter::new(config).unwrap();
+
+        interp.with_gil(|py| {
+            py.run_bound("import logging; logging.error('to stderr')", None, None)
+                .unwrap();
+        });
+
+        assert!(records().is_empty());
+    }
+}
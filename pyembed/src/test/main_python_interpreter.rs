@@ -3,10 +3,11 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    super::{default_interpreter_config, run_py_test},
-    crate::MainPythonInterpreter,
+    super::{default_interpreter_config, run_py_test, PYTHON_INTERPRETER_PATH},
+    crate::{MainPythonInterpreter, OxidizedPythonInterpreterConfig},
     pyo3::ffi as pyffi,
     rusty_fork::rusty_fork_test,
+    std::{path::PathBuf, process::Command},
 };
 
 rusty_fork_test! {
@@ -35,8 +36,132 @@ rusty_fork_test! {
         std::mem::drop(interp);
     }
 
+    /// An interpreter can be initialized again after the previous one is finalized.
+    #[test]
+    fn interpreter_new_drop_cycles() {
+        for _ in 0..3 {
+            let config = default_interpreter_config();
+            let interp = MainPythonInterpreter::new(config).unwrap();
+
+            interp.with_gil(|py| {
+                let sys = py.import_bound("sys").unwrap();
+                // State from the previous interpreter must be gone.
+                assert!(!sys.hasattr("cycle_marker").unwrap());
+                sys.setattr("cycle_marker", true).unwrap();
+                py.import_bound("json").unwrap();
+            });
+
+            std::mem::drop(interp);
+            assert_eq!(unsafe { pyffi::Py_IsInitialized() }, 0);
+        }
+    }
+
     #[test]
     fn multiprocessing_py() {
         run_py_test("test_multiprocessing.py").unwrap()
     }
 }
+
+/// Environment variable marking a run of [origin_stdlib_child] as wanted.
+const ORIGIN_SMOKE_ENV: &str = "PYEMBED_ORIGIN_SMOKE_TEST";
+
+/// Start an interpreter finding libpython and the standard library next to
+/// the executable.
+///
+/// Only does something when run by [dynamic_libpython_other_cwd].
+#[test]
+fn origin_stdlib_child() {
+    if std::env::var_os(ORIGIN_SMOKE_ENV).is_none() {
+        return;
+    }
+
+    let mut config = OxidizedPythonInterpreterConfig::default();
+    config.interpreter_config.parse_argv = Some(false);
+    config.stdlib_path = Some(PathBuf::from("$ORIGIN").join("stdlib"));
+
+    let interp = MainPythonInterpreter::new(config).unwrap();
+    let origin = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+
+    interp.with_gil(|py| {
+        let json = py.import_bound("json").unwrap();
+        let file = json
+            .getattr("__file__")
+            .unwrap()
+            .extract::<PathBuf>()
+            .unwrap();
+        assert!(
+            file.starts_with(origin.join("stdlib")),
+            "{}",
+            file.display()
+        );
+    });
+}
+
+/// Run a copy of the test binary with a dynamically linked libpython from
+/// another working directory.
+///
+/// libpython and the standard library are placed next to the copy, where the
+/// `$ORIGIN` relative rpath and [OxidizedPythonInterpreterConfig::stdlib_path]
+/// must find them.
+#[cfg(unix)]
+#[test]
+#[ignore = "needs PYO3_CONFIG_FILE to link a shared libpython; run by CI"]
+fn dynamic_libpython_other_cwd() {
+    let lib_dir = option_env!("PYEMBED_LIBPYTHON_DIR")
+        .expect("PyO3 isn't configured to link a shared libpython");
+
+    let dir = std::env::temp_dir().join(format!("pyembed-origin-{}", std::process::id()));
+    let bin_dir = dir.join("bin");
+    std::fs::create_dir_all(&bin_dir).unwrap();
+
+    let exe = bin_dir.join("pyembed-test");
+    std::fs::copy(std::env::current_exe().unwrap(), &exe).unwrap();
+
+    for entry in std::fs::read_dir(lib_dir).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_name().to_string_lossy().starts_with("libpython") {
+            std::os::unix::fs::symlink(entry.path(), bin_dir.join(entry.file_name())).unwrap();
+        }
+    }
+
+    let output = Command::new(PYTHON_INTERPRETER_PATH)
+        .args([
+            "-c",
+            "import sysconfig; print(sysconfig.get_path('stdlib'))",
+        ])
+        .output()
+        .unwrap();
+    let stdlib = String::from_utf8(output.stdout).unwrap();
+    std::os::unix::fs::symlink(stdlib.trim(), bin_dir.join("stdlib")).unwrap();
+
+    let work_dir = dir.join("work");
+    std::fs::create_dir_all(&work_dir).unwrap();
+
+    let output = Command::new(&exe)
+        .args([
+            "--exact",
+            "test::main_python_interpreter::origin_stdlib_child",
+        ])
+        .current_dir(&work_dir)
+        .env(ORIGIN_SMOKE_ENV, "1")
+        .env_remove("LD_LIBRARY_PATH")
+        .env_remove("DYLD_LIBRARY_PATH")
+        .env_remove("PYTHONHOME")
+        .env_remove("PYTHONPATH")
+        .output()
+        .unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success() && stdout.contains("1 passed"),
+        "{}\n{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -3,15 +3,27 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::{MainPythonInterpreter, OxidizedPythonInterpreterConfig},
+    crate::{packed_resources::Resource, MainPythonInterpreter, OxidizedPythonInterpreterConfig},
     anyhow::{anyhow, Result},
-    std::path::PathBuf,
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        path::PathBuf,
+    },
+    xtask::embed_python::{
+        packed_resources, FileData, PrePackagedResource, PythonModuleBytecodeProvider,
+    },
 };
 
 mod importer;
 mod interpreter_config;
+mod interrupt;
+mod log_forwarding;
 mod main_python_interpreter;
 mod python_resources;
+mod sandbox;
+#[cfg(all(Py_3_12, not(Py_LIMITED_API)))]
+mod sub_interpreter;
 
 pub const PYTHON_INTERPRETER_PATH: &str = env!("PYTHON_INTERPRETER_PATH");
 
@@ -83,3 +95,46 @@ pub fn run_py_test(test_filename: &str) -> Result<()> {
         Ok(())
     }
 }
+
+/// Serialize resources to packed resources data.
+///
+/// This converts the resources for the writer of `cargo xtask
+/// prepare-embed-python`, so tests read what applications embed.
+pub fn write_packed_resources(resources: &[Resource]) -> Vec<u8> {
+    let file = |value: &Option<Cow<[u8]>>| value.as_ref().map(|v| FileData::Memory(v.to_vec()));
+    let bytecode =
+        |value: &Option<Cow<[u8]>>| file(value).map(PythonModuleBytecodeProvider::Provided);
+    let files = |value: &Option<HashMap<Cow<str>, Cow<[u8]>>>| {
+        value.as_ref().map(|map| {
+            map.iter()
+                .map(|(k, v)| (k.to_string(), FileData::Memory(v.to_vec())))
+                .collect::<BTreeMap<_, _>>()
+        })
+    };
+
+    let resources = resources
+        .iter()
+        .map(|resource| PrePackagedResource {
+            name: resource.name.to_string(),
+            is_package: resource.is_package,
+            is_namespace_package: resource.is_namespace_package,
+            in_memory_source: file(&resource.in_memory_source),
+            in_memory_bytecode: bytecode(&resource.in_memory_bytecode),
+            in_memory_bytecode_opt1: bytecode(&resource.in_memory_bytecode_opt1),
+            in_memory_bytecode_opt2: bytecode(&resource.in_memory_bytecode_opt2),
+            in_memory_extension_module_shared_library: file(
+                &resource.in_memory_extension_module_shared_library,
+            ),
+            in_memory_resources: files(&resource.in_memory_package_resources),
+            in_memory_distribution_resources: files(&resource.in_memory_distribution_resources),
+            in_memory_shared_library: file(&resource.in_memory_shared_library),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut data = vec![];
+    packed_resources::write_packed_resources(resources.iter(), &mut data)
+        .expect("serializing packed resources");
+
+    data
+}
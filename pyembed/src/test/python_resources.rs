@@ -3,36 +3,29 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::OxidizedPythonInterpreterConfig,
+    super::write_packed_resources,
+    crate::{
+        packed_resources::Resource, OxidizedPythonInterpreterConfig, PackedResourcesSource,
+        PythonResourcesState,
+    },
     anyhow::{anyhow, Result},
-    oxidized_importer::{PackedResourcesSource, PyTempDir, PythonResourcesState},
-    python_packed_resources::Resource,
+    oxidized_importer::PyTempDir,
     rusty_fork::rusty_fork_test,
 };
 
 #[test]
 fn multiple_resource_blobs() -> Result<()> {
-    let mut state0 = PythonResourcesState::default();
-    state0
-        .add_resource(Resource {
-            name: "foo".into(),
-            is_python_module: true,
-            in_memory_source: Some(vec![42].into()),
-            ..Default::default()
-        })
-        .unwrap();
-    let data0 = state0.serialize_resources(true, true)?;
-
-    let mut state1 = PythonResourcesState::default();
-    state1
-        .add_resource(Resource {
-            name: "bar".into(),
-            is_python_module: true,
-            in_memory_source: Some(vec![42, 42].into()),
-            ..Default::default()
-        })
-        .unwrap();
-    let data1 = state1.serialize_resources(true, true)?;
+    let data0 = write_packed_resources(&[Resource {
+        name: "foo".into(),
+        in_memory_source: Some(vec![42].into()),
+        ..Default::default()
+    }]);
+
+    let data1 = write_packed_resources(&[Resource {
+        name: "bar".into(),
+        in_memory_source: Some(vec![42, 42].into()),
+        ..Default::default()
+    }]);
 
     let config = OxidizedPythonInterpreterConfig::default().resolve()?;
 
@@ -53,16 +46,11 @@ fn test_memory_mapped_file_resources() -> Result<()> {
         .ok_or_else(|| anyhow!("unable to find current exe parent"))?
         .to_path_buf();
 
-    let mut state0 = PythonResourcesState::default();
-    state0
-        .add_resource(Resource {
-            name: "foo".into(),
-            is_python_module: true,
-            in_memory_source: Some(vec![42].into()),
-            ..Default::default()
-        })
-        .unwrap();
-    let data0 = state0.serialize_resources(true, true)?;
+    let data0 = write_packed_resources(&[Resource {
+        name: "foo".into(),
+        in_memory_source: Some(vec![42].into()),
+        ..Default::default()
+    }]);
 
     let resources_dir = current_dir.join("resources");
     if !resources_dir.exists() {
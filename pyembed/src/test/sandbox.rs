@@ -0,0 +1,112 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    super::{default_interpreter_config, PYTHON_INTERPRETER_PATH},
+    crate::{ImportSandbox, MainPythonInterpreter, SandboxViolationAction},
+    pyo3::{exceptions::PyImportError, prelude::*},
+    rusty_fork::rusty_fork_test,
+    std::path::PathBuf,
+};
+
+/// The directories of the host Python's standard library.
+fn stdlib_dirs() -> Vec<PathBuf> {
+    let output = std::process::Command::new(PYTHON_INTERPRETER_PATH)
+        .args([
+            "-c",
+            "import sysconfig; print(sysconfig.get_path('stdlib')); print(sysconfig.get_path('platstdlib'))",
+        ])
+        .output()
+        .unwrap();
+
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// A directory outside the standard library with a `sandboxed` module.
+fn module_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pyembed-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("sandboxed.py"), "VALUE = 42\n").unwrap();
+
+    dir
+}
+
+fn new_interpreter<'a>(allowed_paths: Vec<PathBuf>) -> MainPythonInterpreter<'a, 'a> {
+    let mut config = default_interpreter_config();
+    config.import_sandbox = Some(ImportSandbox {
+        allowed_paths,
+        violation_action: SandboxViolationAction::Block,
+    });
+
+    MainPythonInterpreter::new(config).unwrap()
+}
+
+rusty_fork_test! {
+    #[test]
+    fn sandbox_blocks_imports() {
+        let dir = module_dir("sandbox-blocked");
+        let interp = new_interpreter(stdlib_dirs());
+
+        interp.with_gil(|py| {
+            // The standard library is allowed.
+            py.import_bound("json").unwrap();
+
+            py.import_bound("sys")
+                .unwrap()
+                .getattr("path")
+                .unwrap()
+                .call_method1("insert", (0, &dir))
+                .unwrap();
+
+            let err = py.import_bound("sandboxed").unwrap_err();
+            assert!(err.is_instance_of::<PyImportError>(py));
+
+            // Reading Python files as data is not an import.
+            let source: String = py
+                .eval_bound(
+                    &format!("open({:?}).read()", dir.join("sandboxed.py")),
+                    None,
+                    None,
+                )
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(source, "VALUE = 42\n");
+        });
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sandbox_allows_imports() {
+        let dir = module_dir("sandbox-allowed");
+        let mut allowed_paths = stdlib_dirs();
+        allowed_paths.push(dir.clone());
+        let interp = new_interpreter(allowed_paths);
+
+        interp.with_gil(|py| {
+            py.import_bound("sys")
+                .unwrap()
+                .getattr("path")
+                .unwrap()
+                .call_method1("insert", (0, &dir))
+                .unwrap();
+
+            let value: i32 = py
+                .import_bound("sandboxed")
+                .unwrap()
+                .getattr("VALUE")
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(value, 42);
+        });
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
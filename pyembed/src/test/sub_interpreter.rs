@@ -0,0 +1,90 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    super::default_interpreter_config,
+    crate::{MainPythonInterpreter, SubInterpreterConfig},
+    pyo3::prelude::*,
+    rusty_fork::rusty_fork_test,
+};
+
+rusty_fork_test! {
+    /// Sub-interpreters have their own `sys.modules`.
+    #[test]
+    fn sub_interpreter_isolated_modules() {
+        let interp = MainPythonInterpreter::new(default_interpreter_config()).unwrap();
+
+        let mut a = interp.new_sub_interpreter(SubInterpreterConfig::default()).unwrap();
+        let mut b = interp.new_sub_interpreter(SubInterpreterConfig::default()).unwrap();
+        assert_ne!(a.id(), b.id());
+
+        a.run("import sys; sys.modules['plugin_state'] = sys").unwrap();
+        a.run("import sys; assert 'plugin_state' in sys.modules").unwrap();
+        b.run("import sys; assert 'plugin_state' not in sys.modules").unwrap();
+
+        interp.with_gil(|py| {
+            let sys = py.import_bound("sys").unwrap();
+            let modules = sys.getattr("modules").unwrap();
+            assert!(!modules.contains("plugin_state").unwrap());
+        });
+    }
+
+    /// Sub-interpreters with their own GIL run on separate threads.
+    #[test]
+    fn sub_interpreter_own_gil_threads() {
+        let interp = MainPythonInterpreter::new(default_interpreter_config()).unwrap();
+
+        let subs = (0..4)
+            .map(|_| interp.new_sub_interpreter(SubInterpreterConfig::default()).unwrap())
+            .collect::<Vec<_>>();
+
+        std::thread::scope(|scope| {
+            let handles = subs
+                .into_iter()
+                .map(|mut sub| {
+                    scope.spawn(move || {
+                        sub.run("assert sum(x * x for x in range(100000)) == 333328333350000")
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for handle in handles {
+                handle.join().unwrap().unwrap();
+            }
+        });
+    }
+
+    /// Uncaught exceptions are reported as errors.
+    #[test]
+    fn sub_interpreter_run_error() {
+        let interp = MainPythonInterpreter::new(default_interpreter_config()).unwrap();
+
+        let mut sub = interp.new_sub_interpreter(SubInterpreterConfig::default()).unwrap();
+        assert!(sub.run("raise ValueError('plugin failed')").is_err());
+        assert!(sub.run("pass\0").is_err());
+        sub.run("pass").unwrap();
+    }
+
+    /// Legacy sub-interpreters share the main GIL.
+    #[test]
+    fn sub_interpreter_legacy() {
+        let interp = MainPythonInterpreter::new(default_interpreter_config()).unwrap();
+
+        let mut sub = interp.new_sub_interpreter(SubInterpreterConfig::legacy()).unwrap();
+        sub.run("import json; json.dumps({})").unwrap();
+    }
+
+    /// Invalid combinations of settings are rejected.
+    #[test]
+    fn sub_interpreter_invalid_config() {
+        let interp = MainPythonInterpreter::new(default_interpreter_config()).unwrap();
+
+        let config = SubInterpreterConfig {
+            use_main_obmalloc: true,
+            ..Default::default()
+        };
+
+        assert!(interp.new_sub_interpreter(config).is_err());
+    }
+}
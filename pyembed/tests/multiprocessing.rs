@@ -0,0 +1,78 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! `multiprocessing` workers started with the `spawn` method.
+//!
+//! Workers and the resource tracker run this executable again, so it has its
+//! own `main()` dispatching to them like an application embedding Python.
+//! The interpreter is configured like an embedded distribution: `sys.frozen`
+//! is set and the standard library of the Python pyembed links against is
+//! used from its location, not through a host Python fallback.
+
+use {
+    pyembed::{MainPythonInterpreter, OxidizedPythonInterpreterConfig},
+    pyo3::prelude::*,
+    std::path::PathBuf,
+};
+
+const SCRIPT: &str = "
+import math
+import multiprocessing
+import os
+
+ctx = multiprocessing.get_context('spawn')
+
+# Do it twice to ensure state isn't funky.
+for _ in range(2):
+    with ctx.Pool(4) as pool:
+        assert pool.map(math.factorial, range(64)) == [math.factorial(i) for i in range(64)]
+        assert pool.apply(os.getpid) != os.getpid()
+";
+
+/// The standard library directory of the Python pyembed links against.
+fn stdlib_path() -> PathBuf {
+    let output = std::process::Command::new(env!("PYTHON_INTERPRETER_PATH"))
+        .args([
+            "-c",
+            "import sysconfig; print(sysconfig.get_path('stdlib'))",
+        ])
+        .output()
+        .unwrap();
+
+    PathBuf::from(String::from_utf8(output.stdout).unwrap().trim_end())
+}
+
+fn main() {
+    let mut config = OxidizedPythonInterpreterConfig::default();
+    config.interpreter_config.parse_argv = Some(false);
+    config.interpreter_config.site_import = Some(false);
+    config.stdlib_path = Some(stdlib_path());
+    config.sys_frozen = true;
+    config.multiprocessing_auto_dispatch = true;
+
+    let interp = MainPythonInterpreter::new(config).unwrap();
+
+    if interp.is_multiprocessing() {
+        std::process::exit(interp.run());
+    }
+
+    interp.with_gil(|py| {
+        let sys = py.import_bound("sys").unwrap();
+        assert!(sys.getattr("frozen").unwrap().is_truthy().unwrap());
+        assert_eq!(
+            sys.getattr("executable")
+                .unwrap()
+                .extract::<PathBuf>()
+                .unwrap(),
+            std::env::current_exe().unwrap().canonicalize().unwrap()
+        );
+
+        if let Err(err) = py.run_bound(SCRIPT, None, None) {
+            err.print(py);
+            panic!("running spawned workers failed");
+        }
+    });
+
+    println!("test multiprocessing_spawn ... ok");
+}
@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! The packed resources data format.
+
+Packed resources serialize a set of [crate::Resource]s into a single blob. The
+build tooling writes them and [crate::PackedResources] reads them.
+
+All integers are little-endian. Offsets are relative to the start of the blob.
+
+```text
+header:
+    magic: [u8; 8] = b"PYEMBRES"
+    version: u32 = 1
+    resource_count: u32
+index: resource_count entries, sorted by name, each:
+    name: span
+    flags: u32
+    fields: [span; 8] (see `FIELD_*`)
+data:
+    bytes referenced by spans
+```
+
+A _span_ is a `(offset: u32, length: u32)` pair. An offset of `u32::MAX`
+denotes an absent value.
+
+The package resources and distribution resources fields point at a _map_:
+
+```text
+map:
+    count: u32
+    entries: count × (key: span, value: span)
+```
+
+Keys are UTF-8 paths relative to the package or distribution. They use `/`
+as the directory separator.
+
+Because the index is sorted, a single resource can be looked up by name
+without parsing the entire blob.
+*/
+
+/// Magic bytes at the start of packed resources data.
+pub const MAGIC: &[u8; 8] = b"PYEMBRES";
+
+/// The version of the format described by this module.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Size of the header in bytes.
+pub const HEADER_SIZE: usize = 16;
+
+/// Marks an absent span.
+pub const ABSENT_OFFSET: u32 = u32::MAX;
+
+/// Resource flag: the resource is a package.
+pub const FLAG_IS_PACKAGE: u32 = 1 << 0;
+
+/// Resource flag: the resource is a namespace package.
+pub const FLAG_IS_NAMESPACE_PACKAGE: u32 = 1 << 1;
+
+/// Field index: module source code.
+pub const FIELD_SOURCE: usize = 0;
+/// Field index: bytecode for optimization level 0.
+pub const FIELD_BYTECODE: usize = 1;
+/// Field index: bytecode for optimization level 1.
+pub const FIELD_BYTECODE_OPT1: usize = 2;
+/// Field index: bytecode for optimization level 2.
+pub const FIELD_BYTECODE_OPT2: usize = 3;
+/// Field index: shared library of an extension module.
+pub const FIELD_EXTENSION_MODULE_SHARED_LIBRARY: usize = 4;
+/// Field index: map of package resources.
+pub const FIELD_PACKAGE_RESOURCES: usize = 5;
+/// Field index: map of distribution metadata files.
+pub const FIELD_DISTRIBUTION_RESOURCES: usize = 6;
+/// Field index: a shared library that isn't an extension module.
+pub const FIELD_SHARED_LIBRARY: usize = 7;
+
+/// Number of span fields in an index entry.
+pub const FIELD_COUNT: usize = 8;
+
+/// Size of a span in bytes.
+pub const SPAN_SIZE: usize = 8;
+
+/// Size of an index entry in bytes.
+pub const INDEX_ENTRY_SIZE: usize = SPAN_SIZE + 4 + FIELD_COUNT * SPAN_SIZE;
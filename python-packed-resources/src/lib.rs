@@ -0,0 +1,21 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Python resources packed for embedding in an executable.
+
+A [Resource] describes a Python module and its associated data (bytecode,
+package resources, distribution metadata, shared libraries). The build
+tooling produces resources and `pyembed` imports Python modules from them.
+
+Resources are serialized to the binary format described in [format], which
+is typically embedded in the executable with `include_bytes!()` or
+memory-mapped from a file next to it. [PackedResources] reads it without
+copying: the [Resource] instances it produces borrow from the data.
+*/
+
+pub mod format;
+mod reader;
+mod resource;
+
+pub use crate::{reader::PackedResources, resource::Resource};
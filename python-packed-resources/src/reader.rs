@@ -0,0 +1,187 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Reading of packed resources data.
+
+use {
+    crate::{
+        format::{
+            ABSENT_OFFSET, FIELD_BYTECODE, FIELD_BYTECODE_OPT1, FIELD_BYTECODE_OPT2,
+            FIELD_DISTRIBUTION_RESOURCES, FIELD_EXTENSION_MODULE_SHARED_LIBRARY,
+            FIELD_PACKAGE_RESOURCES, FIELD_SHARED_LIBRARY, FIELD_SOURCE, FLAG_IS_NAMESPACE_PACKAGE,
+            FLAG_IS_PACKAGE, FORMAT_VERSION, HEADER_SIZE, INDEX_ENTRY_SIZE, MAGIC, SPAN_SIZE,
+        },
+        Resource,
+    },
+    std::{borrow::Cow, collections::HashMap},
+};
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, &'static str> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or("packed resources data truncated")
+}
+
+/// Read a span, returning the slice it refers to.
+fn read_span(data: &[u8], offset: usize) -> Result<Option<&[u8]>, &'static str> {
+    let start = read_u32(data, offset)?;
+    let length = read_u32(data, offset + 4)? as usize;
+
+    if start == ABSENT_OFFSET {
+        return Ok(None);
+    }
+
+    let start = start as usize;
+    let end = start
+        .checked_add(length)
+        .ok_or("packed resources span out of bounds")?;
+
+    data.get(start..end)
+        .map(Some)
+        .ok_or("packed resources span out of bounds")
+}
+
+fn read_str_span(data: &[u8], offset: usize) -> Result<Option<&str>, &'static str> {
+    read_span(data, offset)?
+        .map(|b| std::str::from_utf8(b).map_err(|_| "packed resources string is not UTF-8"))
+        .transpose()
+}
+
+#[allow(clippy::type_complexity)]
+fn read_map<'a>(
+    data: &'a [u8],
+    map: Option<&'a [u8]>,
+) -> Result<Option<HashMap<Cow<'a, str>, Cow<'a, [u8]>>>, &'static str> {
+    let map = match map {
+        Some(map) => map,
+        None => return Ok(None),
+    };
+
+    // Spans within a map are relative to the blob, not the map.
+    let base = map.as_ptr() as usize - data.as_ptr() as usize;
+    let count = read_u32(map, 0)? as usize;
+
+    if (map.len() - 4) / (2 * SPAN_SIZE) < count {
+        return Err("packed resources map truncated");
+    }
+
+    let mut res = HashMap::with_capacity(count);
+    for i in 0..count {
+        let entry = base + 4 + i * 2 * SPAN_SIZE;
+
+        let key = read_str_span(data, entry)?.ok_or("packed resources map key absent")?;
+        let value =
+            read_span(data, entry + SPAN_SIZE)?.ok_or("packed resources map value absent")?;
+
+        res.insert(Cow::Borrowed(key), Cow::Borrowed(value));
+    }
+
+    Ok(Some(res))
+}
+
+/// A parsed view of packed resources data.
+#[derive(Clone, Copy, Debug)]
+pub struct PackedResources<'a> {
+    data: &'a [u8],
+    count: usize,
+}
+
+impl<'a> PackedResources<'a> {
+    /// Parse packed resources data.
+    ///
+    /// Only the header is validated eagerly. Errors in individual entries are
+    /// reported when they are accessed.
+    pub fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        if data.len() < HEADER_SIZE || &data[0..8] != MAGIC {
+            return Err("packed resources data has invalid magic");
+        }
+
+        let version = read_u32(data, 8)?;
+        if version != FORMAT_VERSION {
+            return Err("packed resources data has unsupported format version");
+        }
+
+        let count = read_u32(data, 12)? as usize;
+
+        if (data.len() - HEADER_SIZE) / INDEX_ENTRY_SIZE < count {
+            return Err("packed resources index truncated");
+        }
+
+        Ok(Self { data, count })
+    }
+
+    /// The number of resources.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether there are no resources.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn entry_offset(&self, index: usize) -> usize {
+        HEADER_SIZE + index * INDEX_ENTRY_SIZE
+    }
+
+    fn name_at(&self, index: usize) -> Result<&'a str, &'static str> {
+        read_str_span(self.data, self.entry_offset(index))?.ok_or("packed resources name absent")
+    }
+
+    /// Obtain the resource at a given index.
+    pub fn resource_at(&self, index: usize) -> Result<Resource<'a>, &'static str> {
+        if index >= self.count {
+            return Err("packed resources index out of bounds");
+        }
+
+        let data = self.data;
+        let entry = self.entry_offset(index);
+        let flags = read_u32(data, entry + SPAN_SIZE)?;
+        let field = |i: usize| read_span(data, entry + SPAN_SIZE + 4 + i * SPAN_SIZE);
+        let bytes = |i: usize| -> Result<Option<Cow<'a, [u8]>>, &'static str> {
+            Ok(field(i)?.map(Cow::Borrowed))
+        };
+
+        Ok(Resource {
+            name: Cow::Borrowed(self.name_at(index)?),
+            is_package: flags & FLAG_IS_PACKAGE != 0,
+            is_namespace_package: flags & FLAG_IS_NAMESPACE_PACKAGE != 0,
+            in_memory_source: bytes(FIELD_SOURCE)?,
+            in_memory_bytecode: bytes(FIELD_BYTECODE)?,
+            in_memory_bytecode_opt1: bytes(FIELD_BYTECODE_OPT1)?,
+            in_memory_bytecode_opt2: bytes(FIELD_BYTECODE_OPT2)?,
+            in_memory_extension_module_shared_library: bytes(
+                FIELD_EXTENSION_MODULE_SHARED_LIBRARY,
+            )?,
+            in_memory_package_resources: read_map(data, field(FIELD_PACKAGE_RESOURCES)?)?,
+            in_memory_distribution_resources: read_map(data, field(FIELD_DISTRIBUTION_RESOURCES)?)?,
+            in_memory_shared_library: bytes(FIELD_SHARED_LIBRARY)?,
+        })
+    }
+
+    /// Look up a resource by name.
+    ///
+    /// This is a binary search over the index and doesn't parse other entries.
+    pub fn get(&self, name: &str) -> Result<Option<Resource<'a>>, &'static str> {
+        let (mut low, mut high) = (0, self.count);
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+
+            match self.name_at(mid)?.cmp(name) {
+                std::cmp::Ordering::Equal => return self.resource_at(mid).map(Some),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Iterate over all resources, in name order.
+    pub fn iter(&self) -> impl Iterator<Item = Result<Resource<'a>, &'static str>> + 'a {
+        let this = *self;
+        (0..self.count).map(move |i| this.resource_at(i))
+    }
+}
@@ -0,0 +1,67 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{borrow::Cow, collections::HashMap};
+
+/// A Python resource.
+///
+/// Field naming follows the `in_memory_*` convention: every field holds the
+/// data itself, not a reference to a file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Resource<'a> {
+    /// Fully qualified name of the resource, e.g. `pyrocko.io`.
+    pub name: Cow<'a, str>,
+
+    /// Whether the resource is a Python package.
+    pub is_package: bool,
+
+    /// Whether the resource is a namespace package.
+    pub is_namespace_package: bool,
+
+    /// Python module source code.
+    pub in_memory_source: Option<Cow<'a, [u8]>>,
+
+    /// Marshaled bytecode for optimization level 0 (without a `.pyc` header).
+    pub in_memory_bytecode: Option<Cow<'a, [u8]>>,
+
+    /// Marshaled bytecode for optimization level 1.
+    pub in_memory_bytecode_opt1: Option<Cow<'a, [u8]>>,
+
+    /// Marshaled bytecode for optimization level 2.
+    pub in_memory_bytecode_opt2: Option<Cow<'a, [u8]>>,
+
+    /// Shared library data of an extension module.
+    pub in_memory_extension_module_shared_library: Option<Cow<'a, [u8]>>,
+
+    /// Non-module files belonging to the package, keyed by relative path.
+    pub in_memory_package_resources: Option<HashMap<Cow<'a, str>, Cow<'a, [u8]>>>,
+
+    /// Distribution metadata files (`METADATA`, `entry_points.txt`, ...), keyed by file name.
+    pub in_memory_distribution_resources: Option<HashMap<Cow<'a, str>, Cow<'a, [u8]>>>,
+
+    /// A shared library that isn't an extension module, e.g. a library an
+    /// extension module links against.
+    pub in_memory_shared_library: Option<Cow<'a, [u8]>>,
+}
+
+impl<'a> Resource<'a> {
+    /// Obtain the bytecode for a given optimization level, if available.
+    pub fn bytecode(&self, optimize_level: i32) -> Option<&[u8]> {
+        match optimize_level {
+            0 => self.in_memory_bytecode.as_deref(),
+            1 => self.in_memory_bytecode_opt1.as_deref(),
+            _ => self.in_memory_bytecode_opt2.as_deref(),
+        }
+    }
+
+    /// Whether this resource represents an importable Python module.
+    pub fn is_module(&self) -> bool {
+        self.is_package
+            || self.is_namespace_package
+            || self.in_memory_source.is_some()
+            || self.in_memory_bytecode.is_some()
+            || self.in_memory_bytecode_opt1.is_some()
+            || self.in_memory_bytecode_opt2.is_some()
+    }
+}
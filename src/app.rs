@@ -1,10 +1,29 @@
-use crate::state::State;
+use crate::{
+    session::{Session, SharedSession},
+    state::State,
+};
+
+/// Storage key of the session [State].
+const STATE_KEY: &str = "state";
+
+/// Colors of markers, selected by their kind.
+const MARKER_COLORS: [egui::Color32; 4] = [
+    egui::Color32::from_rgb(220, 60, 60),
+    egui::Color32::from_rgb(60, 160, 60),
+    egui::Color32::from_rgb(60, 100, 220),
+    egui::Color32::from_rgb(220, 160, 40),
+];
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct App {
-    state: State,
+    /// Traces, markers and settings, shared with Python plugins.
+    #[serde(skip)]
+    session: SharedSession,
+    /// Which Python interpreter is in use, shown in the menu bar.
+    #[serde(skip)]
+    python_description: Option<String>,
     // // Example stuff:
     // label: String,
     //
@@ -15,7 +34,8 @@ pub struct App {
 impl Default for App {
     fn default() -> Self {
         Self {
-            state: State::default(),
+            session: SharedSession::default(),
+            python_description: None,
             // Example stuff:
             // label: "Hello World!".to_owned(),
             // value: 2.7,
@@ -29,20 +49,41 @@ impl App {
         // This is also where you can customize the look and feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
 
+        let app = Self::default();
+
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        // if let Some(storage) = cc.storage {
-        //     return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
-        // }
+        if let Some(state) = cc
+            .storage
+            .and_then(|storage| eframe::get_value::<State>(storage, STATE_KEY))
+        {
+            app.session.lock().unwrap().state = state;
+        }
 
-        Default::default()
+        app
+    }
+
+    /// Show and edit `session`, which Python plugins may access as well.
+    ///
+    /// The state restored from the previous run is carried over to it.
+    pub fn with_session(mut self, session: SharedSession) -> Self {
+        let state = std::mem::take(&mut self.session.lock().unwrap().state);
+        session.lock().unwrap().state = state;
+        self.session = session;
+        self
+    }
+
+    /// Show which Python interpreter plugins run in.
+    pub fn with_python_description(mut self, description: String) -> Self {
+        self.python_description = Some(description);
+        self
     }
 }
 
 impl eframe::App for App {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        // eframe::set_value(storage, eframe::APP_KEY, self);
+        eframe::set_value(storage, STATE_KEY, &self.session.lock().unwrap().state);
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
@@ -64,11 +105,19 @@ impl eframe::App for App {
                     ui.add_space(16.0);
                 }
                 // egui::widgets::global_dark_light_mode_buttons(ui);
+
+                if let Some(description) = &self.python_description {
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(description);
+                    });
+                }
             });
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
+            show_session(ui, &mut self.session.lock().unwrap());
+
             // ui.heading("eframe template");
             //
             // ui.horizontal(|ui| {
@@ -95,14 +144,124 @@ impl eframe::App for App {
         });
        
         egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
-            ui.add(egui::Slider::new(&mut self.state.highpass_hz, 0.0..=100.0).text("Highpass [Hz]"));
-            ui.add(egui::Slider::new(&mut self.state.lowpass_hz, 0.0..=100.0).text("Lowpass [Hz]"));
-            ui.add(egui::Slider::new(&mut self.state.gain, 0.0..=100.0).text("Gain"));
-            ui.add(egui::Slider::new(&mut self.state.rotate_deg, 0.0..=100.0).text("Rotate [deg]"));
+            let mut session = self.session.lock().unwrap();
+            let state = &mut session.state;
+            ui.add(egui::Slider::new(&mut state.highpass_hz, 0.0..=100.0).text("Highpass [Hz]"));
+            ui.add(egui::Slider::new(&mut state.lowpass_hz, 0.0..=100.0).text("Lowpass [Hz]"));
+            ui.add(egui::Slider::new(&mut state.gain, 0.0..=100.0).text("Gain"));
+            ui.add(egui::Slider::new(&mut state.rotate_deg, 0.0..=100.0).text("Rotate [deg]"));
         });
     }
 }
 
+/// Draw the traces of `session` in rows, overlaid by its markers, over the
+/// session's time window.
+///
+/// Dragging pans and scrolling zooms the time window.
+fn show_session(ui: &mut egui::Ui, session: &mut Session) {
+    let window = session.time_window;
+    ui.label(format!("{:.3} s to {:.3} s", window.tmin, window.tmax));
+
+    let (response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::drag());
+    let rect = response.rect;
+    let duration = window.tmax - window.tmin;
+    let to_x = |t: f64| rect.left() + ((t - window.tmin) / duration) as f32 * rect.width();
+    let to_t = |x: f32| window.tmin + (x - rect.left()) as f64 / rect.width() as f64 * duration;
+
+    let row_height = rect.height() / session.traces.len().max(1) as f32;
+    let row_rect = |i: usize| {
+        egui::Rect::from_min_size(
+            egui::pos2(rect.left(), rect.top() + i as f32 * row_height),
+            egui::vec2(rect.width(), row_height),
+        )
+    };
+
+    for marker in &session.markers {
+        let color = MARKER_COLORS[marker.kind.rem_euclid(MARKER_COLORS.len() as i32) as usize];
+        let left = to_x(marker.tmin);
+        let right = to_x(marker.tmax).max(left + 1.0);
+
+        // Markers without channels span all rows.
+        let rows = if marker.nslc_ids.is_empty() {
+            vec![rect.y_range()]
+        } else {
+            session
+                .traces
+                .iter()
+                .enumerate()
+                .filter(|(_, trace)| marker.nslc_ids.contains(&trace.nslc_id()))
+                .map(|(i, _)| row_rect(i).y_range())
+                .collect()
+        };
+
+        for y_range in rows {
+            painter.rect_filled(
+                egui::Rect::from_x_y_ranges(left..=right, y_range),
+                0.0,
+                color.gamma_multiply(0.4),
+            );
+        }
+    }
+
+    let stroke = egui::Stroke::new(1.0, ui.visuals().text_color());
+    for (i, trace) in session.traces.iter().enumerate() {
+        let row = row_rect(i);
+        painter.text(
+            row.left_top(),
+            egui::Align2::LEFT_TOP,
+            format!(
+                "{}.{}.{}.{}",
+                trace.network, trace.station, trace.location, trace.channel
+            ),
+            egui::FontId::monospace(12.0),
+            stroke.color,
+        );
+
+        // Only the samples within the time window, plus one on each side.
+        let first = ((window.tmin - trace.tmin) / trace.deltat).floor().max(0.0) as usize;
+        let last = (((window.tmax - trace.tmin) / trace.deltat).ceil() + 1.0).max(0.0) as usize;
+        let samples = &trace.ydata[first.min(trace.ydata.len())..last.min(trace.ydata.len())];
+
+        let amax = samples.iter().fold(0.0f32, |amax, y| amax.max(y.abs()));
+        if amax == 0.0 {
+            continue;
+        }
+
+        let scale = session.state.gain * row.height() * 0.5 / amax;
+        let points = samples
+            .iter()
+            .enumerate()
+            .map(|(j, y)| {
+                egui::pos2(
+                    to_x(trace.tmin + (first + j) as f64 * trace.deltat),
+                    row.center().y - y * scale,
+                )
+            })
+            .collect();
+
+        painter
+            .with_clip_rect(row)
+            .add(egui::Shape::line(points, stroke));
+    }
+
+    if response.dragged() {
+        let shift = to_t(rect.left()) - to_t(rect.left() + response.drag_delta().x);
+        session.time_window.tmin += shift;
+        session.time_window.tmax += shift;
+    }
+
+    if let Some(pointer) = response.hover_pos() {
+        let scroll = ui.input(|input| input.smooth_scroll_delta.y);
+        if scroll != 0.0 {
+            // Zoom around the time under the pointer.
+            let center = to_t(pointer.x);
+            let factor = (-scroll as f64 / 200.0).exp();
+            session.time_window.tmin = center + (window.tmin - center) * factor;
+            session.time_window.tmax = center + (window.tmax - center) * factor;
+        }
+    }
+}
+
 fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
     ui.horizontal(|ui| {
         ui.spacing_mut().item_spacing.x = 0.0;
@@ -0,0 +1,199 @@
+//! Butterworth filters for regularly sampled data.
+//!
+//! The filters are causal IIR filters built from second order sections and
+//! behave like `scipy.signal.butter` followed by `scipy.signal.lfilter`,
+//! which is what pyrocko uses for `Trace.highpass` and `Trace.lowpass`.
+
+use std::f64::consts::PI;
+
+/// Frequency band let through by a filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Band {
+    Lowpass,
+    Highpass,
+}
+
+/// Invalid filter parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FilterError {
+    /// The filter order is zero.
+    ZeroOrder,
+    /// The corner frequency is not between zero and the Nyquist frequency.
+    InvalidCorner { corner: f64, nyquist: f64 },
+}
+
+impl std::fmt::Display for FilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ZeroOrder => write!(f, "filter order must be at least 1"),
+            Self::InvalidCorner { corner, nyquist } => write!(
+                f,
+                "corner frequency {} Hz is not between 0 Hz and the Nyquist frequency {} Hz",
+                corner, nyquist
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// A biquad in transposed direct form II.
+#[derive(Clone, Copy, Debug)]
+struct Section {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Section {
+    fn apply(&self, data: &mut [f64]) {
+        let (mut z1, mut z2) = (0.0, 0.0);
+        for x in data.iter_mut() {
+            let y = self.b[0] * *x + z1;
+            z1 = self.b[1] * *x - self.a[0] * y + z2;
+            z2 = self.b[2] * *x - self.a[1] * y;
+            *x = y;
+        }
+    }
+}
+
+/// Design the sections of a Butterworth filter using the bilinear transform.
+fn sections(order: usize, corner: f64, deltat: f64, band: Band) -> Vec<Section> {
+    let k = (PI * corner * deltat).tan();
+    let mut sections = Vec::with_capacity(order.div_ceil(2));
+
+    for i in 0..order / 2 {
+        let q = 1.0 / (2.0 * (PI * (2 * i + 1) as f64 / (2 * order) as f64).sin());
+        let norm = 1.0 / (1.0 + k / q + k * k);
+        let a = [2.0 * (k * k - 1.0) * norm, (1.0 - k / q + k * k) * norm];
+        let b = match band {
+            Band::Lowpass => [k * k * norm, 2.0 * k * k * norm, k * k * norm],
+            Band::Highpass => [norm, -2.0 * norm, norm],
+        };
+        sections.push(Section { b, a });
+    }
+
+    if order % 2 == 1 {
+        let norm = 1.0 / (1.0 + k);
+        let a = [(k - 1.0) * norm, 0.0];
+        let b = match band {
+            Band::Lowpass => [k * norm, k * norm, 0.0],
+            Band::Highpass => [norm, -norm, 0.0],
+        };
+        sections.push(Section { b, a });
+    }
+
+    sections
+}
+
+/// Apply a Butterworth filter of `order` with `corner` frequency in Hz to
+/// `data` sampled every `deltat` seconds.
+///
+/// With `demean`, the mean is removed before filtering.
+pub fn butterworth(
+    data: &mut [f32],
+    deltat: f64,
+    order: usize,
+    corner: f64,
+    band: Band,
+    demean: bool,
+) -> Result<(), FilterError> {
+    if order == 0 {
+        return Err(FilterError::ZeroOrder);
+    }
+    let nyquist = 0.5 / deltat;
+    if !(corner > 0.0 && corner < nyquist) {
+        return Err(FilterError::InvalidCorner { corner, nyquist });
+    }
+
+    let mut work: Vec<f64> = data.iter().map(|&x| f64::from(x)).collect();
+    if demean && !work.is_empty() {
+        let mean = work.iter().sum::<f64>() / work.len() as f64;
+        work.iter_mut().for_each(|x| *x -= mean);
+    }
+
+    for section in sections(order, corner, deltat, band) {
+        section.apply(&mut work);
+    }
+
+    for (x, y) in data.iter_mut().zip(work) {
+        *x = y as f32;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn impulse(len: usize) -> Vec<f32> {
+        let mut data = vec![0.0; len];
+        data[0] = 1.0;
+        data
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn impulse_response() {
+        // At half the Nyquist frequency, scipy.signal.butter(1, 0.5) gives
+        // b = [0.5, 0.5] and a = [1, 0].
+        let mut data = impulse(4);
+        butterworth(&mut data, 1.0, 1, 0.25, Band::Lowpass, false).unwrap();
+        assert_close(&data, &[0.5, 0.5, 0.0, 0.0]);
+
+        let mut data = impulse(4);
+        butterworth(&mut data, 1.0, 1, 0.25, Band::Highpass, false).unwrap();
+        assert_close(&data, &[0.5, -0.5, 0.0, 0.0]);
+
+        // scipy.signal.butter(2, 0.5) gives b = [0.29289322, 0.58578644,
+        // 0.29289322] and a = [1, 0, 0.17157288].
+        let mut data = impulse(3);
+        butterworth(&mut data, 1.0, 2, 0.25, Band::Lowpass, false).unwrap();
+        assert_close(&data, &[0.29289322, 0.58578644, 0.24264069]);
+    }
+
+    #[test]
+    fn step_response() {
+        for order in 1..=5 {
+            let mut data = vec![1.0; 2000];
+            butterworth(&mut data, 0.01, order, 2.0, Band::Lowpass, false).unwrap();
+            assert!((data[1999] - 1.0).abs() < 1e-4, "order {}", order);
+
+            let mut data = vec![1.0; 2000];
+            butterworth(&mut data, 0.01, order, 2.0, Band::Highpass, false).unwrap();
+            assert!(data[1999].abs() < 1e-4, "order {}", order);
+        }
+    }
+
+    #[test]
+    fn demean() {
+        let mut data = vec![3.0; 10];
+        butterworth(&mut data, 0.01, 4, 2.0, Band::Lowpass, true).unwrap();
+        assert!(data.iter().all(|x| *x == 0.0));
+
+        let mut data = Vec::new();
+        butterworth(&mut data, 0.01, 4, 2.0, Band::Lowpass, true).unwrap();
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let mut data = vec![0.0; 10];
+        assert_eq!(
+            butterworth(&mut data, 0.01, 0, 2.0, Band::Lowpass, false),
+            Err(FilterError::ZeroOrder)
+        );
+
+        for corner in [0.0, -1.0, 50.0, 100.0, f64::NAN] {
+            assert!(matches!(
+                butterworth(&mut data, 0.01, 2, corner, Band::Highpass, false),
+                Err(FilterError::InvalidCorner { nyquist, .. }) if nyquist == 50.0
+            ));
+        }
+    }
+}
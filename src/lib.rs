@@ -1,5 +1,9 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+pub mod filter;
+pub mod plugins;
+pub mod python;
+pub mod session;
 mod state;
 pub use app::App;
@@ -2,7 +2,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 
-include!("/Users/roman/dev/PyOxidizer/embedtest/default_python_config.rs");
+#[cfg(embedded_python)]
+include!(env!("EMBED_PYTHON_CONFIG"));
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
@@ -22,13 +23,104 @@ fn main() -> eframe::Result<()> {
     };
 
 
-    let config = default_python_config();
+    #[cfg(embedded_python)]
+    let mut config = default_python_config();
+    // The shipped app only imports what it bundles next to the executable and
+    // the user's plugins. Development builds report other imports instead of
+    // failing them.
+    #[cfg(embedded_python)]
+    {
+        let mut allowed_paths = vec!["$ORIGIN".into()];
+        allowed_paths.extend(snuffler::plugins::plugin_dirs());
+
+        config.import_sandbox = Some(pyembed::ImportSandbox {
+            allowed_paths,
+            violation_action: if cfg!(debug_assertions) {
+                pyembed::SandboxViolationAction::Log
+            } else {
+                pyembed::SandboxViolationAction::Block
+            },
+        });
+    }
+    // The bundled OpenSSL and fontconfig look for their configuration where
+    // the distribution was built. Point them to the system's instead.
+    #[cfg(embedded_python)]
+    {
+        if std::env::var_os("SSL_CERT_FILE").is_none() {
+            if let Some(bundle) = pyembed::osutils::resolve_ca_bundle() {
+                std::env::set_var("SSL_CERT_FILE", bundle);
+            }
+        }
+        if std::env::var_os("FONTCONFIG_FILE").is_none() {
+            if let Some(file) = pyembed::osutils::resolve_fontconfig_file() {
+                std::env::set_var("FONTCONFIG_FILE", file);
+            }
+        }
+    }
+    #[cfg(not(embedded_python))]
+    let mut config = pyembed::OxidizedPythonInterpreterConfig::default();
+    // Without an embedded distribution, e.g. when `cargo xtask
+    // prepare-embed-python` was not run, use the Python found on the host.
+    config.host_python_fallback = true;
+    // Route Python `logging` and `warnings` output through `env_logger`.
+    config.log_forwarding = true;
+    // Python must not own SIGINT: Ctrl-C is forwarded to running jobs below.
+    config.forward_interrupts = true;
+    // `multiprocessing` only starts `spawn` workers by re-running this
+    // executable with `--multiprocessing-fork` if `sys.frozen` is set. When
+    // falling back to the host's Python, `sys.executable` is the host
+    // interpreter, which runs workers by itself.
+    config.sys_frozen = cfg!(embedded_python);
+    config.multiprocessing_auto_dispatch = true;
+
+    // Plugins reach the viewer through the builtin `snuffler` module.
+    let session = snuffler::session::SharedSession::default();
+    snuffler::python::install(session.clone()).expect("failed to install snuffler module");
 
     let interp = pyembed::MainPythonInterpreter::new(config).unwrap();
 
-    // is a instance.
+    // Workers and helpers of `multiprocessing` do their job and exit
+    // without opening a window.
+    if interp.is_multiprocessing() {
+        std::process::exit(interp.run());
+    }
+
+    let python_description = match interp.host_python() {
+        Some(host) => format!("host {}", host),
+        None => interp.with_gil(|py| {
+            let version = py.version_info();
+            format!(
+                "embedded Python {}.{}.{}",
+                version.major, version.minor, version.patch
+            )
+        }),
+    };
+    log::info!("running {}", python_description);
+    if let Some(terminfo_dirs) = interp.terminfo_dirs() {
+        log::debug!("{}", terminfo_dirs);
+    }
+
+    let interrupt = interp.interrupt_handle();
+
+    // The first Ctrl-C interrupts a running Python job. With no job running or
+    // on a second Ctrl-C, exit like an application without Python would.
+    #[cfg(unix)]
+    pyembed::interrupt::forward_sigint(interrupt.clone(), |action| match action {
+        pyembed::InterruptAction::Interrupted => {
+            log::info!("interrupting running Python job; press Ctrl-C again to force quit");
+        }
+        pyembed::InterruptAction::Idle | pyembed::InterruptAction::ForceQuit => {
+            std::process::exit(130);
+        }
+    })
+    .expect("failed to install SIGINT handler");
+
     interp.with_gil(|py| {
-        py.run("print('hello, world')", None, None).unwrap();
+        let _job = interrupt.enter_job(py).unwrap();
+        // Errors include the `KeyboardInterrupt` of an interrupted job.
+        if let Err(err) = py.run_bound("print('hello, world')", None, None) {
+            err.print(py);
+        }
     });
 
     // interpreter.with_gil(|py| {
@@ -41,7 +133,13 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "snuffler",
         native_options,
-        Box::new(|cc| Box::new(snuffler::App::new(cc))),
+        Box::new(|cc| {
+            Box::new(
+                snuffler::App::new(cc)
+                    .with_session(session)
+                    .with_python_description(python_description),
+            )
+        }),
     )
 }
 
@@ -0,0 +1,25 @@
+//! Locations of user plugins ("snufflings").
+
+use std::path::PathBuf;
+
+/// Environment variable listing additional plugin directories, separated like `PATH`.
+pub const PLUGIN_PATH_ENV: &str = "SNUFFLER_PLUGIN_PATH";
+
+/// Directories user plugins are loaded from.
+///
+/// As with pyrocko's snuffler, plugins live in `~/.snufflings`. Directories
+/// listed in [PLUGIN_PATH_ENV] are added.
+pub fn plugin_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }) {
+        dirs.push(PathBuf::from(home).join(".snufflings"));
+    }
+    if let Some(paths) = std::env::var_os(PLUGIN_PATH_ENV) {
+        dirs.extend(std::env::split_paths(&paths));
+    }
+
+    // Relative directories would depend on the current directory.
+    dirs.retain(|dir| dir.is_absolute());
+
+    dirs
+}
@@ -1,5 +1,733 @@
-use pyo3::types::{PyModule, PyTuple};
-use pyo3::{IntoPy, Py, PyAny, PyResult, Python};
+//! The `snuffler` builtin module, giving Python plugins access to the
+//! running viewer session.
+//!
+//! The module is compiled into the binary and registered in the
+//! interpreter's inittab, so plugins can `import snuffler` without it being
+//! on `sys.path`.
+
+use crate::filter::{self, Band};
+use crate::session::{Marker, SharedSession, Trace};
+use pyo3::exceptions::{PyException, PyIndexError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::{MutexGuard, OnceLock};
+
+type NslcId = (String, String, String, String);
+
+pyo3::create_exception!(
+    snuffler,
+    NoData,
+    PyException,
+    "Raised when an operation leaves a trace without samples."
+);
+
+/// The session the `snuffler` module operates on.
+static SESSION: OnceLock<SharedSession> = OnceLock::new();
+
+/// Make the `snuffler` module available to the Python interpreter.
+///
+/// The module operates on `session`. Only one session can be installed per
+/// process, and it must be installed before the interpreter is created: the
+/// module is appended to the inittab the interpreter starts from.
+pub fn install(session: SharedSession) -> Result<(), &'static str> {
+    SESSION
+        .set(session)
+        .map_err(|_| "a session is installed already")?;
+
+    pyo3::append_to_inittab!(snuffler);
+
+    Ok(())
+}
+
+/// Convert an array-like, e.g. a numpy array or a list of numbers, to samples.
+fn extract_samples(ydata: &Bound<'_, PyAny>) -> PyResult<Vec<f32>> {
+    let py = ydata.py();
+    let ydata = match py.import_bound("numpy") {
+        Ok(numpy) => numpy
+            .call_method1("asarray", (ydata, "float32"))?
+            .call_method0("tolist")?,
+        Err(_) => ydata.clone(),
+    };
+
+    ydata
+        .iter()?
+        .map(|sample| sample?.extract::<f32>())
+        .collect()
+}
+
+fn session() -> PyResult<MutexGuard<'static, crate::session::Session>> {
+    SESSION
+        .get()
+        .ok_or_else(|| PyRuntimeError::new_err("no snuffler session is active"))?
+        .lock()
+        .map_err(|_| PyRuntimeError::new_err("snuffler session is poisoned"))
+}
+
+/// Match `text` against a shell-style pattern with `*` and `?` wildcards.
+fn fnmatch(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), text.chars().collect());
+    // Position of the last `*` in the pattern and the text position it matched up to.
+    let mut backtrack = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Check that `tmin` to `tmax` is a valid marker span.
+fn check_span(tmin: f64, tmax: f64) -> PyResult<()> {
+    if tmin.is_nan() || tmax.is_nan() {
+        return Err(PyValueError::new_err("tmin and tmax must not be NaN"));
+    }
+    if tmax < tmin {
+        return Err(PyValueError::new_err("tmax must not be before tmin"));
+    }
+
+    Ok(())
+}
+
+fn join_nslc(nslc: &NslcId) -> String {
+    format!("{}.{}.{}.{}", nslc.0, nslc.1, nslc.2, nslc.3)
+}
+
+/// Samples of a [PyTrace], shared with the numpy arrays viewing them.
+///
+/// numpy views the samples through `__array_interface__` and keeps this
+/// object alive as the base of its views. The samples are never resized, so
+/// the pointer handed out stays valid. Without numpy, this object is used as
+/// a sequence of the samples instead.
+#[pyclass(name = "Samples", module = "snuffler", sequence)]
+struct Samples {
+    data: Vec<f32>,
+}
+
+impl Samples {
+    fn index(&self, index: isize) -> PyResult<usize> {
+        let len = self.data.len() as isize;
+        let index = if index < 0 { index + len } else { index };
+        if !(0..len).contains(&index) {
+            return Err(PyIndexError::new_err("sample index out of range"));
+        }
+
+        Ok(index as usize)
+    }
+}
+
+#[pymethods]
+impl Samples {
+    #[getter]
+    fn __array_interface__(mut slf: PyRefMut<'_, Self>) -> PyResult<Bound<'_, PyDict>> {
+        let typestr = if cfg!(target_endian = "little") {
+            "<f4"
+        } else {
+            ">f4"
+        };
+        let data = (slf.data.as_mut_ptr() as usize, false);
+
+        let dict = PyDict::new_bound(slf.py());
+        dict.set_item("version", 3)?;
+        dict.set_item("shape", (slf.data.len(),))?;
+        dict.set_item("typestr", typestr)?;
+        dict.set_item("data", data)?;
+
+        Ok(dict)
+    }
+
+    fn __len__(&self) -> usize {
+        self.data.len()
+    }
+
+    fn __getitem__(&self, index: isize) -> PyResult<f32> {
+        Ok(self.data[self.index(index)?])
+    }
+
+    fn __setitem__(&mut self, index: isize, value: f32) -> PyResult<()> {
+        let index = self.index(index)?;
+        self.data[index] = value;
+
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Samples({:?})", self.data)
+    }
+}
+
+/// A seismogram, compatible with the commonly used parts of `pyrocko.trace.Trace`.
+///
+/// `ydata` is a numpy array if numpy is available and a sequence of the
+/// samples otherwise. Either way it is a view, so `tr.ydata[i] = x` changes
+/// the trace. Assigning to `ydata` replaces the samples with a copy.
+#[pyclass(name = "Trace", module = "snuffler")]
+struct PyTrace {
+    /// Codes and timing of the trace. Its samples are kept in `ydata`.
+    header: Trace,
+    ydata: Py<Samples>,
+}
+
+impl PyTrace {
+    fn from_trace(py: Python<'_>, mut trace: Trace) -> PyResult<Self> {
+        let data = std::mem::take(&mut trace.ydata);
+
+        Ok(Self {
+            header: trace,
+            ydata: Py::new(py, Samples { data })?,
+        })
+    }
+
+    fn to_trace(&self, py: Python<'_>) -> Trace {
+        Trace {
+            ydata: self.ydata.borrow(py).data.clone(),
+            ..self.header.clone()
+        }
+    }
+
+    /// Filter the samples in place, so views of them see the result.
+    fn filter(
+        &self,
+        py: Python<'_>,
+        order: usize,
+        corner: f64,
+        band: Band,
+        demean: bool,
+    ) -> PyResult<()> {
+        let mut samples = self.ydata.borrow_mut(py);
+        filter::butterworth(
+            &mut samples.data,
+            self.header.deltat,
+            order,
+            corner,
+            band,
+            demean,
+        )
+        .map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+}
+
+#[pymethods]
+impl PyTrace {
+    #[new]
+    #[pyo3(signature = (
+        network=String::new(),
+        station="STA".to_owned(),
+        location=String::new(),
+        channel=String::new(),
+        tmin=0.0,
+        tmax=None,
+        deltat=1.0,
+        ydata=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        network: String,
+        station: String,
+        location: String,
+        channel: String,
+        tmin: f64,
+        tmax: Option<f64>,
+        deltat: f64,
+        ydata: Option<Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        if deltat <= 0.0 {
+            return Err(PyValueError::new_err("deltat must be positive"));
+        }
+
+        let ydata = match (ydata, tmax) {
+            (Some(ydata), _) => extract_samples(&ydata)?,
+            (None, Some(tmax)) if tmax >= tmin => {
+                vec![0.0; ((tmax - tmin) / deltat).round() as usize + 1]
+            }
+            (None, _) => Vec::new(),
+        };
+
+        Self::from_trace(
+            py,
+            Trace {
+                network,
+                station,
+                location,
+                channel,
+                tmin,
+                deltat,
+                ydata,
+            },
+        )
+    }
+
+    #[getter]
+    fn network(&self) -> &str {
+        &self.header.network
+    }
+
+    #[setter]
+    fn set_network(&mut self, value: String) {
+        self.header.network = value;
+    }
+
+    #[getter]
+    fn station(&self) -> &str {
+        &self.header.station
+    }
+
+    #[setter]
+    fn set_station(&mut self, value: String) {
+        self.header.station = value;
+    }
+
+    #[getter]
+    fn location(&self) -> &str {
+        &self.header.location
+    }
+
+    #[setter]
+    fn set_location(&mut self, value: String) {
+        self.header.location = value;
+    }
+
+    #[getter]
+    fn channel(&self) -> &str {
+        &self.header.channel
+    }
+
+    #[setter]
+    fn set_channel(&mut self, value: String) {
+        self.header.channel = value;
+    }
+
+    #[getter]
+    fn nslc_id(&self) -> NslcId {
+        self.header.nslc_id()
+    }
+
+    #[getter]
+    fn tmin(&self) -> f64 {
+        self.header.tmin
+    }
+
+    #[setter]
+    fn set_tmin(&mut self, value: f64) {
+        self.header.tmin = value;
+    }
+
+    #[getter]
+    fn tmax(&self, py: Python<'_>) -> f64 {
+        let len = self.data_len(py);
+        self.header.tmin + len.saturating_sub(1) as f64 * self.header.deltat
+    }
+
+    #[getter]
+    fn deltat(&self) -> f64 {
+        self.header.deltat
+    }
+
+    #[setter]
+    fn set_deltat(&mut self, value: f64) -> PyResult<()> {
+        if value <= 0.0 {
+            return Err(PyValueError::new_err("deltat must be positive"));
+        }
+        self.header.deltat = value;
+
+        Ok(())
+    }
+
+    #[getter(ydata)]
+    fn ydata_attr(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let samples = self.ydata.bind(py);
+        match py.import_bound("numpy") {
+            Ok(numpy) => Ok(numpy.call_method1("asarray", (samples,))?.unbind()),
+            Err(_) => Ok(samples.clone().into_any().unbind()),
+        }
+    }
+
+    #[setter]
+    fn set_ydata(&mut self, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let data = extract_samples(value)?;
+        self.ydata = Py::new(value.py(), Samples { data })?;
+
+        Ok(())
+    }
+
+    fn get_ydata(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.ydata_attr(py)
+    }
+
+    #[pyo3(name = "set_ydata")]
+    fn set_ydata_method(&mut self, ydata: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.set_ydata(ydata)
+    }
+
+    #[pyo3(signature = (network=None, station=None, location=None, channel=None))]
+    fn set_codes(
+        &mut self,
+        network: Option<String>,
+        station: Option<String>,
+        location: Option<String>,
+        channel: Option<String>,
+    ) {
+        let trace = &mut self.header;
+        for (code, value) in [
+            (&mut trace.network, network),
+            (&mut trace.station, station),
+            (&mut trace.location, location),
+            (&mut trace.channel, channel),
+        ] {
+            if let Some(value) = value {
+                *code = value;
+            }
+        }
+    }
+
+    fn data_len(&self, py: Python<'_>) -> usize {
+        self.ydata.borrow(py).data.len()
+    }
+
+    fn copy(&self, py: Python<'_>) -> PyResult<Self> {
+        Self::from_trace(py, self.to_trace(py))
+    }
+
+    /// Move the trace in time by `tshift` seconds.
+    fn shift(&mut self, tshift: f64) {
+        self.header.tmin += tshift;
+    }
+
+    /// Cut the trace to the samples between `tmin` and `tmax`.
+    ///
+    /// Returns the trace itself if `inplace` and a cut copy otherwise. Raises
+    /// `NoData` if no samples are left, or if `want_incomplete` is false and
+    /// the trace does not cover the whole window.
+    #[pyo3(signature = (tmin, tmax, inplace=true, include_last=false, want_incomplete=true))]
+    fn chop(
+        mut slf: PyRefMut<'_, Self>,
+        tmin: f64,
+        tmax: f64,
+        inplace: bool,
+        include_last: bool,
+        want_incomplete: bool,
+    ) -> PyResult<PyObject> {
+        let py = slf.py();
+        let trace = slf.to_trace(py);
+        if !want_incomplete && (tmin < trace.tmin || tmax > trace.tmax()) {
+            return Err(NoData::new_err("trace does not cover the requested window"));
+        }
+
+        let chopped = trace
+            .chop(tmin, tmax, include_last)
+            .ok_or_else(|| NoData::new_err("no samples in the requested window"))?;
+        let chopped = Self::from_trace(py, chopped)?;
+
+        if inplace {
+            *slf = chopped;
+            Ok(slf.into_py(py))
+        } else {
+            Ok(chopped.into_py(py))
+        }
+    }
+
+    /// Apply a causal Butterworth highpass filter of `order` at `corner` Hz.
+    #[pyo3(signature = (order, corner, demean=true))]
+    fn highpass(&self, py: Python<'_>, order: usize, corner: f64, demean: bool) -> PyResult<()> {
+        self.filter(py, order, corner, Band::Highpass, demean)
+    }
+
+    /// Apply a causal Butterworth lowpass filter of `order` at `corner` Hz.
+    #[pyo3(signature = (order, corner, demean=true))]
+    fn lowpass(&self, py: Python<'_>, order: usize, corner: f64, demean: bool) -> PyResult<()> {
+        self.filter(py, order, corner, Band::Lowpass, demean)
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> String {
+        format!(
+            "Trace({}, tmin={}, tmax={}, deltat={}, samples={})",
+            join_nslc(&self.header.nslc_id()),
+            self.header.tmin,
+            self.tmax(py),
+            self.header.deltat,
+            self.data_len(py)
+        )
+    }
+}
+
+/// A time span of interest, compatible with `pyrocko.gui.marker.Marker`.
+#[pyclass(name = "Marker", module = "snuffler")]
+#[derive(Clone)]
+struct PyMarker {
+    inner: Marker,
+}
+
+#[pymethods]
+impl PyMarker {
+    #[new]
+    #[pyo3(signature = (nslc_ids, tmin, tmax, kind=0))]
+    fn new(nslc_ids: Vec<NslcId>, tmin: f64, tmax: f64, kind: i32) -> PyResult<Self> {
+        check_span(tmin, tmax)?;
+
+        Ok(Self {
+            inner: Marker {
+                nslc_ids,
+                tmin,
+                tmax,
+                kind,
+            },
+        })
+    }
+
+    #[getter(nslc_ids)]
+    fn nslc_ids_attr(&self) -> Vec<NslcId> {
+        self.inner.nslc_ids.clone()
+    }
+
+    #[setter]
+    fn set_nslc_ids(&mut self, value: Vec<NslcId>) {
+        self.inner.nslc_ids = value;
+    }
+
+    #[getter(tmin)]
+    fn tmin_attr(&self) -> f64 {
+        self.inner.tmin
+    }
+
+    #[setter]
+    fn set_tmin(&mut self, value: f64) {
+        self.inner.tmin = value;
+    }
+
+    #[getter(tmax)]
+    fn tmax_attr(&self) -> f64 {
+        self.inner.tmax
+    }
+
+    #[setter]
+    fn set_tmax(&mut self, value: f64) {
+        self.inner.tmax = value;
+    }
+
+    #[getter]
+    fn kind(&self) -> i32 {
+        self.inner.kind
+    }
+
+    #[setter]
+    fn set_kind(&mut self, value: i32) {
+        self.inner.kind = value;
+    }
+
+    fn get_tmin(&self) -> f64 {
+        self.inner.tmin
+    }
+
+    fn get_tmax(&self) -> f64 {
+        self.inner.tmax
+    }
+
+    fn get_nslc_ids(&self) -> Vec<NslcId> {
+        self.nslc_ids_attr()
+    }
+
+    #[pyo3(name = "set_kind")]
+    fn set_kind_method(&mut self, kind: i32) {
+        self.set_kind(kind);
+    }
+
+    /// Set channels and time span at once.
+    fn set(&mut self, nslc_ids: Vec<NslcId>, tmin: f64, tmax: f64) -> PyResult<()> {
+        check_span(tmin, tmax)?;
+        self.inner.nslc_ids = nslc_ids;
+        self.inner.tmin = tmin;
+        self.inner.tmax = tmax;
+
+        Ok(())
+    }
+
+    /// Whether the channel `nslc` matches one of the marker's channel patterns.
+    fn match_nslc(&self, nslc: NslcId) -> bool {
+        let nslc = join_nslc(&nslc);
+        self.inner
+            .nslc_ids
+            .iter()
+            .any(|pattern| fnmatch(&join_nslc(pattern), &nslc))
+    }
+
+    /// The only channel of the marker.
+    fn one_nslc(&self) -> PyResult<NslcId> {
+        match self.inner.nslc_ids.as_slice() {
+            [nslc] => Ok(nslc.clone()),
+            _ => Err(PyValueError::new_err(
+                "marker does not refer to exactly one channel",
+            )),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        let nslc_ids: Vec<String> = self.inner.nslc_ids.iter().map(join_nslc).collect();
+        format!(
+            "Marker([{}], tmin={}, tmax={}, kind={})",
+            nslc_ids.join(", "),
+            self.inner.tmin,
+            self.inner.tmax,
+            self.inner.kind
+        )
+    }
+}
+
+/// Return copies of the loaded traces.
+#[pyfunction]
+fn get_traces(py: Python<'_>) -> PyResult<Vec<PyTrace>> {
+    let session = session()?;
+
+    session
+        .traces
+        .iter()
+        .map(|trace| PyTrace::from_trace(py, trace.clone()))
+        .collect()
+}
+
+/// Add a copy of `trace` to the viewer.
+#[pyfunction]
+fn add_trace(trace: PyRef<'_, PyTrace>) -> PyResult<()> {
+    let trace = trace.to_trace(trace.py());
+    session()?.traces.push(trace);
+
+    Ok(())
+}
+
+/// Return copies of the markers.
+#[pyfunction]
+fn get_markers() -> PyResult<Vec<PyMarker>> {
+    let session = session()?;
+
+    Ok(session
+        .markers
+        .iter()
+        .map(|marker| PyMarker {
+            inner: marker.clone(),
+        })
+        .collect())
+}
+
+/// Add a copy of `marker` to the viewer.
+#[pyfunction]
+fn add_marker(marker: PyRef<'_, PyMarker>) -> PyResult<()> {
+    session()?.markers.push(marker.inner.clone());
+
+    Ok(())
+}
+
+/// Remove all markers.
+#[pyfunction]
+fn clear_markers() -> PyResult<()> {
+    session()?.markers.clear();
+
+    Ok(())
+}
+
+/// Return the time window shown in the viewer as `(tmin, tmax)`.
+#[pyfunction]
+fn get_time_window() -> PyResult<(f64, f64)> {
+    let window = session()?.time_window;
+
+    Ok((window.tmin, window.tmax))
+}
+
+/// Show the time window from `tmin` to `tmax` in the viewer.
+#[pyfunction]
+fn set_time_window(tmin: f64, tmax: f64) -> PyResult<()> {
+    if !tmin.is_finite() || !tmax.is_finite() {
+        return Err(PyValueError::new_err("tmin and tmax must be finite"));
+    }
+    if tmax <= tmin {
+        return Err(PyValueError::new_err("tmax must be after tmin"));
+    }
+
+    let mut session = session()?;
+    session.time_window.tmin = tmin;
+    session.time_window.tmax = tmax;
+
+    Ok(())
+}
+
+/// Return the filter settings as a dict.
+#[pyfunction]
+fn get_filter_settings(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let session = session()?;
+    let state = &session.state;
+
+    let dict = PyDict::new_bound(py);
+    dict.set_item("highpass_hz", state.highpass_hz)?;
+    dict.set_item("lowpass_hz", state.lowpass_hz)?;
+    dict.set_item("gain", state.gain)?;
+    dict.set_item("rotate_deg", state.rotate_deg)?;
+
+    Ok(dict)
+}
+
+/// Change filter settings. Settings not given are left as they are.
+#[pyfunction]
+#[pyo3(signature = (highpass_hz=None, lowpass_hz=None, gain=None, rotate_deg=None))]
+fn set_filter_settings(
+    highpass_hz: Option<f32>,
+    lowpass_hz: Option<f32>,
+    gain: Option<f32>,
+    rotate_deg: Option<f32>,
+) -> PyResult<()> {
+    let mut session = session()?;
+    let state = &mut session.state;
+
+    if let Some(value) = highpass_hz {
+        state.highpass_hz = value;
+    }
+    if let Some(value) = lowpass_hz {
+        state.lowpass_hz = value;
+    }
+    if let Some(value) = gain {
+        state.gain = value;
+    }
+    if let Some(value) = rotate_deg {
+        state.rotate_deg = value;
+    }
+
+    Ok(())
+}
+
+#[pymodule]
+fn snuffler(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyTrace>()?;
+    m.add_class::<PyMarker>()?;
+    m.add("NoData", m.py().get_type_bound::<NoData>())?;
+
+    m.add_function(wrap_pyfunction!(get_traces, m)?)?;
+    m.add_function(wrap_pyfunction!(add_trace, m)?)?;
+    m.add_function(wrap_pyfunction!(get_markers, m)?)?;
+    m.add_function(wrap_pyfunction!(add_marker, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_markers, m)?)?;
+    m.add_function(wrap_pyfunction!(get_time_window, m)?)?;
+    m.add_function(wrap_pyfunction!(set_time_window, m)?)?;
+    m.add_function(wrap_pyfunction!(get_filter_settings, m)?)?;
+    m.add_function(wrap_pyfunction!(set_filter_settings, m)?)?;
+
+    Ok(())
+}
 
 // pub fn call_function<T: IntoPy<Py<PyTuple>>>(
 //     function_name: &str,
@@ -19,3 +747,148 @@ use pyo3::{IntoPy, Py, PyAny, PyResult, Python};
 //         Ok(())
 //     })
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyList;
+
+    fn new_trace(py: Python<'_>, ydata: Bound<'_, PyAny>) -> PyTrace {
+        PyTrace::new(
+            py,
+            String::new(),
+            "STA".to_owned(),
+            String::new(),
+            String::new(),
+            0.0,
+            None,
+            1.0,
+            Some(ydata),
+        )
+        .unwrap()
+    }
+
+    fn nslc(network: &str, station: &str, location: &str, channel: &str) -> NslcId {
+        (
+            network.to_owned(),
+            station.to_owned(),
+            location.to_owned(),
+            channel.to_owned(),
+        )
+    }
+
+    #[test]
+    fn ydata_array_likes() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let expected = vec![1.0, 2.5, -3.0];
+            let list = PyList::new_bound(py, [1.0, 2.5, -3.0]);
+            let array = py
+                .import_bound("array")
+                .unwrap()
+                .call_method1("array", ("d", &list))
+                .unwrap();
+
+            let mut trace = new_trace(py, list.clone().into_any());
+            assert_eq!(trace.to_trace(py).ydata, expected);
+
+            trace.set_ydata(&array).unwrap();
+            assert_eq!(trace.to_trace(py).ydata, expected);
+
+            if let Ok(numpy) = py.import_bound("numpy") {
+                trace.set_ydata(&PyList::empty_bound(py)).unwrap();
+                let ndarray = numpy.call_method1("asarray", (&list, "float64")).unwrap();
+                trace.set_ydata(&ndarray).unwrap();
+                assert_eq!(trace.to_trace(py).ydata, expected);
+            }
+
+            assert!(trace
+                .set_ydata(&pyo3::types::PyString::new_bound(py, "abc"))
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn ydata_view() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let trace = new_trace(py, PyList::new_bound(py, [1.0, 2.0, 3.0]).into_any());
+
+            let ydata = trace.ydata_attr(py).unwrap().into_bound(py);
+            ydata.set_item(0, 5.0).unwrap();
+            ydata.set_item(-1, 7.0).unwrap();
+            assert_eq!(trace.to_trace(py).ydata, vec![5.0, 2.0, 7.0]);
+            assert!(ydata.set_item(3, 0.0).is_err());
+
+            // Filtering works in place, so views see the result.
+            trace.lowpass(py, 1, 0.25, false).unwrap();
+            let samples: Vec<f32> = ydata
+                .iter()
+                .unwrap()
+                .map(|y| y.unwrap().extract().unwrap())
+                .collect();
+            assert_eq!(samples, vec![2.5, 3.5, 4.5]);
+        });
+    }
+
+    #[test]
+    fn fnmatch_patterns() {
+        assert!(fnmatch("", ""));
+        assert!(fnmatch("*", ""));
+        assert!(fnmatch("*", "GE.STA..BHZ"));
+        assert!(fnmatch("GE.STA..BHZ", "GE.STA..BHZ"));
+        assert!(fnmatch("GE.*.*.BH?", "GE.STA..BHZ"));
+        assert!(fnmatch("*.BH*", "GE.STA..BHZ"));
+        assert!(fnmatch("a*b*c", "aXbYbZc"));
+        assert!(fnmatch("a**c", "ac"));
+
+        assert!(!fnmatch("", "a"));
+        assert!(!fnmatch("GE.*.*.BH?", "GE.STA..BHZZ"));
+        assert!(!fnmatch("GE.STA..BHZ", "ge.sta..bhz"));
+        assert!(!fnmatch("a*b*c", "aXbYbZ"));
+        assert!(!fnmatch("?", ""));
+    }
+
+    #[test]
+    fn marker() {
+        let mut marker = PyMarker::new(vec![nslc("GE", "*", "", "BH?")], 1.0, 2.0, 1).unwrap();
+        assert!(marker.match_nslc(nslc("GE", "STA", "", "BHZ")));
+        assert!(!marker.match_nslc(nslc("GE", "STA", "", "HHZ")));
+        assert!(!marker.match_nslc(nslc("GE", "STA", "00", "BHZ")));
+        assert_eq!(marker.one_nslc().unwrap(), nslc("GE", "*", "", "BH?"));
+
+        marker.set(vec![], 3.0, 3.0).unwrap();
+        assert_eq!((marker.get_tmin(), marker.get_tmax()), (3.0, 3.0));
+        assert!(!marker.match_nslc(nslc("GE", "STA", "", "BHZ")));
+        assert!(marker.one_nslc().is_err());
+
+        assert!(marker.set(vec![], 2.0, 1.0).is_err());
+        assert!(marker.set(vec![], f64::NAN, 1.0).is_err());
+        assert_eq!((marker.get_tmin(), marker.get_tmax()), (3.0, 3.0));
+
+        assert!(PyMarker::new(vec![], 2.0, 1.0, 0).is_err());
+        assert!(PyMarker::new(vec![], 1.0, f64::NAN, 0).is_err());
+    }
+
+    #[test]
+    fn time_window() {
+        pyo3::prepare_freethreaded_python();
+        SESSION.get_or_init(SharedSession::default);
+
+        for (tmin, tmax) in [
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (f64::NAN, 1.0),
+            (0.0, f64::NAN),
+            (0.0, f64::INFINITY),
+        ] {
+            let err = set_time_window(tmin, tmax).unwrap_err();
+            Python::with_gil(|py| assert!(err.is_instance_of::<PyValueError>(py)));
+        }
+
+        set_time_window(1.0, 2.0).unwrap();
+        assert_eq!(get_time_window().unwrap(), (1.0, 2.0));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::state::State;
+use std::sync::{Arc, Mutex};
+
+/// A seismogram of a single channel, sampled at a regular interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    pub network: String,
+    pub station: String,
+    pub location: String,
+    pub channel: String,
+    /// Time of the first sample, in seconds since the epoch.
+    pub tmin: f64,
+    /// Sampling interval in seconds.
+    pub deltat: f64,
+    pub ydata: Vec<f32>,
+}
+
+impl Trace {
+    /// Network, station, location and channel code.
+    pub fn nslc_id(&self) -> (String, String, String, String) {
+        (
+            self.network.clone(),
+            self.station.clone(),
+            self.location.clone(),
+            self.channel.clone(),
+        )
+    }
+
+    /// Time of the last sample.
+    pub fn tmax(&self) -> f64 {
+        self.tmin + self.ydata.len().saturating_sub(1) as f64 * self.deltat
+    }
+
+    /// Copy of the samples between `tmin` and `tmax`.
+    ///
+    /// Both times are rounded to the nearest sample. The sample at `tmax` is
+    /// only included with `include_last`. Returns [None] if no samples are in
+    /// the window.
+    pub fn chop(&self, tmin: f64, tmax: f64, include_last: bool) -> Option<Trace> {
+        let len = self.ydata.len() as f64;
+        let ibeg = ((tmin - self.tmin) / self.deltat).round().max(0.0);
+        let iend = ((tmax - self.tmin) / self.deltat).round() + f64::from(u8::from(include_last));
+        let iend = iend.min(len);
+        if ibeg >= iend {
+            return None;
+        }
+
+        let (ibeg, iend) = (ibeg as usize, iend as usize);
+        Some(Trace {
+            network: self.network.clone(),
+            station: self.station.clone(),
+            location: self.location.clone(),
+            channel: self.channel.clone(),
+            tmin: self.tmin + ibeg as f64 * self.deltat,
+            deltat: self.deltat,
+            ydata: self.ydata[ibeg..iend].to_vec(),
+        })
+    }
+}
+
+/// A time span of interest, optionally tied to channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+    /// Channels the marker applies to. Empty for all channels.
+    pub nslc_ids: Vec<(String, String, String, String)>,
+    pub tmin: f64,
+    pub tmax: f64,
+    /// Kind of the marker, which selects its color.
+    pub kind: i32,
+}
+
+/// The time span shown in the viewer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeWindow {
+    pub tmin: f64,
+    pub tmax: f64,
+}
+
+impl Default for TimeWindow {
+    fn default() -> Self {
+        Self {
+            tmin: 0.0,
+            tmax: 60.0,
+        }
+    }
+}
+
+/// Data of a viewer session, shared between the GUI and Python.
+#[derive(Debug, Default)]
+pub struct Session {
+    pub traces: Vec<Trace>,
+    pub markers: Vec<Marker>,
+    pub time_window: TimeWindow,
+    pub state: State,
+}
+
+/// A [Session] accessible from the GUI and the Python interpreter.
+pub type SharedSession = Arc<Mutex<Session>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(tmin: f64, deltat: f64, len: usize) -> Trace {
+        Trace {
+            network: "GE".to_owned(),
+            station: "STA".to_owned(),
+            location: String::new(),
+            channel: "BHZ".to_owned(),
+            tmin,
+            deltat,
+            ydata: (0..len).map(|i| i as f32).collect(),
+        }
+    }
+
+    #[test]
+    fn chop() {
+        let trace = trace(10.0, 0.5, 10);
+        assert_eq!(trace.tmax(), 14.5);
+
+        let chopped = trace.chop(11.0, 12.0, false).unwrap();
+        assert_eq!(chopped.tmin, 11.0);
+        assert_eq!(chopped.ydata, vec![2.0, 3.0]);
+        assert_eq!(chopped.nslc_id(), trace.nslc_id());
+
+        let chopped = trace.chop(11.0, 12.0, true).unwrap();
+        assert_eq!(chopped.ydata, vec![2.0, 3.0, 4.0]);
+
+        // Times are rounded to the nearest sample.
+        let chopped = trace.chop(11.1, 11.9, false).unwrap();
+        assert_eq!(chopped.tmin, 11.0);
+        assert_eq!(chopped.ydata, vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn chop_bounds() {
+        let trace = trace(10.0, 0.5, 10);
+
+        // Windows reaching past the trace are cut to it.
+        let chopped = trace.chop(0.0, 100.0, false).unwrap();
+        assert_eq!(chopped, trace);
+
+        let chopped = trace.chop(14.0, 100.0, true).unwrap();
+        assert_eq!(chopped.ydata, vec![8.0, 9.0]);
+
+        assert_eq!(trace.chop(20.0, 30.0, true), None);
+        assert_eq!(trace.chop(0.0, 5.0, true), None);
+        assert_eq!(trace.chop(12.0, 12.0, false), None);
+        assert_eq!(trace.chop(12.0, 11.0, true), None);
+    }
+}
@@ -0,0 +1,309 @@
+//! Compilation of Python source code to bytecode.
+//!
+//! Compilation is performed by a long-running Python process of the target
+//! distribution, so bytecode matches the interpreter that will execute it.
+//! Results are cached on disk, keyed by a hash of the inputs, so repeated
+//! builds only compile modified sources.
+//!
+//! Sources are handed to the compiler as bytes. So Python itself honors the
+//! PEP 263 encoding declaration (see [super::python_source_encoding]) and a
+//! BOM, exactly as it does when importing a file.
+
+use color_eyre::eyre::{self, WrapErr};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Python code run by [BytecodeCompiler].
+///
+/// Announces `importlib.util.MAGIC_NUMBER`, then compiles requests read from
+/// stdin until EOF. A request is a `<name length> <source length> <optimize>`
+/// line followed by the UTF-8 name and the source bytes. The response is an
+/// `ok <length>` or `error <length>` line followed by the marshaled code object
+/// or the error message.
+const COMPILER_SOURCE: &str = r#"
+import importlib.util
+import marshal
+import sys
+
+stdin = sys.stdin.buffer
+stdout = sys.stdout.buffer
+
+stdout.write(importlib.util.MAGIC_NUMBER.hex().encode("ascii") + b"\n")
+stdout.flush()
+
+while True:
+    header = stdin.readline()
+    if not header:
+        break
+
+    name_len, source_len, optimize = (int(v) for v in header.split())
+    name = stdin.read(name_len).decode("utf-8")
+    source = stdin.read(source_len)
+
+    try:
+        code = compile(source, name, "exec", dont_inherit=True, optimize=optimize)
+        status, data = b"ok", marshal.dumps(code)
+    except Exception as e:
+        status, data = b"error", ("%s: %s" % (type(e).__name__, e)).encode("utf-8")
+
+    stdout.write(b"%s %d\n" % (status, len(data)))
+    stdout.write(data)
+    stdout.flush()
+"#;
+
+/// An optimization level for Python bytecode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BytecodeOptimizationLevel {
+    Zero,
+    One,
+    Two,
+}
+
+impl From<BytecodeOptimizationLevel> for i32 {
+    fn from(level: BytecodeOptimizationLevel) -> Self {
+        match level {
+            BytecodeOptimizationLevel::Zero => 0,
+            BytecodeOptimizationLevel::One => 1,
+            BytecodeOptimizationLevel::Two => 2,
+        }
+    }
+}
+
+/// Compute the cache key of a compilation.
+fn cache_key(magic: &str, name: &str, source: &[u8], level: BytecodeOptimizationLevel) -> String {
+    let mut hasher = Sha256::new();
+    for part in [magic.as_bytes(), name.as_bytes(), source] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.update([i32::from(level) as u8]);
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Compiles Python source code to marshaled code objects.
+pub struct BytecodeCompiler {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+
+    /// Hex encoded bytecode magic number of the compiling interpreter.
+    magic: String,
+
+    /// Directory holding cached compilation results.
+    cache_dir: PathBuf,
+}
+
+impl BytecodeCompiler {
+    /// Start a compiler using the given Python executable.
+    ///
+    /// Compilation results are cached in `cache_dir`, which is created if needed.
+    pub fn new(python_exe: &Path, cache_dir: &Path) -> eyre::Result<Self> {
+        std::fs::create_dir_all(cache_dir)
+            .wrap_err_with(|| format!("creating directory {}", cache_dir.display()))?;
+
+        let mut child = Command::new(python_exe)
+            .args(["-I", "-S", "-c", COMPILER_SOURCE])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .wrap_err_with(|| format!("running {}", python_exe.display()))?;
+
+        let stdin = child.stdin.take();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let mut magic = String::new();
+        stdout
+            .read_line(&mut magic)
+            .wrap_err("reading bytecode magic number")?;
+        let magic = magic.trim().to_string();
+
+        if magic.is_empty() {
+            return Err(eyre::eyre!(
+                "{} did not start the bytecode compiler",
+                python_exe.display()
+            ));
+        }
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+            magic,
+            cache_dir: cache_dir.to_path_buf(),
+        })
+    }
+
+    /// Compile Python source code to a marshaled code object.
+    ///
+    /// `name` is recorded as the filename of the code object.
+    pub fn compile(
+        &mut self,
+        name: &str,
+        source: &[u8],
+        level: BytecodeOptimizationLevel,
+    ) -> eyre::Result<Vec<u8>> {
+        let cache_path = self
+            .cache_dir
+            .join(cache_key(&self.magic, name, source, level));
+
+        if let Ok(data) = std::fs::read(&cache_path) {
+            return Ok(data);
+        }
+
+        let data = self.compile_uncached(name, source, level)?;
+
+        // Write to a temporary file and rename so concurrent or interrupted
+        // runs never observe partial cache entries.
+        let temp_path = cache_path.with_extension(format!("tmp{}", std::process::id()));
+        std::fs::write(&temp_path, &data)
+            .and_then(|_| std::fs::rename(&temp_path, &cache_path))
+            .wrap_err_with(|| format!("writing {}", cache_path.display()))?;
+
+        Ok(data)
+    }
+
+    fn compile_uncached(
+        &mut self,
+        name: &str,
+        source: &[u8],
+        level: BytecodeOptimizationLevel,
+    ) -> eyre::Result<Vec<u8>> {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| eyre::eyre!("bytecode compiler is closed"))?;
+
+        writeln!(
+            stdin,
+            "{} {} {}",
+            name.len(),
+            source.len(),
+            i32::from(level)
+        )
+        .and_then(|_| stdin.write_all(name.as_bytes()))
+        .and_then(|_| stdin.write_all(source))
+        .and_then(|_| stdin.flush())
+        .wrap_err("sending source to bytecode compiler")?;
+
+        let mut header = String::new();
+        self.stdout
+            .read_line(&mut header)
+            .wrap_err("reading bytecode compiler response")?;
+
+        let (status, len) = header
+            .trim()
+            .split_once(' ')
+            .and_then(|(status, len)| Some((status, len.parse::<usize>().ok()?)))
+            .ok_or_else(|| eyre::eyre!("bytecode compiler exited unexpectedly"))?;
+
+        let mut data = vec![0; len];
+        self.stdout
+            .read_exact(&mut data)
+            .wrap_err("reading bytecode compiler response")?;
+
+        match status {
+            "ok" => Ok(data),
+            _ => Err(eyre::eyre!(
+                "compiling {}: {}",
+                name,
+                String::from_utf8_lossy(&data)
+            )),
+        }
+    }
+}
+
+impl Drop for BytecodeCompiler {
+    fn drop(&mut self) {
+        // Closing stdin makes the compiler exit.
+        self.stdin.take();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declared_encoding() {
+        let cache_dir = std::env::temp_dir().join(format!("xtask-bytecode-{}", std::process::id()));
+        let mut compiler = BytecodeCompiler::new(Path::new("python3"), &cache_dir).unwrap();
+        let level = BytecodeOptimizationLevel::Zero;
+
+        compiler
+            .compile("utf8", b"x = '\xc3\xa9'\n", level)
+            .unwrap();
+        compiler
+            .compile("bom", b"\xef\xbb\xbfx = '\xc3\xa9'\n", level)
+            .unwrap();
+        assert!(compiler.compile("invalid", b"x = '\xe9'\n", level).is_err());
+
+        for encoding in ["latin-1", "iso-8859-1", "cp1252"] {
+            let source = format!("# -*- coding: {} -*-\n", encoding).into_bytes();
+            compiler
+                .compile(
+                    encoding,
+                    &[source.as_slice(), b"x = '\xe9'\n"].concat(),
+                    level,
+                )
+                .unwrap();
+        }
+
+        // ASCII rejects non-ASCII bytes instead of decoding them as windows-1252.
+        assert!(compiler
+            .compile("ascii", b"# coding: ascii\nx = '\xe9'\n", level)
+            .is_err());
+
+        // The declaration is only honored on the first two lines.
+        assert!(compiler
+            .compile("late", b"\n\n# coding: latin-1\nx = '\xe9'\n", level)
+            .is_err());
+
+        drop(compiler);
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn cache_key_inputs() {
+        let key = cache_key("cb0d0d0a", "foo", b"x = 1", BytecodeOptimizationLevel::Zero);
+        assert_eq!(key.len(), 64);
+
+        for other in [
+            cache_key("cb0d0d0a", "foo", b"x = 1", BytecodeOptimizationLevel::One),
+            cache_key("cb0d0d0a", "bar", b"x = 1", BytecodeOptimizationLevel::Zero),
+            cache_key("cb0d0d0a", "foo", b"x = 2", BytecodeOptimizationLevel::Zero),
+            cache_key("a70d0d0a", "foo", b"x = 1", BytecodeOptimizationLevel::Zero),
+            cache_key("cb0d0d0a", "foox", b" = 1", BytecodeOptimizationLevel::Zero),
+        ] {
+            assert_ne!(key, other);
+        }
+    }
+
+    #[test]
+    fn policy_levels() {
+        let mut policy = super::super::PythonPackagingPolicy::default();
+        assert_eq!(
+            policy.bytecode_optimization_levels("foo"),
+            vec![BytecodeOptimizationLevel::Zero]
+        );
+
+        policy.set_bytecode_optimize_levels(false, true, true);
+        assert_eq!(
+            policy.bytecode_optimization_levels("foo"),
+            vec![
+                BytecodeOptimizationLevel::One,
+                BytecodeOptimizationLevel::Two
+            ]
+        );
+
+        policy.register_no_bytecode_module("foo");
+        assert!(policy.bytecode_optimization_levels("foo").is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+//! Detection of `__file__` usage in Python source code.
+//!
+//! Modules imported from memory have no `__file__`, so code deriving paths
+//! from it, e.g. to read data files shipped next to it, breaks. This finds
+//! such usage so affected packages can be reported or placed on the
+//! filesystem instead.
+//!
+//! Sources are scanned as raw bytes. The encodings Python accepts for source
+//! files are ASCII compatible, so `__file__`, quotes and comments can be
+//! found without decoding them.
+
+use std::fmt;
+
+/// An occurrence of `__file__` in Python source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DunderFileLocation {
+    /// 1-based line number.
+    pub line: usize,
+
+    /// 1-based column, in bytes.
+    pub column: usize,
+
+    /// The source line, without surrounding whitespace.
+    ///
+    /// Bytes that aren't valid UTF-8 are replaced.
+    pub text: String,
+}
+
+/// `__file__` usage in a collected module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DunderFileUsage {
+    /// Full name of the module.
+    pub module: String,
+
+    /// Path of the module's source file, relative to `site-packages`.
+    pub path: String,
+
+    /// Where `__file__` occurs.
+    pub location: DunderFileLocation,
+}
+
+impl fmt::Display for DunderFileUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.path, self.location.line, self.location.column, self.location.text
+        )
+    }
+}
+
+/// Path of a module's source file relative to `site-packages`.
+pub fn module_source_path(name: &str, is_package: bool) -> String {
+    let path = name.replace('.', "/");
+
+    if is_package {
+        format!("{}/__init__.py", path)
+    } else {
+        format!("{}.py", path)
+    }
+}
+
+/// Whether a byte may be part of an identifier.
+///
+/// Non-ASCII bytes are assumed to be, as they can only occur in identifiers,
+/// strings and comments.
+fn is_identifier_byte(c: u8) -> bool {
+    c == b'_' || c.is_ascii_alphanumeric() || !c.is_ascii()
+}
+
+/// Find occurrences of `__file__` as an identifier in Python source.
+///
+/// Comments and string literals are skipped, except for the replacement
+/// fields of f-strings, which are searched as a whole.
+pub fn find_dunder_file_locations(source: &[u8]) -> Vec<DunderFileLocation> {
+    let chars = source;
+    let mut found = vec![];
+
+    // Find whole-word matches in chars[start..end].
+    let search = |start: usize, end: usize, found: &mut Vec<usize>| {
+        let needle = b"__file__";
+        let mut i = start;
+
+        while i + needle.len() <= end {
+            if &chars[i..i + needle.len()] == needle
+                && (i == 0 || !is_identifier_byte(chars[i - 1]))
+                && chars
+                    .get(i + needle.len())
+                    .map_or(true, |c| !is_identifier_byte(*c))
+            {
+                found.push(i);
+                i += needle.len();
+            } else {
+                i += 1;
+            }
+        }
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == b'#' {
+            while i < chars.len() && chars[i] != b'\n' {
+                i += 1;
+            }
+        } else if is_identifier_byte(c) {
+            let start = i;
+            while i < chars.len() && is_identifier_byte(chars[i]) {
+                i += 1;
+            }
+
+            let word = &chars[start..i];
+            let is_prefix = word.len() <= 2
+                && word
+                    .iter()
+                    .all(|c| matches!(c.to_ascii_lowercase(), b'r' | b'u' | b'b' | b'f'));
+
+            if word == b"__file__" {
+                found.push(start);
+            } else if is_prefix && matches!(chars.get(i), Some(b'\'' | b'"')) {
+                let is_fstring = word.iter().any(|c| c.to_ascii_lowercase() == b'f');
+                let (content_start, end) = skip_string(&chars, i);
+
+                if is_fstring {
+                    search(content_start, end, &mut found);
+                }
+
+                i = end;
+            }
+        } else if c == b'\'' || c == b'"' {
+            i = skip_string(&chars, i).1;
+        } else {
+            i += 1;
+        }
+    }
+
+    let lines = source.split(|c| *c == b'\n').collect::<Vec<_>>();
+    let mut line_starts = vec![0];
+    line_starts.extend(
+        chars
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c == b'\n')
+            .map(|(i, _)| i + 1),
+    );
+
+    found
+        .into_iter()
+        .map(|index| {
+            let line = line_starts.partition_point(|start| *start <= index);
+
+            DunderFileLocation {
+                line,
+                column: index - line_starts[line - 1] + 1,
+                text: String::from_utf8_lossy(lines[line - 1]).trim().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Skip a string literal whose opening quote is at `start`.
+///
+/// Returns the index of the first content character and the index after the
+/// closing quote.
+fn skip_string(chars: &[u8], start: usize) -> (usize, usize) {
+    let quote = chars[start];
+    let triple = chars.get(start..start + 3) == Some(&[quote; 3][..]);
+    let delimiter_len = if triple { 3 } else { 1 };
+
+    let mut i = start + delimiter_len;
+    let content_start = i;
+
+    while i < chars.len() {
+        match chars[i] {
+            b'\\' => i += 2,
+            // Unterminated single-quoted strings end at the line.
+            b'\n' if !triple => return (content_start, i),
+            c if c == quote && (!triple || chars.get(i..i + 3) == Some(&[quote; 3][..])) => {
+                return (content_start, i + delimiter_len);
+            }
+            _ => i += 1,
+        }
+    }
+
+    (content_start, chars.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(source: &str) -> Vec<(usize, usize)> {
+        find_dunder_file_locations(source.as_bytes())
+            .into_iter()
+            .map(|l| (l.line, l.column))
+            .collect()
+    }
+
+    #[test]
+    fn identifiers() {
+        let source = "import os\n\ndata = os.path.join(os.path.dirname(__file__), 'data')\n";
+        assert_eq!(
+            find_dunder_file_locations(source.as_bytes()),
+            vec![DunderFileLocation {
+                line: 3,
+                column: 37,
+                text: "data = os.path.join(os.path.dirname(__file__), 'data')".to_string(),
+            }]
+        );
+
+        assert_eq!(positions("x = pyrocko.__file__"), vec![(1, 13)]);
+        assert_eq!(positions("__file__"), vec![(1, 1)]);
+        assert!(positions("my__file__ = __file__s").is_empty());
+    }
+
+    #[test]
+    fn comments_and_strings() {
+        assert!(positions("# uses __file__\n").is_empty());
+        assert!(positions("x = '__file__'\ny = \"__file__\"\n").is_empty());
+        assert!(positions("\"\"\"Docs mention __file__.\n\nAgain: __file__\n\"\"\"\n").is_empty());
+        assert!(positions("x = r'\\'__file__'").is_empty());
+        assert!(positions("x = b'__file__' # __file__").is_empty());
+
+        // Code after strings is still searched.
+        assert_eq!(positions("x = '#'; y = __file__"), vec![(1, 14)]);
+        assert_eq!(positions("'''a\n''' + __file__"), vec![(2, 7)]);
+    }
+
+    #[test]
+    fn fstrings() {
+        assert_eq!(positions("x = f'{__file__}'"), vec![(1, 8)]);
+        assert_eq!(positions("x = rf\"\"\"\n{__file__}\"\"\""), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn undecoded_source() {
+        // Latin-1 isn't valid UTF-8, but needn't be decoded.
+        let source = b"# -*- coding: latin-1 -*-\nx = '\xe9'; y = __file__\n";
+        assert_eq!(
+            find_dunder_file_locations(source),
+            vec![DunderFileLocation {
+                line: 2,
+                column: 14,
+                text: "x = '\u{fffd}'; y = __file__".to_string(),
+            }]
+        );
+
+        // Non-ASCII identifier characters are part of the word.
+        assert!(find_dunder_file_locations("\u{e9}__file__ = 1".as_bytes()).is_empty());
+        assert_eq!(positions("\u{e9} = __file__"), vec![(1, 6)]);
+    }
+
+    #[test]
+    fn usage_display() {
+        let usage = DunderFileUsage {
+            module: "pyrocko.util".to_string(),
+            path: module_source_path("pyrocko.util", false),
+            location: DunderFileLocation {
+                line: 12,
+                column: 5,
+                text: "here = __file__".to_string(),
+            },
+        };
+
+        assert_eq!(usage.to_string(), "pyrocko/util.py:12:5: here = __file__");
+        assert_eq!(module_source_path("pyrocko", true), "pyrocko/__init__.py");
+    }
+}
@@ -0,0 +1,517 @@
+//! Analysis of whether collected extension modules can be loaded.
+//!
+//! An extension module that was collected fine can still fail at import time:
+//! a shared library it needs may not be shipped, or it may reference Python
+//! symbols the embedding binary doesn't export. This inspects the ELF
+//! extension modules held by a [PythonResourceCollector] so such problems are
+//! reported at build time rather than by users of the bundle.
+
+use super::{
+    FileData, PrePackagedResource, PythonPackagingPolicy, PythonResourceCollector,
+    StandaloneDistribution,
+};
+use color_eyre::eyre::{self, WrapErr};
+use object::elf::{self, FileHeader32, FileHeader64};
+use object::read::elf::{Dyn, FileHeader};
+use object::{Endianness, FileKind, Object, ObjectSymbol, SymbolScope};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Shared libraries expected on every Linux system.
+///
+/// This is the manylinux policy list: wheels may depend on these without
+/// shipping them.
+const SYSTEM_LIBRARIES: &[&str] = &[
+    "libc.so.6",
+    "libm.so.6",
+    "libdl.so.2",
+    "librt.so.1",
+    "libpthread.so.0",
+    "libutil.so.1",
+    "libnsl.so.1",
+    "libresolv.so.2",
+    "libcrypt.so.1",
+    "libgcc_s.so.1",
+    "libstdc++.so.6",
+    "libX11.so.6",
+    "libXext.so.6",
+    "libXrender.so.1",
+    "libICE.so.6",
+    "libSM.so.6",
+    "libGL.so.1",
+    "libgobject-2.0.so.0",
+    "libgthread-2.0.so.0",
+    "libglib-2.0.so.0",
+];
+
+/// Whether a `NEEDED` entry names a system library.
+///
+/// `allowed` are further libraries assumed to be present on target systems.
+fn is_system_library(name: &str, allowed: &[String]) -> bool {
+    SYSTEM_LIBRARIES.contains(&name)
+        || name.starts_with("ld-linux")
+        || allowed.iter().any(|allowed| allowed == name)
+}
+
+/// Whether a symbol is part of the Python C API, i.e. provided by libpython.
+fn is_python_symbol(name: &str) -> bool {
+    name.starts_with("Py") || name.starts_with("_Py")
+}
+
+/// Dynamic linking information of an ELF shared library.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ElfDynamicInfo {
+    /// Shared libraries named by `DT_NEEDED` entries.
+    pub needed: Vec<String>,
+
+    /// Dynamic symbols the library requires. Weak references are excluded.
+    pub undefined_symbols: BTreeSet<String>,
+
+    /// Global dynamic symbols the library defines.
+    pub defined_symbols: BTreeSet<String>,
+}
+
+fn parse_elf_dynamic<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> eyre::Result<ElfDynamicInfo> {
+    let header = Elf::parse(data)?;
+    let endian = header.endian()?;
+    let sections = header.sections(endian, data)?;
+
+    let mut info = ElfDynamicInfo::default();
+
+    if let Some((entries, link)) = sections.dynamic(endian, data)? {
+        let strings = sections.strings(endian, data, link)?;
+
+        for entry in entries {
+            if entry.tag32(endian) == Some(elf::DT_NEEDED) {
+                let name = entry.string(endian, strings)?;
+                info.needed.push(String::from_utf8_lossy(name).to_string());
+            }
+        }
+    }
+
+    let file = object::File::parse(data)?;
+    for symbol in file.dynamic_symbols() {
+        let name = symbol.name()?;
+        if name.is_empty() {
+            continue;
+        }
+
+        if symbol.is_undefined() {
+            if !symbol.is_weak() {
+                info.undefined_symbols.insert(name.to_string());
+            }
+        } else if symbol.is_global() {
+            info.defined_symbols.insert(name.to_string());
+        }
+    }
+
+    Ok(info)
+}
+
+/// Obtain dynamic linking information from ELF shared library data.
+///
+/// Returns `Ok(None)` if the data isn't ELF.
+pub fn elf_dynamic_info(data: &[u8]) -> eyre::Result<Option<ElfDynamicInfo>> {
+    match FileKind::parse(data) {
+        Ok(FileKind::Elf32) => parse_elf_dynamic::<FileHeader32<Endianness>>(data).map(Some),
+        Ok(FileKind::Elf64) => parse_elf_dynamic::<FileHeader64<Endianness>>(data).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Collect the global symbols defined by the object files of libpython.
+pub fn libpython_exported_symbols(dist: &StandaloneDistribution) -> eyre::Result<BTreeSet<String>> {
+    let mut symbols = BTreeSet::new();
+
+    for path in dist.objs_core.values() {
+        let data = std::fs::read(path).wrap_err_with(|| format!("reading {}", path.display()))?;
+        let file =
+            object::File::parse(&*data).wrap_err_with(|| format!("parsing {}", path.display()))?;
+
+        symbols.extend(
+            file.symbols()
+                .filter(|symbol| {
+                    symbol.is_definition() && symbol.scope() != SymbolScope::Compilation
+                })
+                .filter_map(|symbol| symbol.name().ok().map(|name| name.to_string())),
+        );
+    }
+
+    Ok(symbols)
+}
+
+/// How an extension module can be loaded.
+///
+/// Only ELF extension modules are analyzed, and those can't be loaded from
+/// memory: that requires a Windows distribution exporting its symbols.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExtensionLoadability {
+    /// The extension module must be loaded from a file next to the binary.
+    RelativePath,
+
+    /// The extension module won't load, for the given reasons.
+    Fails(Vec<String>),
+}
+
+/// The result of analyzing an extension module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionAnalysis {
+    /// Full name of the extension module.
+    pub name: String,
+
+    /// Whether the extension module is currently placed in memory.
+    pub in_memory: bool,
+
+    /// Shared libraries the extension module needs, including indirectly,
+    /// that are shipped with the bundle.
+    pub bundled_libraries: Vec<String>,
+
+    /// How the extension module can be loaded.
+    pub loadability: ExtensionLoadability,
+}
+
+impl ExtensionAnalysis {
+    /// Whether the extension module won't load where it's placed.
+    pub fn is_failure(&self) -> bool {
+        matches!(self.loadability, ExtensionLoadability::Fails(_))
+            || (self.in_memory && self.loadability == ExtensionLoadability::RelativePath)
+    }
+}
+
+/// Environment extension modules are analyzed against.
+pub struct LoadabilityContext<'a> {
+    /// Whether extension modules can be loaded from files.
+    pub file_loading: bool,
+
+    /// Extension modules known not to work on the target.
+    pub broken_extensions: &'a [String],
+
+    /// Shared libraries beyond [SYSTEM_LIBRARIES] expected on the target.
+    pub allowed_libraries: &'a [String],
+
+    /// Symbols exported by libpython.
+    pub python_symbols: &'a BTreeSet<String>,
+
+    /// Shipped shared libraries, by file name.
+    pub bundled_libraries: &'a BTreeMap<String, ElfDynamicInfo>,
+}
+
+impl LoadabilityContext<'_> {
+    /// Determine how an extension module with the given linking info can be loaded.
+    ///
+    /// Returns the bundled shared libraries it depends on and its loadability.
+    pub fn assess(&self, name: &str, info: &ElfDynamicInfo) -> (Vec<String>, ExtensionLoadability) {
+        let mut reasons = vec![];
+
+        if self.broken_extensions.iter().any(|broken| broken == name) {
+            reasons.push("marked as broken for the target by the packaging policy".to_string());
+        }
+
+        // Resolve NEEDED entries transitively through bundled libraries.
+        let mut bundled = BTreeSet::new();
+        let mut pending = vec![(name.to_string(), info)];
+        let mut available = info.defined_symbols.clone();
+
+        while let Some((owner, info)) = pending.pop() {
+            for needed in &info.needed {
+                if is_system_library(needed, self.allowed_libraries) {
+                    continue;
+                }
+
+                match self.bundled_libraries.get(needed) {
+                    Some(library) => {
+                        if bundled.insert(needed.clone()) {
+                            available.extend(library.defined_symbols.iter().cloned());
+                            pending.push((needed.clone(), library));
+                        }
+                    }
+                    None => {
+                        reasons.push(format!("{} needs {}, which isn't shipped", owner, needed));
+                    }
+                }
+            }
+        }
+
+        let missing = info
+            .undefined_symbols
+            .iter()
+            .filter(|symbol| is_python_symbol(symbol))
+            .filter(|symbol| !self.python_symbols.contains(*symbol) && !available.contains(*symbol))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            reasons.push(format!(
+                "references symbols libpython doesn't export: {}",
+                missing.join(", ")
+            ));
+        }
+
+        let loadability = if !reasons.is_empty() {
+            ExtensionLoadability::Fails(reasons)
+        } else if self.file_loading {
+            ExtensionLoadability::RelativePath
+        } else {
+            ExtensionLoadability::Fails(vec![
+                "the distribution can't load extension modules from memory or files".to_string(),
+            ])
+        };
+
+        (bundled.into_iter().collect(), loadability)
+    }
+}
+
+/// Obtain the shared library data of an extension module resource.
+fn extension_module_data(resource: &PrePackagedResource) -> Option<(bool, &FileData)> {
+    if let Some(data) = &resource.in_memory_extension_module_shared_library {
+        Some((true, data))
+    } else {
+        resource
+            .relative_path_extension_module_shared_library
+            .as_ref()
+            .map(|(_, data)| (false, data))
+    }
+}
+
+/// Obtain the file name and data of a shared library resource.
+fn shared_library_data(resource: &PrePackagedResource) -> Option<(String, &FileData)> {
+    let file_name = |name: &str| {
+        Path::new(name)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| name.to_string())
+    };
+
+    if let Some(data) = &resource.in_memory_shared_library {
+        Some((file_name(&resource.name), data))
+    } else {
+        resource
+            .relative_path_shared_library
+            .as_ref()
+            .map(|(_, path, data)| (file_name(&path.to_string_lossy()), data))
+    }
+}
+
+/// Analyze the ELF extension modules of a collector.
+///
+/// Extension modules in other formats aren't analyzed.
+pub fn analyze_extension_modules(
+    collector: &PythonResourceCollector,
+    dist: &StandaloneDistribution,
+    policy: &PythonPackagingPolicy,
+) -> eyre::Result<Vec<ExtensionAnalysis>> {
+    let mut bundled_libraries = BTreeMap::new();
+    for resource in collector.iter_resources().map(|(_, resource)| resource) {
+        if let Some((file_name, data)) = shared_library_data(resource) {
+            let data = data.resolve_content()?;
+
+            if let Some(info) = elf_dynamic_info(&data)
+                .wrap_err_with(|| format!("analyzing shared library {}", resource.name))?
+            {
+                bundled_libraries.insert(file_name, info);
+            }
+        }
+    }
+
+    let python_symbols = libpython_exported_symbols(dist)?;
+    let context = LoadabilityContext {
+        file_loading: dist.is_extension_module_file_loadable(),
+        broken_extensions: policy
+            .broken_extensions_for_triple(&dist.target_triple)
+            .map(|v| v.as_slice())
+            .unwrap_or_default(),
+        allowed_libraries: policy.allowed_system_libraries(),
+        python_symbols: &python_symbols,
+        bundled_libraries: &bundled_libraries,
+    };
+
+    let mut res = vec![];
+    for resource in collector.iter_resources().map(|(_, resource)| resource) {
+        let Some((in_memory, data)) = extension_module_data(resource) else {
+            continue;
+        };
+
+        let data = data.resolve_content()?;
+        let Some(info) = elf_dynamic_info(&data)
+            .wrap_err_with(|| format!("analyzing extension module {}", resource.name))?
+        else {
+            continue;
+        };
+
+        let (bundled_libraries, loadability) = context.assess(&resource.name, &info);
+
+        res.push(ExtensionAnalysis {
+            name: resource.name.clone(),
+            in_memory,
+            bundled_libraries,
+            loadability,
+        });
+    }
+
+    Ok(res)
+}
+
+/// Log the results of [analyze_extension_modules] and error if any extension fails.
+pub fn check_extension_modules(analyses: &[ExtensionAnalysis]) -> eyre::Result<()> {
+    let mut failures = vec![];
+
+    for analysis in analyses {
+        match &analysis.loadability {
+            ExtensionLoadability::RelativePath if analysis.in_memory => {
+                log::error!(
+                    "{}: placed in memory but only loadable from a file",
+                    analysis.name
+                );
+                failures.push(analysis.name.as_str());
+            }
+            ExtensionLoadability::RelativePath => {
+                log::info!("{}: loadable from a file", analysis.name);
+            }
+            ExtensionLoadability::Fails(reasons) => {
+                for reason in reasons {
+                    log::error!("{}: {}", analysis.name, reason);
+                }
+                failures.push(analysis.name.as_str());
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre::eyre!(
+            "extension modules won't load: {}",
+            failures.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(needed: &[&str], undefined: &[&str], defined: &[&str]) -> ElfDynamicInfo {
+        ElfDynamicInfo {
+            needed: needed.iter().map(|s| s.to_string()).collect(),
+            undefined_symbols: undefined.iter().map(|s| s.to_string()).collect(),
+            defined_symbols: defined.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn assess() {
+        let python_symbols = ["PyLong_FromLong", "PyModule_Create2"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let bundled_libraries = [
+            (
+                "libfoo.so.1".to_string(),
+                info(&["libbar.so.2", "libc.so.6"], &[], &["foo"]),
+            ),
+            (
+                "libbar.so.2".to_string(),
+                info(&["libm.so.6"], &[], &["bar"]),
+            ),
+            (
+                "libbad.so.1".to_string(),
+                info(&["libmissing.so"], &[], &[]),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let broken = vec!["demo._broken".to_string()];
+        let allowed = vec!["libGLU.so.1".to_string()];
+
+        let mut context = LoadabilityContext {
+            file_loading: true,
+            broken_extensions: &broken,
+            allowed_libraries: &allowed,
+            python_symbols: &python_symbols,
+            bundled_libraries: &bundled_libraries,
+        };
+
+        let good = info(
+            &[
+                "libfoo.so.1",
+                "libpthread.so.0",
+                "ld-linux-x86-64.so.2",
+                "libGLU.so.1",
+            ],
+            &["PyLong_FromLong", "foo", "memcpy"],
+            &["PyInit__good"],
+        );
+        assert_eq!(
+            context.assess("demo._good", &good),
+            (
+                vec!["libbar.so.2".to_string(), "libfoo.so.1".to_string()],
+                ExtensionLoadability::RelativePath
+            )
+        );
+
+        context.file_loading = false;
+        assert!(matches!(
+            context.assess("demo._good", &good).1,
+            ExtensionLoadability::Fails(_)
+        ));
+        context.file_loading = true;
+
+        assert!(matches!(
+            context.assess("demo._broken", &good).1,
+            ExtensionLoadability::Fails(_)
+        ));
+
+        let ExtensionLoadability::Fails(reasons) = context
+            .assess(
+                "demo._bad",
+                &info(&["libbad.so.1", "libGLX.so.0"], &["_PyObject_Private"], &[]),
+            )
+            .1
+        else {
+            panic!("expected failure");
+        };
+        assert_eq!(
+            reasons,
+            vec![
+                "demo._bad needs libGLX.so.0, which isn't shipped".to_string(),
+                "libbad.so.1 needs libmissing.so, which isn't shipped".to_string(),
+                "references symbols libpython doesn't export: _PyObject_Private".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn failures() {
+        let analysis = |in_memory, loadability| ExtensionAnalysis {
+            name: "demo".to_string(),
+            in_memory,
+            bundled_libraries: vec![],
+            loadability,
+        };
+
+        assert!(!analysis(false, ExtensionLoadability::RelativePath).is_failure());
+        assert!(analysis(true, ExtensionLoadability::RelativePath).is_failure());
+        assert!(analysis(false, ExtensionLoadability::Fails(vec![])).is_failure());
+
+        assert!(
+            check_extension_modules(&[analysis(false, ExtensionLoadability::RelativePath)]).is_ok()
+        );
+        assert!(
+            check_extension_modules(&[analysis(true, ExtensionLoadability::RelativePath)]).is_err()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_current_exe() {
+        let data = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let info = elf_dynamic_info(&data).unwrap().unwrap();
+
+        assert!(info.needed.iter().any(|name| name.starts_with("libc.so")));
+        assert!(info.undefined_symbols.contains("malloc"));
+
+        assert_eq!(elf_dynamic_info(b"not a shared library").unwrap(), None);
+    }
+}
@@ -0,0 +1,572 @@
+//! Building and linking libpython for embedding.
+//!
+//! A statically linked libpython is assembled from the distribution's core
+//! object files, the object files of the builtin extension modules and a
+//! generated `config.c` defining `_PyImport_Inittab`. The result is consumed
+//! by the root crate's build script through cargo directives and a PyO3
+//! build configuration file.
+//!
+//! For development builds, the distribution's shared libpython can be linked
+//! dynamically instead.
+
+use super::{FileData, LibraryDependency};
+use color_eyre::eyre::{self, WrapErr};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the file holding cargo directives for the root build script.
+pub const CARGO_METADATA_FILENAME: &str = "cargo_metadata.txt";
+
+/// Name of the PyO3 build configuration file.
+pub const PYO3_CONFIG_FILENAME: &str = "pyo3-build-config.txt";
+
+/// Name of the static library libpython is archived as, without `lib` prefix.
+pub const LIBPYTHON_NAME: &str = "python3";
+
+/// Name of the directory holding files to install next to the executable.
+pub const RUNTIME_DIRNAME: &str = "runtime";
+
+/// An instruction for the linker, as understood by cargo.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LinkingAnnotation {
+    /// Link a macOS framework.
+    LinkFramework(String),
+
+    /// Link a library of unspecified type.
+    LinkLibrary(String),
+
+    /// Link a static library.
+    LinkLibraryStatic(String),
+
+    /// Add a path to the library search path.
+    Search(PathBuf),
+
+    /// Add a path to the native library search path.
+    SearchNative(PathBuf),
+
+    /// Pass an argument to the linker.
+    Argument(String),
+}
+
+impl LinkingAnnotation {
+    /// The cargo build script directive for this annotation.
+    pub fn to_cargo_annotation(&self) -> String {
+        match self {
+            Self::LinkFramework(framework) => {
+                format!("cargo:rustc-link-lib=framework={}", framework)
+            }
+            Self::LinkLibrary(lib) => format!("cargo:rustc-link-lib={}", lib),
+            Self::LinkLibraryStatic(lib) => format!("cargo:rustc-link-lib=static={}", lib),
+            Self::Search(path) => format!("cargo:rustc-link-search={}", path.display()),
+            Self::SearchNative(path) => {
+                format!("cargo:rustc-link-search=native={}", path.display())
+            }
+            Self::Argument(arg) => format!("cargo:rustc-link-arg={}", arg),
+        }
+    }
+}
+
+/// State needed to build libpython.
+#[derive(Clone, Debug, Default)]
+pub struct LibPythonBuildContext {
+    /// Compiler flags for the object defining `_PyImport_Inittab`.
+    pub inittab_cflags: Option<Vec<String>>,
+
+    /// Header files to compile against.
+    ///
+    /// Keys are paths relative to the include directory.
+    pub includes: BTreeMap<PathBuf, FileData>,
+
+    /// Object files to archive into libpython.
+    pub object_files: Vec<FileData>,
+
+    /// Directories to search for libraries.
+    pub library_search_paths: BTreeSet<PathBuf>,
+
+    /// System libraries to link.
+    pub system_libraries: BTreeSet<String>,
+
+    /// Non-system libraries to link dynamically.
+    pub dynamic_libraries: BTreeSet<String>,
+
+    /// Libraries to link statically.
+    pub static_libraries: BTreeSet<String>,
+
+    /// macOS frameworks to link.
+    pub frameworks: BTreeSet<String>,
+
+    /// Extension module initialization functions for `_PyImport_Inittab`.
+    ///
+    /// Keys are module names. A value of `NULL` registers a module whose
+    /// initialization is handled by the interpreter itself.
+    pub init_functions: BTreeMap<String, String>,
+}
+
+impl LibPythonBuildContext {
+    /// Record a library dependency.
+    pub fn add_library_dependency(&mut self, library: &LibraryDependency) {
+        if library.framework {
+            self.frameworks.insert(library.name.clone());
+        } else if library.system {
+            self.system_libraries.insert(library.name.clone());
+        } else if let Some(path) = library
+            .static_library
+            .as_ref()
+            .and_then(|d| d.backing_path())
+        {
+            if let Some(parent) = path.parent() {
+                self.library_search_paths.insert(parent.to_path_buf());
+            }
+            self.static_libraries.insert(library.name.clone());
+        } else if let Some(path) = library
+            .dynamic_library
+            .as_ref()
+            .and_then(|d| d.backing_path())
+        {
+            if let Some(parent) = path.parent() {
+                self.library_search_paths.insert(parent.to_path_buf());
+            }
+            self.dynamic_libraries.insert(library.name.clone());
+        } else {
+            self.dynamic_libraries.insert(library.name.clone());
+        }
+    }
+
+    /// Merge multiple contexts into one.
+    pub fn merge(contexts: &[&Self]) -> Self {
+        let mut res = Self::default();
+
+        for context in contexts {
+            if let Some(flags) = &context.inittab_cflags {
+                res.inittab_cflags = Some(flags.clone());
+            }
+
+            res.includes.extend(context.includes.clone());
+            res.object_files
+                .extend(context.object_files.iter().cloned());
+            res.library_search_paths
+                .extend(context.library_search_paths.iter().cloned());
+            res.system_libraries
+                .extend(context.system_libraries.iter().cloned());
+            res.dynamic_libraries
+                .extend(context.dynamic_libraries.iter().cloned());
+            res.static_libraries
+                .extend(context.static_libraries.iter().cloned());
+            res.frameworks.extend(context.frameworks.iter().cloned());
+            res.init_functions.extend(context.init_functions.clone());
+        }
+
+        res
+    }
+
+    /// Linking annotations for everything libpython depends on.
+    pub fn linking_annotations(&self) -> Vec<LinkingAnnotation> {
+        let mut res = vec![];
+
+        for path in &self.library_search_paths {
+            res.push(LinkingAnnotation::SearchNative(path.clone()));
+        }
+        for framework in &self.frameworks {
+            res.push(LinkingAnnotation::LinkFramework(framework.clone()));
+        }
+        for lib in &self.system_libraries {
+            res.push(LinkingAnnotation::LinkLibrary(lib.clone()));
+        }
+        for lib in &self.dynamic_libraries {
+            res.push(LinkingAnnotation::LinkLibrary(lib.clone()));
+        }
+        for lib in &self.static_libraries {
+            res.push(LinkingAnnotation::LinkLibraryStatic(lib.clone()));
+        }
+
+        res
+    }
+}
+
+/// Generate the source of `config.c`, which defines `_PyImport_Inittab`.
+pub fn make_config_c(init_functions: &BTreeMap<String, String>) -> String {
+    let mut lines = vec!["#include \"Python.h\"".to_string()];
+
+    for init_fn in init_functions.values() {
+        if init_fn != "NULL" {
+            lines.push(format!("extern PyObject* {}(void);", init_fn));
+        }
+    }
+
+    lines.push("struct _inittab _PyImport_Inittab[] = {".to_string());
+    for (name, init_fn) in init_functions {
+        lines.push(format!("    {{\"{}\", {}}},", name, init_fn));
+    }
+    lines.push("    {0, 0}".to_string());
+    lines.push("};".to_string());
+
+    lines.join("\n") + "\n"
+}
+
+/// A linked libpython.
+#[derive(Clone, Debug)]
+pub struct LibpythonInfo {
+    /// Path of the libpython library.
+    pub libpython_path: PathBuf,
+
+    /// Linking annotations needed to link libpython into a binary.
+    pub linking_annotations: Vec<LinkingAnnotation>,
+}
+
+/// Build a static libpython archive in `out_dir`.
+///
+/// `config.c` is compiled for the target and archived together with the
+/// context's object files.
+pub fn link_libpython(
+    context: &LibPythonBuildContext,
+    host_triple: &str,
+    target_triple: &str,
+    opt_level: &str,
+    out_dir: &Path,
+) -> eyre::Result<LibpythonInfo> {
+    let temp_dir = out_dir.join("libpython-build");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir)
+            .wrap_err_with(|| format!("removing {}", temp_dir.display()))?;
+    }
+
+    let include_dir = temp_dir.join("include");
+    for (rel_path, data) in &context.includes {
+        let path = include_dir.join(rel_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(&path, data.resolve_content()?)
+            .wrap_err_with(|| format!("writing {}", path.display()))?;
+    }
+
+    let config_c_path = temp_dir.join("config.c");
+    std::fs::write(&config_c_path, make_config_c(&context.init_functions))
+        .wrap_err_with(|| format!("writing {}", config_c_path.display()))?;
+
+    let mut build = cc::Build::new();
+    build
+        .out_dir(&temp_dir)
+        .host(host_triple)
+        .target(target_triple)
+        .opt_level_str(opt_level)
+        .cargo_metadata(false)
+        .include(&include_dir)
+        .file(&config_c_path);
+
+    for flag in context.inittab_cflags.iter().flatten() {
+        build.flag(flag);
+    }
+
+    for (i, object) in context.object_files.iter().enumerate() {
+        let path = match object.backing_path() {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = temp_dir.join(format!("object-{}.o", i));
+                std::fs::write(&path, object.resolve_content()?)
+                    .wrap_err_with(|| format!("writing {}", path.display()))?;
+                path
+            }
+        };
+
+        build.object(path);
+    }
+
+    build
+        .try_compile(LIBPYTHON_NAME)
+        .map_err(|e| eyre::eyre!("building libpython: {}", e))?;
+
+    let filename = if target_triple.contains("-windows-") {
+        format!("{}.lib", LIBPYTHON_NAME)
+    } else {
+        format!("lib{}.a", LIBPYTHON_NAME)
+    };
+    let libpython_path = out_dir.join(&filename);
+    std::fs::rename(temp_dir.join(&filename), &libpython_path)
+        .wrap_err_with(|| format!("moving {} to {}", filename, out_dir.display()))?;
+
+    let mut linking_annotations = vec![
+        LinkingAnnotation::SearchNative(out_dir.to_path_buf()),
+        LinkingAnnotation::LinkLibraryStatic(LIBPYTHON_NAME.to_string()),
+    ];
+    linking_annotations.extend(context.linking_annotations());
+
+    std::fs::remove_dir_all(&temp_dir)
+        .wrap_err_with(|| format!("removing {}", temp_dir.display()))?;
+
+    Ok(LibpythonInfo {
+        libpython_path,
+        linking_annotations,
+    })
+}
+
+/// Prepare a shared libpython for dynamic linking.
+///
+/// The library is copied to the runtime directory in `out_dir`, to be
+/// installed next to the executable, which finds it through an `$ORIGIN`
+/// relative rpath. It is linked by its file name, as the library is usually
+/// named after its soname, e.g. `libpython3.12.so.1.0`.
+///
+/// The rpath is part of the annotations, as link arguments of pyembed's
+/// build script don't apply to the executables depending on it.
+pub fn link_shared_libpython(
+    shared_library: &Path,
+    target_triple: &str,
+    out_dir: &Path,
+) -> eyre::Result<LibpythonInfo> {
+    let runtime_dir = out_dir.join(RUNTIME_DIRNAME);
+    std::fs::create_dir_all(&runtime_dir)
+        .wrap_err_with(|| format!("creating {}", runtime_dir.display()))?;
+
+    let filename = shared_library
+        .file_name()
+        .ok_or_else(|| eyre::eyre!("{} has no file name", shared_library.display()))?;
+    let libpython_path = runtime_dir.join(filename);
+    std::fs::copy(shared_library, &libpython_path).wrap_err_with(|| {
+        format!(
+            "copying {} to {}",
+            shared_library.display(),
+            runtime_dir.display()
+        )
+    })?;
+
+    let mut linking_annotations = vec![
+        LinkingAnnotation::SearchNative(runtime_dir),
+        LinkingAnnotation::LinkLibrary(format!("dylib:+verbatim={}", filename.to_string_lossy())),
+    ];
+    if target_triple.contains("-apple-") {
+        linking_annotations.push(LinkingAnnotation::Argument(
+            "-Wl,-rpath,@executable_path".to_string(),
+        ));
+    } else if target_triple.contains("-linux-") {
+        linking_annotations.push(LinkingAnnotation::Argument(
+            "-Wl,-rpath,$ORIGIN".to_string(),
+        ));
+    }
+
+    Ok(LibpythonInfo {
+        libpython_path,
+        linking_annotations,
+    })
+}
+
+/// Write linking annotations as cargo directives for the root build script.
+pub fn write_cargo_metadata(annotations: &[LinkingAnnotation], path: &Path) -> eyre::Result<()> {
+    let mut content = String::new();
+    for annotation in annotations {
+        content.push_str(&annotation.to_cargo_annotation());
+        content.push('\n');
+    }
+
+    std::fs::write(path, content).wrap_err_with(|| format!("writing {}", path.display()))
+}
+
+/// PyO3 build configuration, as read from `PYO3_CONFIG_FILE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PyO3BuildConfig {
+    /// Python implementation, e.g. `CPython`.
+    pub implementation: String,
+
+    /// Python `major.minor` version.
+    pub version: String,
+
+    /// Whether libpython is a shared library.
+    pub shared: bool,
+
+    /// Name of the library libpython is linked as.
+    pub lib_name: String,
+
+    /// Directory containing libpython.
+    pub lib_dir: PathBuf,
+
+    /// Python interpreter usable at build time.
+    pub executable: PathBuf,
+
+    /// Pointer width of the target, in bits.
+    pub pointer_width: u32,
+
+    /// Build flags of the interpreter, e.g. `Py_DEBUG`.
+    pub build_flags: Vec<String>,
+
+    /// Whether PyO3 should not emit its own linking directives.
+    ///
+    /// Linking is done by the root build script instead.
+    pub suppress_build_script_link_lines: bool,
+}
+
+impl PyO3BuildConfig {
+    /// Pointer width of a target triple, in bits.
+    pub fn pointer_width_for_triple(target_triple: &str) -> u32 {
+        let arch = target_triple.split('-').next().unwrap_or_default();
+
+        match arch {
+            "i386" | "i586" | "i686" | "x86" | "armv7" | "arm" | "wasm32" => 32,
+            _ => 64,
+        }
+    }
+
+    /// Write the configuration in PyO3's `key=value` format.
+    pub fn to_writer(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "implementation={}", self.implementation)?;
+        writeln!(writer, "version={}", self.version)?;
+        writeln!(writer, "shared={}", self.shared)?;
+        writeln!(writer, "abi3=false")?;
+        writeln!(writer, "lib_name={}", self.lib_name)?;
+        writeln!(writer, "lib_dir={}", self.lib_dir.display())?;
+        writeln!(writer, "executable={}", self.executable.display())?;
+        writeln!(writer, "pointer_width={}", self.pointer_width)?;
+        writeln!(writer, "build_flags={}", self.build_flags.join(","))?;
+        writeln!(
+            writer,
+            "suppress_build_script_link_lines={}",
+            self.suppress_build_script_link_lines
+        )?;
+
+        Ok(())
+    }
+
+    /// Write the configuration to a file.
+    pub fn write_file(&self, path: &Path) -> eyre::Result<()> {
+        let mut buffer = vec![];
+        self.to_writer(&mut buffer)?;
+
+        std::fs::write(path, buffer).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_c() {
+        let init_functions = [
+            ("_io", "PyInit__io"),
+            ("builtins", "NULL"),
+            ("zlib", "PyInit_zlib"),
+        ]
+        .into_iter()
+        .map(|(name, init_fn)| (name.to_string(), init_fn.to_string()))
+        .collect();
+
+        assert_eq!(
+            make_config_c(&init_functions),
+            "#include \"Python.h\"\n\
+             extern PyObject* PyInit__io(void);\n\
+             extern PyObject* PyInit_zlib(void);\n\
+             struct _inittab _PyImport_Inittab[] = {\n    \
+             {\"_io\", PyInit__io},\n    \
+             {\"builtins\", NULL},\n    \
+             {\"zlib\", PyInit_zlib},\n    \
+             {0, 0}\n\
+             };\n"
+        );
+    }
+
+    #[test]
+    fn library_dependencies() {
+        let dependency =
+            |name: &str, static_library: Option<&str>, system: bool| LibraryDependency {
+                name: name.to_string(),
+                static_library: static_library.map(|p| FileData::Path(PathBuf::from(p))),
+                static_filename: None,
+                dynamic_library: None,
+                dynamic_filename: None,
+                framework: false,
+                system,
+            };
+
+        let mut context = LibPythonBuildContext::default();
+        context.add_library_dependency(&dependency("ssl", Some("/dist/build/lib/libssl.a"), false));
+        context.add_library_dependency(&dependency("m", None, true));
+
+        let mut other = LibPythonBuildContext::default();
+        other.add_library_dependency(&dependency("dl", None, true));
+
+        let merged = LibPythonBuildContext::merge(&[&context, &other]);
+        assert_eq!(
+            merged
+                .linking_annotations()
+                .iter()
+                .map(|a| a.to_cargo_annotation())
+                .collect::<Vec<_>>(),
+            vec![
+                "cargo:rustc-link-search=native=/dist/build/lib",
+                "cargo:rustc-link-lib=dl",
+                "cargo:rustc-link-lib=m",
+                "cargo:rustc-link-lib=static=ssl",
+            ]
+        );
+    }
+
+    #[test]
+    fn pyo3_config() {
+        let config = PyO3BuildConfig {
+            implementation: "CPython".to_string(),
+            version: "3.12".to_string(),
+            shared: false,
+            lib_name: LIBPYTHON_NAME.to_string(),
+            lib_dir: PathBuf::from("/out"),
+            executable: PathBuf::from("/dist/python/install/bin/python3"),
+            pointer_width: PyO3BuildConfig::pointer_width_for_triple("x86_64-unknown-linux-gnu"),
+            build_flags: vec![],
+            suppress_build_script_link_lines: true,
+        };
+
+        let mut buffer = vec![];
+        config.to_writer(&mut buffer).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "implementation=CPython\n\
+             version=3.12\n\
+             shared=false\n\
+             abi3=false\n\
+             lib_name=python3\n\
+             lib_dir=/out\n\
+             executable=/dist/python/install/bin/python3\n\
+             pointer_width=64\n\
+             build_flags=\n\
+             suppress_build_script_link_lines=true\n"
+        );
+        assert_eq!(
+            PyO3BuildConfig::pointer_width_for_triple("i686-pc-windows-msvc"),
+            32
+        );
+    }
+
+    #[test]
+    fn shared_libpython() {
+        let dir = std::env::temp_dir().join(format!("libpython-test-{}", std::process::id()));
+        let library = dir.join("dist").join("libpython3.12.so.1.0");
+        std::fs::create_dir_all(library.parent().unwrap()).unwrap();
+        std::fs::write(&library, b"ELF").unwrap();
+
+        let out_dir = dir.join("out");
+        let info = link_shared_libpython(&library, "x86_64-unknown-linux-gnu", &out_dir).unwrap();
+
+        assert_eq!(
+            info.libpython_path,
+            out_dir.join(RUNTIME_DIRNAME).join("libpython3.12.so.1.0")
+        );
+        assert!(info.libpython_path.exists());
+        assert_eq!(
+            info.linking_annotations
+                .iter()
+                .map(|a| a.to_cargo_annotation())
+                .collect::<Vec<_>>(),
+            vec![
+                format!(
+                    "cargo:rustc-link-search=native={}",
+                    out_dir.join(RUNTIME_DIRNAME).display()
+                ),
+                "cargo:rustc-link-lib=dylib:+verbatim=libpython3.12.so.1.0".to_string(),
+                "cargo:rustc-link-arg=-Wl,-rpath,$ORIGIN".to_string(),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,831 @@
+//! Licensing of embedded software components.
+//!
+//! Everything shipped in the bundle is described by a [LicensedComponent]:
+//! the Python distribution, the libraries its extension modules link and every
+//! package installed from a wheel. [LicensedComponents] renders them as a
+//! `COPYING`-style document and as a JSON manifest listing each component.
+
+use color_eyre::eyre::{self, WrapErr};
+use spdx::{Expression, ParseMode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// The type of a license.
+#[derive(Clone, Debug)]
+pub enum LicenseFlavor {
+    /// No explicit licensing defined.
+    None,
+
+    /// An SPDX license expression using only known SPDX identifiers.
+    Spdx(Expression),
+
+    /// An SPDX expression referencing licenses that aren't SPDX identifiers.
+    OtherExpression(Expression),
+
+    /// License is in the public domain.
+    PublicDomain,
+
+    /// Licensing is declared, but not in a form we understand.
+    Unknown(Vec<String>),
+}
+
+/// Where the source code of a component can be obtained.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceLocation {
+    /// Source location is not defined.
+    NotSet,
+    /// Source code can be found at a URL.
+    Url(String),
+}
+
+/// Describes the type of a software component.
+#[derive(Clone, Debug)]
+pub enum ComponentFlavor {
+    /// A Python distribution.
+    PythonDistribution(String),
+    /// A Python module in the standard library.
+    PythonStandardLibraryModule(String),
+    /// A compiled Python extension module in the standard library.
+    PythonStandardLibraryExtensionModule(String),
+    /// A compiled Python extension module.
+    PythonExtensionModule(String),
+    /// A Python module.
+    PythonModule(String),
+    /// A Python package installed from a wheel.
+    PythonPackage(String),
+    /// A generic software library.
+    Library(String),
+    /// A Rust crate.
+    RustCrate(String),
+}
+
+impl std::fmt::Display for ComponentFlavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PythonDistribution(name) => f.write_str(name),
+            Self::PythonStandardLibraryModule(name) => {
+                f.write_fmt(format_args!("Python stdlib module {}", name))
+            }
+            Self::PythonStandardLibraryExtensionModule(name) => {
+                f.write_fmt(format_args!("Python stdlib extension {}", name))
+            }
+            Self::PythonExtensionModule(name) => {
+                f.write_fmt(format_args!("Python extension module {}", name))
+            }
+            Self::PythonModule(name) => f.write_fmt(format_args!("Python module {}", name)),
+            Self::PythonPackage(name) => f.write_fmt(format_args!("Python package {}", name)),
+            Self::Library(name) => f.write_fmt(format_args!("library {}", name)),
+            Self::RustCrate(name) => f.write_fmt(format_args!("Rust crate {}", name)),
+        }
+    }
+}
+
+impl PartialEq for ComponentFlavor {
+    fn eq(&self, other: &Self) -> bool {
+        // If both entities have a Python module name, equivalence is whether
+        // the module names agree, as there can only be a single entity for a given
+        // module name.
+        match (self.python_module_name(), other.python_module_name()) {
+            (Some(a), Some(b)) => a.eq(b),
+            // Comparing a module with a non-module is always not equivalent.
+            (Some(_), None) => false,
+            (None, Some(_)) => false,
+            (None, None) => match (self, other) {
+                (Self::PythonDistribution(a), Self::PythonDistribution(b)) => a.eq(b),
+                (Self::PythonPackage(a), Self::PythonPackage(b)) => a.eq(b),
+                (Self::Library(a), Self::Library(b)) => a.eq(b),
+                (Self::RustCrate(a), Self::RustCrate(b)) => a.eq(b),
+                _ => false,
+            },
+        }
+    }
+}
+
+impl Eq for ComponentFlavor {}
+
+impl PartialOrd for ComponentFlavor {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ComponentFlavor {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self.python_module_name(), other.python_module_name()) {
+            (Some(a), Some(b)) => a.cmp(b),
+            _ => {
+                let a = (self.ordinal_value(), self.to_string());
+                let b = (other.ordinal_value(), other.to_string());
+
+                a.cmp(&b)
+            }
+        }
+    }
+}
+
+impl ComponentFlavor {
+    /// Obtain the name of the Python module this component describes, if any.
+    pub fn python_module_name(&self) -> Option<&str> {
+        match self {
+            Self::PythonStandardLibraryModule(name)
+            | Self::PythonStandardLibraryExtensionModule(name)
+            | Self::PythonExtensionModule(name)
+            | Self::PythonModule(name) => Some(name),
+            Self::PythonDistribution(_)
+            | Self::PythonPackage(_)
+            | Self::Library(_)
+            | Self::RustCrate(_) => None,
+        }
+    }
+
+    /// Sort key grouping components of the same type.
+    fn ordinal_value(&self) -> u8 {
+        match self {
+            Self::PythonDistribution(_) => 0,
+            Self::PythonStandardLibraryModule(_) => 1,
+            Self::PythonStandardLibraryExtensionModule(_) => 2,
+            Self::PythonExtensionModule(_) => 3,
+            Self::PythonModule(_) => 4,
+            Self::PythonPackage(_) => 5,
+            Self::Library(_) => 6,
+            Self::RustCrate(_) => 7,
+        }
+    }
+
+    /// Short identifier of the component type, as used in the JSON manifest.
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::PythonDistribution(_) => "python-distribution",
+            Self::PythonStandardLibraryModule(_) => "python-stdlib-module",
+            Self::PythonStandardLibraryExtensionModule(_) => "python-stdlib-extension-module",
+            Self::PythonExtensionModule(_) => "python-extension-module",
+            Self::PythonModule(_) => "python-module",
+            Self::PythonPackage(_) => "python-package",
+            Self::Library(_) => "library",
+            Self::RustCrate(_) => "rust-crate",
+        }
+    }
+
+    /// The name of the component, without its type.
+    fn name(&self) -> &str {
+        match self {
+            Self::PythonDistribution(name)
+            | Self::PythonStandardLibraryModule(name)
+            | Self::PythonStandardLibraryExtensionModule(name)
+            | Self::PythonExtensionModule(name)
+            | Self::PythonModule(name)
+            | Self::PythonPackage(name)
+            | Self::Library(name)
+            | Self::RustCrate(name) => name,
+        }
+    }
+}
+
+/// Represents a software component with licensing information.
+#[derive(Clone, Debug)]
+pub struct LicensedComponent {
+    /// Type of component.
+    flavor: ComponentFlavor,
+
+    /// Version of the component, if known.
+    version: Option<String>,
+
+    /// The type of license.
+    license: LicenseFlavor,
+
+    /// Location where source code for this component can be obtained.
+    source_location: SourceLocation,
+
+    /// Homepage for project.
+    homepage: Option<String>,
+
+    /// List of authors.
+    authors: Vec<String>,
+
+    /// Specified license text for this component.
+    ///
+    /// If empty, license texts will be derived from SPDX identifiers, if available.
+    license_texts: Vec<String>,
+}
+
+impl LicensedComponent {
+    /// Construct a new instance from parameters.
+    pub fn new(flavor: ComponentFlavor, license: LicenseFlavor) -> Self {
+        Self {
+            flavor,
+            version: None,
+            license,
+            source_location: SourceLocation::NotSet,
+            homepage: None,
+            authors: vec![],
+            license_texts: vec![],
+        }
+    }
+
+    /// Construct a new instance from an SPDX expression.
+    pub fn new_spdx(flavor: ComponentFlavor, spdx_expression: &str) -> eyre::Result<Self> {
+        Ok(Self::new(flavor, parse_spdx_expression(spdx_expression)?))
+    }
+
+    /// The type of this component.
+    pub fn flavor(&self) -> &ComponentFlavor {
+        &self.flavor
+    }
+
+    /// The version of this component.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Set the version of this component.
+    pub fn set_version(&mut self, version: impl ToString) {
+        self.version = Some(version.to_string());
+    }
+
+    /// The license of this component.
+    pub fn license(&self) -> &LicenseFlavor {
+        &self.license
+    }
+
+    /// Obtain the SPDX expression of the license, if there is one.
+    pub fn spdx_expression(&self) -> Option<&Expression> {
+        match &self.license {
+            LicenseFlavor::Spdx(expression) | LicenseFlavor::OtherExpression(expression) => {
+                Some(expression)
+            }
+            LicenseFlavor::None | LicenseFlavor::PublicDomain | LicenseFlavor::Unknown(_) => None,
+        }
+    }
+
+    /// Obtain all SPDX license identifiers referenced by the license.
+    pub fn all_spdx_licenses(&self) -> BTreeSet<spdx::LicenseId> {
+        self.spdx_expression()
+            .map(|expression| {
+                expression
+                    .requirements()
+                    .filter_map(|req| req.req.license.id())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Obtain all SPDX license exceptions referenced by the license.
+    pub fn all_spdx_exceptions(&self) -> BTreeSet<spdx::ExceptionId> {
+        self.spdx_expression()
+            .map(|expression| {
+                expression
+                    .requirements()
+                    .filter_map(|req| req.req.exception)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Where the source code of this component can be obtained.
+    pub fn source_location(&self) -> &SourceLocation {
+        &self.source_location
+    }
+
+    /// Set where the source code of this component can be obtained.
+    pub fn set_source_location(&mut self, location: SourceLocation) {
+        self.source_location = location;
+    }
+
+    /// The homepage of this component.
+    pub fn homepage(&self) -> Option<&str> {
+        self.homepage.as_deref()
+    }
+
+    /// Set the homepage of this component.
+    pub fn set_homepage(&mut self, value: impl ToString) {
+        self.homepage = Some(value.to_string());
+    }
+
+    /// The authors of this component.
+    pub fn authors(&self) -> &[String] {
+        &self.authors
+    }
+
+    /// Add an author of this component.
+    pub fn add_author(&mut self, value: impl ToString) {
+        self.authors.push(value.to_string());
+    }
+
+    /// License texts specific to this component.
+    pub fn license_texts(&self) -> &[String] {
+        &self.license_texts
+    }
+
+    /// Add a license text specific to this component.
+    pub fn add_license_text(&mut self, text: impl ToString) {
+        self.license_texts.push(text.to_string());
+    }
+
+    /// Human readable description of the license.
+    pub fn license_summary(&self) -> String {
+        match &self.license {
+            LicenseFlavor::None => "none declared".to_string(),
+            LicenseFlavor::Spdx(expression) => expression.as_ref().to_string(),
+            LicenseFlavor::OtherExpression(expression) => {
+                format!("{} (not all SPDX identifiers)", expression.as_ref())
+            }
+            LicenseFlavor::PublicDomain => "public domain".to_string(),
+            LicenseFlavor::Unknown(terms) => terms.join("; "),
+        }
+    }
+}
+
+/// Parse an SPDX license expression.
+///
+/// Parsing is lenient about common deviations like `/` for `OR` or lowercase
+/// operators, which are frequent in package metadata.
+pub fn parse_spdx_expression(value: &str) -> eyre::Result<LicenseFlavor> {
+    let expression = Expression::parse_mode(value, ParseMode::LAX)
+        .map_err(|e| eyre::eyre!("invalid SPDX expression {:?}: {}", value, e))?;
+
+    Ok(if expression.evaluate(|req| req.license.id().is_some()) {
+        LicenseFlavor::Spdx(expression)
+    } else {
+        LicenseFlavor::OtherExpression(expression)
+    })
+}
+
+/// SPDX identifiers of unambiguous trove license classifiers.
+const CLASSIFIER_LICENSES: &[(&str, &str)] = &[
+    ("OSI Approved :: Apache Software License", "Apache-2.0"),
+    (
+        "OSI Approved :: GNU General Public License v2 (GPLv2)",
+        "GPL-2.0-only",
+    ),
+    (
+        "OSI Approved :: GNU General Public License v2 or later (GPLv2+)",
+        "GPL-2.0-or-later",
+    ),
+    (
+        "OSI Approved :: GNU General Public License v3 (GPLv3)",
+        "GPL-3.0-only",
+    ),
+    (
+        "OSI Approved :: GNU General Public License v3 or later (GPLv3+)",
+        "GPL-3.0-or-later",
+    ),
+    (
+        "OSI Approved :: GNU Lesser General Public License v2 (LGPLv2)",
+        "LGPL-2.0-only",
+    ),
+    (
+        "OSI Approved :: GNU Lesser General Public License v2 or later (LGPLv2+)",
+        "LGPL-2.0-or-later",
+    ),
+    (
+        "OSI Approved :: GNU Lesser General Public License v3 (LGPLv3)",
+        "LGPL-3.0-only",
+    ),
+    (
+        "OSI Approved :: GNU Lesser General Public License v3 or later (LGPLv3+)",
+        "LGPL-3.0-or-later",
+    ),
+    ("OSI Approved :: ISC License (ISCL)", "ISC"),
+    ("OSI Approved :: MIT License", "MIT"),
+    (
+        "OSI Approved :: MIT No Attribution License (MIT-0)",
+        "MIT-0",
+    ),
+    (
+        "OSI Approved :: Mozilla Public License 2.0 (MPL 2.0)",
+        "MPL-2.0",
+    ),
+    (
+        "OSI Approved :: Python Software Foundation License",
+        "PSF-2.0",
+    ),
+    ("OSI Approved :: The Unlicense (Unlicense)", "Unlicense"),
+    ("OSI Approved :: zlib/libpng License", "Zlib"),
+];
+
+/// Files in a `.dist-info` directory that hold license texts, by name prefix.
+const LICENSE_FILE_PREFIXES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "NOTICE", "AUTHORS"];
+
+/// Obtain the non-placeholder values of a metadata header.
+fn header_values<'a>(
+    headers: &'a [(String, String)],
+    key: &'a str,
+) -> impl Iterator<Item = &'a str> + 'a {
+    headers
+        .iter()
+        .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v.as_str())
+        .filter(|v| !v.is_empty() && *v != "UNKNOWN")
+}
+
+/// Derive a [LicensedComponent] from the files of a package's `.dist-info`.
+///
+/// The license is taken from `License-Expression` (PEP 639), then the
+/// `License` field if it is a valid SPDX expression, then the license
+/// classifier if there is exactly one. License texts are read from the files named by `License-File`,
+/// or from conventionally named files if there are none.
+pub fn package_licensed_component(
+    files: &BTreeMap<String, Vec<u8>>,
+) -> eyre::Result<LicensedComponent> {
+    let metadata = files
+        .get("METADATA")
+        .ok_or_else(|| eyre::eyre!("distribution lacks METADATA"))?;
+    let metadata = String::from_utf8_lossy(metadata);
+    let headers = super::wheel::parse_headers(&metadata);
+
+    let values = |key| header_values(&headers, key);
+
+    let name = values("Name")
+        .next()
+        .ok_or_else(|| eyre::eyre!("METADATA lacks Name"))?;
+
+    let license = if let Some(expression) = values("License-Expression").next() {
+        parse_spdx_expression(expression)
+            .wrap_err_with(|| format!("parsing License-Expression of {}", name))?
+    } else {
+        let license_field = values("License").next();
+        let classifiers = values("Classifier")
+            .filter_map(|v| v.strip_prefix("License :: "))
+            .collect::<Vec<_>>();
+
+        // The License field is free-form text, so only accept it if it
+        // happens to be a strictly valid expression of SPDX identifiers.
+        let from_field = license_field
+            .and_then(|v| Expression::parse(v).ok())
+            .filter(|e| e.evaluate(|req| req.license.id().is_some()))
+            .map(LicenseFlavor::Spdx);
+
+        // Several license classifiers don't say whether the licenses are
+        // alternatives or all apply, so only a single one is trusted.
+        let from_classifiers = classifiers
+            .iter()
+            .map(|c| {
+                CLASSIFIER_LICENSES
+                    .iter()
+                    .find(|(classifier, _)| classifier == c)
+                    .map(|(_, id)| *id)
+            })
+            .collect::<Option<BTreeSet<_>>>()
+            .filter(|ids| ids.len() == 1)
+            .and_then(|ids| ids.into_iter().next());
+
+        if let Some(flavor) = from_field {
+            flavor
+        } else if classifiers == ["Public Domain"] {
+            LicenseFlavor::PublicDomain
+        } else if let Some(id) = from_classifiers {
+            parse_spdx_expression(id)?
+        } else if license_field.is_some() || !classifiers.is_empty() {
+            LicenseFlavor::Unknown(
+                license_field
+                    .into_iter()
+                    .chain(classifiers.iter().copied())
+                    .map(|v| v.to_string())
+                    .collect(),
+            )
+        } else {
+            LicenseFlavor::None
+        }
+    };
+
+    let mut component =
+        LicensedComponent::new(ComponentFlavor::PythonPackage(name.to_string()), license);
+
+    if let Some(version) = values("Version").next() {
+        component.set_version(version);
+    }
+
+    if let Some(homepage) = values("Home-page").next().or_else(|| {
+        values("Project-URL")
+            .filter_map(|v| v.split_once(','))
+            .find(|(label, _)| label.trim().eq_ignore_ascii_case("homepage"))
+            .map(|(_, url)| url.trim())
+    }) {
+        component.set_homepage(homepage);
+    }
+
+    if let Some(url) = values("Project-URL")
+        .filter_map(|v| v.split_once(','))
+        .find(|(label, _)| {
+            ["source", "source code", "repository"].contains(&label.trim().to_lowercase().as_str())
+        })
+        .map(|(_, url)| url.trim())
+    {
+        component.set_source_location(SourceLocation::Url(url.to_string()));
+    }
+
+    for author in values("Author").chain(values("Author-email")) {
+        component.add_author(author);
+    }
+
+    // PEP 639 places license files under licenses/. Older tools put them at
+    // the root of .dist-info.
+    let mut license_files = values("License-File")
+        .filter_map(|path| {
+            files
+                .get(&format!("licenses/{}", path))
+                .or_else(|| files.get(path))
+        })
+        .collect::<Vec<_>>();
+
+    if license_files.is_empty() {
+        license_files = files
+            .iter()
+            .filter(|(path, _)| {
+                let file_name = path.rsplit('/').next().unwrap_or(path);
+                LICENSE_FILE_PREFIXES
+                    .iter()
+                    .any(|prefix| file_name.to_uppercase().starts_with(prefix))
+            })
+            .map(|(_, data)| data)
+            .collect();
+    }
+
+    for data in license_files {
+        component.add_license_text(String::from_utf8_lossy(data));
+    }
+
+    Ok(component)
+}
+
+/// A collection of licensed components.
+#[derive(Clone, Debug, Default)]
+pub struct LicensedComponents {
+    components: BTreeMap<ComponentFlavor, LicensedComponent>,
+}
+
+impl LicensedComponents {
+    /// Add a component, replacing an existing one of the same flavor.
+    pub fn add_component(&mut self, component: LicensedComponent) {
+        self.components.insert(component.flavor.clone(), component);
+    }
+
+    /// Iterate over the components, ordered by flavor.
+    pub fn iter_components(&self) -> impl Iterator<Item = &LicensedComponent> {
+        self.components.values()
+    }
+
+    /// Components whose licensing couldn't be resolved to SPDX identifiers.
+    pub fn components_without_spdx_license(&self) -> impl Iterator<Item = &LicensedComponent> {
+        self.iter_components().filter(|component| {
+            !matches!(
+                component.license,
+                LicenseFlavor::Spdx(_) | LicenseFlavor::PublicDomain
+            )
+        })
+    }
+
+    /// Produce a document describing the licensing of all components.
+    ///
+    /// Each component is listed with its license and specific license texts.
+    /// Standard texts of the SPDX licenses referenced by components without
+    /// specific texts follow at the end, once per license.
+    pub fn aggregate_license_document(&self) -> String {
+        let mut text = String::new();
+        let mut spdx_licenses = BTreeSet::new();
+        let mut spdx_exceptions = BTreeSet::new();
+
+        text.push_str(
+            "This software includes the components listed below, which are\n\
+             distributed under the following licenses.\n",
+        );
+
+        for component in self.iter_components() {
+            let title = match &component.version {
+                Some(version) => format!("{} {}", component.flavor, version),
+                None => component.flavor.to_string(),
+            };
+
+            writeln!(text, "\n{}\n{}\n", title, "=".repeat(title.len())).unwrap();
+            writeln!(text, "License: {}", component.license_summary()).unwrap();
+            if let Some(homepage) = &component.homepage {
+                writeln!(text, "Homepage: {}", homepage).unwrap();
+            }
+            if let SourceLocation::Url(url) = &component.source_location {
+                writeln!(text, "Source: {}", url).unwrap();
+            }
+            if !component.authors.is_empty() {
+                writeln!(text, "Authors: {}", component.authors.join(", ")).unwrap();
+            }
+
+            if component.license_texts.is_empty() {
+                spdx_licenses.extend(component.all_spdx_licenses());
+                spdx_exceptions.extend(component.all_spdx_exceptions());
+            }
+
+            for license_text in &component.license_texts {
+                writeln!(text, "\n{}", license_text.trim_end()).unwrap();
+            }
+        }
+
+        for license in spdx_licenses {
+            let title = format!("{} ({})", license.full_name, license.name);
+            writeln!(text, "\n{}\n{}\n", title, "=".repeat(title.len())).unwrap();
+            writeln!(text, "{}", license.text().trim_end()).unwrap();
+        }
+
+        for exception in spdx_exceptions {
+            writeln!(
+                text,
+                "\n{}\n{}\n",
+                exception.name,
+                "=".repeat(exception.name.len())
+            )
+            .unwrap();
+            writeln!(text, "{}", exception.text().trim_end()).unwrap();
+        }
+
+        text
+    }
+
+    /// Produce a JSON manifest describing all components.
+    pub fn to_json(&self) -> serde_json::Value {
+        let components = self
+            .iter_components()
+            .map(|component| {
+                let (license_type, expression) = match &component.license {
+                    LicenseFlavor::None => ("none", None),
+                    LicenseFlavor::Spdx(e) => ("spdx", Some(e.as_ref().to_string())),
+                    LicenseFlavor::OtherExpression(e) => {
+                        ("other-expression", Some(e.as_ref().to_string()))
+                    }
+                    LicenseFlavor::PublicDomain => ("public-domain", None),
+                    LicenseFlavor::Unknown(terms) => ("unknown", Some(terms.join("; "))),
+                };
+
+                serde_json::json!({
+                    "type": component.flavor.kind(),
+                    "name": component.flavor.name(),
+                    "version": component.version,
+                    "license_type": license_type,
+                    "license": expression,
+                    "spdx_licenses": component
+                        .all_spdx_licenses()
+                        .into_iter()
+                        .map(|id| id.name)
+                        .collect::<Vec<_>>(),
+                    "homepage": component.homepage,
+                    "source": match &component.source_location {
+                        SourceLocation::NotSet => None,
+                        SourceLocation::Url(url) => Some(url),
+                    },
+                    "authors": component.authors,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::json!({
+            "version": 1,
+            "components": components,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dist_info(metadata: &str, extra: &[(&str, &str)]) -> BTreeMap<String, Vec<u8>> {
+        std::iter::once(("METADATA", metadata))
+            .chain(extra.iter().copied())
+            .map(|(k, v)| (k.to_string(), v.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn spdx_expressions() {
+        assert!(matches!(
+            parse_spdx_expression("MIT OR Apache-2.0").unwrap(),
+            LicenseFlavor::Spdx(_)
+        ));
+        assert!(matches!(
+            parse_spdx_expression("BSD-3-Clause AND LicenseRef-Qt-Commercial").unwrap(),
+            LicenseFlavor::OtherExpression(_)
+        ));
+        assert!(parse_spdx_expression("MIT OR (").is_err());
+    }
+
+    #[test]
+    fn package_license_expression() {
+        let component = package_licensed_component(&dist_info(
+            "Metadata-Version: 2.4\nName: demo\nVersion: 1.0\nLicense-Expression: MIT OR Apache-2.0\nLicense-File: LICENSE\nProject-URL: Homepage, https://example.com\nProject-URL: Source, https://example.com/src\nAuthor-email: Jane <jane@example.com>\n",
+            &[("licenses/LICENSE", "Copyright Jane")],
+        ))
+        .unwrap();
+
+        assert_eq!(
+            component.flavor(),
+            &ComponentFlavor::PythonPackage("demo".to_string())
+        );
+        assert_eq!(component.version(), Some("1.0"));
+        assert_eq!(component.license_summary(), "MIT OR Apache-2.0");
+        assert_eq!(component.homepage(), Some("https://example.com"));
+        assert_eq!(
+            component.source_location(),
+            &SourceLocation::Url("https://example.com/src".to_string())
+        );
+        assert_eq!(component.authors(), ["Jane <jane@example.com>"]);
+        assert_eq!(component.license_texts(), ["Copyright Jane"]);
+    }
+
+    #[test]
+    fn package_license_fallbacks() {
+        let component = package_licensed_component(&dist_info(
+            "Name: a\nLicense: BSD\nClassifier: License :: OSI Approved :: MIT License\n",
+            &[("LICENSE.txt", "text"), ("RECORD", "")],
+        ))
+        .unwrap();
+        assert_eq!(component.license_summary(), "MIT");
+        assert_eq!(component.license_texts(), ["text"]);
+
+        let component = package_licensed_component(&dist_info(
+            "Name: a\nClassifier: License :: OSI Approved :: MIT License\nClassifier: License :: OSI Approved :: MIT License\n",
+            &[],
+        ))
+        .unwrap();
+        assert_eq!(component.license_summary(), "MIT");
+
+        let component = package_licensed_component(&dist_info(
+            "Name: a\nClassifier: License :: OSI Approved :: MIT License\nClassifier: License :: OSI Approved :: GNU General Public License v3 (GPLv3)\n",
+            &[],
+        ))
+        .unwrap();
+        let LicenseFlavor::Unknown(terms) = component.license() else {
+            panic!("expected unknown license");
+        };
+        assert_eq!(
+            terms,
+            &[
+                "OSI Approved :: MIT License".to_string(),
+                "OSI Approved :: GNU General Public License v3 (GPLv3)".to_string(),
+            ]
+        );
+
+        let component =
+            package_licensed_component(&dist_info("Name: b\nLicense: Apache-2.0\n", &[])).unwrap();
+        assert_eq!(component.license_summary(), "Apache-2.0");
+
+        let component = package_licensed_component(&dist_info(
+            "Name: c\nClassifier: License :: Public Domain\n",
+            &[],
+        ))
+        .unwrap();
+        assert!(matches!(component.license(), LicenseFlavor::PublicDomain));
+
+        let component = package_licensed_component(&dist_info(
+            "Name: d\nLicense: Custom terms\n  see website\n",
+            &[],
+        ))
+        .unwrap();
+        assert!(matches!(component.license(), LicenseFlavor::Unknown(_)));
+
+        let component =
+            package_licensed_component(&dist_info("Name: e\nLicense: UNKNOWN\n", &[])).unwrap();
+        assert!(matches!(component.license(), LicenseFlavor::None));
+
+        assert!(package_licensed_component(&BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn documents() {
+        let mut components = LicensedComponents::default();
+
+        let mut python = LicensedComponent::new_spdx(
+            ComponentFlavor::PythonDistribution("cpython".to_string()),
+            "Python-2.0",
+        )
+        .unwrap();
+        python.add_license_text("PSF LICENSE AGREEMENT");
+        components.add_component(python);
+        components.add_component(
+            LicensedComponent::new_spdx(ComponentFlavor::PythonPackage("six".to_string()), "MIT")
+                .unwrap(),
+        );
+        components.add_component(LicensedComponent::new(
+            ComponentFlavor::PythonPackage("mystery".to_string()),
+            LicenseFlavor::None,
+        ));
+
+        let document = components.aggregate_license_document();
+        assert!(
+            document.contains("cpython\n=======\n\nLicense: Python-2.0\n\nPSF LICENSE AGREEMENT")
+        );
+        assert!(document.contains("Python package six\n"));
+        assert!(document.contains("MIT License (MIT)"));
+        // Components with specific texts don't get the standard text.
+        assert!(!document.contains("Python License 2.0 (Python-2.0)"));
+
+        assert_eq!(
+            components
+                .components_without_spdx_license()
+                .map(|c| c.flavor().to_string())
+                .collect::<Vec<_>>(),
+            vec!["Python package mystery"]
+        );
+
+        let json = components.to_json();
+        assert_eq!(json["components"][0]["type"], "python-distribution");
+        assert_eq!(json["components"][1]["name"], "mystery");
+        assert_eq!(json["components"][2]["spdx_licenses"][0], "MIT");
+    }
+}
@@ -6,6 +6,22 @@ use std::collections::{HashMap, BTreeMap, BTreeSet, HashSet};
 use std::sync::Arc;
 use once_cell::sync::Lazy;
 
+pub mod bytecode;
+pub mod dunder_file;
+pub mod extension_analysis;
+pub mod libpython;
+pub mod licensing;
+pub mod packed_resources;
+pub mod pipfile;
+pub mod python_config;
+pub mod stdlib_trim;
+pub mod wheel;
+
+use bytecode::{BytecodeCompiler, BytecodeOptimizationLevel};
+use dunder_file::DunderFileUsage;
+use libpython::{LibPythonBuildContext, LibpythonInfo, PyO3BuildConfig};
+use stdlib_trim::{StdlibTrim, TrimReport};
+pub use licensing::{ComponentFlavor, LicenseFlavor, LicensedComponent, LicensedComponents};
 
 /// Target triples for Linux.
 pub static LINUX_TARGET_TRIPLES: Lazy<Vec<&'static str>> = Lazy::new(|| {
@@ -79,158 +95,6 @@ pub static NO_BYTECODE_MODULES: Lazy<Vec<&'static str>> = Lazy::new(|| {
     ]
 });
 
-/// Represents a software component with licensing information.
-#[derive(Clone, Debug)]
-pub struct LicensedComponent {
-    /// Type of component.
-    flavor: ComponentFlavor,
-
-    // /// The type of license.
-    // license: LicenseFlavor,
-    //
-    // /// Location where source code for this component can be obtained.
-    // source_location: SourceLocation,
-
-    /// Homepage for project.
-    homepage: Option<String>,
-
-    /// List of authors.
-    authors: Vec<String>,
-
-    /// Specified license text for this component.
-    ///
-    /// If empty, license texts will be derived from SPDX identifiers, if available.
-    license_texts: Vec<String>,
-}
-
-// impl PartialEq for LicensedComponent {
-//     fn eq(&self, other: &Self) -> bool {
-//         self.flavor.eq(&other.flavor)
-//     }
-// }
-//
-// impl Eq for LicensedComponent {}
-
-// impl PartialOrd for LicensedComponent {
-//     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-//         self.flavor.partial_cmp(&other.flavor)
-//     }
-// }
-//
-// impl Ord for LicensedComponent {
-//     fn cmp(&self, other: &Self) -> Ordering {
-//         self.flavor.cmp(&other.flavor)
-//     }
-// }
-
-// impl LicensedComponent {
-//     /// Construct a new instance from parameters.
-//     pub fn new(flavor: ComponentFlavor, license: LicenseFlavor) -> Self {
-//         Self {
-//             flavor,
-//             // license,
-//             // source_location: SourceLocation::NotSet,
-//             homepage: None,
-//             authors: vec![],
-//             license_texts: vec![],
-//         }
-//     }
-//
-//     /// Construct a new instance from an SPDX expression.
-//     pub fn new_spdx(flavor: ComponentFlavor, spdx_expression: &str) -> eyre::Result<Self> {
-//         let spdx_expression = Expression::parse(spdx_expression).map_err(|e| eyre::bail!("{}", e))?;
-//
-//         let license = if spdx_expression.evaluate(|req| req.license.id().is_some()) {
-//             LicenseFlavor::Spdx(spdx_expression)
-//         } else {
-//             LicenseFlavor::OtherExpression(spdx_expression)
-//         };
-//
-//         Ok(Self::new(flavor, license))
-//     }
-// }
-
-/// Describes the type of a software component.
-#[derive(Clone, Debug)]
-pub enum ComponentFlavor {
-    /// A Python distribution.
-    PythonDistribution(String),
-    /// A Python module in the standard library.
-    PythonStandardLibraryModule(String),
-    /// A compiled Python extension module in the standard library.
-    PythonStandardLibraryExtensionModule(String),
-    /// A compiled Python extension module.
-    PythonExtensionModule(String),
-    /// A Python module.
-    PythonModule(String),
-    /// A generic software library.
-    Library(String),
-    /// A Rust crate.
-    RustCrate(String),
-}
-
-impl std::fmt::Display for ComponentFlavor {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::PythonDistribution(name) => f.write_str(name),
-            Self::PythonStandardLibraryModule(name) => {
-                f.write_fmt(format_args!("Python stdlib module {}", name))
-            }
-            Self::PythonStandardLibraryExtensionModule(name) => {
-                f.write_fmt(format_args!("Python stdlib extension {}", name))
-            }
-            Self::PythonExtensionModule(name) => {
-                f.write_fmt(format_args!("Python extension module {}", name))
-            }
-            Self::PythonModule(name) => f.write_fmt(format_args!("Python module {}", name)),
-            Self::Library(name) => f.write_fmt(format_args!("library {}", name)),
-            Self::RustCrate(name) => f.write_fmt(format_args!("Rust crate {}", name)),
-        }
-    }
-}
-
-// impl PartialEq for ComponentFlavor {
-//     fn eq(&self, other: &Self) -> bool {
-//         // If both entities have a Python module name, equivalence is whether
-//         // the module names agree, as there can only be a single entity for a given
-//         // module name.
-//         match (self.python_module_name(), other.python_module_name()) {
-//             (Some(a), Some(b)) => a.eq(b),
-//             // Comparing a module with a non-module is always not equivalent.
-//             (Some(_), None) => false,
-//             (None, Some(_)) => false,
-//             (None, None) => match (self, other) {
-//                 (Self::PythonDistribution(a), Self::PythonDistribution(b)) => a.eq(b),
-//                 (Self::Library(a), Self::Library(b)) => a.eq(b),
-//                 (Self::RustCrate(a), Self::RustCrate(b)) => a.eq(b),
-//                 _ => false,
-//             },
-//         }
-//     }
-// }
-
-// impl Eq for ComponentFlavor {}
-//
-// impl PartialOrd for ComponentFlavor {
-//     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-//         match (self.python_module_name(), other.python_module_name()) {
-//             (Some(a), Some(b)) => a.partial_cmp(b),
-//             _ => {
-//                 let a = (self.ordinal_value(), self.to_string());
-//                 let b = (other.ordinal_value(), other.to_string());
-//
-//                 a.partial_cmp(&b)
-//             }
-//         }
-//     }
-// }
-//
-// impl Ord for ComponentFlavor {
-//     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-//         self.partial_cmp(other).unwrap()
-//     }
-// }
-
 pub fn walk_tree_files(path: &Path) -> Box<dyn Iterator<Item = walkdir::DirEntry>> {
     let res = walkdir::WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name()));
 
@@ -300,6 +164,15 @@ impl FileData {
     }
 }
 
+/// Provides the bytecode of a Python module.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PythonModuleBytecodeProvider {
+    /// Bytecode is already available.
+    Provided(FileData),
+    /// Bytecode must be compiled from the given source.
+    FromSource(FileData),
+}
+
 /// Represents a dependency on a library.
 ///
 /// The library can be defined a number of ways and multiple variants may be
@@ -387,6 +260,34 @@ impl LinkEntry {
     }
 }
 
+/// A variant of an extension module of the distribution that can be linked
+/// into libpython.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DistributionExtensionModule {
+    /// Name of the module.
+    pub name: String,
+
+    /// Name of the variant, e.g. `default`.
+    pub variant: String,
+
+    /// Name of the C function initializing the module.
+    ///
+    /// `NULL` for modules initialized by the interpreter itself.
+    pub init_fn: String,
+
+    /// Whether the module's object files are part of the core distribution.
+    pub in_core: bool,
+
+    /// Whether the interpreter can't run without the module.
+    pub required: bool,
+
+    /// Object files implementing the module.
+    pub object_files: Vec<PathBuf>,
+
+    /// Libraries the module links against.
+    pub link_libraries: Vec<LibraryDependency>,
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct PythonBuildExtensionInfo {
     in_core: bool,
@@ -572,17 +473,27 @@ pub struct PyembedPythonInterpreterConfig {
     pub set_missing_path_configuration: bool,
     pub oxidized_importer: bool,
     pub filesystem_importer: bool,
-    // pub packed_resources: Vec<PyembedPackedResourcesSource>,
+    pub packed_resources: Vec<PyembedPackedResourcesSource>,
     pub argvb: bool,
     pub multiprocessing_auto_dispatch: bool,
     // pub multiprocessing_start_method: MultiprocessingStartMethod,
     pub sys_frozen: bool,
     pub sys_meipass: bool,
     // pub terminfo_resolution: TerminfoResolution,
+    pub stdlib_path: Option<PathBuf>,
     pub tcl_library: Option<PathBuf>,
     pub write_modules_directory_env: Option<String>,
 }
 
+/// Where the embedded interpreter loads packed resources data from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PyembedPackedResourcesSource {
+    /// Embed the file in the binary with `include_bytes!`.
+    MemoryIncludeBytes(PathBuf),
+    /// Memory map the file at run-time. `$ORIGIN` expands to the executable's directory.
+    MemoryMappedPath(PathBuf),
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 // #[cfg_attr(feature = "serialization", derive(Deserialize, Serialize))]
 // #[cfg_attr(feature = "serialization", serde(default))]
@@ -591,6 +502,8 @@ pub struct PythonInterpreterConfig {
     pub profile: PythonInterpreterProfile,
     // pub allocator: Option<Allocator>,
     pub configure_locale: Option<bool>,
+    /// Paths to add to `sys.path`. `$ORIGIN` expands to the executable's directory.
+    pub module_search_paths: Option<Vec<PathBuf>>,
 }
 
 impl Default for PyembedPythonInterpreterConfig {
@@ -617,13 +530,14 @@ impl Default for PyembedPythonInterpreterConfig {
             set_missing_path_configuration: true,
             oxidized_importer: true,
             filesystem_importer: false,
-            // packed_resources: vec![],
+            packed_resources: vec![],
             argvb: false,
             multiprocessing_auto_dispatch: true,
             // multiprocessing_start_method: MultiprocessingStartMethod::Auto,
             sys_frozen: true,
             sys_meipass: false,
             // terminfo_resolution: TerminfoResolution::None,
+            stdlib_path: None,
             tcl_library: None,
             write_modules_directory_env: None,
         }
@@ -815,7 +729,10 @@ pub struct StandaloneDistribution {
     // apple_sdk_info: Option<AppleSdkInfo>,
 
     /// Holds license information for the core distribution.
-    // pub core_license: Option<LicensedComponent>,
+    pub core_license: Option<LicensedComponent>,
+
+    /// License information of extension modules with their own licensing.
+    pub extension_licenses: BTreeMap<String, LicensedComponent>,
 
     /// SPDX license shortnames that apply to this distribution.
     ///
@@ -838,15 +755,17 @@ pub struct StandaloneDistribution {
     pub objs_core: BTreeMap<PathBuf, PathBuf>,
 
     /// Linking information for the core Python implementation.
-    // pub links_core: Vec<LibraryDependency>,
+    pub links_core: Vec<LibraryDependency>,
 
     /// Filesystem location of pythonXY shared library for this distribution.
-    ///
-    ///pub libpython_shared_library: Option<PathBuf>,
+    pub libpython_shared_library: Option<PathBuf>,
 
     /// Extension modules available to this distribution.
     // pub extension_modules: BTreeMap<String, PythonExtensionModuleVariants>,
 
+    /// Variants of the extension modules that can be linked into libpython.
+    pub builtin_extension_modules: BTreeMap<String, Vec<DistributionExtensionModule>>,
+
     pub frozen_c: Vec<u8>,
 
     /// Include files for Python.
@@ -883,6 +802,9 @@ pub struct StandaloneDistribution {
     /// Suffixes for Python module types.
     // module_suffixes: PythonModuleSuffixes,
 
+    /// File name suffixes of extension modules.
+    pub extension_module_suffixes: Vec<String>,
+
     /// List of strings denoting C Runtime requirements.
     pub crt_features: Vec<String>,
 
@@ -1075,24 +997,61 @@ impl StandaloneDistribution {
         dbg!(&pi);
 
         // Derive the distribution's license from a license file, if present.
-        // let core_license = if let Some(ref python_license_path) = pi.license_path {
-        //     let license_path = python_path.join(python_license_path);
-        //     let license_text = std::fs::read_to_string(&license_path).with_context(|| {
-        //         format!("unable to read Python license {}", license_path.display())
-        //     })?;
-        //
-        //     let expression = pi.licenses.clone().unwrap().join(" OR ");
-        //
-        //     let mut component = LicensedComponent::new_spdx(
-        //         ComponentFlavor::PythonDistribution(pi.python_implementation_name.clone()),
-        //         &expression,
-        //     )?;
-        //     component.add_license_text(license_text);
-        //
-        //     Some(component)
-        // } else {
-        //     None
-        // };
+        let core_license = if let Some(ref python_license_path) = pi.license_path {
+            let license_path = python_path.join(python_license_path);
+            let license_text = std::fs::read_to_string(&license_path).wrap_err_with(|| {
+                format!("unable to read Python license {}", license_path.display())
+            })?;
+
+            let expression = pi.licenses.clone().unwrap_or_default().join(" OR ");
+
+            let mut component = LicensedComponent::new_spdx(
+                ComponentFlavor::PythonDistribution(pi.python_implementation_name.clone()),
+                &expression,
+            )?;
+            component.set_version(&pi.python_version);
+            component.add_license_text(license_text);
+
+            Some(component)
+        } else {
+            None
+        };
+
+        // Extension module licenses cover the libraries they link, e.g.
+        // OpenSSL for _ssl. Variants of a module share their licensing, so
+        // the first one is representative.
+        let mut extension_licenses = BTreeMap::new();
+        for (module, variants) in &pi.build_info.extensions {
+            let Some(entry) = variants.first() else {
+                continue;
+            };
+
+            let component_flavor =
+                ComponentFlavor::PythonStandardLibraryExtensionModule(module.clone());
+
+            let mut license = if entry.license_public_domain.unwrap_or(false) {
+                LicensedComponent::new(component_flavor, LicenseFlavor::PublicDomain)
+            } else if let Some(licenses) = &entry.licenses {
+                let expression = licenses.join(" OR ");
+                LicensedComponent::new_spdx(component_flavor, &expression)?
+            } else {
+                // Without own licensing, the module is covered by the
+                // distribution's license.
+                continue;
+            };
+
+            if let Some(license_paths) = &entry.license_paths {
+                for path in license_paths {
+                    let path = python_path.join(path);
+                    let text = std::fs::read_to_string(&path)
+                        .wrap_err_with(|| format!("reading {}", path.display()))?;
+
+                    license.add_license_text(text);
+                }
+            }
+
+            extension_licenses.insert(module.clone(), license);
+        }
 
         // Collect object files for libpython.
         for obj in &pi.build_info.core.objs {
@@ -1114,6 +1073,28 @@ impl StandaloneDistribution {
             links_core.push(depends);
         }
 
+        let mut builtin_extension_modules = BTreeMap::new();
+        for (module, variants) in &pi.build_info.extensions {
+            let variants = variants
+                .iter()
+                .map(|entry| DistributionExtensionModule {
+                    name: module.clone(),
+                    variant: entry.variant.clone(),
+                    init_fn: entry.init_fn.clone(),
+                    in_core: entry.in_core,
+                    required: entry.required,
+                    object_files: entry.objs.iter().map(|p| python_path.join(p)).collect(),
+                    link_libraries: entry
+                        .links
+                        .iter()
+                        .map(|link| link.to_library_dependency(&python_path))
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+
+            builtin_extension_modules.insert(module.clone(), variants);
+        }
+
         // let module_suffixes = PythonModuleSuffixes {
         //     source: pi
         //         .python_suffixes
@@ -1350,7 +1331,8 @@ impl StandaloneDistribution {
             python_symbol_visibility: pi.python_symbol_visibility,
             extension_module_loading: pi.python_extension_module_loading,
             // apple_sdk_info,
-            // core_license,
+            core_license,
+            extension_licenses,
             licenses: pi.licenses.clone(),
             license_path: pi.license_path.as_ref().map(PathBuf::from),
             tcl_library_path: pi
@@ -1361,10 +1343,16 @@ impl StandaloneDistribution {
             // extension_modules,
             frozen_c,
             includes,
-            // links_core,
+            links_core,
+            builtin_extension_modules,
             libraries,
             objs_core,
-            // libpython_shared_library,
+            libpython_shared_library: pi
+                .build_info
+                .core
+                .shared_lib
+                .as_ref()
+                .map(|path| python_path.join(path)),
             py_modules,
             resources,
             // venv_base,
@@ -1372,6 +1360,11 @@ impl StandaloneDistribution {
             inittab_cflags: pi.build_info.inittab_cflags,
             cache_tag: pi.python_implementation_cache_tag,
             // module_suffixes,
+            extension_module_suffixes: pi
+                .python_suffixes
+                .get("extension")
+                .cloned()
+                .unwrap_or_default(),
             crt_features: pi.crt_features,
             config_vars: pi.python_config_vars,
         })
@@ -1436,6 +1429,10 @@ pub struct PythonPackagingPolicy {
 
     broken_extensions: HashMap<String, Vec<String>>,
 
+    /// Shared libraries beyond the manylinux policy list that are assumed to
+    /// be present on target systems, so extension modules may need them.
+    allowed_system_libraries: Vec<String>,
+
     /// Whether to write Python bytecode at optimization level 0.
     bytecode_optimize_level_zero: bool,
 
@@ -1447,6 +1444,9 @@ pub struct PythonPackagingPolicy {
 
     /// Python modules for which bytecode should not be generated by default.
     no_bytecode_modules: HashSet<String>,
+
+    /// Filesystem-relative prefix to move packages using `__file__` to.
+    dunder_file_reroute_prefix: Option<String>,
 }
 
 impl Default for PythonPackagingPolicy {
@@ -1454,8 +1454,8 @@ impl Default for PythonPackagingPolicy {
         PythonPackagingPolicy {
             // extension_module_filter: ExtensionModuleFilter::All,
             preferred_extension_module_variants: HashMap::new(),
-            // resources_location: ConcreteResourceLocation::InMemory,
-            // resources_location_fallback: None,
+            resources_location: ConcreteResourceLocation::InMemory,
+            resources_location_fallback: None,
             allow_in_memory_shared_library_loading: false,
             allow_files: false,
             file_scanner_emit_files: false,
@@ -1467,10 +1467,12 @@ impl Default for PythonPackagingPolicy {
             include_test: false,
             include_file_resources: false,
             broken_extensions: HashMap::new(),
+            allowed_system_libraries: vec![],
             bytecode_optimize_level_zero: true,
             bytecode_optimize_level_one: false,
             bytecode_optimize_level_two: false,
             no_bytecode_modules: HashSet::new(),
+            dunder_file_reroute_prefix: None,
         }
     }
 }
@@ -1565,6 +1567,37 @@ impl PythonPackagingPolicy {
             .push(extension.to_string());
     }
 
+    /// Obtain the prefix packages using `__file__` are moved to, if any.
+    pub fn dunder_file_reroute_prefix(&self) -> Option<&str> {
+        self.dunder_file_reroute_prefix.as_deref()
+    }
+
+    /// Set the prefix packages using `__file__` are moved to.
+    ///
+    /// If set, packages with in-memory modules referencing `__file__` are moved
+    /// to `ConcreteResourceLocation::RelativePath(prefix)` entirely, including
+    /// their resource files.
+    pub fn set_dunder_file_reroute_prefix(&mut self, prefix: Option<String>) {
+        self.dunder_file_reroute_prefix = prefix;
+    }
+
+    /// Obtain the extensions marked as broken on a target platform.
+    pub fn broken_extensions_for_triple(&self, target_triple: &str) -> Option<&Vec<String>> {
+        self.broken_extensions.get(target_triple)
+    }
+
+    /// Obtain the shared libraries assumed to be present on target systems.
+    pub fn allowed_system_libraries(&self) -> &[String] {
+        &self.allowed_system_libraries
+    }
+
+    /// Allow extension modules to need a shared library that isn't shipped.
+    ///
+    /// `name` is a `DT_NEEDED` entry, e.g. `libGLU.so.1`.
+    pub fn register_allowed_system_library(&mut self, name: &str) {
+        self.allowed_system_libraries.push(name.to_string());
+    }
+
     /// Register a Python module as one that should not generate bytecode.
     ///
     /// When source modules matching names registered with this function are added,
@@ -1576,6 +1609,29 @@ impl PythonPackagingPolicy {
         self.no_bytecode_modules.insert(name.to_string());
     }
 
+    /// Set whether to write Python bytecode at each optimization level.
+    pub fn set_bytecode_optimize_levels(&mut self, zero: bool, one: bool, two: bool) {
+        self.bytecode_optimize_level_zero = zero;
+        self.bytecode_optimize_level_one = one;
+        self.bytecode_optimize_level_two = two;
+    }
+
+    /// Optimization levels to generate bytecode for when adding a Python module.
+    pub fn bytecode_optimization_levels(&self, name: &str) -> Vec<BytecodeOptimizationLevel> {
+        if self.no_bytecode_modules.contains(name) {
+            return vec![];
+        }
+
+        [
+            (self.bytecode_optimize_level_zero, BytecodeOptimizationLevel::Zero),
+            (self.bytecode_optimize_level_one, BytecodeOptimizationLevel::One),
+            (self.bytecode_optimize_level_two, BytecodeOptimizationLevel::Two),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, level)| enabled.then_some(level))
+        .collect()
+    }
+
     // /// Set the primary location for added resources.
     // pub fn set_resources_location(&mut self, location: ConcreteResourceLocation) {
     //     // self.resources_location = location;
@@ -1651,46 +1707,371 @@ impl PythonResourceCollector {
         }
     }
 
+    /// Iterate over the collected resources, by name.
+    pub fn iter_resources(&self) -> impl Iterator<Item = (&String, &PrePackagedResource)> {
+        self.resources.iter()
+    }
+
+    fn entry(&mut self, name: &str) -> &mut PrePackagedResource {
+        self.resources
+            .entry(name.to_string())
+            .or_insert_with(|| PrePackagedResource {
+                name: name.to_string(),
+                ..Default::default()
+            })
+    }
+
+    /// Resolve the location of a resource from the policy's preferred locations.
+    ///
+    /// The policy's primary location is used if allowed, then its fallback.
+    fn resolve_location(
+        policy: &PythonPackagingPolicy,
+        allowed: &[AbstractResourceLocation],
+        what: &str,
+    ) -> eyre::Result<ConcreteResourceLocation> {
+        [
+            Some(policy.resources_location()),
+            policy.resources_location_fallback().as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|location| allowed.contains(&AbstractResourceLocation::from(*location)))
+        .cloned()
+        .ok_or_else(|| eyre::eyre!("no allowed location for {}", what))
+    }
+
+    /// Add a Python module's source code.
+    ///
+    /// In-memory modules get bytecode for the optimization levels requested by
+    /// the policy. It is produced by [Self::compile_resources].
+    pub fn add_python_module_source(
+        &mut self,
+        name: &str,
+        source: FileData,
+        is_package: bool,
+        policy: &PythonPackagingPolicy,
+    ) -> eyre::Result<()> {
+        let location = Self::resolve_location(policy, &self.allowed_locations, name)?;
+
+        let entry = self.entry(name);
+        entry.is_module = true;
+        entry.is_package = is_package;
+
+        match location {
+            ConcreteResourceLocation::InMemory => {
+                for level in policy.bytecode_optimization_levels(name) {
+                    let bytecode = Some(PythonModuleBytecodeProvider::FromSource(source.clone()));
+
+                    match level {
+                        BytecodeOptimizationLevel::Zero => entry.in_memory_bytecode = bytecode,
+                        BytecodeOptimizationLevel::One => entry.in_memory_bytecode_opt1 = bytecode,
+                        BytecodeOptimizationLevel::Two => entry.in_memory_bytecode_opt2 = bytecode,
+                    }
+                }
+
+                entry.in_memory_source = Some(source);
+            }
+            ConcreteResourceLocation::RelativePath(prefix) => {
+                entry.relative_path_module_source = Some((prefix, source));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add an extension module at a path relative to `site-packages`.
+    pub fn add_extension_module_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+        data: FileData,
+        policy: &PythonPackagingPolicy,
+    ) -> eyre::Result<()> {
+        let location =
+            Self::resolve_location(policy, &self.allowed_extension_module_locations, name)?;
+
+        let entry = self.entry(name);
+        entry.is_module = true;
+        entry.is_extension_module = true;
+
+        match location {
+            ConcreteResourceLocation::InMemory => {
+                entry.in_memory_extension_module_shared_library = Some(data);
+            }
+            ConcreteResourceLocation::RelativePath(prefix) => {
+                entry.relative_path_extension_module_shared_library =
+                    Some((Path::new(&prefix).join(path), data));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add a shared library at a path relative to `site-packages`.
+    ///
+    /// Shared libraries follow the locations allowed for extension modules,
+    /// which load them.
+    pub fn add_shared_library_file(
+        &mut self,
+        path: &Path,
+        data: FileData,
+        policy: &PythonPackagingPolicy,
+    ) -> eyre::Result<()> {
+        let name = path.to_string_lossy().replace('\\', "/");
+        let location =
+            Self::resolve_location(policy, &self.allowed_extension_module_locations, &name)?;
+
+        let entry = self.entry(&name);
+        entry.is_shared_library = true;
+
+        match location {
+            ConcreteResourceLocation::InMemory => {
+                entry.in_memory_shared_library = Some(data);
+            }
+            ConcreteResourceLocation::RelativePath(prefix) => {
+                entry.relative_path_shared_library = Some((prefix, path.to_path_buf(), data));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add a file of a distribution's metadata, e.g. `METADATA`.
+    pub fn add_distribution_resource(
+        &mut self,
+        distribution: &str,
+        file_name: &str,
+        data: FileData,
+        policy: &PythonPackagingPolicy,
+    ) -> eyre::Result<()> {
+        let location = Self::resolve_location(policy, &self.allowed_locations, distribution)?;
+        let entry = self.entry(distribution);
+
+        match location {
+            ConcreteResourceLocation::InMemory => {
+                entry
+                    .in_memory_distribution_resources
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(file_name.to_string(), data);
+            }
+            ConcreteResourceLocation::RelativePath(prefix) => {
+                let path = Path::new(&prefix)
+                    .join(format!("{}.dist-info", distribution))
+                    .join(file_name);
+
+                entry
+                    .relative_path_distribution_resources
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(file_name.to_string(), (path, data));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add a non-module file belonging to a package.
+    pub fn add_package_resource(
+        &mut self,
+        package: &str,
+        file_name: &str,
+        data: FileData,
+        policy: &PythonPackagingPolicy,
+    ) -> eyre::Result<()> {
+        let location = Self::resolve_location(policy, &self.allowed_locations, package)?;
+        let entry = self.entry(package);
+
+        match location {
+            ConcreteResourceLocation::InMemory => {
+                entry
+                    .in_memory_resources
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(file_name.to_string(), data);
+            }
+            ConcreteResourceLocation::RelativePath(prefix) => {
+                let mut path = PathBuf::from(prefix);
+                path.extend(package.split('.'));
+                path.extend(file_name.split('/'));
+
+                entry
+                    .relative_path_package_resources
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(file_name.to_string(), (path, data));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Obtain a copy of this collector with all pending bytecode compiled.
+    pub fn compile_resources(&self, compiler: &mut BytecodeCompiler) -> eyre::Result<Self> {
+        let mut res = self.clone();
+
+        for (name, resource) in res.resources.iter_mut() {
+            for (bytecode, level) in [
+                (&mut resource.in_memory_bytecode, BytecodeOptimizationLevel::Zero),
+                (&mut resource.in_memory_bytecode_opt1, BytecodeOptimizationLevel::One),
+                (&mut resource.in_memory_bytecode_opt2, BytecodeOptimizationLevel::Two),
+            ] {
+                if let Some(PythonModuleBytecodeProvider::FromSource(source)) = bytecode {
+                    let data = compiler.compile(name, &source.resolve_content()?, level)?;
+                    *bytecode = Some(PythonModuleBytecodeProvider::Provided(FileData::Memory(data)));
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
     /// Searches for Python sources for references to __file__.
     ///
     /// __file__ usage can be problematic for in-memory modules. This method searches
-    /// for its occurrences and returns module names having it present.
-    pub fn find_dunder_file(&self) -> eyre::Result<BTreeSet<String>> {
-        let mut res = BTreeSet::new();
+    /// in-memory modules for its occurrences and returns their locations.
+    pub fn find_dunder_file(&self) -> eyre::Result<Vec<DunderFileUsage>> {
+        let mut res = vec![];
 
         for (name, module) in &self.resources {
-            if let Some(location) = &module.in_memory_source {
-                if has_dunder_file(&location.resolve_content()?)? {
-                    res.insert(name.clone());
-                }
+            let source = module.in_memory_source.as_ref().or_else(|| {
+                [
+                    &module.in_memory_bytecode,
+                    &module.in_memory_bytecode_opt1,
+                    &module.in_memory_bytecode_opt2,
+                ]
+                .into_iter()
+                .find_map(|bytecode| match bytecode {
+                    Some(PythonModuleBytecodeProvider::FromSource(source)) => Some(source),
+                    _ => None,
+                })
+            });
+
+            let Some(source) = source else {
+                continue;
+            };
+
+            let source = source.resolve_content()?;
+
+            res.extend(
+                dunder_file::find_dunder_file_locations(&source)
+                    .into_iter()
+                    .map(|location| DunderFileUsage {
+                        module: name.clone(),
+                        path: dunder_file::module_source_path(name, module.is_package),
+                        location,
+                    }),
+            );
+        }
+
+        Ok(res)
+    }
+
+    /// Move in-memory modules and resources of packages to the filesystem.
+    ///
+    /// All modules and package resources within the given top-level packages
+    /// are moved to `prefix`, so the packages can find files relative to
+    /// their `__file__`. Bytecode isn't moved: Python compiles sources
+    /// imported from the filesystem itself.
+    pub fn reroute_packages_to_relative_path(
+        &mut self,
+        packages: &BTreeSet<String>,
+        prefix: &str,
+    ) -> eyre::Result<()> {
+        for (name, resource) in self.resources.iter_mut() {
+            let top_level = name.split('.').next().unwrap_or(name);
+            if !packages.contains(top_level) {
+                continue;
             }
 
-            if let Some(PythonModuleBytecodeProvider::FromSource(location)) =
-                &module.in_memory_bytecode
-            {
-                if has_dunder_file(&location.resolve_content()?)? {
-                    res.insert(name.clone());
-                }
+            let source = resource.in_memory_source.take().or_else(|| {
+                [
+                    &resource.in_memory_bytecode,
+                    &resource.in_memory_bytecode_opt1,
+                    &resource.in_memory_bytecode_opt2,
+                ]
+                .into_iter()
+                .find_map(|bytecode| match bytecode {
+                    Some(PythonModuleBytecodeProvider::FromSource(source)) => Some(source.clone()),
+                    _ => None,
+                })
+            });
+
+            if let Some(source) = source {
+                resource.in_memory_bytecode = None;
+                resource.in_memory_bytecode_opt1 = None;
+                resource.in_memory_bytecode_opt2 = None;
+                resource.relative_path_module_source = Some((prefix.to_string(), source));
+            } else if resource.in_memory_bytecode.is_some() {
+                return Err(eyre::eyre!(
+                    "{} has bytecode but no source and can't be moved to the filesystem",
+                    name
+                ));
             }
 
-            if let Some(PythonModuleBytecodeProvider::FromSource(location)) =
-                &module.in_memory_bytecode_opt1
-            {
-                if has_dunder_file(&location.resolve_content()?)? {
-                    res.insert(name.clone());
+            if let Some(resources) = resource.in_memory_resources.take() {
+                let relative = resource
+                    .relative_path_package_resources
+                    .get_or_insert_with(BTreeMap::new);
+
+                for (file_name, data) in resources {
+                    let mut path = PathBuf::from(prefix);
+                    path.extend(name.split('.'));
+                    path.extend(file_name.split('/'));
+
+                    relative.insert(file_name, (path, data));
                 }
             }
+        }
+
+        Ok(())
+    }
+
+    /// Write resources at filesystem-relative locations below a directory.
+    pub fn write_relative_path_resources(&self, dest_dir: &Path) -> eyre::Result<()> {
+        let mut files: Vec<(PathBuf, &FileData)> = vec![];
+
+        for (name, resource) in &self.resources {
+            if let Some((prefix, source)) = &resource.relative_path_module_source {
+                let path = dunder_file::module_source_path(name, resource.is_package);
+                files.push((Path::new(prefix).join(path), source));
+            }
+
+            if let Some((path, data)) = &resource.relative_path_extension_module_shared_library {
+                files.push((path.clone(), data));
+            }
+
+            if let Some((prefix, path, data)) = &resource.relative_path_shared_library {
+                files.push((Path::new(prefix).join(path), data));
+            }
 
-            if let Some(PythonModuleBytecodeProvider::FromSource(location)) =
-                &module.in_memory_bytecode_opt2
+            for (path, data) in resource
+                .relative_path_package_resources
+                .iter()
+                .chain(resource.relative_path_distribution_resources.iter())
+                .flat_map(|resources| resources.values())
             {
-                if has_dunder_file(&location.resolve_content()?)? {
-                    res.insert(name.clone());
-                }
+                files.push((path.clone(), data));
             }
         }
 
-        Ok(res)
+        for (path, data) in files {
+            let path = dest_dir.join(path);
+
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .wrap_err_with(|| format!("creating directory {}", parent.display()))?;
+            }
+
+            std::fs::write(&path, data.resolve_content()?)
+                .wrap_err_with(|| format!("writing {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Serialize the collected resources to packed resources data.
+    ///
+    /// Only the `in_memory_*` fields of resources are serialized. See
+    /// [packed_resources] for the format.
+    pub fn write_packed_resources<W: std::io::Write>(&self, writer: &mut W) -> eyre::Result<()> {
+        packed_resources::write_packed_resources(self.resources.values(), writer)
     }
 }
 
@@ -1710,8 +2091,8 @@ pub fn python_source_encoding(source: &[u8]) -> Vec<u8> {
             break;
         }
 
-        if let Some(m) = RE_CODING.find(line) {
-            return m.as_bytes().to_vec();
+        if let Some(captures) = RE_CODING.captures(line) {
+            return captures[1].to_vec();
         }
     }
 
@@ -1720,19 +2101,9 @@ pub fn python_source_encoding(source: &[u8]) -> Vec<u8> {
 
 /// Whether __file__ occurs in Python source code.
 pub fn has_dunder_file(source: &[u8]) -> eyre::Result<bool> {
-    // We can't just look for b"__file__ because the source file may be in
-    // encodings like UTF-16. So we need to decode to Unicode first then look for
-    // the code points.
-    let encoding = python_source_encoding(source);
-
-    let encoder = match encoding_rs::Encoding::for_label(&encoding) {
-        Some(encoder) => encoder,
-        None => encoding_rs::UTF_8,
-    };
-
-    let (source, ..) = encoder.decode(source);
-
-    Ok(source.contains("__file__"))
+    // Source encodings are ASCII compatible, so no decoding is needed. See
+    // [dunder_file].
+    Ok(!dunder_file::find_dunder_file_locations(source).is_empty())
 }
 
 /// Describes the concrete location of a Python resource.
@@ -1931,17 +2302,9 @@ impl StandaloneDistribution {
     //     self.apple_sdk_info.as_ref()
     // }
 
-    // fn create_bytecode_compiler(
-    //     &self,
-    //     env: &Environment,
-    // ) -> Result<Box<dyn PythonBytecodeCompiler>> {
-    //     let temp_dir = env.temporary_directory("pyoxidizer-bytecode-compiler")?;
-    //
-    //     Ok(Box::new(BytecodeCompiler::new(
-    //         &self.python_exe,
-    //         temp_dir.path(),
-    //     )?))
-    // }
+    fn create_bytecode_compiler(&self, cache_dir: &Path) -> eyre::Result<BytecodeCompiler> {
+        BytecodeCompiler::new(&self.python_exe, cache_dir)
+    }
 
     fn create_packaging_policy(&self) -> eyre::Result<PythonPackagingPolicy> {
         let mut policy = PythonPackagingPolicy::default();
@@ -2309,6 +2672,9 @@ pub struct StandalonePythonExecutableBuilder {
     /// Path to install tcl/tk files into.
     tcl_files_path: Option<String>,
 
+    /// Directory caching compiled bytecode across runs.
+    bytecode_cache_dir: PathBuf,
+
     // /// Describes how Windows runtime DLLs should be handled during builds.
     // windows_runtime_dlls_mode: WindowsRuntimeDllsMode,
 }
@@ -2374,175 +2740,449 @@ impl StandalonePythonExecutableBuilder {
         Ok(())
     }
 
-    pub fn to_embedded_python_context(
-        &self,
-        // env: &Environment,
-        opt_level: &str,
-    ) -> eyre::Result<EmbeddedPythonContext> {
-        let mut file_seen = false;
-        for module in self.resources_collector.find_dunder_file()? {
-            file_seen = true;
-            log::warn!("warning: {} contains __file__", module);
+    /// Move packages using `__file__` to the filesystem, if the policy asks for it.
+    ///
+    /// The reroute prefix is added to `sys.path` and filesystem importing is
+    /// enabled, so the moved packages can be imported. Without a prefix, the
+    /// `__file__` usages are only reported.
+    pub fn reroute_dunder_file_packages(&mut self) -> eyre::Result<BTreeSet<String>> {
+        let Some(prefix) = self.packaging_policy.dunder_file_reroute_prefix() else {
+            let usages = self.resources_collector.find_dunder_file()?;
+            for usage in &usages {
+                log::warn!("{}: in-memory module {} uses __file__", usage, usage.module);
+            }
+            if !usages.is_empty() {
+                log::warn!("in-memory modules don't have __file__ and this may create problems at run-time");
+                log::warn!("set a __file__ reroute prefix to load them from the filesystem");
+            }
+
+            return Ok(BTreeSet::new());
+        };
+        let prefix = prefix.to_string();
+
+        let mut packages = BTreeSet::new();
+        for usage in self.resources_collector.find_dunder_file()? {
+            log::info!("{}: {} uses __file__", usage, usage.module);
+
+            let top_level = usage.module.split('.').next().unwrap_or(&usage.module);
+            packages.insert(top_level.to_string());
         }
 
-        if file_seen {
-            log::warn!("__file__ was encountered in some embedded modules");
-            log::warn!("PyOxidizer does not set __file__ and this may create problems at run-time");
-            log::warn!("See https://github.com/indygreg/PyOxidizer/issues/69 for more");
+        if packages.is_empty() {
+            return Ok(packages);
         }
 
-        // let compiled_resources = {
-        //     let temp_dir = env.temporary_directory("pyoxidizer-bytecode-compile")?;
-        //     let mut compiler = BytecodeCompiler::new(self.host_python_exe_path(), temp_dir.path())?;
-        //     let resources = self.resources_collector.compile_resources(&mut compiler)?;
-        //
-        //     temp_dir.close().context("closing temporary directory")?;
-        //
-        //     resources
-        // };
+        for package in &packages {
+            log::info!("loading {} from {} because it uses __file__", package, prefix);
+        }
+
+        self.resources_collector
+            .reroute_packages_to_relative_path(&packages, &prefix)?;
+
+        let search_path = PathBuf::from("$ORIGIN").join(&prefix);
+        let search_paths = self
+            .config
+            .config
+            .module_search_paths
+            .get_or_insert_with(Vec::new);
+        if !search_paths.contains(&search_path) {
+            search_paths.push(search_path);
+        }
+        self.config.filesystem_importer = true;
 
-        let mut pending_resources = vec![];
+        Ok(packages)
+    }
 
-        let mut extra_files = compiled_resources.extra_files_manifest()?;
+    /// Add the source modules of the distribution's standard library.
+    ///
+    /// With `trim`, only modules reachable through imports from the entry
+    /// modules are added and a report comparing the sizes is returned.
+    pub fn add_stdlib_modules(
+        &mut self,
+        trim: Option<&StdlibTrim>,
+    ) -> eyre::Result<Option<TrimReport>> {
+        let mut excluded = vec![];
+        if !self.packaging_policy.include_test {
+            excluded = self.target_distribution.stdlib_test_packages();
+        }
 
-        let mut config = self.config.clone();
+        let stdlib =
+            stdlib_trim::find_stdlib_modules(&self.target_distribution.stdlib_path, &excluded)?;
+
+        let (kept, report) = match trim {
+            Some(trim) => {
+                let mut entry_sources = vec![];
+
+                for (name, resource) in self.resources_collector.iter_resources() {
+                    let is_entry = trim
+                        .entry_modules
+                        .iter()
+                        .any(|m| name == m || name.starts_with(&format!("{}.", m)));
+
+                    let source = resource.in_memory_source.as_ref().or(resource
+                        .relative_path_module_source
+                        .as_ref()
+                        .map(|(_, s)| s));
+
+                    if let (true, Some(source)) = (is_entry, source) {
+                        let source = source.resolve_content()?;
+                        let source = String::from_utf8_lossy(&source).into_owned();
+                        entry_sources.push((name.clone(), resource.is_package, source));
+                    }
+                }
 
-        match &self.resources_load_mode {
-            PackedResourcesLoadMode::None => {}
-            PackedResourcesLoadMode::EmbeddedInBinary(filename) => {
-                pending_resources.push((compiled_resources, PathBuf::from(filename)));
-                config
-                    .packed_resources
-                    .push(PyembedPackedResourcesSource::MemoryIncludeBytes(
-                        PathBuf::from(filename),
+                for dir in &trim.source_dirs {
+                    for entry in walk_tree_files(dir) {
+                        let path = entry.path();
+                        if path.extension().and_then(|s| s.to_str()) != Some("py") {
+                            continue;
+                        }
+
+                        let name = path
+                            .strip_prefix(dir)?
+                            .with_extension("")
+                            .to_string_lossy()
+                            .replace(std::path::MAIN_SEPARATOR, ".");
+                        let (name, is_package) = match name.strip_suffix(".__init__") {
+                            Some(package) => (package.to_string(), true),
+                            None => (name, false),
+                        };
+
+                        let source = std::fs::read(path)
+                            .wrap_err_with(|| format!("reading {}", path.display()))?;
+                        let source = String::from_utf8_lossy(&source).into_owned();
+                        entry_sources.push((name, is_package, source));
+                    }
+                }
+
+                if entry_sources.is_empty() {
+                    return Err(eyre::eyre!(
+                        "no entry modules found for trimming the standard library"
                     ));
+                }
+
+                let kept =
+                    stdlib_trim::stdlib_closure(&stdlib, &entry_sources, &trim.allowed_modules)?;
+                let report = TrimReport::new(&stdlib, &kept);
+
+                (kept, Some(report))
             }
-            PackedResourcesLoadMode::BinaryRelativePathMemoryMapped(path) => {
-                // We need to materialize the file in extra_files. So compile now.
-                let mut buffer = vec![];
-                compiled_resources
-                    .write_packed_resources(&mut buffer)
-                    .context("serializing packed resources")?;
-                extra_files.add_file_entry(Path::new(path), buffer)?;
-
-                config
-                    .packed_resources
-                    .push(PyembedPackedResourcesSource::MemoryMappedPath(
-                        PathBuf::from("$ORIGIN").join(path),
-                    ));
+            None => (stdlib.keys().cloned().collect(), None),
+        };
+
+        for name in &kept {
+            let module = &stdlib[name];
+
+            self.resources_collector.add_python_module_source(
+                name,
+                FileData::Path(module.path.clone()),
+                module.is_package,
+                &self.packaging_policy,
+            )?;
+        }
+
+        Ok(report)
+    }
+
+    /// Copy the distribution's standard library to the runtime directory.
+    ///
+    /// This is an alternative to embedding it, for quicker development builds.
+    /// The interpreter finds it relative to the executable, next to which
+    /// the runtime directory's content is installed.
+    pub fn write_filesystem_stdlib(&mut self, dest_path: &Path) -> eyre::Result<()> {
+        let stdlib_path = &self.target_distribution.stdlib_path;
+        let dest_dir = dest_path.join(libpython::RUNTIME_DIRNAME).join("stdlib");
+
+        let mut excluded = vec![];
+        if !self.packaging_policy.include_test {
+            excluded = self.target_distribution.stdlib_test_packages();
+        }
+
+        if dest_dir.exists() {
+            std::fs::remove_dir_all(&dest_dir)
+                .wrap_err_with(|| format!("removing {}", dest_dir.display()))?;
+        }
+
+        for entry in walk_tree_files(stdlib_path) {
+            let rel_path = entry.path().strip_prefix(stdlib_path)?;
+            let components = rel_path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>();
+
+            if components
+                .iter()
+                .any(|c| c == "site-packages" || c == "__pycache__")
+            {
+                continue;
+            }
+
+            let dotted = components.join(".");
+            if excluded
+                .iter()
+                .any(|p| dotted.starts_with(&format!("{}.", p)))
+            {
+                continue;
             }
+
+            let path = dest_dir.join(rel_path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .wrap_err_with(|| format!("creating {}", parent.display()))?;
+            }
+            std::fs::copy(entry.path(), &path)
+                .wrap_err_with(|| format!("copying {}", entry.path().display()))?;
         }
 
-        let link_settings = self.resolve_python_link_settings(env, opt_level)?;
+        self.config.stdlib_path = Some(PathBuf::from("$ORIGIN").join("stdlib"));
 
-        if self.link_mode == LibpythonLinkMode::Dynamic {
-            if let Some(p) = &self.target_distribution.libpython_shared_library {
-                let manifest_path = Path::new(p.file_name().unwrap());
-                let content = std::fs::read(p)?;
+        Ok(())
+    }
 
-                extra_files.add_file_entry(manifest_path, content)?;
+    /// Obtain licensing information for all components of the executable.
+    ///
+    /// Covers the Python distribution, the extension modules bringing their
+    /// own licensing and every package whose metadata was collected.
+    pub fn licensed_components(&self) -> eyre::Result<LicensedComponents> {
+        let mut components = LicensedComponents::default();
 
-                // Always look for and add the python3.dll variant if it exists. This DLL
-                // exports the stable subset of the Python ABI and it is required by some
-                // extensions.
-                let python3_dll_path = p.with_file_name("python3.dll");
-                let manifest_path = Path::new(python3_dll_path.file_name().unwrap());
-                if python3_dll_path.exists() {
-                    let content = std::fs::read(&python3_dll_path)?;
+        if let Some(component) = &self.target_distribution.core_license {
+            components.add_component(component.clone());
+        }
 
-                    extra_files.add_file_entry(manifest_path, content)?;
-                }
+        for component in self.target_distribution.extension_licenses.values() {
+            components.add_component(component.clone());
+        }
+
+        for (name, resource) in self.resources_collector.iter_resources() {
+            let mut files = BTreeMap::new();
+
+            for (file_name, data) in resource.in_memory_distribution_resources.iter().flatten() {
+                files.insert(file_name.clone(), data.resolve_content()?);
             }
+            for (file_name, (_, data)) in resource
+                .relative_path_distribution_resources
+                .iter()
+                .flatten()
+            {
+                files.insert(file_name.clone(), data.resolve_content()?);
+            }
+
+            if files.is_empty() {
+                continue;
+            }
+
+            components.add_component(
+                licensing::package_licensed_component(&files)
+                    .wrap_err_with(|| format!("resolving licensing of {}", name))?,
+            );
         }
 
-        if let Some(tcl_files_path) = self.tcl_files_path() {
-            for (path, location) in self.target_distribution.tcl_files()? {
-                let install_path = PathBuf::from(tcl_files_path).join(path);
+        Ok(components)
+    }
+
+    /// Write licensing information next to the embedding artifacts.
+    ///
+    /// Writes the aggregated license texts to [Self::licenses_filename], if
+    /// set, and a JSON manifest of all components to `licenses.json`.
+    pub fn synchronize_licensing(&self, dest_path: &Path) -> eyre::Result<()> {
+        let components = self.licensed_components()?;
+
+        for component in components.components_without_spdx_license() {
+            log::warn!(
+                "{} has no SPDX license: {}",
+                component.flavor(),
+                component.license_summary()
+            );
+        }
+
+        if let Some(filename) = &self.licenses_filename {
+            let path = dest_path.join(filename);
+            std::fs::write(&path, components.aggregate_license_document())
+                .wrap_err_with(|| format!("writing {}", path.display()))?;
+        }
+
+        let path = dest_path.join("licenses.json");
+        std::fs::write(&path, serde_json::to_vec_pretty(&components.to_json())?)
+            .wrap_err_with(|| format!("writing {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Select the variants of the distribution's extension modules to build into libpython.
+    ///
+    /// Modules broken on the target are skipped unless they are required. The
+    /// policy's preferred variant is chosen if present, the first one otherwise.
+    pub fn builtin_extension_modules(&self) -> Vec<&DistributionExtensionModule> {
+        let broken = self
+            .packaging_policy
+            .broken_extensions_for_triple(&self.target_triple);
+        let preferred = self.packaging_policy.preferred_extension_module_variants();
+
+        let mut res = vec![];
+        for (name, variants) in &self.target_distribution.builtin_extension_modules {
+            let variant = preferred
+                .get(name)
+                .and_then(|preferred| variants.iter().find(|v| &v.variant == preferred))
+                .or_else(|| variants.first());
+
+            let Some(variant) = variant else {
+                continue;
+            };
 
-                extra_files.add_file_entry(&install_path, location)?;
+            if !variant.required && broken.map_or(false, |broken| broken.contains(name)) {
+                log::info!(
+                    "not building {} into libpython as it is broken on {}",
+                    name,
+                    self.target_triple
+                );
+                continue;
             }
+
+            res.push(variant);
         }
 
-        // Install Windows runtime DLLs if told to do so.
-        extra_files.add_manifest(&self.resolve_windows_runtime_dll_files()?)?;
+        res
+    }
 
-        let python_implementation = if self
-            .target_distribution
-            .python_implementation
-            .starts_with("cpython")
-        {
-            PythonImplementation::CPython
-        } else if self
-            .target_distribution
-            .python_implementation
-            .starts_with("pypy")
-        {
-            PythonImplementation::PyPy
-        } else {
-            return Err(anyhow!(
-                "unknown Python implementation: {}",
-                self.target_distribution.python_implementation
-            ));
-        };
+    /// Build libpython and resolve the settings for linking it.
+    ///
+    /// Build artifacts are written to `dest_path`. When linking dynamically,
+    /// the distribution's shared libpython is used as is.
+    pub fn resolve_python_link_settings(
+        &self,
+        opt_level: &str,
+        dest_path: &Path,
+    ) -> eyre::Result<LibpythonInfo> {
+        let dist = &self.target_distribution;
 
-        let python_version =
-            PythonVersion::from_str(&self.target_distribution.python_major_minor_version())
-                .map_err(|e| anyhow!("unable to determine Python version: {}", e))?;
+        if self.link_mode == LibpythonLinkMode::Dynamic {
+            let shared_library = dist.libpython_shared_library.as_ref().ok_or_else(|| {
+                eyre::eyre!("distribution has no shared libpython to link dynamically")
+            })?;
+
+            return libpython::link_shared_libpython(
+                shared_library,
+                &self.target_triple,
+                dest_path,
+            )
+            .context("preparing shared libpython");
+        }
 
-        // Populate build flags that influence PyO3 configuration.
-        let mut python_build_flags = BuildFlags::new();
+        let mut core = LibPythonBuildContext {
+            inittab_cflags: Some(dist.inittab_cflags.clone()),
+            ..Default::default()
+        };
 
-        if self
-            .target_distribution
-            .python_config_vars()
-            .get("Py_DEBUG")
-            == Some(&"1".to_string())
-        {
-            python_build_flags.0.insert(BuildFlag::Py_DEBUG);
+        for (name, path) in &dist.includes {
+            core.includes
+                .insert(PathBuf::from(name), FileData::Path(path.clone()));
         }
-        if self
-            .target_distribution
-            .python_config_vars()
-            .get("Py_REF_DEBUG")
-            == Some(&"1".to_string())
-        {
-            python_build_flags.0.insert(BuildFlag::Py_REF_DEBUG);
+
+        // libpython gets its own _PyImport_Inittab, so the distribution's
+        // object defining it is left out.
+        for path in dist.objs_core.values() {
+            if path != &dist.inittab_object {
+                core.object_files.push(FileData::Path(path.clone()));
+            }
         }
-        if self
-            .target_distribution
-            .python_config_vars()
-            .get("Py_TRACE_REFS")
-            == Some(&"1".to_string())
-        {
-            python_build_flags.0.insert(BuildFlag::Py_TRACE_REFS);
+
+        for library in &dist.links_core {
+            core.add_library_dependency(library);
         }
-        if self
-            .target_distribution
-            .python_config_vars()
-            .get("COUNT_ALLOCS")
-            == Some(&"1".to_string())
-        {
-            python_build_flags.0.insert(BuildFlag::COUNT_ALLOCS);
-        }
-
-        let mut context = EmbeddedPythonContext {
-            config,
-            link_settings,
-            pending_resources,
-            extra_files,
-            host_triple: self.host_triple.clone(),
-            target_triple: self.target_triple.clone(),
-            python_implementation,
-            python_version,
-            python_exe_host: self.host_python_exe.clone(),
-            python_build_flags,
-            licensing_filename: self.licenses_filename.clone(),
-            licensing: self.licensed_components()?,
+
+        let mut extensions = vec![];
+        for module in self.builtin_extension_modules() {
+            let mut context = LibPythonBuildContext::default();
+            context
+                .init_functions
+                .insert(module.name.clone(), module.init_fn.clone());
+
+            // Objects of core modules are part of the core objects already.
+            if !module.in_core {
+                context.object_files.extend(
+                    module
+                        .object_files
+                        .iter()
+                        .map(|path| FileData::Path(path.clone())),
+                );
+            }
+
+            for library in &module.link_libraries {
+                context.add_library_dependency(library);
+            }
+
+            extensions.push(context);
+        }
+
+        let contexts = std::iter::once(&core)
+            .chain(extensions.iter())
+            .collect::<Vec<_>>();
+        let context = LibPythonBuildContext::merge(&contexts);
+
+        libpython::link_libpython(
+            &context,
+            &self.host_triple,
+            &self.target_triple,
+            opt_level,
+            dest_path,
+        )
+        .context("linking libpython")
+    }
+
+    /// Configuration for PyO3 to build against the embedded libpython.
+    pub fn pyo3_build_config(&self, lib_dir: &Path) -> eyre::Result<PyO3BuildConfig> {
+        let dist = &self.target_distribution;
+
+        let implementation = if dist.python_implementation.starts_with("cpython") {
+            "CPython"
+        } else if dist.python_implementation.starts_with("pypy") {
+            "PyPy"
+        } else {
+            return Err(eyre::eyre!(
+                "unknown Python implementation: {}",
+                dist.python_implementation
+            ));
         };
 
-        context.synchronize_licensing()?;
+        let build_flags = ["Py_DEBUG", "Py_REF_DEBUG", "Py_TRACE_REFS", "COUNT_ALLOCS"]
+            .into_iter()
+            .filter(|flag| dist.python_config_vars().get(*flag).map(String::as_str) == Some("1"))
+            .map(String::from)
+            .collect();
+
+        Ok(PyO3BuildConfig {
+            implementation: implementation.to_string(),
+            version: dist.python_major_minor_version(),
+            shared: self.link_mode == LibpythonLinkMode::Dynamic,
+            lib_name: libpython::LIBPYTHON_NAME.to_string(),
+            lib_dir: lib_dir.to_path_buf(),
+            executable: dist.python_exe.clone(),
+            pointer_width: PyO3BuildConfig::pointer_width_for_triple(&self.target_triple),
+            build_flags,
+            suppress_build_script_link_lines: true,
+        })
+    }
+
+    /// Write the files the root build script consumes to link libpython.
+    ///
+    /// These are the cargo linking directives and the PyO3 build configuration,
+    /// to be pointed at by `PYO3_CONFIG_FILE`.
+    pub fn write_link_settings(&self, opt_level: &str, dest_path: &Path) -> eyre::Result<()> {
+        let info = self.resolve_python_link_settings(opt_level, dest_path)?;
+
+        libpython::write_cargo_metadata(
+            &info.linking_annotations,
+            &dest_path.join(libpython::CARGO_METADATA_FILENAME),
+        )?;
+
+        let lib_dir = info.libpython_path.parent().unwrap_or(dest_path);
+        self.pyo3_build_config(lib_dir)?
+            .write_file(&dest_path.join(libpython::PYO3_CONFIG_FILENAME))?;
 
-        Ok(context)
+        log::info!("using {}", info.libpython_path.display());
+
+        Ok(())
     }
 }
 
@@ -2553,6 +3193,11 @@ pub fn generate_python_embedding_artifacts(
     // flavor: &str,
     // python_version: Option<&str>,
     dest_path: &Path,
+    pipfile: Option<&pipfile::PipfileInstall>,
+    trim: Option<&StdlibTrim>,
+    link_mode: LibpythonLinkMode,
+    allowed_libraries: &[String],
+    dunder_file_reroute_prefix: Option<String>,
 ) -> eyre::Result<()> {
     // let flavor = DistributionFlavor::try_from(flavor)?;
         // .map_err(|e| eyre::eyre!("{}", e))?;
@@ -2582,9 +3227,13 @@ pub fn generate_python_embedding_artifacts(
     //     .host_distribution(Some(dist.python_major_minor_version().as_str()), None)
     //     .wrap_err("resolving host distribution")?;
 
-    let packaging_policy = dist
+    let mut packaging_policy = dist
         .create_packaging_policy()
         .context("creating packaging policy")?;
+    for name in allowed_libraries {
+        packaging_policy.register_allowed_system_library(name);
+    }
+    packaging_policy.set_dunder_file_reroute_prefix(dunder_file_reroute_prefix);
     dbg!(&packaging_policy);
 
     let mut interpreter_config = dist
@@ -2610,7 +3259,6 @@ pub fn generate_python_embedding_artifacts(
     // )?;
     //
     
-    let link_mode = LibpythonLinkMode::Static;
     let supports_in_memory_dynamically_linked_extension_loading =
         dist.supports_in_memory_shared_library_loading();
 
@@ -2651,7 +3299,7 @@ pub fn generate_python_embedding_artifacts(
                 allowed_locations,
                 allowed_extension_module_locations,
                 allow_new_builtin_extension_modules,
-                packaging_policy.allow_files(),
+                packaging_policy.allow_files,
             ),
             // resources_load_mode: PackedResourcesLoadMode::EmbeddedInBinary(
             //     "packed-resources".to_string(),
@@ -2663,11 +3311,102 @@ pub fn generate_python_embedding_artifacts(
             licenses_filename: Some("COPYING.txt".into()),
             windows_subsystem: "console".to_string(),
             tcl_files_path: None,
+            bytecode_cache_dir: dest_path.join("bytecode-cache"),
             // windows_runtime_dlls_mode: WindowsRuntimeDllsMode::WhenPresent,
         });
        
         builder.add_distribution_core_state()?;
 
+    if let Some(pipfile) = pipfile {
+        let wheels = pipfile::resolve_pipfile_wheels(
+            &builder.target_distribution.python_exe,
+            pipfile,
+            &dest_path.join("pip"),
+        )
+        .context("resolving Pipfile packages")?;
+
+        for path in wheels {
+            wheel::WheelArchive::from_path(&path)?
+                .add_to_collector(
+                    &mut builder.resources_collector,
+                    &builder.packaging_policy,
+                    &builder.target_distribution.extension_module_suffixes,
+                )
+                .wrap_err_with(|| format!("adding {}", path.display()))?;
+        }
+    }
+
+    // The standard library is added first so that stdlib modules using
+    // __file__ are rerouted too.
+    if builder.link_mode == LibpythonLinkMode::Dynamic {
+        if trim.is_some() {
+            log::warn!("the standard library isn't trimmed when linking libpython dynamically");
+        }
+
+        builder
+            .write_filesystem_stdlib(&dest_path)
+            .context("writing standard library")?;
+    } else if let Some(report) = builder
+        .add_stdlib_modules(trim)
+        .context("adding standard library modules")?
+    {
+        log::info!("{}", report);
+    }
+
+    builder
+        .reroute_dunder_file_packages()
+        .context("rerouting packages using __file__")?;
+
+    let analyses = extension_analysis::analyze_extension_modules(
+        &builder.resources_collector,
+        &builder.target_distribution,
+        &builder.packaging_policy,
+    )
+    .context("analyzing extension modules")?;
+    extension_analysis::check_extension_modules(&analyses)?;
+
+    let compiled_resources = {
+        let mut compiler = builder
+            .target_distribution
+            .create_bytecode_compiler(&builder.bytecode_cache_dir)
+            .context("creating bytecode compiler")?;
+
+        builder
+            .resources_collector
+            .compile_resources(&mut compiler)
+            .context("compiling bytecode")?
+    };
+
+    let packed_resources_path = dest_path.join("packed-resources");
+    let mut buffer = vec![];
+    compiled_resources
+        .write_packed_resources(&mut buffer)
+        .context("serializing packed resources")?;
+    std::fs::write(&packed_resources_path, buffer)
+        .wrap_err_with(|| format!("writing {}", packed_resources_path.display()))?;
+    builder
+        .config
+        .packed_resources
+        .push(PyembedPackedResourcesSource::MemoryIncludeBytes(
+            packed_resources_path,
+        ));
+
+    builder
+        .synchronize_licensing(&dest_path)
+        .context("writing licensing information")?;
+
+    builder
+        .resources_collector
+        .write_relative_path_resources(&dest_path)
+        .context("writing filesystem-relative resources")?;
+
+    let python_config_path = dest_path.join("default_python_config.rs");
+    std::fs::write(
+        &python_config_path,
+        builder.config.to_oxidized_python_interpreter_config_rs(),
+    )
+    .wrap_err_with(|| format!("writing {}", python_config_path.display()))?;
+
         // Ok(builder)
 
     // builder.set_tcl_files_path(Some("tcl".to_string()));
@@ -2676,9 +3415,11 @@ pub fn generate_python_embedding_artifacts(
     //     .add_distribution_resources(None)
     //     .context("adding distribution resources")?;
 
-    let embedded_context = builder
-        .to_embedded_python_context("1")
-        .context("resolving embedded context")?;
+    let opt_level = "1";
+
+    builder
+        .write_link_settings(opt_level, &dest_path)
+        .context("writing libpython link settings")?;
 
     // embedded_context
     //     .write_files(&dest_path)
@@ -0,0 +1,374 @@
+//! Serialization of Python resources to packed resources data.
+//!
+//! Packed resources are read at run-time by `pyembed`. The format is defined
+//! by [python_packed_resources::format].
+
+use super::{FileData, PrePackagedResource, PythonModuleBytecodeProvider};
+use color_eyre::eyre::{self, WrapErr};
+use python_packed_resources::format::{
+    ABSENT_OFFSET, FIELD_COUNT, FLAG_IS_NAMESPACE_PACKAGE, FLAG_IS_PACKAGE, FORMAT_VERSION,
+    HEADER_SIZE, INDEX_ENTRY_SIZE, MAGIC,
+};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// An `(offset, length)` pair referencing data in the blob.
+type Span = (u32, u32);
+
+const ABSENT: Span = (ABSENT_OFFSET, 0);
+
+/// Accumulates the data section, handing out spans referencing it.
+struct DataWriter {
+    /// Offset of the data section in the blob.
+    start: usize,
+    data: Vec<u8>,
+}
+
+impl DataWriter {
+    fn push(&mut self, value: &[u8]) -> eyre::Result<Span> {
+        let offset = self.start + self.data.len();
+
+        // The absent marker is reserved, so the largest usable offset is one less.
+        if offset + value.len() >= ABSENT_OFFSET as usize {
+            return Err(eyre::eyre!("packed resources data exceeds 4 GiB"));
+        }
+
+        self.data.extend_from_slice(value);
+
+        Ok((offset as u32, value.len() as u32))
+    }
+
+    fn push_file(&mut self, value: &Option<FileData>) -> eyre::Result<Span> {
+        match value {
+            Some(value) => self.push(&value.resolve_content()?),
+            None => Ok(ABSENT),
+        }
+    }
+
+    fn push_bytecode(
+        &mut self,
+        name: &str,
+        value: &Option<PythonModuleBytecodeProvider>,
+    ) -> eyre::Result<Span> {
+        match value {
+            Some(PythonModuleBytecodeProvider::Provided(data)) => {
+                self.push(&data.resolve_content()?)
+            }
+            Some(PythonModuleBytecodeProvider::FromSource(_)) => Err(eyre::eyre!(
+                "bytecode for {} must be compiled before serializing",
+                name
+            )),
+            None => Ok(ABSENT),
+        }
+    }
+
+    fn push_map(&mut self, value: &Option<BTreeMap<String, FileData>>) -> eyre::Result<Span> {
+        let map = match value {
+            Some(map) => map,
+            None => return Ok(ABSENT),
+        };
+
+        let mut encoded = (map.len() as u32).to_le_bytes().to_vec();
+        for (key, value) in map {
+            let key = self.push(key.as_bytes())?;
+            let value = self.push(&value.resolve_content()?)?;
+
+            for span in [key, value] {
+                write_span(&mut encoded, span);
+            }
+        }
+
+        self.push(&encoded)
+    }
+}
+
+fn write_span(dest: &mut Vec<u8>, (offset, length): Span) {
+    dest.extend_from_slice(&offset.to_le_bytes());
+    dest.extend_from_slice(&length.to_le_bytes());
+}
+
+/// Serialize resources to packed resources data.
+///
+/// Resources are written in name order. Resource names must be unique.
+pub fn write_packed_resources<'a, W: Write>(
+    resources: impl Iterator<Item = &'a PrePackagedResource>,
+    dest: &mut W,
+) -> eyre::Result<()> {
+    let mut resources = resources.collect::<Vec<_>>();
+    resources.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if let Some(pair) = resources
+        .windows(2)
+        .find(|pair| pair[0].name == pair[1].name)
+    {
+        return Err(eyre::eyre!("duplicate resource {}", pair[0].name));
+    }
+
+    let mut index = Vec::with_capacity(resources.len() * INDEX_ENTRY_SIZE);
+    let mut data = DataWriter {
+        start: HEADER_SIZE + resources.len() * INDEX_ENTRY_SIZE,
+        data: vec![],
+    };
+
+    for resource in resources {
+        let name = &resource.name;
+
+        let mut flags = 0;
+        if resource.is_package {
+            flags |= FLAG_IS_PACKAGE;
+        }
+        if resource.is_namespace_package {
+            flags |= FLAG_IS_NAMESPACE_PACKAGE;
+        }
+
+        let name_span = data.push(name.as_bytes())?;
+        let fields: [Span; FIELD_COUNT] = [
+            data.push_file(&resource.in_memory_source)?,
+            data.push_bytecode(name, &resource.in_memory_bytecode)?,
+            data.push_bytecode(name, &resource.in_memory_bytecode_opt1)?,
+            data.push_bytecode(name, &resource.in_memory_bytecode_opt2)?,
+            data.push_file(&resource.in_memory_extension_module_shared_library)?,
+            data.push_map(&resource.in_memory_resources)?,
+            data.push_map(&resource.in_memory_distribution_resources)?,
+            data.push_file(&resource.in_memory_shared_library)?,
+        ];
+
+        write_span(&mut index, name_span);
+        index.extend_from_slice(&flags.to_le_bytes());
+        for span in fields {
+            write_span(&mut index, span);
+        }
+    }
+
+    let count = (index.len() / INDEX_ENTRY_SIZE) as u32;
+
+    dest.write_all(MAGIC)
+        .and_then(|_| dest.write_all(&FORMAT_VERSION.to_le_bytes()))
+        .and_then(|_| dest.write_all(&count.to_le_bytes()))
+        .and_then(|_| dest.write_all(&index))
+        .and_then(|_| dest.write_all(&data.data))
+        .wrap_err("writing packed resources")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use python_packed_resources::{PackedResources, Resource};
+    use std::borrow::Cow;
+
+    fn write(resources: &[PrePackagedResource]) -> Vec<u8> {
+        let mut buffer = vec![];
+        write_packed_resources(resources.iter(), &mut buffer).unwrap();
+        buffer
+    }
+
+    fn memory(data: &[u8]) -> Option<FileData> {
+        Some(FileData::Memory(data.to_vec()))
+    }
+
+    fn bytecode(data: &[u8]) -> Option<PythonModuleBytecodeProvider> {
+        Some(PythonModuleBytecodeProvider::Provided(FileData::Memory(
+            data.to_vec(),
+        )))
+    }
+
+    fn files(entries: &[(&str, &[u8])]) -> Option<BTreeMap<String, FileData>> {
+        Some(
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), FileData::Memory(v.to_vec())))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn empty() {
+        let data = write(&[]);
+        assert_eq!(data.len(), HEADER_SIZE);
+
+        let resources = PackedResources::parse(&data).unwrap();
+        assert!(resources.is_empty());
+        assert_eq!(resources.get("foo").unwrap(), None);
+    }
+
+    #[test]
+    fn minimal_module() {
+        let data = write(&[PrePackagedResource {
+            name: "foo".to_string(),
+            ..Default::default()
+        }]);
+
+        let resources = PackedResources::parse(&data).unwrap();
+        assert_eq!(resources.len(), 1);
+
+        let resource = resources.resource_at(0).unwrap();
+        assert_eq!(
+            resource,
+            Resource {
+                name: Cow::Borrowed("foo"),
+                ..Default::default()
+            }
+        );
+        assert!(!resource.is_module());
+    }
+
+    #[test]
+    fn all_fields() {
+        let data = write(&[PrePackagedResource {
+            name: "pkg".to_string(),
+            is_package: true,
+            is_namespace_package: true,
+            in_memory_source: memory(b"source"),
+            in_memory_bytecode: bytecode(b"bc0"),
+            in_memory_bytecode_opt1: bytecode(b"bc1"),
+            in_memory_bytecode_opt2: bytecode(b"bc2"),
+            in_memory_extension_module_shared_library: memory(b"extension"),
+            in_memory_resources: files(&[("data.txt", b"data"), ("sub/empty", b"")]),
+            in_memory_distribution_resources: files(&[("METADATA", b"Name: pkg\n")]),
+            in_memory_shared_library: memory(b"library"),
+            ..Default::default()
+        }]);
+
+        let resources = PackedResources::parse(&data).unwrap();
+        let resource = resources.get("pkg").unwrap().unwrap();
+
+        assert!(resource.is_package);
+        assert!(resource.is_namespace_package);
+        assert_eq!(resource.in_memory_source.as_deref(), Some(&b"source"[..]));
+        assert_eq!(resource.bytecode(0), Some(&b"bc0"[..]));
+        assert_eq!(resource.bytecode(1), Some(&b"bc1"[..]));
+        assert_eq!(resource.bytecode(2), Some(&b"bc2"[..]));
+        assert_eq!(
+            resource
+                .in_memory_extension_module_shared_library
+                .as_deref(),
+            Some(&b"extension"[..])
+        );
+        assert_eq!(
+            resource.in_memory_shared_library.as_deref(),
+            Some(&b"library"[..])
+        );
+
+        let package_resources = resource.in_memory_package_resources.unwrap();
+        assert_eq!(package_resources.len(), 2);
+        assert_eq!(package_resources["data.txt"].as_ref(), b"data");
+        assert_eq!(package_resources["sub/empty"].as_ref(), b"");
+
+        let distribution_resources = resource.in_memory_distribution_resources.unwrap();
+        assert_eq!(distribution_resources.len(), 1);
+        assert_eq!(distribution_resources["METADATA"].as_ref(), b"Name: pkg\n");
+    }
+
+    #[test]
+    fn empty_values_are_present() {
+        let data = write(&[PrePackagedResource {
+            name: "foo".to_string(),
+            in_memory_source: memory(b""),
+            in_memory_resources: files(&[]),
+            ..Default::default()
+        }]);
+
+        let resource = PackedResources::parse(&data)
+            .unwrap()
+            .resource_at(0)
+            .unwrap();
+
+        assert_eq!(resource.in_memory_source.as_deref(), Some(&b""[..]));
+        assert!(resource.in_memory_bytecode.is_none());
+        assert!(resource
+            .in_memory_package_resources
+            .as_ref()
+            .unwrap()
+            .is_empty());
+        assert!(resource.is_module());
+    }
+
+    #[test]
+    fn sorted_lookup() {
+        let names = ["zlib_helper", "a", "a.b", "a.b.c", "b", "aa", "m"];
+
+        let data = write(
+            &names
+                .iter()
+                .map(|name| PrePackagedResource {
+                    name: name.to_string(),
+                    in_memory_source: memory(name.as_bytes()),
+                    ..Default::default()
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let resources = PackedResources::parse(&data).unwrap();
+
+        let mut sorted = names.to_vec();
+        sorted.sort();
+        assert_eq!(
+            resources
+                .iter()
+                .map(|r| r.unwrap().name.into_owned())
+                .collect::<Vec<_>>(),
+            sorted
+        );
+
+        for name in names {
+            let resource = resources.get(name).unwrap().unwrap();
+            assert_eq!(resource.in_memory_source.as_deref(), Some(name.as_bytes()));
+        }
+
+        assert_eq!(resources.get("a.b.d").unwrap(), None);
+        assert_eq!(resources.get("").unwrap(), None);
+        assert_eq!(resources.get("zzz").unwrap(), None);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let resource = PrePackagedResource {
+            name: "foo".to_string(),
+            ..Default::default()
+        };
+
+        let mut buffer = vec![];
+        assert!(write_packed_resources([&resource, &resource].into_iter(), &mut buffer).is_err());
+    }
+
+    #[test]
+    fn uncompiled_bytecode() {
+        let resource = PrePackagedResource {
+            name: "foo".to_string(),
+            in_memory_bytecode: Some(PythonModuleBytecodeProvider::FromSource(FileData::Memory(
+                b"pass".to_vec(),
+            ))),
+            ..Default::default()
+        };
+
+        let mut buffer = vec![];
+        assert!(write_packed_resources([&resource].into_iter(), &mut buffer).is_err());
+    }
+
+    #[test]
+    fn reader_rejects_corruption() {
+        let data = write(&[PrePackagedResource {
+            name: "foo".to_string(),
+            in_memory_source: memory(b"source"),
+            ..Default::default()
+        }]);
+
+        // Bad magic.
+        let mut bad = data.clone();
+        bad[0] = b'X';
+        assert!(PackedResources::parse(&bad).is_err());
+
+        // Unknown version.
+        let mut bad = data.clone();
+        bad[8] = 2;
+        assert!(PackedResources::parse(&bad).is_err());
+
+        // Index larger than the data.
+        assert!(PackedResources::parse(&data[..HEADER_SIZE + 4]).is_err());
+
+        // Spans beyond the end of the data.
+        let truncated = &data[..data.len() - 1];
+        let resources = PackedResources::parse(truncated).unwrap();
+        assert!(resources.resource_at(0).is_err());
+    }
+}
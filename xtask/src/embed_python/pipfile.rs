@@ -0,0 +1,236 @@
+//! Installation of `Pipfile` packages into the embedded bundle.
+//!
+//! Packages listed in the `[packages]` section of a `Pipfile` are resolved
+//! with the distribution's own `pip`. Resolution is offline: wheels are only
+//! taken from a local wheelhouse directory, so builds are reproducible and
+//! don't need network access. The resolved wheels are then read natively and
+//! added to a [super::PythonResourceCollector].
+
+use color_eyre::eyre::{self, WrapErr};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where to find the packages to install.
+#[derive(Clone, Debug)]
+pub struct PipfileInstall {
+    /// Path to the `Pipfile`.
+    pub pipfile: PathBuf,
+
+    /// Directory holding the wheels to install.
+    pub wheelhouse: PathBuf,
+}
+
+/// Parse the `[packages]` section of a `Pipfile` into pip requirement specifiers.
+pub fn parse_pipfile_requirements(content: &str) -> eyre::Result<Vec<String>> {
+    let doc: toml::Table = content.parse().wrap_err("parsing Pipfile")?;
+
+    let packages = match doc.get("packages") {
+        Some(toml::Value::Table(packages)) => packages,
+        Some(_) => return Err(eyre::eyre!("Pipfile [packages] is not a table")),
+        None => return Ok(vec![]),
+    };
+
+    packages
+        .iter()
+        .map(|(name, spec)| {
+            let (version, extras, markers) = match spec {
+                toml::Value::String(version) => (version.as_str(), vec![], None),
+                toml::Value::Table(table) => {
+                    if table.contains_key("git") || table.contains_key("path") {
+                        return Err(eyre::eyre!(
+                            "{}: only versioned packages can be installed from a wheelhouse",
+                            name
+                        ));
+                    }
+
+                    let version = table.get("version").and_then(|v| v.as_str()).unwrap_or("*");
+                    let extras = table
+                        .get("extras")
+                        .and_then(|v| v.as_array())
+                        .map(|extras| extras.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
+                        .unwrap_or_default();
+                    let markers = table.get("markers").and_then(|v| v.as_str());
+
+                    (version, extras, markers)
+                }
+                _ => return Err(eyre::eyre!("{}: invalid Pipfile package entry", name)),
+            };
+
+            let mut requirement = name.clone();
+            if !extras.is_empty() {
+                requirement.push_str(&format!("[{}]", extras.join(",")));
+            }
+            if version != "*" {
+                requirement.push_str(version);
+            }
+            if let Some(markers) = markers {
+                requirement.push_str(&format!("; {}", markers));
+            }
+
+            Ok(requirement)
+        })
+        .collect()
+}
+
+/// Resolve the wheels to install for the packages of a `Pipfile`.
+///
+/// Resolution is delegated to the distribution's own `pip`, restricted to the
+/// wheelhouse. Nothing is installed: pip only reports the wheels it would
+/// install, which are then read with [super::wheel::WheelArchive].
+pub fn resolve_pipfile_wheels(
+    python_exe: &Path,
+    install: &PipfileInstall,
+    temp_dir: &Path,
+) -> eyre::Result<Vec<PathBuf>> {
+    let content = std::fs::read_to_string(&install.pipfile)
+        .wrap_err_with(|| format!("reading {}", install.pipfile.display()))?;
+    let requirements = parse_pipfile_requirements(&content)
+        .wrap_err_with(|| format!("parsing {}", install.pipfile.display()))?;
+
+    if requirements.is_empty() {
+        return Ok(vec![]);
+    }
+
+    log::info!(
+        "resolving {} from {}",
+        requirements.join(", "),
+        install.wheelhouse.display()
+    );
+
+    std::fs::create_dir_all(temp_dir)
+        .wrap_err_with(|| format!("creating directory {}", temp_dir.display()))?;
+    let report_path = temp_dir.join("pip-report.json");
+
+    let status = Command::new(python_exe)
+        .args(["-m", "pip", "install"])
+        .args(["--dry-run", "--ignore-installed", "--quiet"])
+        .args(["--no-index", "--only-binary", ":all:"])
+        .arg("--disable-pip-version-check")
+        .arg("--find-links")
+        .arg(&install.wheelhouse)
+        .arg("--report")
+        .arg(&report_path)
+        .args(&requirements)
+        .status()
+        .wrap_err_with(|| format!("running {} -m pip", python_exe.display()))?;
+
+    if !status.success() {
+        return Err(eyre::eyre!(
+            "pip failed to resolve Pipfile packages: {}",
+            status
+        ));
+    }
+
+    let report = std::fs::read(&report_path)
+        .wrap_err_with(|| format!("reading {}", report_path.display()))?;
+
+    parse_pip_report(&report)
+}
+
+/// Extract the paths of the wheels to install from a `pip install --report`.
+fn parse_pip_report(report: &[u8]) -> eyre::Result<Vec<PathBuf>> {
+    let report: serde_json::Value =
+        serde_json::from_slice(report).wrap_err("parsing pip report")?;
+
+    let mut wheels = report
+        .get("install")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| eyre::eyre!("pip report lacks install list"))?
+        .iter()
+        .map(|item| {
+            let url = item
+                .pointer("/download_info/url")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| eyre::eyre!("pip report entry lacks download URL"))?;
+
+            url::Url::parse(url)
+                .ok()
+                .filter(|url| url.scheme() == "file")
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or_else(|| eyre::eyre!("{} is not in the wheelhouse", url))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    wheels.sort();
+
+    Ok(wheels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requirements() {
+        let requirements = parse_pipfile_requirements(
+            r#"
+[[source]]
+url = "https://pypi.org/simple"
+verify_ssl = true
+name = "pypi"
+
+[packages]
+PyQt5 = "*"
+numpy = "<2"
+pyrocko = "*"
+requests = { version = ">=2", extras = ["socks"], markers = "python_version >= '3.8'" }
+
+[dev-packages]
+pytest = "*"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            requirements,
+            vec![
+                "PyQt5",
+                "numpy<2",
+                "pyrocko",
+                "requests[socks]>=2; python_version >= '3.8'",
+            ]
+        );
+    }
+
+    #[test]
+    fn requirements_reject_non_index() {
+        assert!(parse_pipfile_requirements("[packages]\nfoo = { path = \"./foo\" }\n").is_err());
+        assert!(parse_pipfile_requirements("[packages]\nfoo = 1\n").is_err());
+        assert!(parse_pipfile_requirements("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn pip_report() {
+        let report = br#"{
+            "version": "1",
+            "install": [
+                {
+                    "download_info": {"url": "file:///wheels/numpy-1.26.4-cp312-cp312-manylinux_2_17_x86_64.whl"},
+                    "metadata": {"name": "numpy", "version": "1.26.4"}
+                },
+                {
+                    "download_info": {"url": "file:///wheels/my%20dir/six-1.16.0-py2.py3-none-any.whl"},
+                    "metadata": {"name": "six", "version": "1.16.0"}
+                },
+                {
+                    "download_info": {"url": "file:///wheels/caf%C3%A9%23%25/pyrocko-2024.1.10-cp312-cp312-linux_x86_64.whl"},
+                    "metadata": {"name": "pyrocko", "version": "2024.1.10"}
+                }
+            ]
+        }"#;
+
+        assert_eq!(
+            parse_pip_report(report).unwrap(),
+            vec![
+                PathBuf::from("/wheels/café#%/pyrocko-2024.1.10-cp312-cp312-linux_x86_64.whl"),
+                PathBuf::from("/wheels/my dir/six-1.16.0-py2.py3-none-any.whl"),
+                PathBuf::from("/wheels/numpy-1.26.4-cp312-cp312-manylinux_2_17_x86_64.whl"),
+            ]
+        );
+
+        assert!(parse_pip_report(
+            br#"{"install": [{"download_info": {"url": "https://example.com/x.whl"}}]}"#
+        )
+        .is_err());
+    }
+}
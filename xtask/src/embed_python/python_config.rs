@@ -0,0 +1,186 @@
+//! Rendering of the interpreter configuration as Rust code.
+//!
+//! The executable `include!`s the rendered `default_python_config()`
+//! function, so the embedded interpreter starts with the configuration the
+//! artifacts were built for.
+//!
+//! The code only names types exported by `pyembed`. Enum values of the
+//! interpreter configuration, e.g. the profile, are converted from their
+//! string forms, which `pyembed`'s types accept through `TryFrom<&str>`.
+
+use super::{PyembedPackedResourcesSource, PyembedPythonInterpreterConfig};
+use std::fmt::Write;
+use std::path::Path;
+
+fn path_rs(path: &Path) -> String {
+    format!("std::path::PathBuf::from({:?})", path.display().to_string())
+}
+
+fn option_rs<T>(value: &Option<T>, f: impl Fn(&T) -> String) -> String {
+    match value {
+        Some(value) => format!("Some({})", f(value)),
+        None => "None".to_string(),
+    }
+}
+
+impl PyembedPythonInterpreterConfig {
+    /// Render Rust code defining a `default_python_config()` function.
+    ///
+    /// The function returns a `pyembed::OxidizedPythonInterpreterConfig`
+    /// equivalent to this instance.
+    pub fn to_oxidized_python_interpreter_config_rs(&self) -> String {
+        let module_search_paths = option_rs(&self.config.module_search_paths, |paths| {
+            let paths: Vec<String> = paths.iter().map(|p| path_rs(p)).collect();
+            format!("vec![{}]", paths.join(", "))
+        });
+
+        let packed_resources: Vec<String> = self
+            .packed_resources
+            .iter()
+            .map(|source| match source {
+                PyembedPackedResourcesSource::MemoryIncludeBytes(path) => format!(
+                    "pyembed::PackedResourcesSource::Memory(include_bytes!({:?}))",
+                    path.display().to_string()
+                ),
+                PyembedPackedResourcesSource::MemoryMappedPath(path) => format!(
+                    "pyembed::PackedResourcesSource::MemoryMappedPath({})",
+                    path_rs(path)
+                ),
+            })
+            .collect();
+
+        let fields = [
+            (
+                "interpreter_config.profile",
+                format!("{:?}.try_into().unwrap()", self.config.profile.to_string()),
+            ),
+            (
+                "interpreter_config.configure_locale",
+                format!("{:?}", self.config.configure_locale),
+            ),
+            (
+                "interpreter_config.module_search_paths",
+                module_search_paths,
+            ),
+            (
+                "allocator_backend",
+                format!(
+                    "{:?}.try_into().unwrap()",
+                    self.allocator_backend.to_string()
+                ),
+            ),
+            ("allocator_raw", self.allocator_raw.to_string()),
+            ("allocator_mem", self.allocator_mem.to_string()),
+            ("allocator_obj", self.allocator_obj.to_string()),
+            (
+                "allocator_pymalloc_arena",
+                self.allocator_pymalloc_arena.to_string(),
+            ),
+            ("allocator_debug", self.allocator_debug.to_string()),
+            (
+                "set_missing_path_configuration",
+                self.set_missing_path_configuration.to_string(),
+            ),
+            ("oxidized_importer", self.oxidized_importer.to_string()),
+            ("filesystem_importer", self.filesystem_importer.to_string()),
+            (
+                "packed_resources",
+                format!("vec![{}]", packed_resources.join(", ")),
+            ),
+            ("argvb", self.argvb.to_string()),
+            (
+                "multiprocessing_auto_dispatch",
+                self.multiprocessing_auto_dispatch.to_string(),
+            ),
+            ("sys_frozen", self.sys_frozen.to_string()),
+            ("sys_meipass", self.sys_meipass.to_string()),
+            ("stdlib_path", option_rs(&self.stdlib_path, |p| path_rs(p))),
+            ("tcl_library", option_rs(&self.tcl_library, |p| path_rs(p))),
+            (
+                "write_modules_directory_env",
+                option_rs(&self.write_modules_directory_env, |v| {
+                    format!("{:?}.to_string()", v)
+                }),
+            ),
+        ];
+
+        let mut code = String::from(
+            "/// Obtain the configuration of the embedded Python interpreter.
+///
+/// Generated by `cargo xtask prepare-embed-python`.
+#[allow(clippy::field_reassign_with_default)]
+fn default_python_config<'a>() -> pyembed::OxidizedPythonInterpreterConfig<'a> {
+    let mut config = pyembed::OxidizedPythonInterpreterConfig::default();
+",
+        );
+        for (field, value) in fields {
+            writeln!(code, "    config.{} = {};", field, value).unwrap();
+        }
+        code.push_str("    config\n}\n");
+
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn render() {
+        let mut config = PyembedPythonInterpreterConfig::default();
+        config.config.module_search_paths = Some(vec![PathBuf::from("$ORIGIN/lib")]);
+        config.packed_resources = vec![
+            PyembedPackedResourcesSource::MemoryIncludeBytes(PathBuf::from(
+                "/embed-dest/packed-resources",
+            )),
+            PyembedPackedResourcesSource::MemoryMappedPath(PathBuf::from("$ORIGIN/resources")),
+        ];
+        config.stdlib_path = Some(PathBuf::from("$ORIGIN/stdlib"));
+        config.write_modules_directory_env = Some("SNUFFLER_MODULES".to_string());
+
+        assert_eq!(
+            config.to_oxidized_python_interpreter_config_rs(),
+            r#"/// Obtain the configuration of the embedded Python interpreter.
+///
+/// Generated by `cargo xtask prepare-embed-python`.
+#[allow(clippy::field_reassign_with_default)]
+fn default_python_config<'a>() -> pyembed::OxidizedPythonInterpreterConfig<'a> {
+    let mut config = pyembed::OxidizedPythonInterpreterConfig::default();
+    config.interpreter_config.profile = "isolated".try_into().unwrap();
+    config.interpreter_config.configure_locale = Some(true);
+    config.interpreter_config.module_search_paths = Some(vec![std::path::PathBuf::from("$ORIGIN/lib")]);
+    config.allocator_backend = "default".try_into().unwrap();
+    config.allocator_raw = true;
+    config.allocator_mem = false;
+    config.allocator_obj = false;
+    config.allocator_pymalloc_arena = false;
+    config.allocator_debug = false;
+    config.set_missing_path_configuration = true;
+    config.oxidized_importer = true;
+    config.filesystem_importer = false;
+    config.packed_resources = vec![pyembed::PackedResourcesSource::Memory(include_bytes!("/embed-dest/packed-resources")), pyembed::PackedResourcesSource::MemoryMappedPath(std::path::PathBuf::from("$ORIGIN/resources"))];
+    config.argvb = false;
+    config.multiprocessing_auto_dispatch = true;
+    config.sys_frozen = true;
+    config.sys_meipass = false;
+    config.stdlib_path = Some(std::path::PathBuf::from("$ORIGIN/stdlib"));
+    config.tcl_library = None;
+    config.write_modules_directory_env = Some("SNUFFLER_MODULES".to_string());
+    config
+}
+"#
+        );
+    }
+
+    #[test]
+    fn render_escapes_paths() {
+        let mut config = PyembedPythonInterpreterConfig::default();
+        config.stdlib_path = Some(PathBuf::from("C:\\dev\\\"quoted\"\\stdlib"));
+
+        assert!(config.to_oxidized_python_interpreter_config_rs().contains(
+            r#"config.stdlib_path = Some(std::path::PathBuf::from("C:\\dev\\\"quoted\"\\stdlib"));"#
+        ));
+    }
+}
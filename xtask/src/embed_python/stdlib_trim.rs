@@ -0,0 +1,682 @@
+//! Trimming of the standard library to the modules an application imports.
+//!
+//! Import statements are collected statically, starting from a set of entry
+//! modules, to compute the closure of reachable standard library modules.
+//! Imports that can't be seen statically, e.g. through `importlib` with
+//! computed names, are covered by an allow-list.
+
+use color_eyre::eyre::{self, WrapErr};
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Modules always kept, as the interpreter imports them while starting up or
+/// resolves them dynamically.
+///
+/// A `.*` suffix includes all submodules of a package.
+pub static ALWAYS_INCLUDED_MODULES: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    vec![
+        "__future__",
+        "_collections_abc",
+        "_sitebuiltins",
+        "abc",
+        "codecs",
+        // Codecs are looked up by name at run-time.
+        "encodings.*",
+        "genericpath",
+        "importlib.*",
+        "io",
+        // pyembed's tests import it from the bundled standard library.
+        "json.*",
+        "linecache",
+        "logging.*",
+        // Imported by pyembed to dispatch multiprocessing workers and helpers.
+        "multiprocessing.resource_tracker",
+        "multiprocessing.spawn",
+        "ntpath",
+        "os",
+        "pathlib",
+        "posixpath",
+        "site",
+        "stat",
+        // Imported by pyembed to forward interrupts to Python jobs.
+        "threading",
+        "traceback",
+        // Imported by pyembed to name the loaded modules file.
+        "uuid",
+        "warnings",
+        "zipimport",
+    ]
+});
+
+/// Options for trimming the standard library.
+#[derive(Clone, Debug, Default)]
+pub struct StdlibTrim {
+    /// Modules whose imports are followed.
+    ///
+    /// Modules of collected packages are found by name. Prefix matching
+    /// applies, so `pyrocko` includes all of `pyrocko`'s modules.
+    pub entry_modules: Vec<String>,
+
+    /// Directories with Python sources, e.g. plugins, whose imports are followed.
+    pub source_dirs: Vec<PathBuf>,
+
+    /// Additional modules to keep, e.g. because they are imported dynamically.
+    ///
+    /// A `.*` suffix includes all submodules of a package.
+    pub allowed_modules: Vec<String>,
+}
+
+/// A module of the standard library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StdlibModule {
+    /// Path of the source file.
+    pub path: PathBuf,
+
+    /// Whether the module is a package.
+    pub is_package: bool,
+
+    /// Size of the source file.
+    pub size: u64,
+}
+
+/// Find the Python source modules of a standard library directory.
+///
+/// `site-packages`, bytecode caches and packages in `excluded_packages`, e.g.
+/// tests, are skipped.
+pub fn find_stdlib_modules(
+    stdlib_path: &Path,
+    excluded_packages: &[String],
+) -> eyre::Result<BTreeMap<String, StdlibModule>> {
+    let mut modules = BTreeMap::new();
+
+    for entry in super::walk_tree_files(stdlib_path) {
+        let path = entry.path();
+        let relative = path
+            .strip_prefix(stdlib_path)
+            .wrap_err_with(|| format!("{} is outside the standard library", path.display()))?;
+
+        if path.extension().and_then(|s| s.to_str()) != Some("py") {
+            continue;
+        }
+
+        let components = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+
+        if components
+            .iter()
+            .any(|c| c == "site-packages" || c == "__pycache__" || c.contains('-'))
+        {
+            continue;
+        }
+
+        let mut parts = components;
+        let file_name = parts.pop().unwrap_or_default();
+        let is_package = file_name == "__init__.py";
+        if !is_package {
+            parts.push(file_name.trim_end_matches(".py").to_string());
+        }
+
+        let name = parts.join(".");
+        if name.is_empty()
+            || excluded_packages
+                .iter()
+                .any(|p| name == *p || name.starts_with(&format!("{}.", p)))
+        {
+            continue;
+        }
+
+        modules.insert(
+            name,
+            StdlibModule {
+                path: path.to_path_buf(),
+                is_package,
+                size: entry.metadata().map(|m| m.len()).unwrap_or_default(),
+            },
+        );
+    }
+
+    Ok(modules)
+}
+
+/// Replace comments and the content of string literals with spaces.
+///
+/// Returns the sanitized source and the string literals, in order of
+/// appearance.
+fn sanitize_source(source: &str) -> (String, Vec<String>) {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut res = String::with_capacity(source.len());
+    let mut literals = vec![];
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '\'' || c == '"' {
+            let triple = chars.get(i..i + 3) == Some(&[c; 3][..]);
+            let delimiter_len = if triple { 3 } else { 1 };
+            let start = i + delimiter_len;
+            let mut end = start;
+            let mut closed = false;
+
+            while end < chars.len() {
+                match chars[end] {
+                    '\\' => end += 2,
+                    // Unterminated single-quoted strings end at the line.
+                    '\n' if !triple => break,
+                    q if q == c && (!triple || chars.get(end..end + 3) == Some(&[c; 3][..])) => {
+                        closed = true;
+                        break;
+                    }
+                    _ => end += 1,
+                }
+            }
+
+            let end = end.min(chars.len());
+            let literal = chars[start..end].iter().collect::<String>();
+
+            // Keep newlines so statements in triple-quoted strings don't
+            // merge with code.
+            res.push('"');
+            res.extend(literal.chars().filter(|c| *c == '\n'));
+            res.push('"');
+            literals.push(literal);
+
+            i = if closed { end + delimiter_len } else { end };
+        } else {
+            res.push(c);
+            i += 1;
+        }
+    }
+
+    (res, literals)
+}
+
+/// Split sanitized source into logical lines and statements.
+fn logical_statements(source: &str) -> Vec<String> {
+    let mut statements = vec![];
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '[' | '{' => {
+                depth += 1;
+                current.push(c);
+            }
+            ')' | ']' | '}' => {
+                depth = depth.saturating_sub(1);
+                current.push(c);
+            }
+            '\\' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                current.push(' ');
+            }
+            '\n' if depth > 0 => current.push(' '),
+            '\n' | ';' => statements.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    statements.push(current);
+
+    statements
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+static RE_IMPORT: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"^import\s+(.+)$").unwrap());
+static RE_FROM_IMPORT: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r"^from\s+(\.*)\s*([\w.]*)\s+import\s+(.+)$").unwrap());
+static RE_DYNAMIC_IMPORT: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r#"\b(?:import_module|__import__)\(\s*""#).unwrap());
+
+/// Find the modules a Python module may import.
+///
+/// `name` and `is_package` of the importing module resolve relative imports.
+/// For `from a import b`, both `a` and `a.b` are returned, as `b` may be a
+/// submodule. Calls of `importlib.import_module()` and `__import__()` with a
+/// string literal are recognized as well.
+///
+/// The encodings Python accepts for source files are ASCII compatible, so the
+/// source needn't be decoded: a lossy UTF-8 conversion keeps the statements
+/// intact.
+pub fn find_imports(source: &str, name: &str, is_package: bool) -> BTreeSet<String> {
+    let (sanitized, literals) = sanitize_source(source);
+    let mut imports = BTreeSet::new();
+
+    for statement in logical_statements(&sanitized) {
+        // Compound statements like `try: import foo`.
+        let statement = match statement.split_once(':') {
+            Some((_, rest))
+                if rest.trim_start().starts_with("import ")
+                    || rest.trim_start().starts_with("from ") =>
+            {
+                rest.trim_start()
+            }
+            _ => statement.as_str(),
+        };
+
+        if let Some(captures) = RE_IMPORT.captures(statement) {
+            for item in captures[1].split(',') {
+                if let Some(module) = item.split_whitespace().next() {
+                    imports.insert(module.to_string());
+                }
+            }
+        } else if let Some(captures) = RE_FROM_IMPORT.captures(statement) {
+            let level = captures[1].len();
+            let base = if level == 0 {
+                captures[2].to_string()
+            } else {
+                // The package a relative import is relative to.
+                let mut package = name.split('.').collect::<Vec<_>>();
+                if !is_package {
+                    package.pop();
+                }
+                for _ in 1..level {
+                    package.pop();
+                }
+
+                [package.join("."), captures[2].to_string()]
+                    .into_iter()
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(".")
+            };
+
+            if base.is_empty() {
+                continue;
+            }
+
+            let names =
+                captures[3].trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace());
+            for item in names.split(',') {
+                match item.split_whitespace().next() {
+                    Some("*") | None => {}
+                    Some(member) => {
+                        imports.insert(format!("{}.{}", base, member));
+                    }
+                }
+            }
+
+            imports.insert(base);
+        }
+    }
+
+    // String literals are replaced by "" in the sanitized source, in order.
+    let mut literal_index = 0;
+    let mut literal_starts = vec![];
+    for (i, c) in sanitized.char_indices() {
+        if c == '"' {
+            literal_starts.push(i);
+        }
+    }
+    let literal_opening = literal_starts
+        .iter()
+        .step_by(2)
+        .copied()
+        .collect::<Vec<_>>();
+
+    for m in RE_DYNAMIC_IMPORT.find_iter(&sanitized) {
+        let quote = m.end() - 1;
+
+        while literal_index < literal_opening.len() && literal_opening[literal_index] < quote {
+            literal_index += 1;
+        }
+
+        if literal_opening.get(literal_index) == Some(&quote) {
+            let literal = literals[literal_index].trim();
+            if !literal.is_empty() && !literal.starts_with('.') {
+                imports.insert(literal.to_string());
+            }
+        }
+    }
+
+    imports
+}
+
+/// Whether an allow-list entry matches a module name.
+fn allow_list_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix(".*") {
+        Some(package) => name == package || name.starts_with(&format!("{}.", package)),
+        None => name == pattern,
+    }
+}
+
+/// Compute the standard library modules reachable from entry sources.
+///
+/// `entry_sources` are the `(name, is package, source)` of the entry modules.
+/// Parent packages of reachable modules are reachable as well, as importing
+/// a module imports its parents.
+pub fn stdlib_closure(
+    stdlib: &BTreeMap<String, StdlibModule>,
+    entry_sources: &[(String, bool, String)],
+    allowed_modules: &[String],
+) -> eyre::Result<BTreeSet<String>> {
+    let mut reachable = BTreeSet::new();
+    let mut pending = VecDeque::new();
+
+    let mut add = |name: &str, pending: &mut VecDeque<String>| {
+        let parts = name.split('.').collect::<Vec<_>>();
+
+        for i in 1..=parts.len() {
+            let name = parts[..i].join(".");
+
+            if stdlib.contains_key(&name) && reachable.insert(name.clone()) {
+                pending.push_back(name);
+            }
+        }
+    };
+
+    for (name, is_package, source) in entry_sources {
+        for import in find_imports(source, name, *is_package) {
+            add(&import, &mut pending);
+        }
+    }
+
+    let patterns = ALWAYS_INCLUDED_MODULES
+        .iter()
+        .map(|s| s.to_string())
+        .chain(allowed_modules.iter().cloned())
+        .collect::<Vec<_>>();
+    for name in stdlib.keys() {
+        if patterns.iter().any(|p| allow_list_matches(p, name)) {
+            add(name, &mut pending);
+        }
+    }
+
+    while let Some(name) = pending.pop_front() {
+        let module = &stdlib[&name];
+        let source = std::fs::read(&module.path)
+            .wrap_err_with(|| format!("reading {}", module.path.display()))?;
+        let source = String::from_utf8_lossy(&source);
+
+        for import in find_imports(&source, &name, module.is_package) {
+            add(&import, &mut pending);
+        }
+    }
+
+    Ok(reachable)
+}
+
+/// Sizes of the standard library before and after trimming.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrimReport {
+    /// Number of modules in the standard library.
+    pub total_modules: usize,
+
+    /// Size of the sources of all modules.
+    pub total_size: u64,
+
+    /// Number of modules kept.
+    pub kept_modules: usize,
+
+    /// Size of the sources of kept modules.
+    pub kept_size: u64,
+
+    /// Excluded top-level packages and modules with the size of their sources,
+    /// largest first.
+    pub largest_excluded: Vec<(String, u64)>,
+}
+
+impl TrimReport {
+    /// Compare the full standard library with the kept modules.
+    pub fn new(stdlib: &BTreeMap<String, StdlibModule>, kept: &BTreeSet<String>) -> Self {
+        let mut excluded = BTreeMap::<String, u64>::new();
+        let mut res = Self::default();
+
+        for (name, module) in stdlib {
+            res.total_modules += 1;
+            res.total_size += module.size;
+
+            if kept.contains(name) {
+                res.kept_modules += 1;
+                res.kept_size += module.size;
+            } else {
+                let top_level = name.split('.').next().unwrap_or(name);
+                *excluded.entry(top_level.to_string()).or_default() += module.size;
+            }
+        }
+
+        res.largest_excluded = excluded.into_iter().collect();
+        res.largest_excluded
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        res.largest_excluded.truncate(10);
+
+        res
+    }
+}
+
+fn format_size(size: u64) -> String {
+    format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0))
+}
+
+impl fmt::Display for TrimReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let saved = if self.total_size > 0 {
+            100.0 * (self.total_size - self.kept_size) as f64 / self.total_size as f64
+        } else {
+            0.0
+        };
+
+        writeln!(
+            f,
+            "standard library: kept {} of {} modules, {} of {} ({:.0}% smaller)",
+            self.kept_modules,
+            self.total_modules,
+            format_size(self.kept_size),
+            format_size(self.total_size),
+            saved
+        )?;
+
+        if !self.largest_excluded.is_empty() {
+            writeln!(f, "largest excluded:")?;
+        }
+        for (name, size) in &self.largest_excluded {
+            writeln!(f, "  {:<24} {}", name, format_size(*size))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Modules compiled into libpython, which are never trimmed.
+    const BUILTIN_MODULES: &[&str] = &[
+        "_frozen_importlib",
+        "_frozen_importlib_external",
+        "_imp",
+        "builtins",
+        "marshal",
+        "sys",
+    ];
+
+    #[test]
+    fn always_included_covers_pyembed() {
+        static RE_RUST_IMPORT: Lazy<regex::Regex> =
+            Lazy::new(|| regex::Regex::new(r#"\.import(?:_bound)?\(\s*"([\w.]+)"\s*\)"#).unwrap());
+        // Python code is embedded as raw strings.
+        static RE_RAW_STRING: Lazy<regex::Regex> =
+            Lazy::new(|| regex::Regex::new(r##"(?s)r#"(.*?)"#"##).unwrap());
+        static RE_PYTHON_IMPORT: Lazy<regex::Regex> = Lazy::new(|| {
+            regex::Regex::new(r"(?m)^\s*(?:import|from)\s+([A-Za-z_][\w.]*)").unwrap()
+        });
+
+        let src_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../pyembed/src");
+        let mut imported = BTreeSet::new();
+
+        for entry in super::super::walk_tree_files(&src_dir) {
+            let path = entry.path();
+            let relative = path.strip_prefix(&src_dir).unwrap();
+
+            // Tests and modules that aren't compiled don't matter.
+            if path.extension().and_then(|s| s.to_str()) != Some("rs")
+                || relative.starts_with("test")
+                || relative == Path::new("technotes.rs")
+                || relative == Path::new("interpreter_config.rs")
+            {
+                continue;
+            }
+
+            let source = std::fs::read_to_string(path).unwrap();
+            imported.extend(
+                RE_RUST_IMPORT
+                    .captures_iter(&source)
+                    .map(|c| c[1].to_string()),
+            );
+            for code in RE_RAW_STRING.captures_iter(&source) {
+                imported.extend(
+                    RE_PYTHON_IMPORT
+                        .captures_iter(&code[1])
+                        .map(|c| c[1].to_string()),
+                );
+            }
+        }
+
+        assert!(imported.contains("uuid"));
+        assert!(imported.contains("logging"));
+
+        let missing = imported
+            .iter()
+            .filter(|name| !BUILTIN_MODULES.contains(&name.as_str()))
+            .filter(|name| {
+                !ALWAYS_INCLUDED_MODULES
+                    .iter()
+                    .any(|p| allow_list_matches(p, name))
+            })
+            .collect::<Vec<_>>();
+
+        assert!(
+            missing.is_empty(),
+            "modules imported by pyembed may be trimmed: {:?}",
+            missing
+        );
+    }
+
+    fn imports(source: &str) -> Vec<String> {
+        find_imports(source, "pkg.sub.mod", false)
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn import_statements() {
+        assert_eq!(
+            imports("import os, sys as system\nimport xml.etree.ElementTree as ET\n"),
+            vec!["os", "sys", "xml.etree.ElementTree"]
+        );
+        assert_eq!(
+            imports("from collections import (\n    OrderedDict,\n    abc as cabc,\n)\n"),
+            vec!["collections", "collections.OrderedDict", "collections.abc"]
+        );
+        assert_eq!(imports("from json import *"), vec!["json"]);
+        assert_eq!(
+            imports("def f():\n    try: import zlib\n    except ImportError: pass\n"),
+            vec!["zlib"]
+        );
+        assert_eq!(imports("import a; import \\\n  b"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn relative_imports() {
+        assert_eq!(
+            imports("from . import x\nfrom .. import y\nfrom .z import w\n"),
+            vec![
+                "pkg",
+                "pkg.sub",
+                "pkg.sub.x",
+                "pkg.sub.z",
+                "pkg.sub.z.w",
+                "pkg.y"
+            ]
+        );
+        assert_eq!(
+            find_imports("from .util import f", "pkg", true)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["pkg.util", "pkg.util.f"]
+        );
+    }
+
+    #[test]
+    fn ignores_comments_and_strings() {
+        assert!(imports("# import os\nx = 'import sys'\n\"\"\"\nimport json\n\"\"\"\n").is_empty());
+    }
+
+    #[test]
+    fn dynamic_imports() {
+        assert_eq!(
+            imports("m = importlib.import_module('xml.dom')\nn = __import__(\"csv\")\no = import_module(name)\n"),
+            vec!["csv", "xml.dom"]
+        );
+        assert_eq!(imports("x = 'a'\nm = import_module(\"b\")\n"), vec!["b"]);
+    }
+
+    #[test]
+    fn allow_list() {
+        assert!(allow_list_matches("encodings.*", "encodings"));
+        assert!(allow_list_matches("encodings.*", "encodings.utf_8"));
+        assert!(!allow_list_matches("encodings.*", "encodingsx"));
+        assert!(allow_list_matches("os", "os"));
+        assert!(!allow_list_matches("os", "os.path"));
+    }
+
+    #[test]
+    fn closure() {
+        let dir = std::env::temp_dir().join(format!("stdlib-trim-test-{}", std::process::id()));
+        let files = [
+            ("os.py", "import stat\n"),
+            ("stat.py", ""),
+            ("json/__init__.py", "from .decoder import JSONDecoder\n"),
+            ("json/decoder.py", "import re\n"),
+            ("re.py", ""),
+            ("tkinter/__init__.py", "import os\n"),
+            ("test/__init__.py", ""),
+            ("site-packages/foo.py", ""),
+            ("lib-dynload/x.py", ""),
+        ];
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let stdlib = find_stdlib_modules(&dir, &["test".to_string()]).unwrap();
+        assert_eq!(
+            stdlib.keys().collect::<Vec<_>>(),
+            vec!["json", "json.decoder", "os", "re", "stat", "tkinter"]
+        );
+
+        let entry = vec![(
+            "app".to_string(),
+            false,
+            "import json\nimport numpy\n".to_string(),
+        )];
+        let kept = stdlib_closure(&stdlib, &entry, &[]).unwrap();
+        assert_eq!(
+            kept.into_iter().collect::<Vec<_>>(),
+            vec!["json", "json.decoder", "os", "re", "stat"]
+        );
+
+        let kept = stdlib_closure(&stdlib, &entry, &["tkinter".to_string()]).unwrap();
+        assert!(kept.contains("tkinter"));
+
+        let report = TrimReport::new(&stdlib, &kept);
+        assert_eq!(report.total_modules, 6);
+        assert_eq!(report.kept_modules, 6);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,774 @@
+//! Reading of Python wheel archives.
+//!
+//! Wheels (`.whl`, PEP 427) are zip archives holding the files of a
+//! distribution as they would be installed into `site-packages`, plus a
+//! `<name>-<version>.dist-info` directory with metadata. [WheelArchive] reads
+//! them without running any Python, verifies their `RECORD` and adds their
+//! contents to a [PythonResourceCollector].
+
+use super::{FileData, PythonPackagingPolicy, PythonResourceCollector};
+use base64::Engine;
+use color_eyre::eyre::{self, WrapErr};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Seek};
+use std::path::Path;
+
+/// What a file in `site-packages` is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SitePackagesEntry {
+    /// Python module source: `(module name, is package)`.
+    ModuleSource(String, bool),
+
+    /// An extension module.
+    ExtensionModule(String),
+
+    /// A shared library that isn't an extension module, e.g. a library vendored
+    /// by `auditwheel` or bundled with Qt.
+    SharedLibrary,
+
+    /// A file of a distribution's metadata: `(distribution, file name)`.
+    DistributionResource(String, String),
+
+    /// A non-module file within a package: `(package, relative path)`.
+    PackageResource(String, String),
+
+    /// Something we don't package, e.g. scripts, caches or top-level files.
+    Ignored,
+}
+
+/// Whether a file name looks like a shared library.
+fn is_shared_library(file_name: &str) -> bool {
+    file_name.ends_with(".dylib")
+        || file_name.ends_with(".dll")
+        || file_name.ends_with(".so")
+        || file_name.contains(".so.")
+}
+
+fn is_identifier(value: &str) -> bool {
+    let mut chars = value.chars();
+
+    matches!(chars.next(), Some(c) if c == '_' || c.is_alphabetic())
+        && chars.all(|c| c == '_' || c.is_alphanumeric())
+}
+
+/// Find the regular packages, i.e. directories holding an `__init__.py`.
+///
+/// `paths` are relative to `site-packages`. Returns dotted package names.
+fn find_packages<'a>(paths: impl Iterator<Item = &'a Path>) -> BTreeSet<String> {
+    paths
+        .filter(|path| path.file_name() == Some("__init__.py".as_ref()))
+        .filter_map(|path| {
+            let dirs = path
+                .parent()?
+                .components()
+                .map(|c| c.as_os_str().to_str())
+                .collect::<Option<Vec<_>>>()?;
+
+            if !dirs.is_empty() && dirs.iter().all(|d| is_identifier(d)) {
+                Some(dirs.join("."))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Classify a file in `site-packages` by its relative path.
+///
+/// `extension_suffixes` are the suffixes of extension modules, longest first.
+/// `packages` are the regular packages, as found by [find_packages].
+fn classify_site_packages_path(
+    path: &Path,
+    extension_suffixes: &[&str],
+    packages: &BTreeSet<String>,
+) -> SitePackagesEntry {
+    let parts = match path
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(parts) if !parts.is_empty() => parts,
+        _ => return SitePackagesEntry::Ignored,
+    };
+
+    let (dirs, file_name) = parts.split_at(parts.len() - 1);
+    let file_name = file_name[0];
+
+    if dirs.contains(&"__pycache__") {
+        return SitePackagesEntry::Ignored;
+    }
+
+    if let Some(dist_dir) = dirs.first().and_then(|d| d.strip_suffix(".dist-info")) {
+        // Directory names are `<name>-<version>`, with `-` escaped in names.
+        let name = dist_dir.split('-').next().unwrap_or(dist_dir);
+
+        return SitePackagesEntry::DistributionResource(name.to_string(), parts[1..].join("/"));
+    }
+
+    let is_package_path = dirs.iter().all(|d| is_identifier(d));
+    let qualify = |stem: &str| {
+        dirs.iter()
+            .copied()
+            .chain(std::iter::once(stem))
+            .collect::<Vec<_>>()
+            .join(".")
+    };
+
+    if is_package_path {
+        if !dirs.is_empty() && file_name == "__init__.py" {
+            return SitePackagesEntry::ModuleSource(dirs.join("."), true);
+        }
+
+        if let Some(stem) = file_name.strip_suffix(".py") {
+            if is_identifier(stem) {
+                return SitePackagesEntry::ModuleSource(qualify(stem), false);
+            }
+        }
+
+        if let Some(stem) = extension_suffixes
+            .iter()
+            .find_map(|suffix| file_name.strip_suffix(suffix))
+        {
+            if is_identifier(stem) {
+                return SitePackagesEntry::ExtensionModule(qualify(stem));
+            }
+        }
+    }
+
+    // Shared libraries can only be loaded from the filesystem. Wherever they
+    // are, extension modules locate them relative to their own location.
+    if is_shared_library(file_name) {
+        return SitePackagesEntry::SharedLibrary;
+    }
+
+    // Other files are resources of the deepest package enclosing them, keyed
+    // by their path within it. So `importlib.resources.files()` of that
+    // package finds them. This includes files in directories that can't be
+    // packages, such as `matplotlib/mpl-data`.
+    let enclosing_package = (1..=dirs.len()).rev().find(|&depth| {
+        dirs[..depth].iter().all(|d| is_identifier(d))
+            && packages.contains(&dirs[..depth].join("."))
+    });
+
+    match enclosing_package {
+        Some(depth) => {
+            SitePackagesEntry::PackageResource(dirs[..depth].join("."), parts[depth..].join("/"))
+        }
+        // Without `__init__.py` files, the directory is a namespace package.
+        None if !dirs.is_empty() && is_package_path => {
+            SitePackagesEntry::PackageResource(dirs.join("."), file_name.to_string())
+        }
+        // Top-level data files and directories that aren't packages (`bin`,
+        // `*.data`, ...) aren't packaged.
+        None => SitePackagesEntry::Ignored,
+    }
+}
+
+/// Parse RFC 822 style headers, as used by `WHEEL` and `METADATA`.
+///
+/// Parsing stops at the first empty line, which starts the message body.
+pub fn parse_headers(content: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = vec![];
+
+    for line in content.lines() {
+        if line.is_empty() {
+            break;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    headers
+}
+
+/// Parse a line of a `RECORD` file, which is CSV.
+fn parse_record_line(line: &str) -> eyre::Result<Vec<String>> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(eyre::eyre!("unterminated quote in RECORD line: {}", line));
+    }
+
+    fields.push(field);
+
+    Ok(fields)
+}
+
+/// Verify data against a `RECORD` hash like `sha256=<urlsafe base64>`.
+fn verify_record_hash(data: &[u8], hash: &str) -> eyre::Result<()> {
+    let (algorithm, expected) = hash
+        .split_once('=')
+        .ok_or_else(|| eyre::eyre!("malformed hash {}", hash))?;
+
+    let digest = match algorithm {
+        "sha256" => Sha256::digest(data).to_vec(),
+        "sha384" => Sha384::digest(data).to_vec(),
+        "sha512" => Sha512::digest(data).to_vec(),
+        _ => return Err(eyre::eyre!("unsupported hash algorithm {}", algorithm)),
+    };
+
+    let actual = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest);
+
+    if actual != expected.trim_end_matches('=') {
+        return Err(eyre::eyre!(
+            "hash mismatch: expected {}, got {}",
+            expected,
+            actual
+        ));
+    }
+
+    Ok(())
+}
+
+/// A parsed wheel archive.
+#[derive(Clone, Debug)]
+pub struct WheelArchive {
+    /// Distribution name, as in `METADATA`.
+    name: String,
+
+    /// Distribution version, as in `METADATA`.
+    version: String,
+
+    /// Name of the `.dist-info` directory.
+    dist_info: String,
+
+    /// Headers of the `WHEEL` file.
+    wheel: Vec<(String, String)>,
+
+    /// Files in the archive, by path.
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl WheelArchive {
+    /// Read a wheel from a file.
+    pub fn from_path(path: &Path) -> eyre::Result<Self> {
+        let f =
+            std::fs::File::open(path).wrap_err_with(|| format!("opening {}", path.display()))?;
+
+        Self::from_reader(f).wrap_err_with(|| format!("reading wheel {}", path.display()))
+    }
+
+    /// Read a wheel from a zip archive.
+    ///
+    /// The `RECORD` is verified: every file must be listed with a matching
+    /// hash and size.
+    pub fn from_reader<R: Read + Seek>(reader: R) -> eyre::Result<Self> {
+        let mut archive = zip::ZipArchive::new(reader).wrap_err("reading zip archive")?;
+
+        let mut files = BTreeMap::new();
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+
+            let name = entry
+                .enclosed_name()
+                .and_then(|p| p.to_str().map(|s| s.replace('\\', "/")))
+                .ok_or_else(|| eyre::eyre!("unsafe path in wheel: {}", entry.name()))?;
+
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry
+                .read_to_end(&mut data)
+                .wrap_err_with(|| format!("reading {}", name))?;
+
+            files.insert(name, data);
+        }
+
+        let dist_infos = files
+            .keys()
+            .filter_map(|name| name.split_once('/'))
+            .filter(|(dir, file)| dir.ends_with(".dist-info") && *file == "WHEEL")
+            .map(|(dir, _)| dir.to_string())
+            .collect::<Vec<_>>();
+
+        let dist_info = match dist_infos.as_slice() {
+            [dist_info] => dist_info.clone(),
+            [] => return Err(eyre::eyre!("wheel lacks .dist-info/WHEEL")),
+            _ => return Err(eyre::eyre!("wheel has multiple .dist-info directories")),
+        };
+
+        let read_text = |file: &str| -> eyre::Result<String> {
+            let path = format!("{}/{}", dist_info, file);
+            let data = files
+                .get(&path)
+                .ok_or_else(|| eyre::eyre!("wheel lacks {}", path))?;
+
+            String::from_utf8(data.clone()).wrap_err_with(|| format!("{} is not UTF-8", path))
+        };
+
+        let wheel = parse_headers(&read_text("WHEEL")?);
+        let metadata = parse_headers(&read_text("METADATA")?);
+        let record = read_text("RECORD")?;
+
+        let header = |headers: &[(String, String)], key: &str| {
+            headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.clone())
+        };
+
+        let wheel_version = header(&wheel, "Wheel-Version")
+            .ok_or_else(|| eyre::eyre!("WHEEL lacks Wheel-Version"))?;
+        if wheel_version.split('.').next() != Some("1") {
+            return Err(eyre::eyre!("unsupported Wheel-Version {}", wheel_version));
+        }
+
+        let res = Self {
+            name: header(&metadata, "Name").ok_or_else(|| eyre::eyre!("METADATA lacks Name"))?,
+            version: header(&metadata, "Version")
+                .ok_or_else(|| eyre::eyre!("METADATA lacks Version"))?,
+            dist_info,
+            wheel,
+            files,
+        };
+
+        res.verify_record(&record)?;
+
+        Ok(res)
+    }
+
+    /// Verify the archive's files against the content of its `RECORD`.
+    fn verify_record(&self, record: &str) -> eyre::Result<()> {
+        let record_path = format!("{}/RECORD", self.dist_info);
+        let mut unverified = self
+            .files
+            .keys()
+            .map(|s| s.as_str())
+            .collect::<std::collections::BTreeSet<_>>();
+
+        for line in record.lines().filter(|line| !line.is_empty()) {
+            let fields = parse_record_line(line)?;
+            let (path, hash, size) = match fields.as_slice() {
+                [path, hash, size] => (path, hash, size),
+                _ => return Err(eyre::eyre!("malformed RECORD line: {}", line)),
+            };
+
+            let data = self
+                .files
+                .get(path)
+                .ok_or_else(|| eyre::eyre!("{} is in RECORD but not in the wheel", path))?;
+
+            if !hash.is_empty() {
+                verify_record_hash(data, hash).wrap_err_with(|| format!("verifying {}", path))?;
+            } else if *path != record_path {
+                return Err(eyre::eyre!("{} lacks a hash in RECORD", path));
+            }
+
+            if !size.is_empty() && size.parse::<usize>().ok() != Some(data.len()) {
+                return Err(eyre::eyre!("{} has the wrong size", path));
+            }
+
+            unverified.remove(path.as_str());
+        }
+
+        // Signatures of RECORD can't be listed in it.
+        unverified.remove(format!("{}/RECORD.jws", self.dist_info).as_str());
+        unverified.remove(format!("{}/RECORD.p7s", self.dist_info).as_str());
+
+        if let Some(path) = unverified.into_iter().next() {
+            return Err(eyre::eyre!("{} is not in RECORD", path));
+        }
+
+        Ok(())
+    }
+
+    /// The distribution name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The distribution version.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Whether the wheel is pure Python, i.e. `Root-Is-Purelib: true`.
+    pub fn is_purelib(&self) -> bool {
+        self.wheel
+            .iter()
+            .any(|(k, v)| k == "Root-Is-Purelib" && v.eq_ignore_ascii_case("true"))
+    }
+
+    /// Compatibility tags of the wheel, e.g. `cp312-cp312-manylinux_2_17_x86_64`.
+    pub fn tags(&self) -> Vec<&str> {
+        self.wheel
+            .iter()
+            .filter(|(k, _)| k == "Tag")
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// Files as they are installed into `site-packages`, by relative path.
+    ///
+    /// Files of the `purelib` and `platlib` schemes of the `.data` directory
+    /// are moved to the root. Other schemes (`scripts`, `headers`, `data`)
+    /// don't belong in `site-packages` and are skipped.
+    pub fn site_packages_files(&self) -> impl Iterator<Item = (String, &[u8])> {
+        let data_dir = format!("{}.data/", self.dist_info.trim_end_matches(".dist-info"));
+
+        self.files.iter().filter_map(move |(path, data)| {
+            let path = match path.strip_prefix(&data_dir) {
+                Some(rest) => {
+                    let (scheme, rest) = rest.split_once('/')?;
+
+                    if scheme == "purelib" || scheme == "platlib" {
+                        rest.to_string()
+                    } else {
+                        log::debug!("not packaging {} file {}", scheme, rest);
+                        return None;
+                    }
+                }
+                None => path.clone(),
+            };
+
+            Some((path, data.as_slice()))
+        })
+    }
+
+    /// Add the contents of the wheel to a resource collector.
+    ///
+    /// Resources are placed where the policy says. `extension_suffixes` are
+    /// the file suffixes of extension modules of the target distribution, e.g.
+    /// `.cpython-312-x86_64-linux-gnu.so`.
+    pub fn add_to_collector(
+        &self,
+        collector: &mut PythonResourceCollector,
+        policy: &PythonPackagingPolicy,
+        extension_suffixes: &[String],
+    ) -> eyre::Result<()> {
+        let mut suffixes = extension_suffixes
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>();
+        suffixes.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+        let files = self.site_packages_files().collect::<Vec<_>>();
+        let packages = find_packages(files.iter().map(|(path, _)| Path::new(path)));
+
+        for (path, data) in files {
+            let relative = Path::new(&path);
+            let data = FileData::Memory(data.to_vec());
+
+            match classify_site_packages_path(relative, &suffixes, &packages) {
+                SitePackagesEntry::ModuleSource(name, is_package) => {
+                    collector.add_python_module_source(&name, data, is_package, policy)?;
+                }
+                SitePackagesEntry::ExtensionModule(name) => {
+                    collector.add_extension_module_file(&name, relative, data, policy)?;
+                }
+                SitePackagesEntry::SharedLibrary => {
+                    collector.add_shared_library_file(relative, data, policy)?;
+                }
+                SitePackagesEntry::DistributionResource(_, file_name) => {
+                    // Use the canonical name from METADATA rather than the
+                    // escaped directory name.
+                    collector.add_distribution_resource(&self.name, &file_name, data, policy)?;
+                }
+                SitePackagesEntry::PackageResource(package, file_name) => {
+                    collector.add_package_resource(&package, &file_name, data, policy)?;
+                }
+                SitePackagesEntry::Ignored => {
+                    log::debug!("not packaging {}", path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Build a wheel from `(path, content)` pairs, generating its RECORD.
+    fn build_wheel(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options = zip::write::SimpleFileOptions::default();
+
+        let mut record = String::new();
+        for (path, data) in files {
+            writer.start_file(*path, options).unwrap();
+            writer.write_all(data).unwrap();
+
+            let hash =
+                base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(data));
+            record.push_str(&format!("{},sha256={},{}\n", path, hash, data.len()));
+        }
+        record.push_str("demo-1.0.dist-info/RECORD,,\n");
+
+        writer
+            .start_file("demo-1.0.dist-info/RECORD", options)
+            .unwrap();
+        writer.write_all(record.as_bytes()).unwrap();
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    const WHEEL: &[u8] = b"Wheel-Version: 1.0\nGenerator: test\nRoot-Is-Purelib: false\nTag: cp312-cp312-linux_x86_64\n";
+    const METADATA: &[u8] =
+        b"Metadata-Version: 2.1\nName: Demo\nVersion: 1.0\n\nLong description.\n";
+
+    fn demo_files() -> Vec<(&'static str, &'static [u8])> {
+        vec![
+            ("demo-1.0.dist-info/WHEEL", WHEEL),
+            ("demo-1.0.dist-info/METADATA", METADATA),
+            ("demo/__init__.py", b"from ._core import x\n"),
+            ("demo/util.py", b"y = 1\n"),
+            ("demo/_core.cpython-312-x86_64-linux-gnu.so", b"\x7fELF"),
+            ("demo/data/table.csv", b"a,b\n"),
+            ("demo.libs/libfoo-1234.so.1", b"\x7fELF"),
+            ("demo-1.0.data/purelib/demo_extra.py", b"z = 2\n"),
+            ("demo-1.0.data/scripts/demo", b"#!python\n"),
+        ]
+    }
+
+    #[test]
+    fn read_wheel() {
+        let wheel =
+            WheelArchive::from_reader(std::io::Cursor::new(build_wheel(&demo_files()))).unwrap();
+
+        assert_eq!(wheel.name(), "Demo");
+        assert_eq!(wheel.version(), "1.0");
+        assert!(!wheel.is_purelib());
+        assert_eq!(wheel.tags(), vec!["cp312-cp312-linux_x86_64"]);
+
+        let paths = wheel
+            .site_packages_files()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        assert!(paths.contains(&"demo_extra.py".to_string()));
+        assert!(!paths.iter().any(|p| p.contains("scripts")));
+    }
+
+    #[test]
+    fn record_verification() {
+        let mut files = demo_files();
+        let data = build_wheel(&files);
+        assert!(WheelArchive::from_reader(std::io::Cursor::new(data)).is_ok());
+
+        // A file missing from RECORD.
+        let mut writer =
+            zip::ZipWriter::new_append(std::io::Cursor::new(build_wheel(&files))).unwrap();
+        writer
+            .start_file("demo/sneaky.py", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"import os\n").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        assert!(WheelArchive::from_reader(std::io::Cursor::new(data)).is_err());
+
+        // Content not matching the RECORD hash.
+        let hash = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(b"y"));
+        assert!(verify_record_hash(b"y", &format!("sha256={}", hash)).is_ok());
+        assert!(verify_record_hash(b"z", &format!("sha256={}", hash)).is_err());
+        assert!(verify_record_hash(b"y", &format!("md5={}", hash)).is_err());
+
+        // No METADATA.
+        files.retain(|(path, _)| !path.ends_with("METADATA"));
+        assert!(WheelArchive::from_reader(std::io::Cursor::new(build_wheel(&files))).is_err());
+    }
+
+    #[test]
+    fn record_lines() {
+        assert_eq!(
+            parse_record_line("a/b.py,sha256=abc,12").unwrap(),
+            vec!["a/b.py", "sha256=abc", "12"]
+        );
+        assert_eq!(
+            parse_record_line("\"a,\"\"b\"\".py\",,").unwrap(),
+            vec!["a,\"b\".py", "", ""]
+        );
+        assert!(parse_record_line("\"a,b").is_err());
+    }
+
+    #[test]
+    fn headers() {
+        let headers =
+            parse_headers("Name: foo\nClassifier: a\n  continued\nVersion: 1\n\nName: body\n");
+        assert_eq!(
+            headers,
+            vec![
+                ("Name".to_string(), "foo".to_string()),
+                ("Classifier".to_string(), "a\ncontinued".to_string()),
+                ("Version".to_string(), "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn classify() {
+        let suffixes = [".cpython-312-x86_64-linux-gnu.so", ".abi3.so", ".so"];
+        let packages = ["numpy", "pyrocko", "PyQt5"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let classify =
+            |path: &str| classify_site_packages_path(Path::new(path), &suffixes, &packages);
+
+        assert_eq!(
+            classify("numpy/__init__.py"),
+            SitePackagesEntry::ModuleSource("numpy".into(), true)
+        );
+        assert_eq!(
+            classify("numpy/linalg/linalg.py"),
+            SitePackagesEntry::ModuleSource("numpy.linalg.linalg".into(), false)
+        );
+        assert_eq!(
+            classify("six.py"),
+            SitePackagesEntry::ModuleSource("six".into(), false)
+        );
+        assert_eq!(
+            classify("numpy/core/_multiarray_umath.cpython-312-x86_64-linux-gnu.so"),
+            SitePackagesEntry::ExtensionModule("numpy.core._multiarray_umath".into())
+        );
+        assert_eq!(
+            classify("PyQt5/QtCore.abi3.so"),
+            SitePackagesEntry::ExtensionModule("PyQt5.QtCore".into())
+        );
+        assert_eq!(
+            classify("numpy-1.26.4.dist-info/METADATA"),
+            SitePackagesEntry::DistributionResource("numpy".into(), "METADATA".into())
+        );
+        assert_eq!(
+            classify("pyrocko/data/earthmodels/ak135-f-average.m.nd"),
+            SitePackagesEntry::PackageResource(
+                "pyrocko".into(),
+                "data/earthmodels/ak135-f-average.m.nd".into()
+            )
+        );
+        assert_eq!(
+            classify("PyQt5/Qt5/lib/libQt5Core.so.5"),
+            SitePackagesEntry::SharedLibrary
+        );
+        assert_eq!(
+            classify("PyQt5/Qt5/translations/qt_de.qm"),
+            SitePackagesEntry::PackageResource("PyQt5".into(), "Qt5/translations/qt_de.qm".into())
+        );
+        assert_eq!(
+            classify("numpy/core/__pycache__/x.cpython-312.pyc"),
+            SitePackagesEntry::Ignored
+        );
+        assert_eq!(
+            classify("numpy.libs/libopenblas64_p-r0-0cf96a72.3.23.dev.so"),
+            SitePackagesEntry::SharedLibrary
+        );
+        assert_eq!(classify("README.txt"), SitePackagesEntry::Ignored);
+    }
+
+    #[test]
+    fn packages() {
+        let packages = find_packages(
+            [
+                "numpy/__init__.py",
+                "numpy/core/__init__.py",
+                "numpy/core/tests/data/x.csv",
+                "mpl-data/__init__.py",
+                "six.py",
+            ]
+            .iter()
+            .map(Path::new),
+        );
+
+        assert_eq!(
+            packages.into_iter().collect::<Vec<_>>(),
+            vec!["numpy".to_string(), "numpy.core".to_string()]
+        );
+    }
+
+    #[test]
+    fn classify_deepest_package() {
+        let packages = ["numpy", "numpy.core", "numpy.core.tests", "pyrocko"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let classify = |path: &str| classify_site_packages_path(Path::new(path), &[], &packages);
+
+        assert_eq!(
+            classify("numpy/core/tests/data/x.csv"),
+            SitePackagesEntry::PackageResource("numpy.core.tests".into(), "data/x.csv".into())
+        );
+        assert_eq!(
+            classify("numpy/core/include/numpy/ndarrayobject.h"),
+            SitePackagesEntry::PackageResource(
+                "numpy.core".into(),
+                "include/numpy/ndarrayobject.h".into()
+            )
+        );
+        assert_eq!(
+            classify("numpy/py.typed"),
+            SitePackagesEntry::PackageResource("numpy".into(), "py.typed".into())
+        );
+
+        // Namespace packages have no `__init__.py`.
+        assert_eq!(
+            classify("google/protobuf/any.proto"),
+            SitePackagesEntry::PackageResource("google.protobuf".into(), "any.proto".into())
+        );
+    }
+
+    #[test]
+    fn classify_non_identifier_dirs() {
+        let packages = ["matplotlib", "PyQt5"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let classify = |path: &str| classify_site_packages_path(Path::new(path), &[], &packages);
+
+        assert_eq!(
+            classify("matplotlib/mpl-data/matplotlibrc"),
+            SitePackagesEntry::PackageResource("matplotlib".into(), "mpl-data/matplotlibrc".into())
+        );
+        assert_eq!(
+            classify("PyQt5/Qt5/qml/QtQuick.2/qmldir"),
+            SitePackagesEntry::PackageResource("PyQt5".into(), "Qt5/qml/QtQuick.2/qmldir".into())
+        );
+        assert_eq!(
+            classify("PyQt5/Qt5/qml/QtQuick.2/plugins.qmltypes"),
+            SitePackagesEntry::PackageResource(
+                "PyQt5".into(),
+                "Qt5/qml/QtQuick.2/plugins.qmltypes".into()
+            )
+        );
+
+        // Python files in such directories aren't importable modules.
+        assert_eq!(
+            classify("matplotlib/mpl-data/sample_data/helper.py"),
+            SitePackagesEntry::PackageResource(
+                "matplotlib".into(),
+                "mpl-data/sample_data/helper.py".into()
+            )
+        );
+
+        // Outside of any package, they aren't packaged.
+        assert_eq!(
+            classify("foo-1.0.data/scripts/foo"),
+            SitePackagesEntry::Ignored
+        );
+    }
+}
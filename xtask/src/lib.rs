@@ -1,3 +1,5 @@
+pub mod embed_python;
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
@@ -1,12 +1,54 @@
-mod embed_python;
-
 use color_eyre::eyre;
 use clap::Parser;
 use std::path::PathBuf;
+use xtask::embed_python;
 
 #[derive(Parser, Debug)]
 pub enum Command {
-    PrepareEmbedPython,
+    PrepareEmbedPython {
+        /// Directory of wheels to install the Pipfile packages from.
+        ///
+        /// Packages are only bundled if this is given.
+        #[arg(long)]
+        wheelhouse: Option<PathBuf>,
+
+        /// Pipfile listing the packages to bundle.
+        #[arg(long, default_value = "Pipfile")]
+        pipfile: PathBuf,
+
+        /// Only bundle standard library modules reachable from the entry modules.
+        #[arg(long)]
+        trim_stdlib: bool,
+
+        /// Module whose imports are followed when trimming the standard library.
+        #[arg(long = "entry-module", default_value = "pyrocko")]
+        entry_modules: Vec<String>,
+
+        /// Directory of plugin sources whose imports are followed when trimming.
+        #[arg(long = "plugins")]
+        plugin_dirs: Vec<PathBuf>,
+
+        /// Standard library module to keep when trimming, e.g. because it is
+        /// imported dynamically. A `.*` suffix includes all submodules.
+        #[arg(long = "allow-module")]
+        allowed_modules: Vec<String>,
+
+        /// Link libpython dynamically, for quicker development builds.
+        ///
+        /// The standard library is then loaded from the filesystem.
+        #[arg(long)]
+        dynamic_libpython: bool,
+
+        /// Shared library that extension modules may need without it being
+        /// bundled, e.g. `libGLU.so.1`. Can be given multiple times.
+        #[arg(long = "allow-library")]
+        allowed_libraries: Vec<String>,
+
+        /// Load packages using `__file__` from this directory next to the
+        /// executable instead of from memory, e.g. `lib`.
+        #[arg(long)]
+        dunder_file_reroute_prefix: Option<String>,
+    },
 }
 
 /// Simple program to greet a person
@@ -32,8 +74,39 @@ fn main() -> eyre::Result<()> {
     let args = Args::parse();
     let dest = PathBuf::from("./embed-dest");
     match args.command {
-        Command::PrepareEmbedPython => {
-            embed_python::generate_python_embedding_artifacts(&dest)?;
+        Command::PrepareEmbedPython {
+            wheelhouse,
+            pipfile,
+            trim_stdlib,
+            entry_modules,
+            plugin_dirs,
+            allowed_modules,
+            dynamic_libpython,
+            allowed_libraries,
+            dunder_file_reroute_prefix,
+        } => {
+            let pipfile = wheelhouse.map(|wheelhouse| embed_python::pipfile::PipfileInstall {
+                pipfile,
+                wheelhouse,
+            });
+            let trim = trim_stdlib.then_some(embed_python::stdlib_trim::StdlibTrim {
+                entry_modules,
+                source_dirs: plugin_dirs,
+                allowed_modules,
+            });
+            let link_mode = if dynamic_libpython {
+                embed_python::LibpythonLinkMode::Dynamic
+            } else {
+                embed_python::LibpythonLinkMode::Static
+            };
+            embed_python::generate_python_embedding_artifacts(
+                &dest,
+                pipfile.as_ref(),
+                trim.as_ref(),
+                link_mode,
+                &allowed_libraries,
+                dunder_file_reroute_prefix,
+            )?;
         },
     }
     Ok(())